@@ -0,0 +1,57 @@
+use crate::claims::{token_from_headers, Claims};
+use crate::error::Error;
+use crate::verifier::Verifier;
+use actix_web::dev::Payload;
+use actix_web::http::header::{AUTHORIZATION, COOKIE};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Claims of a verified request. The [`Verifier`] must be registered with
+/// `App::new().app_data(web::Data::new(verifier))`, requests without a valid token get a 401.
+#[derive(Debug, Clone)]
+pub struct Authenticated(pub Claims);
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Fetch(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        log::debug!("{}", self);
+        let message = match self {
+            Error::Fetch(_) => "Service unavailable",
+            _ => "Unauthorized",
+        };
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            message: String::from(message),
+        })
+    }
+}
+
+impl FromRequest for Authenticated {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Authenticated, Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let verifier = req.app_data::<web::Data<Verifier>>().cloned();
+        let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let token = token_from_headers(header(AUTHORIZATION), header(COOKIE)).map(String::from);
+
+        Box::pin(async move {
+            let verifier = verifier.expect("auth_client::Verifier is not registered as app data");
+            let token = token.ok_or(Error::MissingToken)?;
+            verifier.verify(&token).await.map(Authenticated)
+        })
+    }
+}
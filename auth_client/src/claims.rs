@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Claims of the access tokens issued by the auth service.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    /// Id of the user.
+    pub sub: String,
+    pub aud: String,
+    pub exp: u64,
+    pub iat: u64,
+    pub nbf: u64,
+    pub jti: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Claims this version of the crate does not know about.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Claims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|granted| granted == role)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope
+            .as_deref()
+            .map(|scopes| scopes.split(' ').any(|granted| granted == scope))
+            .unwrap_or(false)
+    }
+}
+
+/// Token sent in the `Authorization` header (with or without the `Bearer` prefix), or else in
+/// the `Authorization` cookie set by the login endpoint.
+pub fn token_from_headers<'a>(
+    authorization: Option<&'a str>,
+    cookie: Option<&'a str>,
+) -> Option<&'a str> {
+    if let Some(header) = authorization {
+        let token = header.strip_prefix("Bearer ").unwrap_or(header).trim();
+        if !token.is_empty() {
+            return Some(token);
+        }
+    }
+
+    cookie?
+        .split(';')
+        .filter_map(|pair| pair.trim().strip_prefix("Authorization="))
+        .find(|token| !token.is_empty())
+}
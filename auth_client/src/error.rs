@@ -0,0 +1,32 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum Error {
+    /// No token in the request.
+    MissingToken,
+    /// Malformed, expired or wrongly signed token.
+    InvalidToken(jsonwebtoken::errors::Error),
+    /// The token was signed with a key the JWKS does not contain.
+    UnknownKey(String),
+    /// The JWKS could not be fetched.
+    Fetch(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MissingToken => write!(f, "Missing token"),
+            Error::InvalidToken(err) => write!(f, "Invalid token: {}", err),
+            Error::UnknownKey(kid) => write!(f, "Unknown signing key: {}", kid),
+            Error::Fetch(err) => write!(f, "Unable to fetch the JWKS: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(err: jsonwebtoken::errors::Error) -> Error {
+        Error::InvalidToken(err)
+    }
+}
@@ -0,0 +1,152 @@
+//! Small HTTP/1 client, shared by this crate and the auth service for their calls to other
+//! services (JWKS, identity providers, claims callbacks).
+//!
+//! Requests go over HTTPS, plain HTTP is only allowed on the loopback unless explicitly
+//! allowed. Each request is bounded in time, answers in size.
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper_util::rt::TokioIo;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsConnector};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    timeout: Duration,
+    max_response_bytes: usize,
+    plain_http: bool,
+}
+
+impl Default for HttpClient {
+    fn default() -> HttpClient {
+        HttpClient {
+            timeout: DEFAULT_TIMEOUT,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            plain_http: false,
+        }
+    }
+}
+
+impl HttpClient {
+    /// Time allowed for a whole request, answer included, 5 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> HttpClient {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Larger answers are refused, 1 MiB by default.
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> HttpClient {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Allow plain HTTP to any host, e.g. for a sidecar on the same network.
+    pub fn with_plain_http(mut self) -> HttpClient {
+        self.plain_http = true;
+        self
+    }
+
+    /// Whether requests to `uri` are allowed.
+    pub fn supports(&self, uri: &http::Uri) -> bool {
+        match (uri.scheme_str(), uri.host()) {
+            (Some("https"), Some(_)) => true,
+            (Some("http"), Some(host)) => self.plain_http || is_loopback(host),
+            _ => false,
+        }
+    }
+
+    /// Send `request` to the absolute URI it carries, the `Host` header is set from it. Fails
+    /// with `TimedOut` after the timeout and with `InvalidData` when the answer is too large.
+    pub async fn send(
+        &self,
+        request: http::Request<Full<Bytes>>,
+    ) -> Result<http::Response<Bytes>, Error> {
+        let uri = request.uri().clone();
+        tokio::time::timeout(self.timeout, self.send_request(request))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, format!("{} timed out", uri)))?
+    }
+
+    async fn send_request(
+        &self,
+        request: http::Request<Full<Bytes>>,
+    ) -> Result<http::Response<Bytes>, Error> {
+        let uri = request.uri().clone();
+        let host = match uri.host() {
+            Some(host) if self.supports(&uri) => host,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unsupported url: {}", uri),
+                ))
+            }
+        };
+        let authority = uri.authority().map(|a| a.as_str()).unwrap_or(host);
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+        // HTTP/1 servers expect the origin form, the host goes in its header.
+        let (mut parts, body) = request.into_parts();
+        parts.uri = path
+            .parse()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        parts
+            .headers
+            .insert(http::header::HOST, authority.parse().map_err(Error::other)?);
+        let request = http::Request::from_parts(parts, body);
+
+        if uri.scheme_str() == Some("https") {
+            let stream = TcpStream::connect((host, uri.port_u16().unwrap_or(443))).await?;
+            let connector =
+                TlsConnector::from(native_tls::TlsConnector::new().map_err(Error::other)?);
+            let stream = connector
+                .connect(host, stream)
+                .await
+                .map_err(Error::other)?;
+            self.exchange(stream, request).await
+        } else {
+            let stream = TcpStream::connect((host, uri.port_u16().unwrap_or(80))).await?;
+            self.exchange(stream, request).await
+        }
+    }
+
+    async fn exchange<S>(
+        &self,
+        stream: S,
+        request: http::Request<Full<Bytes>>,
+    ) -> Result<http::Response<Bytes>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(Error::other)?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                log::warn!("HTTP connection closed : {:?}", err);
+            }
+        });
+
+        let response = sender.send_request(request).await.map_err(Error::other)?;
+        let (parts, body) = response.into_parts();
+        let body = Limited::new(body, self.max_response_bytes)
+            .collect()
+            .await
+            .map_err(|err| match err.downcast_ref::<LengthLimitError>() {
+                Some(_) => Error::new(ErrorKind::InvalidData, "Response is too large"),
+                None => Error::other(err),
+            })?
+            .to_bytes();
+
+        Ok(http::Response::from_parts(parts, body))
+    }
+}
+
+pub fn is_loopback(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
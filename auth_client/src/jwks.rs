@@ -0,0 +1,164 @@
+use crate::error::Error;
+use crate::http_client::HttpClient;
+use bytes::Bytes;
+use http_body_util::Full;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+pub type KeysFuture<'a> = Pin<Box<dyn Future<Output = Result<JwkSet, Error>> + Send + 'a>>;
+
+/// Where the public signing keys come from.
+pub trait KeySource: Send + Sync + 'static {
+    fn fetch(&self) -> KeysFuture<'_>;
+}
+
+/// Fixed keys, e.g. read from a file at startup.
+impl KeySource for JwkSet {
+    fn fetch(&self) -> KeysFuture<'_> {
+        Box::pin(async move { Ok(self.clone()) })
+    }
+}
+
+impl<S: KeySource> KeySource for std::sync::Arc<S> {
+    fn fetch(&self) -> KeysFuture<'_> {
+        self.as_ref().fetch()
+    }
+}
+
+/// Fetches the JWKS over HTTPS. Plain HTTP is only allowed on the loopback, as anyone on the
+/// network path could otherwise serve their own keys. Implement [`KeySource`] to fetch it
+/// through another client.
+pub struct HttpKeySource {
+    uri: http::Uri,
+    client: HttpClient,
+}
+
+impl HttpKeySource {
+    pub fn new(url: &str) -> Result<HttpKeySource, Error> {
+        let uri: http::Uri = url.parse().map_err(|err| Error::Fetch(format!("{}", err)))?;
+        let client = HttpClient::default();
+        if !client.supports(&uri) {
+            return Err(Error::Fetch(format!("Unsupported JWKS url: {}", url)));
+        }
+
+        Ok(HttpKeySource { uri, client })
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> HttpKeySource {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    async fn get(&self) -> Result<JwkSet, Error> {
+        let fetch_error = |err: &dyn std::fmt::Display| Error::Fetch(err.to_string());
+        let request = http::Request::get(self.uri.clone())
+            .header(http::header::ACCEPT, "application/json")
+            .body(Full::new(Bytes::new()))
+            .map_err(|err| fetch_error(&err))?;
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|err| fetch_error(&err))?;
+        if !response.status().is_success() {
+            return Err(Error::Fetch(format!("JWKS endpoint answered {}", response.status())));
+        }
+        serde_json::from_slice(response.body()).map_err(|err| fetch_error(&err))
+    }
+}
+
+impl KeySource for HttpKeySource {
+    fn fetch(&self) -> KeysFuture<'_> {
+        Box::pin(self.get())
+    }
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Keys of a [`KeySource`], kept for `ttl`. An unknown `kid` triggers a refresh (at most once
+/// per `min_refresh_interval`) so rotated keys are picked up before the ttl expires.
+pub struct JwksCache {
+    source: Box<dyn KeySource>,
+    ttl: Duration,
+    min_refresh_interval: Duration,
+    cached: RwLock<Option<CachedKeys>>,
+}
+
+impl JwksCache {
+    pub fn new(source: impl KeySource) -> JwksCache {
+        JwksCache {
+            source: Box::new(source),
+            ttl: Duration::from_secs(300),
+            min_refresh_interval: Duration::from_secs(10),
+            cached: RwLock::new(None),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> JwksCache {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_min_refresh_interval(mut self, interval: Duration) -> JwksCache {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    /// Key matching `kid`, or the only key of the set when the token has no `kid`.
+    pub async fn find(&self, kid: Option<&str>) -> Result<Jwk, Error> {
+        let seen = {
+            let cached = self.cached.read().await;
+            match cached.as_ref() {
+                Some(cached) => {
+                    let age = cached.fetched_at.elapsed();
+                    match select(&cached.keys, kid) {
+                        Some(key) if age < self.ttl => return Ok(key),
+                        None if age < self.min_refresh_interval => return Err(unknown_key(kid)),
+                        _ => Some(cached.fetched_at),
+                    }
+                }
+                None => None,
+            }
+        };
+
+        let mut cached = self.cached.write().await;
+        // Another task may have refreshed the keys while we were waiting for the lock.
+        if cached.as_ref().map(|cached| cached.fetched_at) == seen {
+            match self.source.fetch().await {
+                Ok(keys) => {
+                    *cached = Some(CachedKeys {
+                        keys,
+                        fetched_at: Instant::now(),
+                    })
+                }
+                // Stale keys are better than rejecting every request while the JWKS is down.
+                Err(err) if cached.is_some() => log::warn!("JWKS refresh failed : {}", err),
+                Err(err) => return Err(err),
+            }
+        }
+
+        cached
+            .as_ref()
+            .and_then(|cached| select(&cached.keys, kid))
+            .ok_or_else(|| unknown_key(kid))
+    }
+}
+
+fn select(keys: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid).cloned(),
+        None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+        None => None,
+    }
+}
+
+fn unknown_key(kid: Option<&str>) -> Error {
+    Error::UnknownKey(kid.unwrap_or_default().to_owned())
+}
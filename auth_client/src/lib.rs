@@ -0,0 +1,38 @@
+//! Verification of the tokens issued by the auth service, without calling it on every request.
+//!
+//! Signing keys are fetched from the service JWKS endpoint (`/.well-known/jwks.json`) and
+//! cached, tokens are then verified locally :
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), auth_client::Error> {
+//! use auth_client::Verifier;
+//!
+//! let verifier = Verifier::from_jwks_url("https://auth.example.com/.well-known/jwks.json")?
+//!     .with_issuer("auth_api")
+//!     .with_audience(&["auth_api"]);
+//! let claims = verifier.verify("eyJ...").await?;
+//! println!("{}", claims.sub);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `actix` feature, [`actix::Authenticated`] extracts the claims in actix-web handlers.
+//! With the `tower` feature, [`tower::AuthLayer`] protects axum (or any tower) services and
+//! stores the [`Claims`] in the request extensions.
+
+pub mod claims;
+pub mod error;
+pub mod http_client;
+pub mod jwks;
+pub mod verifier;
+
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "tower")]
+pub mod tower;
+
+pub use claims::{token_from_headers, Claims};
+pub use error::Error;
+pub use http_client::HttpClient;
+pub use jwks::{HttpKeySource, JwksCache, KeySource};
+pub use verifier::Verifier;
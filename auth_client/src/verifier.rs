@@ -0,0 +1,89 @@
+use crate::claims::Claims;
+use crate::error::Error;
+use crate::jwks::{HttpKeySource, JwksCache, KeySource};
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use std::time::Duration;
+
+/// Verifies tokens against the cached signing keys. The issuer and audience must be set to
+/// the ones configured on the auth service (`JWT_ISSUER`, `JWT_AUDIENCE`), tokens carrying
+/// another audience are rejected.
+pub struct Verifier {
+    keys: JwksCache,
+    validation: Validation,
+}
+
+impl Verifier {
+    pub fn new(source: impl KeySource) -> Verifier {
+        Verifier {
+            keys: JwksCache::new(source),
+            validation: Verifier::default_validation(),
+        }
+    }
+
+    fn default_validation() -> Validation {
+        let mut validation = Validation::default();
+        validation.validate_nbf = true;
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation
+    }
+
+    pub fn from_jwks_url(url: &str) -> Result<Verifier, Error> {
+        Ok(Verifier::new(HttpKeySource::new(url)?))
+    }
+
+    /// How long fetched keys are trusted before being fetched again, 5 minutes by default.
+    pub fn with_ttl(mut self, ttl: Duration) -> Verifier {
+        self.keys = self.keys.with_ttl(ttl);
+        self
+    }
+
+    pub fn with_issuer(mut self, issuer: &str) -> Verifier {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    /// Audiences this service accepts.
+    pub fn with_audience(mut self, audiences: &[&str]) -> Verifier {
+        self.validation.set_audience(audiences);
+        self
+    }
+
+    /// Clock skew tolerated on `exp` and `nbf`, 60 seconds by default.
+    pub fn with_leeway(mut self, leeway: u64) -> Verifier {
+        self.validation.leeway = leeway;
+        self
+    }
+
+    /// Replace the default checks (leeway, required claims, issuer and audience) : the
+    /// algorithm is still the one of the signing key.
+    pub fn with_validation(mut self, validation: Validation) -> Verifier {
+        self.validation = validation;
+        self
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Claims, Error> {
+        if token.is_empty() {
+            return Err(Error::MissingToken);
+        }
+
+        let header = decode_header(token)?;
+        let jwk = self.keys.find(header.kid.as_deref()).await?;
+
+        // Never let the token pick the algorithm, the key decides.
+        let algorithm = match jwk.common.key_algorithm {
+            Some(algorithm) => algorithm.to_string().parse()?,
+            None => header.alg,
+        };
+        if algorithm != header.alg {
+            return Err(Error::InvalidToken(
+                jsonwebtoken::errors::ErrorKind::InvalidAlgorithm.into(),
+            ));
+        }
+
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![algorithm];
+
+        let key = DecodingKey::from_jwk(&jwk)?;
+        Ok(decode::<Claims>(token, &key, &validation)?.claims)
+    }
+}
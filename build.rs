@@ -0,0 +1,40 @@
+use tonic_build::manual::{Builder, Method, Service};
+
+/// gRPC services are declared by hand, their messages are `prost` structs in `src/grpc`,
+/// so no `protoc` is needed to build. `proto/` holds the matching definitions for clients.
+fn main() {
+    let ext_authz = Service::builder()
+        .name("Authorization")
+        .package("envoy.service.auth.v3")
+        .method(
+            Method::builder()
+                .name("check")
+                .route_name("Check")
+                .input_type("crate::grpc::envoy::CheckRequest")
+                .output_type("crate::grpc::envoy::CheckResponse")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .build();
+
+    let auth = Service::builder()
+        .name("AuthService")
+        .package("auth.v1")
+        .method(method("verify_token", "VerifyToken", "VerifyTokenRequest", "VerifyTokenResponse"))
+        .method(method("get_user", "GetUser", "GetUserRequest", "User"))
+        .method(method("check_roles", "CheckRoles", "CheckRolesRequest", "CheckRolesResponse"))
+        .method(method("list_users", "ListUsers", "ListUsersRequest", "ListUsersResponse"))
+        .build();
+
+    Builder::new().compile(&[ext_authz, auth]);
+}
+
+fn method(name: &str, route_name: &str, input: &str, output: &str) -> Method {
+    Method::builder()
+        .name(name)
+        .route_name(route_name)
+        .input_type(format!("crate::grpc::auth::{}", input))
+        .output_type(format!("crate::grpc::auth::{}", output))
+        .codec_path("tonic::codec::ProstCodec")
+        .build()
+}
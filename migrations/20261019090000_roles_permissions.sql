@@ -0,0 +1,55 @@
+CREATE TABLE IF NOT EXISTS roles
+(
+    name        varchar(50) PRIMARY KEY not null,
+    description text                    not null default '',
+    is_builtin  boolean                 not null default false,
+    created_at  date                    not null default now(),
+    updated_at  date                    not null default now()
+);
+
+CREATE TABLE IF NOT EXISTS permissions
+(
+    name        varchar(100) PRIMARY KEY not null,
+    description text                     not null default ''
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions
+(
+    role_name       varchar(50)  not null REFERENCES roles (name) ON DELETE CASCADE ON UPDATE CASCADE,
+    permission_name varchar(100) not null REFERENCES permissions (name) ON DELETE CASCADE ON UPDATE CASCADE,
+    PRIMARY KEY (role_name, permission_name)
+);
+
+CREATE OR REPLACE TRIGGER t_roles_updated_date
+    BEFORE UPDATE
+    ON roles
+    FOR EACH ROW
+EXECUTE PROCEDURE f_set_update_date();
+
+INSERT INTO roles (name, description, is_builtin)
+VALUES ('ROLE_SUPER_ADMIN', 'Full access', true),
+       ('ROLE_ADMIN', 'User administration', true),
+       ('ROLE_USER', 'Default role', true)
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO permissions (name, description)
+VALUES ('user:read', 'Read user accounts'),
+       ('user:delete', 'Soft delete user accounts'),
+       ('user:undelete', 'Restore soft deleted user accounts'),
+       ('user:hard_delete', 'Permanently delete user accounts'),
+       ('stats:read', 'Read user statistics'),
+       ('role:manage', 'Manage roles and permissions')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_name, permission_name)
+SELECT 'ROLE_SUPER_ADMIN', name
+FROM permissions
+ON CONFLICT DO NOTHING;
+
+INSERT INTO role_permissions (role_name, permission_name)
+VALUES ('ROLE_ADMIN', 'user:read'),
+       ('ROLE_ADMIN', 'user:delete'),
+       ('ROLE_ADMIN', 'user:undelete'),
+       ('ROLE_ADMIN', 'user:hard_delete'),
+       ('ROLE_ADMIN', 'stats:read')
+ON CONFLICT DO NOTHING;
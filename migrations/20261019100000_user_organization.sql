@@ -0,0 +1,2 @@
+ALTER TABLE IF EXISTS "user"
+    ADD IF NOT EXISTS organization varchar(255);
@@ -0,0 +1,38 @@
+CREATE TABLE IF NOT EXISTS authz_revision
+(
+    id       boolean PRIMARY KEY not null DEFAULT true CHECK (id),
+    revision bigint              not null DEFAULT 0
+);
+
+INSERT INTO authz_revision (id, revision)
+VALUES (true, 0)
+ON CONFLICT (id) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS relation_tuples
+(
+    namespace         varchar(64)  not null,
+    object_id         varchar(255) not null,
+    relation          varchar(64)  not null,
+    subject_namespace varchar(64)  not null,
+    subject_id        varchar(255) not null,
+    subject_relation  varchar(64)  not null DEFAULT '',
+    created_revision  bigint       not null,
+    deleted_revision  bigint
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS relation_tuples_live_idx
+    ON relation_tuples (namespace, object_id, relation, subject_namespace, subject_id, subject_relation)
+    WHERE deleted_revision IS NULL;
+
+CREATE INDEX IF NOT EXISTS relation_tuples_object_idx
+    ON relation_tuples (namespace, object_id, relation);
+
+INSERT INTO permissions (name, description)
+VALUES ('authz:check', 'Check and expand relation tuples'),
+       ('authz:write', 'Write relation tuples')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_name, permission_name)
+VALUES ('ROLE_SUPER_ADMIN', 'authz:check'),
+       ('ROLE_SUPER_ADMIN', 'authz:write')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,36 @@
+CREATE TABLE IF NOT EXISTS oauth_clients
+(
+    client_id          text PRIMARY KEY not null DEFAULT gen_random_uuid(),
+    -- NULL for public clients, which must use PKCE and no secret.
+    client_secret_hash varchar(255),
+    name               varchar(255)     not null,
+    redirect_uris      text[]           not null default '{}',
+    scopes             text[]           not null default '{}',
+    grant_types        text[]           not null default '{authorization_code}',
+    created_at         timestamptz      not null default now()
+);
+
+CREATE TABLE IF NOT EXISTS oauth_authorization_codes
+(
+    -- SHA-256 of the code, the code itself is only known by the client.
+    code_hash             char(64) PRIMARY KEY not null,
+    client_id             text                 not null REFERENCES oauth_clients (client_id) ON DELETE CASCADE,
+    user_id               text                 not null REFERENCES "user" (id) ON DELETE CASCADE,
+    redirect_uri          text                 not null,
+    scope                 text                 not null default '',
+    code_challenge        varchar(128)         not null,
+    code_challenge_method varchar(10)          not null,
+    expires_at            timestamptz          not null,
+    consumed_at           timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS oauth_authorization_codes_expires_idx
+    ON oauth_authorization_codes (expires_at);
+
+INSERT INTO permissions (name, description)
+VALUES ('oauth_client:manage', 'Register and delete OAuth clients')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_name, permission_name)
+VALUES ('ROLE_SUPER_ADMIN', 'oauth_client:manage')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,8 @@
+ALTER TABLE IF EXISTS "user"
+    ADD IF NOT EXISTS name           varchar(255),
+    ADD IF NOT EXISTS email_verified boolean not null default false;
+
+ALTER TABLE IF EXISTS oauth_authorization_codes
+    -- OpenID Connect request values, echoed in the ID token.
+    ADD IF NOT EXISTS nonce     text,
+    ADD IF NOT EXISTS auth_time timestamptz not null default now();
@@ -0,0 +1,24 @@
+ALTER TABLE IF EXISTS oauth_clients
+    -- client_secret_basic (or post), none for public clients, private_key_jwt with jwks.
+    ADD IF NOT EXISTS token_endpoint_auth_method varchar(50) not null default 'client_secret_basic',
+    ADD IF NOT EXISTS jwks                       jsonb;
+
+UPDATE oauth_clients
+SET token_endpoint_auth_method = 'none'
+WHERE client_secret_hash IS NULL;
+
+-- Machine principals : an OAuth client using the client_credentials grant, owned by a user.
+CREATE TABLE IF NOT EXISTS service_accounts
+(
+    client_id  text PRIMARY KEY not null REFERENCES oauth_clients (client_id) ON DELETE CASCADE,
+    owner_id   text             not null REFERENCES "user" (id) ON DELETE CASCADE,
+    created_at timestamptz      not null default now()
+);
+
+INSERT INTO permissions (name, description)
+VALUES ('service_account:manage', 'Create and delete service accounts')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_name, permission_name)
+VALUES ('ROLE_SUPER_ADMIN', 'service_account:manage')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS oauth_device_codes
+(
+    -- SHA-256 of the device code, the code itself is only known by the device.
+    device_code_hash char(64) PRIMARY KEY not null,
+    -- Short code typed by the user, without separator.
+    user_code        varchar(16)          not null unique,
+    client_id        text                 not null REFERENCES oauth_clients (client_id) ON DELETE CASCADE,
+    scope            text                 not null default '',
+    -- pending, approved, denied or consumed.
+    status           varchar(20)          not null default 'pending',
+    user_id          text REFERENCES "user" (id) ON DELETE CASCADE,
+    auth_time        timestamptz,
+    interval_seconds integer              not null,
+    last_polled_at   timestamptz,
+    expires_at       timestamptz          not null
+);
+
+CREATE INDEX IF NOT EXISTS oauth_device_codes_expires_idx
+    ON oauth_device_codes (expires_at);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS oauth_refresh_tokens
+(
+    -- SHA-256 of the token, the token itself is only known by the client.
+    token_hash char(64) PRIMARY KEY not null,
+    client_id  text                 not null REFERENCES oauth_clients (client_id) ON DELETE CASCADE,
+    user_id    text                 not null REFERENCES "user" (id) ON DELETE CASCADE,
+    scope      text                 not null default '',
+    auth_time  timestamptz          not null,
+    created_at timestamptz          not null default now(),
+    expires_at timestamptz          not null,
+    -- Set on revocation and when the token is exchanged for a new one.
+    revoked_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS oauth_refresh_tokens_user_client_idx
+    ON oauth_refresh_tokens (user_id, client_id);
+
+CREATE INDEX IF NOT EXISTS oauth_refresh_tokens_expires_idx
+    ON oauth_refresh_tokens (expires_at);
+
+-- Access tokens revoked before they expire, by `jti`. Rows are useless once the token expired.
+CREATE TABLE IF NOT EXISTS oauth_revoked_tokens
+(
+    jti        varchar(64) PRIMARY KEY not null,
+    expires_at timestamptz             not null
+);
@@ -0,0 +1,16 @@
+ALTER TABLE IF EXISTS oauth_clients
+    -- Lifetimes of the tokens issued to the client, the defaults when NULL.
+    ADD IF NOT EXISTS access_token_lifetime_seconds  integer,
+    ADD IF NOT EXISTS refresh_token_lifetime_seconds integer,
+    -- Shown on the consent pages (RFC 7591 section 2).
+    ADD IF NOT EXISTS logo_uri                       text,
+    ADD IF NOT EXISTS client_uri                     text,
+    ADD IF NOT EXISTS policy_uri                     text,
+    ADD IF NOT EXISTS tos_uri                        text,
+    -- Secret replaced by a rotation, still accepted until previous_secret_expires_at.
+    ADD IF NOT EXISTS previous_client_secret_hash    varchar(255),
+    ADD IF NOT EXISTS previous_secret_expires_at     timestamptz;
+
+UPDATE permissions
+SET description = 'Register, update and delete OAuth clients, rotate their secrets'
+WHERE name = 'oauth_client:manage';
@@ -0,0 +1,14 @@
+ALTER TABLE IF EXISTS oauth_clients
+    -- Clients of the organization itself, users are not asked for consent.
+    ADD IF NOT EXISTS first_party boolean not null default false;
+
+-- Scopes granted by a user to a client, the consent page is skipped when they cover a request.
+CREATE TABLE IF NOT EXISTS oauth_consents
+(
+    user_id    text        not null REFERENCES "user" (id) ON DELETE CASCADE,
+    client_id  text        not null REFERENCES oauth_clients (client_id) ON DELETE CASCADE,
+    scopes     text[]      not null default '{}',
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now(),
+    PRIMARY KEY (user_id, client_id)
+);
@@ -0,0 +1,22 @@
+-- Logins in progress with an external identity provider, until the user comes back.
+CREATE TABLE IF NOT EXISTS external_login_states
+(
+    -- SHA-256 of the state, the state itself is only known by the browser.
+    state_hash    char(64) PRIMARY KEY not null,
+    provider      varchar(64)          not null,
+    nonce         text                 not null,
+    code_verifier text                 not null,
+    return_to     text                 not null default '/',
+    expires_at    timestamptz          not null
+);
+
+CREATE INDEX IF NOT EXISTS external_login_states_expires_idx
+    ON external_login_states (expires_at);
+
+-- Users provisioned on their first login with an external identity provider.
+ALTER TABLE IF EXISTS "user"
+    ADD IF NOT EXISTS external_provider varchar(64),
+    ADD IF NOT EXISTS external_subject  varchar(255);
+
+CREATE UNIQUE INDEX IF NOT EXISTS user_external_identity_idx
+    ON "user" (external_provider, external_subject);
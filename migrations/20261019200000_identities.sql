@@ -0,0 +1,30 @@
+-- External identities of users, a user may sign in with several providers and a password.
+CREATE TABLE IF NOT EXISTS identities
+(
+    provider      varchar(64)  not null,
+    subject       varchar(255) not null,
+    user_id       text         not null REFERENCES "user" (id) ON DELETE CASCADE,
+    -- Email given by the provider when the identity was linked.
+    email         varchar(255),
+    created_at    timestamptz  not null default now(),
+    last_login_at timestamptz,
+    PRIMARY KEY (provider, subject),
+    CONSTRAINT identities_user_provider_key UNIQUE (user_id, provider)
+);
+
+INSERT INTO identities (provider, subject, user_id, email)
+SELECT external_provider, external_subject, id, email
+FROM "user"
+WHERE external_provider IS NOT NULL
+  AND external_subject IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+DROP INDEX IF EXISTS user_external_identity_idx;
+
+ALTER TABLE IF EXISTS "user"
+    DROP COLUMN IF EXISTS external_provider,
+    DROP COLUMN IF EXISTS external_subject;
+
+ALTER TABLE IF EXISTS external_login_states
+    -- Set when a logged user links an identity instead of signing in.
+    ADD IF NOT EXISTS link_user_id text REFERENCES "user" (id) ON DELETE CASCADE;
@@ -0,0 +1,29 @@
+-- Authentication requests sent to SAML identity providers, until the user comes back.
+CREATE TABLE IF NOT EXISTS saml_requests
+(
+    request_id   varchar(64) PRIMARY KEY not null,
+    provider     varchar(64)             not null,
+    return_to    text                    not null default '/',
+    -- Set when a logged user links an identity instead of signing in.
+    link_user_id text REFERENCES "user" (id) ON DELETE CASCADE,
+    expires_at   timestamptz             not null
+);
+
+CREATE INDEX IF NOT EXISTS saml_requests_expires_idx
+    ON saml_requests (expires_at);
+
+-- Assertions already consumed, kept until they expire so that none is replayed.
+CREATE TABLE IF NOT EXISTS saml_assertions
+(
+    provider     varchar(64)  not null,
+    assertion_id varchar(255) not null,
+    expires_at   timestamptz  not null,
+    PRIMARY KEY (provider, assertion_id)
+);
+
+CREATE INDEX IF NOT EXISTS saml_assertions_expires_idx
+    ON saml_assertions (expires_at);
+
+-- Identities of SAML providers are named `saml:{provider}`.
+ALTER TABLE IF EXISTS identities
+    ALTER COLUMN provider TYPE varchar(128);
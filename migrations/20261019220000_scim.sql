@@ -0,0 +1,28 @@
+-- Users provisioned by a SCIM client, kept when it deactivates them (soft deletion).
+CREATE TABLE IF NOT EXISTS scim_users
+(
+    user_id     text PRIMARY KEY not null REFERENCES "user" (id) ON DELETE CASCADE,
+    client      varchar(64)      not null,
+    external_id varchar(255)
+);
+
+-- Groups pushed by SCIM clients, each client managing its own.
+CREATE TABLE IF NOT EXISTS scim_groups
+(
+    id           text PRIMARY KEY not null DEFAULT gen_random_uuid(),
+    client       varchar(64)      not null,
+    display_name varchar(255)     not null,
+    external_id  varchar(255),
+    created_at   timestamptz      not null default now(),
+    CONSTRAINT scim_groups_client_display_name_key UNIQUE (client, display_name)
+);
+
+CREATE TABLE IF NOT EXISTS scim_group_members
+(
+    group_id text not null REFERENCES scim_groups (id) ON DELETE CASCADE,
+    user_id  text not null REFERENCES "user" (id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS scim_group_members_user_idx
+    ON scim_group_members (user_id);
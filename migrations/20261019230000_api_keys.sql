@@ -0,0 +1,14 @@
+-- Keys users authenticate with instead of their password, only their SHA-256 is stored.
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    id           text PRIMARY KEY not null DEFAULT gen_random_uuid(),
+    user_id      text             not null REFERENCES "user" (id) ON DELETE CASCADE,
+    name         varchar(64)      not null,
+    key_hash     char(64)         not null UNIQUE,
+    created_at   timestamptz      not null default now(),
+    expires_at   timestamptz,
+    last_used_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS api_keys_user_idx
+    ON api_keys (user_id);
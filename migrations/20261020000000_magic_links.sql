@@ -0,0 +1,13 @@
+-- Sign-in links sent by email, bound to the browser which requested them by a nonce cookie.
+CREATE TABLE IF NOT EXISTS magic_links
+(
+    token_hash char(64) PRIMARY KEY not null,
+    user_id    text                 not null REFERENCES "user" (id) ON DELETE CASCADE,
+    nonce_hash char(64)             not null,
+    return_to  text                 not null default '/',
+    created_at timestamptz          not null default now(),
+    expires_at timestamptz          not null
+);
+
+CREATE INDEX IF NOT EXISTS magic_links_expires_idx
+    ON magic_links (expires_at);
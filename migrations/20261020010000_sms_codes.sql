@@ -0,0 +1,27 @@
+-- Phone numbers of the users, in E.164 form. Once verified, `login` also asks for a code sent to it.
+CREATE TABLE IF NOT EXISTS user_phones
+(
+    user_id      text PRIMARY KEY not null REFERENCES "user" (id) ON DELETE CASCADE,
+    phone_number varchar(16)      not null,
+    verified_at  timestamptz,
+    created_at   timestamptz      not null default now()
+);
+
+-- One-time codes sent by SMS, at most one pending code of each purpose per user.
+CREATE TABLE IF NOT EXISTS sms_codes
+(
+    user_id        text        not null REFERENCES "user" (id) ON DELETE CASCADE,
+    purpose        varchar(32) not null,
+    phone_number   varchar(16) not null,
+    code_hash      char(64)    not null,
+    -- Challenge of a login, which the code completes.
+    challenge_hash char(64) UNIQUE,
+    audience       text,
+    attempts       integer     not null default 0,
+    created_at     timestamptz not null default now(),
+    expires_at     timestamptz not null,
+    PRIMARY KEY (user_id, purpose)
+);
+
+CREATE INDEX IF NOT EXISTS sms_codes_expires_idx
+    ON sms_codes (expires_at);
@@ -0,0 +1,10 @@
+-- Nonces handed out for a `login` with the ID token of a provider, each one is accepted once.
+CREATE TABLE IF NOT EXISTS login_nonces
+(
+    nonce_hash char(64) PRIMARY KEY not null,
+    provider   varchar(64)          not null,
+    expires_at timestamptz          not null
+);
+
+CREATE INDEX IF NOT EXISTS login_nonces_expires_idx
+    ON login_nonces (expires_at);
@@ -1,2 +1,3 @@
 pub mod account;
-pub mod roles;
\ No newline at end of file
+pub mod permissions;
+pub mod roles;
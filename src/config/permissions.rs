@@ -0,0 +1,44 @@
+//! Permission strings follow the `resource:action` convention, e.g. `user:delete`.
+//! The constants below are the ones seeded by the migrations and checked by the API,
+//! additional permissions can be created at runtime.
+
+pub const USER_READ: &str = "user:read";
+pub const USER_DELETE: &str = "user:delete";
+pub const USER_UNDELETE: &str = "user:undelete";
+pub const USER_HARD_DELETE: &str = "user:hard_delete";
+pub const STATS_READ: &str = "stats:read";
+pub const ROLE_MANAGE: &str = "role:manage";
+pub const AUTHZ_CHECK: &str = "authz:check";
+pub const AUTHZ_WRITE: &str = "authz:write";
+pub const OAUTH_CLIENT_MANAGE: &str = "oauth_client:manage";
+pub const SERVICE_ACCOUNT_MANAGE: &str = "service_account:manage";
+
+/// Check the `resource:action` format. `*` is accepted as action or as the whole permission.
+pub fn is_valid_permission(permission: &str) -> bool {
+    if permission == "*" {
+        return true;
+    }
+
+    let is_segment = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+
+    match permission.split_once(':') {
+        Some((resource, action)) => is_segment(resource) && (action == "*" || is_segment(action)),
+        None => false,
+    }
+}
+
+/// Whether a granted permission covers the required one, handling `*` and `resource:*`.
+pub fn permission_matches(granted: &str, required: &str) -> bool {
+    if granted == "*" || granted == required {
+        return true;
+    }
+
+    match (granted.split_once(':'), required.split_once(':')) {
+        (Some((granted_resource, "*")), Some((required_resource, _))) => {
+            granted_resource == required_resource
+        }
+        _ => false,
+    }
+}
@@ -1,33 +1,34 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::io::{Error, ErrorKind};
 use std::str::FromStr;
 
-#[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq, Clone)]
-pub enum Role {
-    SUPER_ADMIN,
-    ADMIN,
-    USER,
-}
+/// A role name as stored in `public.roles` and in the `role` column of users.
+///
+/// Roles are data : any name following the `ROLE_[A-Z0-9_]+` convention is valid,
+/// the built-in ones are exposed as associated constants.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Role(Cow<'static, str>);
 
 impl Role {
+    pub const SUPER_ADMIN: Role = Role(Cow::Borrowed("ROLE_SUPER_ADMIN"));
+    pub const ADMIN: Role = Role(Cow::Borrowed("ROLE_ADMIN"));
+    pub const USER: Role = Role(Cow::Borrowed("ROLE_USER"));
+
     pub fn to_str(&self) -> &str {
-        match self {
-            Role::SUPER_ADMIN => "ROLE_SUPER_ADMIN",
-            Role::ADMIN => "ROLE_ADMIN",
-            Role::USER => "ROLE_USER",
-        }
+        &self.0
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        [Role::SUPER_ADMIN, Role::ADMIN, Role::USER].contains(self)
     }
 }
 
 impl Display for Role {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            Role::SUPER_ADMIN => "ROLE_SUPER_ADMIN".to_owned(),
-            Role::ADMIN => "ROLE_ADMIN".to_owned(),
-            Role::USER => "ROLE_USER".to_owned(),
-        };
-        write!(f, "{}", str)
+        write!(f, "{}", self.0)
     }
 }
 
@@ -35,11 +36,60 @@ impl FromStr for Role {
     type Err = Error;
 
     fn from_str(role: &str) -> Result<Self, Self::Err> {
-        match role {
-            "ROLE_SUPER_ADMIN" => Ok(Role::SUPER_ADMIN),
-            "ROLE_ADMIN" => Ok(Role::ADMIN),
-            "ROLE_USER" => Ok(Role::USER),
-            _ => Err(Error::new(ErrorKind::InvalidData, String::from("Invalid Role")))
+        let valid = match role.strip_prefix("ROLE_") {
+            Some(name) => {
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            }
+            None => false,
+        };
+
+        if valid && role.len() <= 50 {
+            Ok(Role(Cow::Owned(role.to_owned())))
+        } else {
+            Err(Error::new(ErrorKind::InvalidData, String::from("Invalid Role")))
+        }
+    }
+}
+
+impl Serialize for Role {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Role {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let role = String::deserialize(deserializer)?;
+        Role::from_str(&role).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Roles of a user who had `current` ones, once the roles of `mapping` follow `values` of an
+/// external directory : each value grants the role it is mapped to, mapped roles no longer
+/// granted are removed, the others are kept. Users are left with at least [`Role::USER`].
+pub fn sync_mapped_roles<'a>(
+    current: &[String],
+    mapping: &HashMap<String, String>,
+    values: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let managed: HashSet<&str> = mapping.values().map(String::as_str).collect();
+    let mut roles: Vec<String> = current
+        .iter()
+        .filter(|role| !managed.contains(role.as_str()))
+        .cloned()
+        .collect();
+
+    for role in values.into_iter().filter_map(|value| mapping.get(value)) {
+        if !roles.contains(role) {
+            roles.push(role.clone());
         }
     }
-}
\ No newline at end of file
+
+    if roles.is_empty() {
+        roles.push(Role::USER.to_string());
+    }
+    roles
+}
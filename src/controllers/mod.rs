@@ -1,16 +1,86 @@
 use std::sync::Arc;
 
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 
-use crate::{repository::Repository, services::access_control::AccessControl};
+use crate::{
+    repository::Repository,
+    services::{
+        access_control::AccessControl,
+        authenticator::Authenticators,
+        authz::RelationAuthz,
+        claims::ClaimsEnricher,
+        client_registration::initial_access_token,
+        crypto::{Jwt, JwtService, TokenConfig},
+        forward_auth::ForwardAuthRules,
+        identity_provider::IdentityProviders,
+        magic_link::MagicLinkLimiter,
+        mailer::Mailer,
+        oidc::ProviderMetadata,
+        policy::PolicyEngine,
+        saml::SamlProviders,
+        scim::ScimClients,
+        sms::SmsSender,
+        sms_code::SmsCodeLimiter,
+    },
+};
 
-pub(crate) mod v1;
+pub mod oauth;
+pub mod saml;
+pub mod scim;
+pub mod v1;
 
 #[derive(Clone)]
 pub struct AppState {
     pub(crate) repository: Arc<Repository>,
     pub(crate) access_control: Arc<AccessControl>,
+    pub(crate) policy_engine: Arc<PolicyEngine>,
+    pub(crate) relation_authz: Arc<RelationAuthz>,
+    pub(crate) forward_auth_rules: Arc<ForwardAuthRules>,
+    pub(crate) claims_enricher: Arc<ClaimsEnricher>,
+    pub(crate) identity_providers: Arc<IdentityProviders>,
+    pub(crate) saml_providers: Arc<SamlProviders>,
+    pub(crate) authenticators: Arc<Authenticators>,
+    pub(crate) scim_clients: Arc<ScimClients>,
+    pub(crate) mailer: Arc<dyn Mailer>,
+    pub(crate) magic_link_limiter: Arc<MagicLinkLimiter>,
+    pub(crate) sms_sender: Arc<dyn SmsSender>,
+    pub(crate) sms_code_limiter: Arc<SmsCodeLimiter>,
+}
+
+impl AppState {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        repository: Repository,
+        access_control: AccessControl,
+        policy_engine: PolicyEngine,
+        relation_authz: RelationAuthz,
+        forward_auth_rules: ForwardAuthRules,
+        claims_enricher: ClaimsEnricher,
+        identity_providers: Arc<IdentityProviders>,
+        saml_providers: SamlProviders,
+        authenticators: Authenticators,
+        scim_clients: ScimClients,
+        mailer: Box<dyn Mailer>,
+        sms_sender: Box<dyn SmsSender>,
+    ) -> AppState {
+        AppState {
+            repository: Arc::from(repository),
+            access_control: Arc::from(access_control),
+            policy_engine: Arc::from(policy_engine),
+            relation_authz: Arc::from(relation_authz),
+            forward_auth_rules: Arc::from(forward_auth_rules),
+            claims_enricher: Arc::from(claims_enricher),
+            identity_providers,
+            saml_providers: Arc::from(saml_providers),
+            authenticators: Arc::from(authenticators),
+            scim_clients: Arc::from(scim_clients),
+            mailer: Arc::from(mailer),
+            magic_link_limiter: Arc::new(MagicLinkLimiter::default()),
+            sms_sender: Arc::from(sms_sender),
+            sms_code_limiter: Arc::new(SmsCodeLimiter::default()),
+        }
+    }
 }
 
 
@@ -30,3 +100,50 @@ pub async fn ping() -> impl Responder {
         message: String::from("Pong"),
     })
 }
+
+#[get("/.well-known/jwks.json")]
+pub async fn jwks() -> impl Responder {
+    HttpResponse::Ok().json(JwtService::jwks())
+}
+
+/// `url` without its trailing slash when it is an absolute HTTP(S) URL, `None` otherwise.
+pub fn base_url(url: &str) -> Option<String> {
+    let url = url.trim().trim_end_matches('/');
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let host = rest.split('/').next().unwrap_or_default();
+    (!host.is_empty() && !rest.contains(['?', '#', '@'])).then(|| url.to_owned())
+}
+
+/// URL the service is configured to be reached on : `PUBLIC_BASE_URL`, or the issuer when
+/// it is a URL. Links sent to users are only built from it, never from the request.
+pub fn configured_base_url(config: &TokenConfig) -> Option<String> {
+    match std::env::var("PUBLIC_BASE_URL") {
+        Ok(url) if !url.is_empty() => base_url(&url),
+        _ => base_url(&config.issuer),
+    }
+}
+
+/// URL the service is reached on : the [`configured_base_url`], the requested host
+/// otherwise.
+pub(crate) fn public_base_url(config: &TokenConfig, req: &HttpRequest) -> String {
+    configured_base_url(config).unwrap_or_else(|| {
+        let connection = req.connection_info();
+        format!("{}://{}", connection.scheme(), connection.host())
+    })
+}
+
+/// OpenID Connect discovery, endpoints are relative to [`public_base_url`].
+#[get("/.well-known/openid-configuration")]
+pub async fn openid_configuration(req: HttpRequest) -> impl Responder {
+    let config = TokenConfig::from_env();
+    let base_url = public_base_url(&config, &req);
+    let algorithm = format!("{:?}", JwtService::signing_algorithm());
+
+    let mut metadata = ProviderMetadata::new(&config.issuer, &base_url, &algorithm);
+    if initial_access_token().is_some() {
+        metadata.registration_endpoint = Some(format!("{}/oauth/register", base_url));
+    }
+    HttpResponse::Ok().json(metadata)
+}
@@ -0,0 +1,257 @@
+use crate::controllers::oauth::pages::{error_page, html_response, ConsentPage};
+use crate::controllers::oauth::session::{
+    csrf_valid, password_user, session_user, with_csrf_cookie,
+};
+use crate::controllers::AppState;
+use crate::repository::oauth_repository::{NewAuthorizationCode, OAuthClient};
+use crate::repository::user_repository::User;
+use crate::services::consent::consent_required;
+use crate::services::oauth::{
+    error_redirect, generate_token, hash_token, redirect_with, AuthorizationGrant, AuthorizeError,
+    AuthorizeParams, OAuthError, OAuthErrorCode, AUTHORIZATION_CODE_TTL_SECONDS, PKCE_METHOD_S256,
+};
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct AuthorizeDecision {
+    #[serde(flatten)]
+    params: AuthorizeParams,
+    #[serde(default)]
+    decision: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((LOCATION, location))
+        .insert_header(("Cache-Control", "no-store"))
+        .finish()
+}
+
+/// Resolve the client and validate the request, or build the response rejecting it.
+async fn validate_request(
+    state: &AppState,
+    params: &AuthorizeParams,
+) -> Result<(OAuthClient, AuthorizationGrant), HttpResponse> {
+    let client = match state.repository.find_oauth_client(&params.client_id).await {
+        Ok(client) => client,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(error_page(&OAuthError::new(
+                OAuthErrorCode::InvalidClient,
+                "Unknown client",
+            )))
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return Err(error_page(&OAuthError::new(
+                OAuthErrorCode::ServerError,
+                "Internal server error",
+            )));
+        }
+    };
+
+    match params.validate(&client) {
+        Ok(grant) => Ok((client, grant)),
+        Err(AuthorizeError::Display(error)) => Err(error_page(&error)),
+        Err(AuthorizeError::Redirect {
+            redirect_uri,
+            state,
+            error,
+        }) => Err(redirect(&error_redirect(
+            &redirect_uri,
+            state.as_deref(),
+            &error,
+        ))),
+    }
+}
+
+fn consent_page(
+    client: &OAuthClient,
+    grant: &AuthorizationGrant,
+    user: Option<&User>,
+    params: &AuthorizeParams,
+    message: Option<&str>,
+    status: StatusCode,
+) -> HttpResponse {
+    let csrf_token = generate_token();
+    let html = ConsentPage {
+        client_name: &client.name,
+        client_metadata: &client.metadata,
+        scopes: &grant.scopes,
+        user_email: user.map(|user| user.email.as_str()),
+        params,
+        csrf_token: &csrf_token,
+        message,
+    }
+    .render();
+
+    with_csrf_cookie(html_response(status, html), &csrf_token)
+}
+
+/// Redirect back to the client with a new authorization code.
+async fn issue_code(
+    state: &AppState,
+    grant: &AuthorizationGrant,
+    user: &User,
+    auth_time: DateTime<Utc>,
+) -> HttpResponse {
+    let code = generate_token();
+    let code_hash = hash_token(&code);
+    let scope = grant.scopes.join(" ");
+    let saved = state
+        .repository
+        .save_authorization_code(NewAuthorizationCode {
+            code_hash: &code_hash,
+            client_id: &grant.client_id,
+            user_id: &user.id,
+            redirect_uri: &grant.redirect_uri,
+            scope: &scope,
+            code_challenge: &grant.code_challenge,
+            code_challenge_method: PKCE_METHOD_S256,
+            nonce: grant.nonce.as_deref(),
+            auth_time,
+            expires_at: Utc::now() + chrono::Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS),
+        })
+        .await;
+    if let Err(err) = saved {
+        log::error!("{:?}", err);
+        let error = OAuthError::new(OAuthErrorCode::ServerError, "Internal server error");
+        return redirect(&error_redirect(
+            &grant.redirect_uri,
+            grant.state.as_deref(),
+            &error,
+        ));
+    }
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(state) = grant.state.as_deref() {
+        params.push(("state", state));
+    }
+    redirect(&redirect_with(&grant.redirect_uri, &params))
+}
+
+/// Whether the user already consented to the request, or does not need to.
+async fn consented(
+    state: &AppState,
+    client: &OAuthClient,
+    grant: &AuthorizationGrant,
+    user: &User,
+) -> bool {
+    let granted = match state
+        .repository
+        .find_consent_scopes(&user.id, &client.client_id)
+        .await
+    {
+        Ok(scopes) => Some(scopes),
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return false;
+        }
+    };
+
+    !consent_required(client.first_party, granted.as_deref(), &grant.scopes)
+}
+
+/// Authorization endpoint (RFC 6749 section 3.1), shows the login and consent page unless
+/// the logged user already consented.
+#[get("/authorize")]
+pub async fn authorize(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AuthorizeParams>,
+) -> impl Responder {
+    let (client, grant) = match validate_request(&state, &query).await {
+        Ok(validated) => validated,
+        Err(response) => return response,
+    };
+    let session = session_user(&state, &req).await;
+    if let Some((user, auth_time)) = &session {
+        if consented(&state, &client, &grant, user).await {
+            return issue_code(&state, &grant, user, *auth_time).await;
+        }
+    }
+    let user = session.map(|(user, _)| user);
+
+    consent_page(
+        &client,
+        &grant,
+        user.as_ref(),
+        &query,
+        None,
+        StatusCode::OK,
+    )
+}
+
+/// Submission of the consent page, redirects back to the client with a code.
+#[post("/authorize")]
+pub async fn authorize_decision(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    form: web::Form<AuthorizeDecision>,
+) -> impl Responder {
+    let form = form.into_inner();
+    let (client, grant) = match validate_request(&state, &form.params).await {
+        Ok(validated) => validated,
+        Err(response) => return response,
+    };
+
+    if !csrf_valid(&req, &form.csrf_token) {
+        return error_page(&OAuthError::new(
+            OAuthErrorCode::InvalidRequest,
+            "The form has expired, please retry from the application",
+        ));
+    }
+
+    if form.decision != "approve" {
+        let error = OAuthError::new(OAuthErrorCode::AccessDenied, "The user denied the request");
+        return redirect(&error_redirect(
+            &grant.redirect_uri,
+            grant.state.as_deref(),
+            &error,
+        ));
+    }
+
+    let session = match session_user(&state, &req).await {
+        Some(session) => Some(session),
+        None => password_user(&state, form.email.as_deref(), form.password.as_deref()).await,
+    };
+    let (user, auth_time) = match session {
+        Some(session) => session,
+        None => {
+            return consent_page(
+                &client,
+                &grant,
+                None,
+                &form.params,
+                Some("Check your information"),
+                StatusCode::UNAUTHORIZED,
+            )
+        }
+    };
+
+    if let Err(err) = state
+        .repository
+        .save_consent(&user.id, &client.client_id, &grant.scopes)
+        .await
+    {
+        log::error!("{:?}", err);
+        let error = OAuthError::new(OAuthErrorCode::ServerError, "Internal server error");
+        return redirect(&error_redirect(
+            &grant.redirect_uri,
+            grant.state.as_deref(),
+            &error,
+        ));
+    }
+
+    issue_code(&state, &grant, &user, auth_time).await
+}
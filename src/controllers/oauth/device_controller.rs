@@ -0,0 +1,303 @@
+use crate::controllers::oauth::pages::{
+    device_code_page, device_done_page, html_response, DeviceConsentPage,
+};
+use crate::controllers::oauth::session::{
+    csrf_valid, password_user, session_user, with_csrf_cookie,
+};
+use crate::controllers::oauth::token_controller::{
+    authenticate_client, oauth_error, server_error, ClientAuthForm,
+};
+use crate::controllers::{public_base_url, AppState};
+use crate::repository::device_code_repository::{DeviceCode, NewDeviceCode};
+use crate::services::crypto::TokenConfig;
+use crate::services::device_authorization::{
+    format_user_code, generate_user_code, normalize_user_code, DEVICE_CODE_TTL_SECONDS,
+    DEVICE_POLL_INTERVAL_SECONDS,
+};
+use crate::services::oauth::{
+    generate_token, hash_token, parse_scope, redirect_with, resolve_scope, OAuthError,
+    OAuthErrorCode, GRANT_DEVICE_CODE,
+};
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// A generated user code may already be in use, it is generated again a few times.
+const USER_CODE_ATTEMPTS: usize = 5;
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceAuthorizationRequest {
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(flatten)]
+    client: ClientAuthForm,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceAuthorizationResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: String,
+    expires_in: i64,
+    interval: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceQuery {
+    #[serde(default)]
+    user_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceDecision {
+    #[serde(default)]
+    user_code: String,
+    #[serde(default)]
+    decision: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+/// Device authorization endpoint (RFC 8628 section 3.1).
+#[post("/device_authorization")]
+pub async fn device_authorization(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Form<DeviceAuthorizationRequest>,
+) -> impl Responder {
+    let client = match authenticate_client(&state, &req, &body.client).await {
+        Ok(client) => client,
+        Err(error) => return oauth_error(error),
+    };
+    if !client
+        .grant_types
+        .iter()
+        .any(|grant| grant == GRANT_DEVICE_CODE)
+    {
+        return oauth_error(OAuthError::new(
+            OAuthErrorCode::UnauthorizedClient,
+            "The client may not use the device authorization grant",
+        ));
+    }
+    let scope = match resolve_scope(body.scope.as_deref(), &client.scopes) {
+        Ok(scopes) => scopes.join(" "),
+        Err(error) => return oauth_error(error),
+    };
+
+    let device_code = generate_token();
+    let device_code_hash = hash_token(&device_code);
+    let mut user_code = None;
+    for _ in 0..USER_CODE_ATTEMPTS {
+        let candidate = generate_user_code();
+        let saved = state
+            .repository
+            .save_device_code(NewDeviceCode {
+                device_code_hash: &device_code_hash,
+                user_code: &candidate,
+                client_id: &client.client_id,
+                scope: &scope,
+                interval_seconds: DEVICE_POLL_INTERVAL_SECONDS,
+                expires_at: Utc::now() + chrono::Duration::seconds(DEVICE_CODE_TTL_SECONDS),
+            })
+            .await;
+        match saved {
+            Ok(()) => {
+                user_code = Some(candidate);
+                break;
+            }
+            Err(sqlx::Error::Database(err)) if err.is_unique_violation() => continue,
+            Err(err) => return oauth_error(server_error(err)),
+        }
+    }
+    let user_code = match user_code {
+        Some(user_code) => format_user_code(&user_code),
+        None => return oauth_error(server_error("No user code available")),
+    };
+
+    let verification_uri = format!(
+        "{}/oauth/device",
+        public_base_url(&TokenConfig::from_env(), &req)
+    );
+    let verification_uri_complete =
+        redirect_with(&verification_uri, &[("user_code", user_code.as_str())]);
+
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-store"))
+        .json(DeviceAuthorizationResponse {
+            device_code,
+            user_code,
+            verification_uri,
+            verification_uri_complete,
+            expires_in: DEVICE_CODE_TTL_SECONDS,
+            interval: DEVICE_POLL_INTERVAL_SECONDS,
+        })
+}
+
+/// Pending device code of what the user typed, or the page asking for it again.
+async fn pending_device_code(state: &AppState, input: &str) -> Result<DeviceCode, HttpResponse> {
+    let invalid = || {
+        html_response(
+            StatusCode::BAD_REQUEST,
+            device_code_page(Some("This code is invalid or has expired")),
+        )
+    };
+    let user_code = normalize_user_code(input).ok_or_else(invalid)?;
+
+    match state.repository.find_pending_device_code(&user_code).await {
+        Ok(device) => Ok(device),
+        Err(sqlx::Error::RowNotFound) => Err(invalid()),
+        Err(err) => {
+            log::error!("{:?}", err);
+            Err(html_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                device_code_page(Some("Internal server error")),
+            ))
+        }
+    }
+}
+
+async fn device_consent_page(
+    state: &AppState,
+    device: &DeviceCode,
+    user_email: Option<&str>,
+    message: Option<&str>,
+    status: StatusCode,
+) -> HttpResponse {
+    let client = match state.repository.find_oauth_client(&device.client_id).await {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return html_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                device_code_page(Some("Internal server error")),
+            );
+        }
+    };
+    let scopes = parse_scope(Some(&device.scope));
+    let csrf_token = generate_token();
+    let html = DeviceConsentPage {
+        client_name: &client.name,
+        client_metadata: &client.metadata,
+        scopes: &scopes,
+        user_code: &format_user_code(&device.user_code),
+        user_email,
+        csrf_token: &csrf_token,
+        message,
+    }
+    .render();
+
+    with_csrf_cookie(html_response(status, html), &csrf_token)
+}
+
+/// Verification URI (RFC 8628 section 3.3), where the user enters and approves the user code.
+#[get("/device")]
+pub async fn device_verification(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<DeviceQuery>,
+) -> impl Responder {
+    let input = match query.user_code.as_deref() {
+        Some(input) if !input.trim().is_empty() => input,
+        _ => return html_response(StatusCode::OK, device_code_page(None)),
+    };
+    let device = match pending_device_code(&state, input).await {
+        Ok(device) => device,
+        Err(response) => return response,
+    };
+    let user = session_user(&state, &req).await.map(|(user, _)| user);
+
+    device_consent_page(
+        &state,
+        &device,
+        user.as_ref().map(|user| user.email.as_str()),
+        None,
+        StatusCode::OK,
+    )
+    .await
+}
+
+/// Submission of the device consent page.
+#[post("/device")]
+pub async fn device_verification_decision(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    form: web::Form<DeviceDecision>,
+) -> impl Responder {
+    let form = form.into_inner();
+    let device = match pending_device_code(&state, &form.user_code).await {
+        Ok(device) => device,
+        Err(response) => return response,
+    };
+
+    if !csrf_valid(&req, &form.csrf_token) {
+        return html_response(
+            StatusCode::BAD_REQUEST,
+            device_code_page(Some("The form has expired, please enter the code again")),
+        );
+    }
+
+    if form.decision != "approve" {
+        return match state.repository.deny_device_code(&device.user_code).await {
+            Ok(()) => html_response(StatusCode::OK, device_done_page(false)),
+            Err(err) => decision_error(err),
+        };
+    }
+
+    let session = match session_user(&state, &req).await {
+        Some(session) => Some(session),
+        None => password_user(&state, form.email.as_deref(), form.password.as_deref()).await,
+    };
+    let (user, auth_time) = match session {
+        Some(session) => session,
+        None => {
+            return device_consent_page(
+                &state,
+                &device,
+                None,
+                Some("Check your information"),
+                StatusCode::UNAUTHORIZED,
+            )
+            .await
+        }
+    };
+
+    let scopes = parse_scope(Some(&device.scope));
+    if let Err(err) = state
+        .repository
+        .save_consent(&user.id, &device.client_id, &scopes)
+        .await
+    {
+        return decision_error(err);
+    }
+    match state
+        .repository
+        .approve_device_code(&device.user_code, &user.id, auth_time)
+        .await
+    {
+        Ok(()) => html_response(StatusCode::OK, device_done_page(true)),
+        Err(err) => decision_error(err),
+    }
+}
+
+/// The code was decided or expired meanwhile.
+fn decision_error(err: sqlx::Error) -> HttpResponse {
+    match err {
+        sqlx::Error::RowNotFound => html_response(
+            StatusCode::BAD_REQUEST,
+            device_code_page(Some("This code is invalid or has expired")),
+        ),
+        err => {
+            log::error!("{:?}", err);
+            html_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                device_code_page(Some("Internal server error")),
+            )
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use crate::controllers::oauth::pages::{
+    external_login_done_page, external_login_error_page, html_response,
+};
+use crate::controllers::oauth::session::{session_cookie, session_token, session_user};
+use crate::controllers::{public_base_url, AppState, CustomResponse};
+use crate::repository::external_login_repository::NewExternalLogin;
+use crate::repository::identity_repository::IDENTITY_USER_PROVIDER_CONSTRAINT;
+use crate::repository::user_repository::User;
+use crate::services::crypto::TokenConfig;
+use crate::services::identity_provider::{
+    provision_user, recently_authenticated, safe_return_to, ExternalIdentity, IdentityProvider,
+    PendingLogin, ProvisioningError, EXTERNAL_LOGIN_TTL_SECONDS,
+};
+use crate::services::oauth::hash_token;
+use actix_web::http::header::{LOCATION, SET_COOKIE};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use cookie::time::Duration;
+use cookie::{Cookie, SameSite};
+use serde::{Deserialize, Serialize};
+
+/// Binds a login to the browser which started it, against login CSRF. `Lax` as the
+/// provider redirects back cross-site.
+const STATE_COOKIE: &str = "EXTERNAL-LOGIN-STATE";
+const STATE_COOKIE_PATH: &str = "/oauth/providers";
+
+#[derive(Serialize, Deserialize)]
+pub struct ProviderLink {
+    name: String,
+    display_name: String,
+    login_url: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginQuery {
+    #[serde(default)]
+    return_to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CallbackQuery {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+fn callback_uri(req: &HttpRequest, provider: &IdentityProvider) -> String {
+    format!(
+        "{}/oauth/providers/{}/callback",
+        public_base_url(&TokenConfig::from_env(), req),
+        provider.name()
+    )
+}
+
+fn state_cookie(value: &str, max_age: Duration) -> Cookie<'static> {
+    Cookie::build((STATE_COOKIE, value.to_owned()))
+        .path(STATE_COOKIE_PATH)
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .build()
+}
+
+fn unknown_provider() -> HttpResponse {
+    external_login_error_page(StatusCode::NOT_FOUND, "Unknown identity provider")
+}
+
+/// Providers users may sign in with, for the login page to offer them.
+#[get("/providers")]
+pub async fn list_identity_providers(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let base_url = public_base_url(&TokenConfig::from_env(), &req);
+    let providers: Vec<ProviderLink> = state
+        .identity_providers
+        .iter()
+        .map(|provider| ProviderLink {
+            name: provider.name().to_owned(),
+            display_name: provider.display_name().to_owned(),
+            login_url: format!("{}/oauth/providers/{}/login", base_url, provider.name()),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(providers)
+}
+
+/// Send the user to the provider, they come back to [`external_login_callback`].
+async fn start_login(
+    state: &AppState,
+    req: &HttpRequest,
+    provider: &IdentityProvider,
+    return_to: Option<&str>,
+    link_user_id: Option<&str>,
+) -> HttpResponse {
+    let login = PendingLogin::generate();
+    let url = match provider
+        .authorization_url(&callback_uri(req, provider), &login)
+        .await
+    {
+        Ok(url) => url,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return external_login_error_page(
+                StatusCode::BAD_GATEWAY,
+                &format!(
+                    "{} is not available, try again later",
+                    provider.display_name()
+                ),
+            );
+        }
+    };
+
+    let saved = state
+        .repository
+        .save_external_login(NewExternalLogin {
+            state_hash: &hash_token(&login.state),
+            provider: provider.name(),
+            nonce: &login.nonce,
+            code_verifier: &login.code_verifier,
+            return_to: &safe_return_to(return_to),
+            link_user_id,
+            expires_at: Utc::now() + chrono::Duration::seconds(EXTERNAL_LOGIN_TTL_SECONDS),
+        })
+        .await;
+    if let Err(err) = saved {
+        log::error!("{:?}", err);
+        return HttpResponse::InternalServerError().json(CustomResponse {
+            message: String::from("Internal server error"),
+        });
+    }
+
+    let cookie = state_cookie(&login.state, Duration::seconds(EXTERNAL_LOGIN_TTL_SECONDS));
+    HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .insert_header(("Cache-Control", "no-store"))
+        .append_header((SET_COOKIE, cookie.to_string()))
+        .finish()
+}
+
+/// Sign in with a provider.
+#[get("/providers/{provider}/login")]
+pub async fn external_login(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<LoginQuery>,
+) -> impl Responder {
+    match state.identity_providers.get(&provider) {
+        Some(provider) => {
+            start_login(&state, &req, provider, query.return_to.as_deref(), None).await
+        }
+        None => unknown_provider(),
+    }
+}
+
+/// Link an identity of a provider to the logged user, who must have signed in recently.
+#[get("/providers/{provider}/link")]
+pub async fn link_external_identity(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<LoginQuery>,
+) -> impl Responder {
+    let provider = match state.identity_providers.get(&provider) {
+        Some(provider) => provider,
+        None => return unknown_provider(),
+    };
+    let user = match session_user(&state, &req).await {
+        Some((user, auth_time)) if recently_authenticated(auth_time, Utc::now()) => user,
+        Some(_) => {
+            return external_login_error_page(
+                StatusCode::FORBIDDEN,
+                &format!("Sign in again to link {}", provider.display_name()),
+            )
+        }
+        None => {
+            return external_login_error_page(
+                StatusCode::UNAUTHORIZED,
+                &format!("Sign in to link {}", provider.display_name()),
+            )
+        }
+    };
+
+    start_login(
+        &state,
+        &req,
+        provider,
+        query.return_to.as_deref(),
+        Some(&user.id),
+    )
+    .await
+}
+
+pub(crate) fn internal_error() -> HttpResponse {
+    external_login_error_page(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// [`provision_user`], its errors rendered as pages.
+pub(crate) async fn provisioned_user(
+    state: &AppState,
+    identity: &ExternalIdentity,
+    display_name: &str,
+) -> Result<User, HttpResponse> {
+    provision_user(&state.repository, identity).await.map_err(|err| match err {
+        ProvisioningError::AccountExists => external_login_error_page(
+            StatusCode::CONFLICT,
+            &format!(
+                "An account already exists with this email address, sign in with your password then link {} from your account",
+                display_name
+            ),
+        ),
+        ProvisioningError::UnverifiedEmail => external_login_error_page(
+            StatusCode::FORBIDDEN,
+            &format!("{} did not share a verified email address", display_name),
+        ),
+        ProvisioningError::Database(err) => {
+            log::error!("{:?}", err);
+            internal_error()
+        }
+    })
+}
+
+/// Link `identity` to the user who started the link.
+pub(crate) async fn linked_identity(
+    state: &AppState,
+    identity: &ExternalIdentity,
+    display_name: &str,
+    user_id: &str,
+) -> Result<(), HttpResponse> {
+    match state.repository.link_identity(user_id, identity).await {
+        Ok(()) => Ok(()),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            let message = match err.constraint() {
+                Some(IDENTITY_USER_PROVIDER_CONSTRAINT) => format!(
+                    "Your account is already linked to {}, unlink it first",
+                    display_name
+                ),
+                _ => format!("This {} account is linked to another user", display_name),
+            };
+            Err(external_login_error_page(StatusCode::CONFLICT, &message))
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            Err(internal_error())
+        }
+    }
+}
+
+/// Redirection endpoint of the provider : the user is identified, then signed in with the
+/// same session cookie as `login`, or the identity is linked to the user who asked for it.
+#[get("/providers/{provider}/callback")]
+pub async fn external_login_callback(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<CallbackQuery>,
+) -> impl Responder {
+    let provider = match state.identity_providers.get(&provider) {
+        Some(provider) => provider,
+        None => return unknown_provider(),
+    };
+
+    let login_state = query.state.as_deref().unwrap_or_default();
+    let same_browser = req.cookie(STATE_COOKIE).is_some_and(|cookie| {
+        !login_state.is_empty()
+            && ring::constant_time::verify_slices_are_equal(
+                cookie.value().as_bytes(),
+                login_state.as_bytes(),
+            )
+            .is_ok()
+    });
+    if !same_browser {
+        return external_login_error_page(
+            StatusCode::BAD_REQUEST,
+            "The sign in was not started from this browser, try again",
+        );
+    }
+    let login = match state
+        .repository
+        .consume_external_login(&hash_token(login_state), provider.name())
+        .await
+    {
+        Ok(login) => login,
+        Err(sqlx::Error::RowNotFound) => {
+            return external_login_error_page(
+                StatusCode::BAD_REQUEST,
+                "The sign in expired, try again",
+            )
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+
+    let code = match (&query.code, &query.error) {
+        (Some(code), None) => code,
+        _ => {
+            return external_login_error_page(
+                StatusCode::FORBIDDEN,
+                &format!("{} did not sign you in", provider.display_name()),
+            )
+        }
+    };
+    let identity = match provider
+        .authenticate(
+            code,
+            &callback_uri(&req, provider),
+            &login.code_verifier,
+            &login.nonce,
+        )
+        .await
+    {
+        Ok(identity) => identity,
+        Err(err) => {
+            log::error!("Login with {} failed : {:?}", provider.name(), err);
+            return external_login_error_page(
+                StatusCode::BAD_GATEWAY,
+                &format!("Sign in with {} failed", provider.display_name()),
+            );
+        }
+    };
+
+    let mut cookies = vec![state_cookie("", Duration::ZERO)];
+    let title = match &login.link_user_id {
+        Some(user_id) => {
+            let linked =
+                linked_identity(&state, &identity, provider.display_name(), user_id).await;
+            if let Err(response) = linked {
+                return response;
+            }
+            "Account linked"
+        }
+        None => {
+            let provisioned = provisioned_user(&state, &identity, provider.display_name()).await;
+            let user = match provisioned {
+                Ok(user) => user,
+                Err(response) => return response,
+            };
+            match session_token(&state, &user).await {
+                Some((token, lifetime)) => cookies.push(session_cookie(&token, lifetime)),
+                None => return internal_error(),
+            }
+            "Signed in"
+        }
+    };
+
+    let mut response = html_response(
+        StatusCode::OK,
+        external_login_done_page(title, &login.return_to),
+    );
+    for cookie in cookies {
+        if let Ok(value) = cookie.to_string().parse() {
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,136 @@
+use crate::controllers::oauth::token_controller::{
+    authenticate_client, oauth_error, server_error, ClientAuthForm,
+};
+use crate::controllers::AppState;
+use crate::repository::oauth_repository::OAuthClient;
+use crate::services::crypto::{Claims, Jwt, JwtService, TokenConfig};
+use crate::services::introspection::Introspection;
+use crate::services::oauth::{hash_token, OAuthError, OAuthErrorCode, AUTH_METHOD_NONE};
+use crate::services::revocation::RevocationList;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenForm {
+    #[serde(default)]
+    token: String,
+    /// Ignored, access tokens are JWTs and refresh tokens are not : the token tells its type.
+    #[serde(default)]
+    token_type_hint: Option<String>,
+    #[serde(flatten)]
+    client: ClientAuthForm,
+}
+
+/// Access tokens of every audience are introspected and revoked here, not only those this
+/// service accepts.
+fn verify_any_audience(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let config = TokenConfig::from_env();
+    JwtService::verify_jwt_for(token, &config, &config.audiences())
+}
+
+/// Access tokens are disclosed to any confidential client, refresh tokens only to the
+/// client they were issued to.
+async fn introspect(
+    state: &AppState,
+    client: &OAuthClient,
+    token: &str,
+) -> Result<Introspection, OAuthError> {
+    if let Ok(claims) = verify_any_audience(token) {
+        return Ok(Introspection::from_claims(&claims));
+    }
+
+    match state
+        .repository
+        .find_refresh_token(&hash_token(token))
+        .await
+    {
+        Ok(refresh_token) if refresh_token.client_id == client.client_id => Ok(
+            Introspection::from_refresh_token(&refresh_token, Utc::now()),
+        ),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => Ok(Introspection::inactive()),
+        Err(err) => Err(server_error(err)),
+    }
+}
+
+/// Introspection endpoint (RFC 7662), for resource servers which do not verify tokens
+/// themselves. Only confidential clients may call it.
+#[post("/introspect")]
+pub async fn introspection(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    form: web::Form<TokenForm>,
+) -> impl Responder {
+    let client = match authenticate_client(&state, &req, &form.client).await {
+        Ok(client) => client,
+        Err(error) => return oauth_error(error),
+    };
+    if client.token_endpoint_auth_method == AUTH_METHOD_NONE {
+        return oauth_error(OAuthError::new(
+            OAuthErrorCode::InvalidClient,
+            "Public clients may not introspect tokens",
+        ));
+    }
+
+    match introspect(&state, &client, &form.token).await {
+        Ok(introspection) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(introspection),
+        Err(error) => oauth_error(error),
+    }
+}
+
+/// Revoke an access token of the client until it expires.
+async fn revoke_access_token(
+    state: &AppState,
+    client: &OAuthClient,
+    claims: &Claims,
+) -> Result<(), OAuthError> {
+    if claims.client_id.as_deref() != Some(client.client_id.as_str()) {
+        return Ok(());
+    }
+
+    let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now);
+    state
+        .repository
+        .revoke_access_token(&claims.jti, expires_at)
+        .await
+        .map_err(server_error)?;
+    RevocationList::revoke(&claims.jti, claims.exp);
+
+    Ok(())
+}
+
+/// Revocation endpoint (RFC 7009). Unknown tokens, and tokens of other clients, are
+/// answered as revoked ones so that the response tells nothing about them. Revoking a
+/// refresh token does not revoke the access tokens already issued with it.
+#[post("/revoke")]
+pub async fn revocation(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    form: web::Form<TokenForm>,
+) -> impl Responder {
+    let client = match authenticate_client(&state, &req, &form.client).await {
+        Ok(client) => client,
+        Err(error) => return oauth_error(error),
+    };
+
+    let revoked = match verify_any_audience(&form.token) {
+        Ok(claims) => revoke_access_token(&state, &client, &claims).await,
+        Err(_) => match state
+            .repository
+            .revoke_refresh_token(&hash_token(&form.token), &client.client_id)
+            .await
+        {
+            Ok(()) | Err(sqlx::Error::RowNotFound) => Ok(()),
+            Err(err) => Err(server_error(err)),
+        },
+    };
+
+    match revoked {
+        Ok(()) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .finish(),
+        Err(error) => oauth_error(error),
+    }
+}
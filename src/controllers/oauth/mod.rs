@@ -0,0 +1,42 @@
+use actix_web::{web, Scope};
+use authorize_controller::{authorize, authorize_decision};
+use device_controller::{
+    device_authorization, device_verification, device_verification_decision,
+};
+use identity_provider_controller::{
+    external_login, external_login_callback, link_external_identity, list_identity_providers,
+};
+use introspection_controller::{introspection, revocation};
+use registration_controller::register;
+use token_controller::token;
+use userinfo_controller::{userinfo, userinfo_post};
+
+pub mod authorize_controller;
+pub mod device_controller;
+pub mod identity_provider_controller;
+pub mod introspection_controller;
+pub mod pages;
+pub mod registration_controller;
+pub mod session;
+pub mod token_controller;
+pub mod userinfo_controller;
+
+/// OAuth 2.0 endpoints, outside of the versioned API as clients expect them at fixed paths.
+pub fn get_oauth_service() -> Scope {
+    web::scope("/oauth")
+        .service(authorize)
+        .service(authorize_decision)
+        .service(token)
+        .service(introspection)
+        .service(revocation)
+        .service(register)
+        .service(device_authorization)
+        .service(device_verification)
+        .service(device_verification_decision)
+        .service(userinfo)
+        .service(userinfo_post)
+        .service(list_identity_providers)
+        .service(external_login)
+        .service(external_login_callback)
+        .service(link_external_identity)
+}
@@ -0,0 +1,325 @@
+//! Server-rendered pages of the authorization endpoint.
+
+use crate::repository::oauth_repository::ClientMetadata;
+use crate::services::oauth::{AuthorizeParams, OAuthError};
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+fn layout(title: &str, body: &str) -> String {
+    layout_with_head(title, "", body)
+}
+
+fn layout_with_head(title: &str, head: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n{}\
+        <title>{}</title>\n</head>\n<body>\n<main>\n{}\n</main>\n</body>\n</html>\n",
+        head,
+        escape(title),
+        body
+    )
+}
+
+pub fn html_response(status: StatusCode, html: String) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .insert_header(("X-Frame-Options", "DENY"))
+        .insert_header((
+            "Content-Security-Policy",
+            "default-src 'none'; img-src https:; form-action 'self'",
+        ))
+        .body(html)
+}
+
+/// Shown instead of redirecting when the client or its redirect URI cannot be trusted.
+pub fn error_page(error: &OAuthError) -> HttpResponse {
+    let body = format!(
+        "<h1>Authorization error</h1>\n<p><code>{}</code></p>\n<p>{}</p>",
+        error.error.as_str(),
+        escape(&error.error_description)
+    );
+    html_response(
+        StatusCode::BAD_REQUEST,
+        layout("Authorization error", &body),
+    )
+}
+
+/// Logo of the client, its URI was checked to be `https` on registration.
+fn client_logo(client_name: &str, metadata: &ClientMetadata) -> String {
+    metadata
+        .logo_uri
+        .as_deref()
+        .map(|logo| {
+            format!(
+                "<img src=\"{}\" alt=\"{}\" width=\"64\" height=\"64\">\n",
+                escape(logo),
+                escape(client_name)
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Links to the website, privacy policy and terms of service of the client.
+fn client_links(metadata: &ClientMetadata) -> String {
+    let links: Vec<String> = [
+        (&metadata.client_uri, "Website"),
+        (&metadata.policy_uri, "Privacy policy"),
+        (&metadata.tos_uri, "Terms of service"),
+    ]
+    .into_iter()
+    .filter_map(|(uri, label)| {
+        uri.as_deref().map(|uri| {
+            format!(
+                "<a href=\"{}\" rel=\"noopener noreferrer\">{}</a>",
+                escape(uri),
+                label
+            )
+        })
+    })
+    .collect();
+
+    match links.is_empty() {
+        true => String::new(),
+        false => format!("\n<p>{}</p>", links.join(" · ")),
+    }
+}
+
+fn hidden(name: &str, value: &str) -> String {
+    format!(
+        "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+        name,
+        escape(value)
+    )
+}
+
+pub struct ConsentPage<'a> {
+    pub client_name: &'a str,
+    pub client_metadata: &'a ClientMetadata,
+    pub scopes: &'a [String],
+    /// Email of the logged user, a login form is shown when `None`.
+    pub user_email: Option<&'a str>,
+    pub params: &'a AuthorizeParams,
+    pub csrf_token: &'a str,
+    pub message: Option<&'a str>,
+}
+
+impl ConsentPage<'_> {
+    pub fn render(&self) -> String {
+        let mut fields = vec![
+            hidden("response_type", &self.params.response_type),
+            hidden("client_id", &self.params.client_id),
+            hidden("redirect_uri", &self.params.redirect_uri),
+            hidden("code_challenge", &self.params.code_challenge),
+            hidden("code_challenge_method", &self.params.code_challenge_method),
+            hidden("csrf_token", self.csrf_token),
+        ];
+        if let Some(scope) = &self.params.scope {
+            fields.push(hidden("scope", scope));
+        }
+        if let Some(state) = &self.params.state {
+            fields.push(hidden("state", state));
+        }
+        if let Some(nonce) = &self.params.nonce {
+            fields.push(hidden("nonce", nonce));
+        }
+
+        let identity = match self.user_email {
+            Some(email) => format!("<p>Signed in as <strong>{}</strong></p>", escape(email)),
+            None => String::from(
+                "<p><label>Email <input type=\"email\" name=\"email\" required autocomplete=\"username\"></label></p>\n\
+                <p><label>Password <input type=\"password\" name=\"password\" required autocomplete=\"current-password\"></label></p>",
+            ),
+        };
+        let scopes = match self.scopes.is_empty() {
+            true => String::from("<li>Your identity</li>"),
+            false => self
+                .scopes
+                .iter()
+                .map(|scope| format!("<li><code>{}</code></li>", escape(scope)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        let message = self
+            .message
+            .map(|message| format!("<p role=\"alert\">{}</p>\n", escape(message)))
+            .unwrap_or_default();
+
+        let body = format!(
+            "{logo}<h1>Authorize {client}</h1>\n{message}\
+            <p><strong>{client}</strong> is requesting access to :</p>\n<ul>\n{scopes}\n</ul>\n\
+            <form method=\"post\" action=\"authorize\">\n{fields}\n{identity}\n\
+            <button type=\"submit\" name=\"decision\" value=\"approve\">Allow</button>\n\
+            <button type=\"submit\" name=\"decision\" value=\"deny\" formnovalidate>Deny</button>\n\
+            </form>{links}",
+            logo = client_logo(self.client_name, self.client_metadata),
+            client = escape(self.client_name),
+            links = client_links(self.client_metadata),
+            message = message,
+            scopes = scopes,
+            fields = fields.join("\n"),
+            identity = identity,
+        );
+
+        layout(&format!("Authorize {}", self.client_name), &body)
+    }
+}
+
+/// Where the user types the code shown by their device.
+pub fn device_code_page(message: Option<&str>) -> String {
+    let message = message
+        .map(|message| format!("<p role=\"alert\">{}</p>\n", escape(message)))
+        .unwrap_or_default();
+    let body = format!(
+        "<h1>Connect a device</h1>\n{}\
+        <form method=\"get\" action=\"device\">\n\
+        <p><label>Code displayed on your device <input type=\"text\" name=\"user_code\" required autocomplete=\"off\" autocapitalize=\"characters\"></label></p>\n\
+        <button type=\"submit\">Continue</button>\n\
+        </form>",
+        message
+    );
+
+    layout("Connect a device", &body)
+}
+
+pub struct DeviceConsentPage<'a> {
+    pub client_name: &'a str,
+    pub client_metadata: &'a ClientMetadata,
+    pub scopes: &'a [String],
+    /// Formatted user code, the user checks it is the one of their device.
+    pub user_code: &'a str,
+    /// Email of the logged user, a login form is shown when `None`.
+    pub user_email: Option<&'a str>,
+    pub csrf_token: &'a str,
+    pub message: Option<&'a str>,
+}
+
+impl DeviceConsentPage<'_> {
+    pub fn render(&self) -> String {
+        let fields = [
+            hidden("user_code", self.user_code),
+            hidden("csrf_token", self.csrf_token),
+        ];
+        let identity = match self.user_email {
+            Some(email) => format!("<p>Signed in as <strong>{}</strong></p>", escape(email)),
+            None => String::from(
+                "<p><label>Email <input type=\"email\" name=\"email\" required autocomplete=\"username\"></label></p>\n\
+                <p><label>Password <input type=\"password\" name=\"password\" required autocomplete=\"current-password\"></label></p>",
+            ),
+        };
+        let scopes = match self.scopes.is_empty() {
+            true => String::from("<li>Your identity</li>"),
+            false => self
+                .scopes
+                .iter()
+                .map(|scope| format!("<li><code>{}</code></li>", escape(scope)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        let message = self
+            .message
+            .map(|message| format!("<p role=\"alert\">{}</p>\n", escape(message)))
+            .unwrap_or_default();
+
+        let body = format!(
+            "{logo}<h1>Connect {client}</h1>\n{message}\
+            <p>Check that your device displays the code <strong>{code}</strong>.</p>\n\
+            <p><strong>{client}</strong> is requesting access to :</p>\n<ul>\n{scopes}\n</ul>\n\
+            <form method=\"post\" action=\"device\">\n{fields}\n{identity}\n\
+            <button type=\"submit\" name=\"decision\" value=\"approve\">Allow</button>\n\
+            <button type=\"submit\" name=\"decision\" value=\"deny\" formnovalidate>Deny</button>\n\
+            </form>{links}",
+            logo = client_logo(self.client_name, self.client_metadata),
+            client = escape(self.client_name),
+            links = client_links(self.client_metadata),
+            message = message,
+            code = escape(self.user_code),
+            scopes = scopes,
+            fields = fields.join("\n"),
+            identity = identity,
+        );
+
+        layout(&format!("Connect {}", self.client_name), &body)
+    }
+}
+
+/// End of the device flow, the user goes back to their device.
+pub fn device_done_page(approved: bool) -> String {
+    let body = match approved {
+        true => "<h1>Device connected</h1>\n<p>You can return to your device.</p>",
+        false => "<h1>Access denied</h1>\n<p>The device was not connected, you can close this page.</p>",
+    };
+
+    layout("Connect a device", body)
+}
+
+/// End of a login, or of a link, with an external identity provider. The session cookie is
+/// `SameSite=Strict` and the provider redirected cross-site : the page navigates to
+/// `return_to` itself so that the cookie is sent.
+pub fn external_login_done_page(title: &str, return_to: &str) -> String {
+    let head = format!(
+        "<meta http-equiv=\"refresh\" content=\"0; url={}\">\n",
+        escape(return_to)
+    );
+    let body = format!(
+        "<h1>{}</h1>\n<p><a href=\"{}\">Continue</a></p>",
+        escape(title),
+        escape(return_to)
+    );
+
+    layout_with_head(title, &head, &body)
+}
+
+/// Second step of a sign-in with a link, for users with a verified phone : the code sent to
+/// it completes `challenge`.
+pub fn sms_code_page(
+    challenge: &str,
+    return_to: &str,
+    phone_number: Option<&str>,
+    message: Option<&str>,
+) -> String {
+    let fields = [
+        hidden("challenge", challenge),
+        hidden("return_to", return_to),
+    ];
+    let sent_to = match phone_number {
+        Some(number) => format!(
+            "<p>A code was sent to <strong>{}</strong>.</p>\n",
+            escape(number)
+        ),
+        None => String::new(),
+    };
+    let message = message
+        .map(|message| format!("<p role=\"alert\">{}</p>\n", escape(message)))
+        .unwrap_or_default();
+    let body = format!(
+        "<h1>Sign in</h1>\n{}{}\
+        <form method=\"post\" action=\"mfa\">\n{}\n\
+        <p><label>Code <input type=\"text\" name=\"code\" required autocomplete=\"one-time-code\" inputmode=\"numeric\"></label></p>\n\
+        <button type=\"submit\">Sign in</button>\n\
+        </form>",
+        message,
+        sent_to,
+        fields.join("\n")
+    );
+
+    layout("Sign in", &body)
+}
+
+/// A login with an external identity provider failed.
+pub fn external_login_error_page(status: StatusCode, message: &str) -> HttpResponse {
+    let body = format!(
+        "<h1>Sign in failed</h1>\n<p role=\"alert\">{}</p>",
+        escape(message)
+    );
+    html_response(status, layout("Sign in failed", &body))
+}
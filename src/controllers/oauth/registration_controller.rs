@@ -0,0 +1,59 @@
+use crate::controllers::oauth::token_controller::server_error;
+use crate::controllers::AppState;
+use crate::services::client_registration::{
+    initial_access_token, initial_access_token_valid, uses_secret, RegistrationRequest,
+    RegistrationResponse,
+};
+use crate::services::crypto::{Hash, HashService};
+use crate::services::oauth::{generate_token, OAuthError};
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+
+fn registration_error(error: OAuthError) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .insert_header(("Cache-Control", "no-store"))
+        .json(error)
+}
+
+/// Dynamic client registration endpoint (RFC 7591 section 3), the registering party
+/// presents the initial access token as a bearer token.
+#[post("/register")]
+pub async fn register(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<RegistrationRequest>,
+) -> impl Responder {
+    let authorization = req
+        .headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok());
+    if !initial_access_token_valid(authorization, initial_access_token().as_deref()) {
+        return HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Bearer error=\"invalid_token\""))
+            .finish();
+    }
+
+    let registration = match body.into_inner().into_registration() {
+        Ok(registration) => registration,
+        Err(error) => return registration_error(error),
+    };
+
+    let client_secret = uses_secret(&registration.token_endpoint_auth_method).then(generate_token);
+    let client_secret_hash = match &client_secret {
+        Some(secret) => match HashService::hash_password(secret) {
+            Ok(hash) => Some(hash),
+            Err(err) => return HttpResponse::InternalServerError().json(server_error(err)),
+        },
+        None => None,
+    };
+
+    match state
+        .repository
+        .save_oauth_client(registration.into_new_client(client_secret_hash))
+        .await
+    {
+        Ok(client) => HttpResponse::Created()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(RegistrationResponse::new(client, client_secret)),
+        Err(err) => HttpResponse::InternalServerError().json(server_error(err)),
+    }
+}
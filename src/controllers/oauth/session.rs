@@ -0,0 +1,145 @@
+//! Who is using the server-rendered pages, and the CSRF protection of their forms.
+
+use crate::controllers::AppState;
+use crate::repository::user_repository::User;
+use crate::services::access_control::AccessControl;
+use crate::services::authenticator::Credentials;
+use crate::services::claims::ClaimsSubject;
+use crate::services::crypto::{Jwt, JwtService, TokenConfig, TokenOptions};
+use actix_web::http::header::SET_COOKIE;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use cookie::time::{Duration, OffsetDateTime};
+use cookie::{Cookie, Expiration, SameSite};
+
+/// Double-submit cookie protecting the forms of the pages.
+const CSRF_COOKIE: &str = "OAUTH-XSRF-TOKEN";
+
+/// User of the session cookie, if any, and when they logged in.
+pub async fn session_user(state: &AppState, req: &HttpRequest) -> Option<(User, DateTime<Utc>)> {
+    let token = AccessControl::token_from_cookie(req.headers().get("cookie")).ok()?;
+    let claims = JwtService::verify_jwt(&token).ok()?;
+    let user = state.repository.find_user_by_id(&claims.sub).await.ok()?;
+    let auth_time = DateTime::from_timestamp(claims.iat as i64, 0)?;
+
+    Some((user, auth_time))
+}
+
+/// User of the credentials typed in a page, authenticated now.
+pub async fn password_user(
+    state: &AppState,
+    email: Option<&str>,
+    password: Option<&str>,
+) -> Option<(User, DateTime<Utc>)> {
+    let user = password_login(
+        state,
+        email.unwrap_or_default(),
+        password.unwrap_or_default(),
+    )
+    .await?;
+    // The pages do not ask for the code, these users sign in on `login` first.
+    match second_factor_required(state, &user.id).await {
+        Ok(false) => {}
+        Ok(true) => return None,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return None;
+        }
+    }
+
+    Some((user, Utc::now()))
+}
+
+/// Whether a password is not enough for the user : they verified a phone number, which
+/// `login` sends a code to.
+pub async fn second_factor_required(state: &AppState, user_id: &str) -> Result<bool, sqlx::Error> {
+    match state.repository.find_user_phone(user_id).await {
+        Ok(phone) => Ok(phone.verified_at.is_some()),
+        Err(sqlx::Error::RowNotFound) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// User of an email and a password, checked by the authenticators of the default realm.
+pub async fn password_login(state: &AppState, email: &str, password: &str) -> Option<User> {
+    state
+        .authenticators
+        .authenticate(None, &Credentials::Password { email, password })
+        .await
+}
+
+/// Token of a new session of `user` for the default audience, as issued by `login`, and
+/// its lifetime.
+pub async fn session_token(state: &AppState, user: &User) -> Option<(String, u64)> {
+    let config = TokenConfig::from_env();
+    let lifetime = config.lifetime(&config.audience)?;
+
+    let subject = ClaimsSubject {
+        user_id: user.id.clone(),
+        email: user.email.clone(),
+        roles: user.role.clone(),
+        audience: config.audience.clone(),
+    };
+    let claims = match state.claims_enricher.enrich(&subject).await {
+        Ok(claims) => claims,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return None;
+        }
+    };
+
+    let options = TokenOptions {
+        audience: Some(config.audience.clone()),
+        roles: user.role.clone(),
+        scope: None,
+        client_id: None,
+        claims,
+        lifetime: None,
+    };
+    match JwtService::generate_jwt_with(&user.id, &options) {
+        Ok(token) => Some((token, lifetime)),
+        Err(err) => {
+            log::error!("{:?}", err);
+            None
+        }
+    }
+}
+
+/// Cookie carrying the session token, read by [`session_user`] and the API.
+pub fn session_cookie(token: &str, lifetime: u64) -> Cookie<'static> {
+    let expiration_date = OffsetDateTime::now_utc() + Duration::seconds(lifetime as i64);
+    Cookie::build(("Authorization", token.to_owned()))
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .expires(Expiration::DateTime(expiration_date))
+        .build()
+}
+
+/// Set the CSRF cookie matching the token rendered in the form of `response`.
+pub fn with_csrf_cookie(mut response: HttpResponse, csrf_token: &str) -> HttpResponse {
+    let cookie = Cookie::build((CSRF_COOKIE, csrf_token))
+        .path("/oauth")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .build();
+
+    if let Ok(value) = cookie.to_string().parse() {
+        response.headers_mut().append(SET_COOKIE, value);
+    }
+    response
+}
+
+/// The submitted token is the one of the cookie.
+pub fn csrf_valid(req: &HttpRequest, csrf_token: &str) -> bool {
+    req.cookie(CSRF_COOKIE).is_some_and(|cookie| {
+        !csrf_token.is_empty()
+            && ring::constant_time::verify_slices_are_equal(
+                cookie.value().as_bytes(),
+                csrf_token.as_bytes(),
+            )
+            .is_ok()
+    })
+}
@@ -0,0 +1,592 @@
+use crate::controllers::oauth::session::second_factor_required;
+use crate::controllers::{public_base_url, AppState};
+use crate::repository::oauth_repository::OAuthClient;
+use crate::repository::token_repository::NewRefreshToken;
+use crate::services::authenticator::Credentials;
+use crate::services::claims::ClaimsSubject;
+use crate::services::crypto::{Hash, HashService, Jwt, JwtService, TokenConfig, TokenOptions};
+use crate::services::device_authorization::check_poll;
+use crate::services::introspection::REFRESH_TOKEN_TTL_SECONDS;
+use crate::services::oauth::{
+    client_assertion, client_credentials, generate_token, hash_token, parse_scope, resolve_scope,
+    verify_client_assertion, verify_pkce, OAuthError, OAuthErrorCode, AUTH_METHOD_NONE,
+    AUTH_METHOD_PRIVATE_KEY_JWT, GRANT_AUTHORIZATION_CODE, GRANT_CLIENT_CREDENTIALS,
+    GRANT_DEVICE_CODE, GRANT_PASSWORD, GRANT_REFRESH_TOKEN, SUPPORTED_GRANT_TYPES,
+};
+use crate::services::oidc::{has_scope, IdTokenClaims, SCOPE_OPENID};
+use actix_web::http::StatusCode;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Client authentication parameters sent in the body of token endpoint requests.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ClientAuthForm {
+    #[serde(default)]
+    pub(crate) client_id: Option<String>,
+    #[serde(default)]
+    pub(crate) client_secret: Option<String>,
+    #[serde(default)]
+    pub(crate) client_assertion_type: Option<String>,
+    #[serde(default)]
+    pub(crate) client_assertion: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenRequest {
+    #[serde(default)]
+    grant_type: String,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    redirect_uri: Option<String>,
+    #[serde(default)]
+    code_verifier: Option<String>,
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(default)]
+    device_code: Option<String>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    /// Email of the user, for the password grant.
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    /// Realm whose authenticators check the password, the default one when not set.
+    #[serde(default)]
+    realm: Option<String>,
+    #[serde(flatten)]
+    client: ClientAuthForm,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    expires_in: u64,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    scope: String,
+    /// Only for clients allowed to use the refresh token grant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    /// Only with the `openid` scope.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+}
+
+/// Error response of RFC 6749 section 5.2.
+pub(crate) fn oauth_error(error: OAuthError) -> HttpResponse {
+    let mut response = match error.error {
+        OAuthErrorCode::InvalidClient => HttpResponse::Unauthorized(),
+        OAuthErrorCode::ServerError => HttpResponse::InternalServerError(),
+        _ => HttpResponse::BadRequest(),
+    };
+    if error.error == OAuthErrorCode::InvalidClient {
+        response.insert_header(("WWW-Authenticate", "Basic realm=\"oauth\""));
+    }
+
+    response
+        .insert_header(("Cache-Control", "no-store"))
+        .json(error)
+}
+
+pub(crate) fn server_error<E: std::fmt::Debug>(err: E) -> OAuthError {
+    log::error!("{:?}", err);
+    OAuthError::new(OAuthErrorCode::ServerError, "Internal server error")
+}
+
+/// Authenticate the client of a token request with its registered method : a secret,
+/// a `private_key_jwt` assertion, or nothing for public clients which rely on PKCE.
+pub(crate) async fn authenticate_client(
+    state: &AppState,
+    req: &HttpRequest,
+    form: &ClientAuthForm,
+) -> Result<OAuthClient, OAuthError> {
+    let authorization = req
+        .headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok());
+    let credentials = match (&form.client_assertion_type, &form.client_assertion) {
+        (Some(assertion_type), Some(assertion)) => {
+            if authorization.is_some() || form.client_secret.is_some() {
+                return Err(OAuthError::new(
+                    OAuthErrorCode::InvalidRequest,
+                    "Only one client authentication method may be used",
+                ));
+            }
+            client_assertion(assertion_type, assertion, form.client_id.as_deref())?
+        }
+        _ => client_credentials(
+            authorization,
+            form.client_id.as_deref(),
+            form.client_secret.as_deref(),
+        )?,
+    };
+    let invalid = || {
+        OAuthError::new(
+            OAuthErrorCode::InvalidClient,
+            "Client authentication failed",
+        )
+    };
+
+    let client = match state
+        .repository
+        .find_oauth_client(&credentials.client_id)
+        .await
+    {
+        Ok(client) => client,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid()),
+        Err(err) => return Err(server_error(err)),
+    };
+
+    match client.token_endpoint_auth_method.as_str() {
+        AUTH_METHOD_NONE => match (&credentials.client_secret, &credentials.client_assertion) {
+            (None, None) => Ok(client),
+            _ => Err(invalid()),
+        },
+        AUTH_METHOD_PRIVATE_KEY_JWT => {
+            let (assertion, jwks) = match (&credentials.client_assertion, &client.jwks) {
+                (Some(assertion), Some(jwks)) => (assertion, jwks),
+                _ => return Err(invalid()),
+            };
+            let config = TokenConfig::from_env();
+            let audiences = [
+                format!("{}/oauth/token", public_base_url(&config, req)),
+                config.issuer,
+            ];
+            verify_client_assertion(assertion, &client.client_id, jwks, &audiences)?;
+            Ok(client)
+        }
+        _ => {
+            let secret = credentials.client_secret.as_deref().ok_or_else(invalid)?;
+            // The secret replaced by a rotation is accepted during its grace period.
+            let previous = client
+                .previous_client_secret_hash
+                .as_deref()
+                .filter(|_| {
+                    client
+                        .previous_secret_expires_at
+                        .is_some_and(|expires_at| expires_at > Utc::now())
+                });
+            let valid = [client.client_secret_hash.as_deref(), previous]
+                .into_iter()
+                .flatten()
+                .any(|hash| matches!(HashService::check_password(secret, hash), Ok(true)));
+            match valid {
+                true => Ok(client),
+                false => Err(invalid()),
+            }
+        }
+    }
+}
+
+async fn authorization_code_grant(
+    state: &AppState,
+    client: &OAuthClient,
+    body: &TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    let invalid_grant =
+        || OAuthError::new(OAuthErrorCode::InvalidGrant, "Invalid authorization code");
+
+    let code = body
+        .code
+        .as_deref()
+        .ok_or_else(|| OAuthError::new(OAuthErrorCode::InvalidRequest, "code is required"))?;
+    let verifier = body.code_verifier.as_deref().ok_or_else(|| {
+        OAuthError::new(OAuthErrorCode::InvalidRequest, "code_verifier is required")
+    })?;
+
+    // Consuming first makes a code unusable after any attempt, replayed or not.
+    let authorization = match state
+        .repository
+        .consume_authorization_code(&hash_token(code))
+        .await
+    {
+        Ok(authorization) => authorization,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid_grant()),
+        Err(err) => return Err(server_error(err)),
+    };
+    if authorization.client_id != client.client_id
+        || body.redirect_uri.as_deref() != Some(authorization.redirect_uri.as_str())
+        || !verify_pkce(verifier, &authorization.code_challenge)
+    {
+        return Err(invalid_grant());
+    }
+
+    user_tokens(
+        state,
+        client,
+        &authorization.user_id,
+        authorization.scope,
+        authorization.nonce,
+        authorization.auth_time,
+    )
+    .await
+}
+
+/// Poll of the device (RFC 8628 section 3.4), tokens are issued once the user approved.
+async fn device_code_grant(
+    state: &AppState,
+    client: &OAuthClient,
+    body: &TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    let invalid_grant = || OAuthError::new(OAuthErrorCode::InvalidGrant, "Invalid device code");
+
+    let device_code = body.device_code.as_deref().ok_or_else(|| {
+        OAuthError::new(OAuthErrorCode::InvalidRequest, "device_code is required")
+    })?;
+    let device_code_hash = hash_token(device_code);
+
+    let poll = match state.repository.poll_device_code(&device_code_hash).await {
+        Ok(poll) => poll,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid_grant()),
+        Err(err) => return Err(server_error(err)),
+    };
+    if poll.client_id != client.client_id {
+        return Err(invalid_grant());
+    }
+    if let Err(error) = check_poll(
+        &poll.status,
+        poll.previous_poll,
+        poll.interval_seconds,
+        poll.expires_at,
+        Utc::now(),
+    ) {
+        if error.error == OAuthErrorCode::SlowDown {
+            if let Err(err) = state
+                .repository
+                .slow_down_device_code(&device_code_hash)
+                .await
+            {
+                return Err(server_error(err));
+            }
+        }
+        return Err(error);
+    }
+
+    let device = match state
+        .repository
+        .consume_device_code(&device_code_hash)
+        .await
+    {
+        Ok(device) => device,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid_grant()),
+        Err(err) => return Err(server_error(err)),
+    };
+    let user_id = device.user_id.ok_or_else(invalid_grant)?;
+
+    user_tokens(
+        state,
+        client,
+        &user_id,
+        device.scope,
+        None,
+        device.auth_time.unwrap_or_else(Utc::now),
+    )
+    .await
+}
+
+/// Lifetime of the access tokens of the client, when it is not the one of the audience.
+fn access_token_lifetime(client: &OAuthClient) -> Option<u64> {
+    client
+        .metadata
+        .access_token_lifetime_seconds
+        .map(|lifetime| lifetime as u64)
+}
+
+/// Access token delegated by a user to a client. It is issued for the OAuth audience, not
+/// for the API of this service, which would grant the client every permission of the user.
+pub fn delegated_token_options(
+    config: &TokenConfig,
+    client: &OAuthClient,
+    roles: Vec<String>,
+    scope: &str,
+    claims: Map<String, Value>,
+) -> TokenOptions {
+    TokenOptions {
+        audience: Some(config.oauth_audience.clone()),
+        roles,
+        scope: Some(scope.to_owned()).filter(|scope| !scope.is_empty()),
+        client_id: Some(client.client_id.clone()),
+        claims,
+        lifetime: access_token_lifetime(client),
+    }
+}
+
+/// Access token, and ID token with the `openid` scope, delegated by a user to a client.
+async fn user_tokens(
+    state: &AppState,
+    client: &OAuthClient,
+    user_id: &str,
+    scope: String,
+    nonce: Option<String>,
+    auth_time: DateTime<Utc>,
+) -> Result<TokenResponse, OAuthError> {
+    let user = match state.repository.find_user_by_id(user_id).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(OAuthError::new(
+                OAuthErrorCode::InvalidGrant,
+                "The user no longer exists",
+            ))
+        }
+        Err(err) => return Err(server_error(err)),
+    };
+
+    let config = TokenConfig::from_env();
+    let subject = ClaimsSubject {
+        user_id: user.id.clone(),
+        email: user.email.clone(),
+        roles: user.role.clone(),
+        audience: config.oauth_audience.clone(),
+    };
+    let claims = state
+        .claims_enricher
+        .enrich(&subject)
+        .await
+        .map_err(server_error)?;
+
+    let scopes = parse_scope(Some(&scope));
+    let id_token = match has_scope(&scopes, SCOPE_OPENID) {
+        true => {
+            let claims = IdTokenClaims::new(
+                &config,
+                &user,
+                &client.client_id,
+                &scopes,
+                nonce,
+                auth_time.timestamp() as u64,
+            );
+            Some(JwtService::sign_claims(&claims).map_err(server_error)?)
+        }
+        false => None,
+    };
+
+    let options = delegated_token_options(&config, client, user.role, &scope, claims);
+    let access_token = JwtService::generate_jwt_with(&user.id, &options).map_err(server_error)?;
+
+    let refresh_token = match client
+        .grant_types
+        .iter()
+        .any(|grant| grant == GRANT_REFRESH_TOKEN)
+    {
+        true => {
+            let refresh_token = generate_token();
+            let lifetime = client
+                .metadata
+                .refresh_token_lifetime_seconds
+                .map(i64::from)
+                .unwrap_or(REFRESH_TOKEN_TTL_SECONDS);
+            state
+                .repository
+                .save_refresh_token(NewRefreshToken {
+                    token_hash: &hash_token(&refresh_token),
+                    client_id: &client.client_id,
+                    user_id: &user.id,
+                    scope: &scope,
+                    auth_time,
+                    expires_at: Utc::now() + chrono::Duration::seconds(lifetime),
+                })
+                .await
+                .map_err(server_error)?;
+            Some(refresh_token)
+        }
+        false => None,
+    };
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: String::from("Bearer"),
+        expires_in: access_token_lifetime(client)
+            .unwrap_or_else(|| config.lifetime(&config.audience).unwrap_or_default()),
+        scope,
+        refresh_token,
+        id_token,
+    })
+}
+
+/// Refresh request (RFC 6749 section 6), the refresh token is exchanged for a new one and
+/// the scope may only be narrowed.
+async fn refresh_token_grant(
+    state: &AppState,
+    client: &OAuthClient,
+    body: &TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    let refresh_token = body.refresh_token.as_deref().ok_or_else(|| {
+        OAuthError::new(OAuthErrorCode::InvalidRequest, "refresh_token is required")
+    })?;
+
+    let previous = match state
+        .repository
+        .consume_refresh_token(&hash_token(refresh_token), &client.client_id)
+        .await
+    {
+        Ok(previous) => previous,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(OAuthError::new(
+                OAuthErrorCode::InvalidGrant,
+                "Invalid refresh token",
+            ))
+        }
+        Err(err) => return Err(server_error(err)),
+    };
+    let granted = parse_scope(Some(&previous.scope));
+    let scope = resolve_scope(body.scope.as_deref(), &granted)?.join(" ");
+
+    user_tokens(
+        state,
+        client,
+        &previous.user_id,
+        scope,
+        None,
+        previous.auth_time,
+    )
+    .await
+}
+
+/// Resource owner password credentials (RFC 6749 section 4.3), checked by the authenticators
+/// of the realm. Only first-party clients are trusted with the passwords of the users.
+async fn password_grant(
+    state: &AppState,
+    client: &OAuthClient,
+    body: &TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    if !client.first_party {
+        return Err(OAuthError::new(
+            OAuthErrorCode::UnauthorizedClient,
+            "The password grant is reserved to first-party clients",
+        ));
+    }
+    let username = body
+        .username
+        .as_deref()
+        .ok_or_else(|| OAuthError::new(OAuthErrorCode::InvalidRequest, "username is required"))?;
+    let password = body
+        .password
+        .as_deref()
+        .ok_or_else(|| OAuthError::new(OAuthErrorCode::InvalidRequest, "password is required"))?;
+    if let Some(realm) = body.realm.as_deref() {
+        if !state.authenticators.has_realm(realm) {
+            return Err(OAuthError::new(
+                OAuthErrorCode::InvalidRequest,
+                "Unknown realm",
+            ));
+        }
+    }
+    let scope = resolve_scope(body.scope.as_deref(), &client.scopes)?.join(" ");
+
+    let credentials = Credentials::Password {
+        email: username,
+        password,
+    };
+    let user = state
+        .authenticators
+        .authenticate(body.realm.as_deref(), &credentials)
+        .await
+        .ok_or_else(|| OAuthError::new(OAuthErrorCode::InvalidGrant, "Invalid credentials"))?;
+    if second_factor_required(state, &user.id)
+        .await
+        .map_err(server_error)?
+    {
+        return Err(OAuthError::new(
+            OAuthErrorCode::InvalidGrant,
+            "A second factor is required, sign in on login",
+        ));
+    }
+
+    user_tokens(state, client, &user.id, scope, None, Utc::now()).await
+}
+
+/// Tokens of a service account, its `sub` is the client id (RFC 9068 section 2.2).
+async fn client_credentials_grant(
+    state: &AppState,
+    client: &OAuthClient,
+    body: &TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    match state
+        .repository
+        .find_service_account(&client.client_id)
+        .await
+    {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(OAuthError::new(
+                OAuthErrorCode::UnauthorizedClient,
+                "Only service accounts may use the client_credentials grant",
+            ))
+        }
+        Err(err) => return Err(server_error(err)),
+    }
+
+    let scope = resolve_scope(body.scope.as_deref(), &client.scopes)?.join(" ");
+    let options = TokenOptions {
+        audience: None,
+        roles: vec![],
+        scope: Some(scope.clone()).filter(|scope| !scope.is_empty()),
+        client_id: Some(client.client_id.clone()),
+        claims: Map::new(),
+        lifetime: access_token_lifetime(client),
+    };
+    let access_token =
+        JwtService::generate_jwt_with(&client.client_id, &options).map_err(server_error)?;
+    let config = TokenConfig::from_env();
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: String::from("Bearer"),
+        expires_in: access_token_lifetime(client)
+            .unwrap_or_else(|| config.lifetime(&config.audience).unwrap_or_default()),
+        scope,
+        refresh_token: None,
+        id_token: None,
+    })
+}
+
+/// Token endpoint (RFC 6749 section 3.2).
+#[post("/token")]
+pub async fn token(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Form<TokenRequest>,
+) -> impl Responder {
+    let client = match authenticate_client(&state, &req, &body.client).await {
+        Ok(client) => client,
+        Err(error) => return oauth_error(error),
+    };
+
+    if !client.grant_types.contains(&body.grant_type) {
+        let error = match SUPPORTED_GRANT_TYPES.contains(&body.grant_type.as_str()) {
+            true => OAuthError::new(
+                OAuthErrorCode::UnauthorizedClient,
+                "The client may not use this grant type",
+            ),
+            false => OAuthError::new(
+                OAuthErrorCode::UnsupportedGrantType,
+                "Unsupported grant_type",
+            ),
+        };
+        return oauth_error(error);
+    }
+
+    let response = match body.grant_type.as_str() {
+        GRANT_AUTHORIZATION_CODE => authorization_code_grant(&state, &client, &body).await,
+        GRANT_CLIENT_CREDENTIALS => client_credentials_grant(&state, &client, &body).await,
+        GRANT_DEVICE_CODE => device_code_grant(&state, &client, &body).await,
+        GRANT_REFRESH_TOKEN => refresh_token_grant(&state, &client, &body).await,
+        GRANT_PASSWORD => password_grant(&state, &client, &body).await,
+        _ => Err(OAuthError::new(
+            OAuthErrorCode::UnsupportedGrantType,
+            "Unsupported grant_type",
+        )),
+    };
+
+    match response {
+        Ok(response) => HttpResponse::build(StatusCode::OK)
+            .insert_header(("Cache-Control", "no-store"))
+            .insert_header(("Pragma", "no-cache"))
+            .json(response),
+        Err(error) => oauth_error(error),
+    }
+}
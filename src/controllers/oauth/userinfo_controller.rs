@@ -0,0 +1,67 @@
+use crate::controllers::AppState;
+use crate::services::crypto::{Jwt, JwtService, TokenConfig};
+use crate::services::oauth::parse_scope;
+use crate::services::oidc::{has_scope, userinfo as userinfo_claims, SCOPE_OPENID};
+use actix_web::http::header::HeaderValue;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+
+/// Bearer token error of RFC 6750 section 3.
+fn bearer_error(mut response: actix_web::HttpResponseBuilder, error: &str) -> HttpResponse {
+    response
+        .insert_header(("WWW-Authenticate", format!("Bearer error=\"{}\"", error)))
+        .insert_header(("Cache-Control", "no-store"))
+        .finish()
+}
+
+fn bearer_token(header: Option<&HeaderValue>) -> Option<&str> {
+    header?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+async fn userinfo_response(state: &AppState, req: &HttpRequest) -> HttpResponse {
+    let token = match bearer_token(req.headers().get("Authorization")) {
+        Some(token) => token,
+        None => {
+            return HttpResponse::Unauthorized()
+                .insert_header(("WWW-Authenticate", "Bearer"))
+                .finish()
+        }
+    };
+    let config = TokenConfig::from_env();
+    let claims = match JwtService::verify_jwt_for(token, &config, &[&config.oauth_audience]) {
+        Ok(claims) => claims,
+        Err(_) => return bearer_error(HttpResponse::Unauthorized(), "invalid_token"),
+    };
+
+    let scopes = parse_scope(claims.scope.as_deref());
+    if !has_scope(&scopes, SCOPE_OPENID) {
+        return bearer_error(HttpResponse::Forbidden(), "insufficient_scope");
+    }
+
+    match state.repository.find_user_by_id(&claims.sub).await {
+        Ok(user) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(userinfo_claims(&user, &scopes)),
+        Err(sqlx::Error::RowNotFound) => {
+            bearer_error(HttpResponse::Unauthorized(), "invalid_token")
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// UserInfo endpoint (OpenID Connect Core section 5.3), claims of the token's user.
+#[get("/userinfo")]
+pub async fn userinfo(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    userinfo_response(&state, &req).await
+}
+
+#[post("/userinfo")]
+pub async fn userinfo_post(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    userinfo_response(&state, &req).await
+}
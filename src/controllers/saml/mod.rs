@@ -0,0 +1,20 @@
+use crate::services::saml::MAX_RESPONSE_BYTES;
+use actix_web::{web, Scope};
+use service_provider_controller::{
+    list_saml_providers, saml_acs, saml_link, saml_login, saml_metadata,
+};
+
+pub mod service_provider_controller;
+
+/// SAML 2.0 service provider endpoints, at the fixed paths identity providers are
+/// configured with.
+pub fn get_saml_service() -> Scope {
+    web::scope("/saml")
+        // Responses are posted as forms, larger than the default limit.
+        .app_data(web::FormConfig::default().limit(MAX_RESPONSE_BYTES + 1024))
+        .service(saml_metadata)
+        .service(list_saml_providers)
+        .service(saml_login)
+        .service(saml_link)
+        .service(saml_acs)
+}
@@ -0,0 +1,343 @@
+use crate::controllers::oauth::identity_provider_controller::{
+    internal_error, linked_identity, provisioned_user,
+};
+use crate::controllers::oauth::pages::{
+    external_login_done_page, external_login_error_page, html_response,
+};
+use crate::controllers::oauth::session::{session_cookie, session_token, session_user};
+use crate::controllers::{public_base_url, AppState, CustomResponse};
+use crate::repository::saml_repository::NewSamlRequest;
+use crate::repository::user_repository::User;
+use crate::services::crypto::TokenConfig;
+use crate::services::identity_provider::{recently_authenticated, safe_return_to};
+use crate::services::saml::{
+    acs_url, new_request_id, ResponseContext, SamlAssertion, SamlProvider, SAML_REQUEST_TTL_SECONDS,
+};
+use actix_web::http::header::{LOCATION, SET_COOKIE};
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use cookie::time::Duration;
+use cookie::{Cookie, SameSite};
+use serde::{Deserialize, Serialize};
+
+/// Binds a request to the browser which sent it, against login CSRF. The response is
+/// posted cross-site, only a `SameSite=None` cookie comes back with it.
+const REQUEST_COOKIE: &str = "SAML-REQUEST";
+const REQUEST_COOKIE_PATH: &str = "/saml";
+
+#[derive(Serialize, Deserialize)]
+pub struct SamlProviderLink {
+    name: String,
+    display_name: String,
+    login_url: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SamlLoginQuery {
+    #[serde(default)]
+    return_to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SamlResponseForm {
+    #[serde(rename = "SAMLResponse")]
+    saml_response: String,
+}
+
+fn base_url(req: &HttpRequest) -> String {
+    public_base_url(&TokenConfig::from_env(), req)
+}
+
+fn request_cookie(value: &str, max_age: Duration) -> Cookie<'static> {
+    Cookie::build((REQUEST_COOKIE, value.to_owned()))
+        .path(REQUEST_COOKIE_PATH)
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::None)
+        .max_age(max_age)
+        .build()
+}
+
+fn unknown_provider() -> HttpResponse {
+    external_login_error_page(StatusCode::NOT_FOUND, "Unknown identity provider")
+}
+
+/// Metadata of the service provider, for identity providers to be configured with.
+#[get("/metadata")]
+pub async fn saml_metadata(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/samlmetadata+xml")
+        .body(state.saml_providers.metadata(&base_url(&req)))
+}
+
+/// SAML providers users may sign in with, for the login page to offer them.
+#[get("/providers")]
+pub async fn list_saml_providers(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let base_url = base_url(&req);
+    let providers: Vec<SamlProviderLink> = state
+        .saml_providers
+        .iter()
+        .map(|provider| SamlProviderLink {
+            name: provider.name().to_owned(),
+            display_name: provider.display_name().to_owned(),
+            login_url: format!("{}/saml/{}/login", base_url, provider.name()),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(providers)
+}
+
+/// Send the user to the provider with an authentication request, the response comes back
+/// to [`saml_acs`].
+async fn start_request(
+    state: &AppState,
+    req: &HttpRequest,
+    provider: &SamlProvider,
+    return_to: Option<&str>,
+    link_user_id: Option<&str>,
+) -> HttpResponse {
+    let base_url = base_url(req);
+    let request_id = new_request_id();
+    let authn_request = provider.authn_request(
+        &request_id,
+        &state.saml_providers.entity_id(&base_url),
+        &acs_url(&base_url, provider),
+        Utc::now(),
+    );
+    let url = match provider.redirect_url(&authn_request) {
+        Ok(url) => url,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+
+    let saved = state
+        .repository
+        .save_saml_request(NewSamlRequest {
+            request_id: &request_id,
+            provider: provider.name(),
+            return_to: &safe_return_to(return_to),
+            link_user_id,
+            expires_at: Utc::now() + chrono::Duration::seconds(SAML_REQUEST_TTL_SECONDS),
+        })
+        .await;
+    if let Err(err) = saved {
+        log::error!("{:?}", err);
+        return HttpResponse::InternalServerError().json(CustomResponse {
+            message: String::from("Internal server error"),
+        });
+    }
+
+    let cookie = request_cookie(&request_id, Duration::seconds(SAML_REQUEST_TTL_SECONDS));
+    HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .insert_header(("Cache-Control", "no-store"))
+        .append_header((SET_COOKIE, cookie.to_string()))
+        .finish()
+}
+
+/// Sign in with a SAML provider.
+#[get("/{provider}/login")]
+pub async fn saml_login(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<SamlLoginQuery>,
+) -> impl Responder {
+    match state.saml_providers.get(&provider) {
+        Some(provider) => {
+            start_request(&state, &req, provider, query.return_to.as_deref(), None).await
+        }
+        None => unknown_provider(),
+    }
+}
+
+/// Link an identity of a SAML provider to the logged user, who must have signed in recently.
+#[get("/{provider}/link")]
+pub async fn saml_link(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<SamlLoginQuery>,
+) -> impl Responder {
+    let provider = match state.saml_providers.get(&provider) {
+        Some(provider) => provider,
+        None => return unknown_provider(),
+    };
+    let user = match session_user(&state, &req).await {
+        Some((user, auth_time)) if recently_authenticated(auth_time, Utc::now()) => user,
+        Some(_) => {
+            return external_login_error_page(
+                StatusCode::FORBIDDEN,
+                &format!("Sign in again to link {}", provider.display_name()),
+            )
+        }
+        None => {
+            return external_login_error_page(
+                StatusCode::UNAUTHORIZED,
+                &format!("Sign in to link {}", provider.display_name()),
+            )
+        }
+    };
+
+    start_request(
+        &state,
+        &req,
+        provider,
+        query.return_to.as_deref(),
+        Some(&user.id),
+    )
+    .await
+}
+
+/// Give `user` the roles mapped from the assertion.
+async fn synced_user(
+    state: &AppState,
+    provider: &SamlProvider,
+    mut user: User,
+    assertion: &SamlAssertion,
+) -> Result<User, HttpResponse> {
+    let roles = provider.roles(&user.role, assertion);
+    if roles == user.role {
+        return Ok(user);
+    }
+
+    match state.repository.update_user_roles(&user.id, &roles).await {
+        Ok(()) => {
+            user.role = roles;
+            Ok(user)
+        }
+        Err(err) => {
+            log::error!(
+                "Roles {:?} of {} could not be granted : {:?}",
+                roles,
+                provider.name(),
+                err
+            );
+            Err(internal_error())
+        }
+    }
+}
+
+/// Assertion consumer service of a provider : the response is validated, then the user is
+/// signed in with the same session cookie as `login`, or the identity is linked to the user
+/// who asked for it.
+#[post("/{provider}/acs")]
+pub async fn saml_acs(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+    form: web::Form<SamlResponseForm>,
+) -> impl Responder {
+    let provider = match state.saml_providers.get(&provider) {
+        Some(provider) => provider,
+        None => return unknown_provider(),
+    };
+
+    let request_id = match req.cookie(REQUEST_COOKIE) {
+        Some(cookie) if !cookie.value().is_empty() => cookie.value().to_owned(),
+        _ => {
+            return external_login_error_page(
+                StatusCode::BAD_REQUEST,
+                "The sign in was not started from this browser, try again",
+            )
+        }
+    };
+    let request = match state
+        .repository
+        .consume_saml_request(&request_id, provider.name())
+        .await
+    {
+        Ok(request) => request,
+        Err(sqlx::Error::RowNotFound) => {
+            return external_login_error_page(
+                StatusCode::BAD_REQUEST,
+                "The sign in expired, try again",
+            )
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+
+    let base_url = base_url(&req);
+    let context = ResponseContext {
+        sp_entity_id: &state.saml_providers.entity_id(&base_url),
+        acs_url: &acs_url(&base_url, provider),
+        request_id: &request_id,
+        now: Utc::now(),
+    };
+    let validated = provider
+        .validate_response(&form.saml_response, &context)
+        .and_then(|assertion| Ok((provider.identity(&assertion)?, assertion)));
+    let (identity, assertion) = match validated {
+        Ok(validated) => validated,
+        Err(err) => {
+            log::error!("Login with {} failed : {:?}", provider.name(), err);
+            return external_login_error_page(
+                StatusCode::FORBIDDEN,
+                &format!("Sign in with {} failed", provider.display_name()),
+            );
+        }
+    };
+
+    let consumed = state
+        .repository
+        .save_saml_assertion(provider.name(), &assertion.id, assertion.expires_at)
+        .await;
+    match consumed {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            log::error!("Replayed assertion {} of {}", assertion.id, provider.name());
+            return external_login_error_page(
+                StatusCode::FORBIDDEN,
+                &format!("Sign in with {} failed", provider.display_name()),
+            );
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    }
+
+    let mut cookies = vec![request_cookie("", Duration::ZERO)];
+    let title = match &request.link_user_id {
+        Some(user_id) => {
+            let linked = linked_identity(&state, &identity, provider.display_name(), user_id).await;
+            if let Err(response) = linked {
+                return response;
+            }
+            "Account linked"
+        }
+        None => {
+            let provisioned = provisioned_user(&state, &identity, provider.display_name()).await;
+            let user = match provisioned {
+                Ok(user) => user,
+                Err(response) => return response,
+            };
+            let user = match synced_user(&state, provider, user, &assertion).await {
+                Ok(user) => user,
+                Err(response) => return response,
+            };
+            match session_token(&state, &user).await {
+                Some((token, lifetime)) => cookies.push(session_cookie(&token, lifetime)),
+                None => return internal_error(),
+            }
+            "Signed in"
+        }
+    };
+
+    let mut response = html_response(
+        StatusCode::OK,
+        external_login_done_page(title, &request.return_to),
+    );
+    for cookie in cookies {
+        if let Ok(value) = cookie.to_string().parse() {
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+    }
+    response
+}
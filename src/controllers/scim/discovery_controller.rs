@@ -0,0 +1,48 @@
+use crate::controllers::scim::{base_url, error_response, scim_response};
+use crate::services::scim::{
+    resource_types, schemas, service_provider_config, ListQuery, ScimError,
+};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpRequest, Responder};
+use serde_json::Value;
+
+/// Features of the server, public as its schemas and resource types.
+#[get("/ServiceProviderConfig")]
+pub async fn scim_service_provider_config(req: HttpRequest) -> impl Responder {
+    scim_response(StatusCode::OK, service_provider_config(&base_url(&req)))
+}
+
+fn list(resources: Vec<Value>) -> impl Responder {
+    let total = resources.len() as i64;
+    scim_response(
+        StatusCode::OK,
+        ListQuery::default().response(resources, total),
+    )
+}
+
+fn find(resources: Vec<Value>, id: &str) -> impl Responder {
+    match resources.into_iter().find(|resource| resource["id"] == id) {
+        Some(resource) => scim_response(StatusCode::OK, resource),
+        None => error_response(ScimError::not_found(format!("Unknown resource {}", id))),
+    }
+}
+
+#[get("/ResourceTypes")]
+pub async fn list_scim_resource_types(req: HttpRequest) -> impl Responder {
+    list(resource_types(&base_url(&req)))
+}
+
+#[get("/ResourceTypes/{id}")]
+pub async fn get_scim_resource_type(req: HttpRequest, id: web::Path<String>) -> impl Responder {
+    find(resource_types(&base_url(&req)), &id)
+}
+
+#[get("/Schemas")]
+pub async fn list_scim_schemas(req: HttpRequest) -> impl Responder {
+    list(schemas(&base_url(&req)))
+}
+
+#[get("/Schemas/{id}")]
+pub async fn get_scim_schema(req: HttpRequest, id: web::Path<String>) -> impl Responder {
+    find(schemas(&base_url(&req)), &id)
+}
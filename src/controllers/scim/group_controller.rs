@@ -0,0 +1,435 @@
+use crate::controllers::scim::{
+    base_url, database_error, error_response, scim_client, scim_response,
+};
+use crate::controllers::AppState;
+use crate::repository::scim_repository::{ScimGroup, ScimGroupMember};
+use crate::services::scim::{
+    group_resource, GroupPatch, GroupResource, ListQuery, MembersChange, PatchRequest, ScimClient,
+    ScimError,
+};
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+fn location(req: &HttpRequest, id: &str) -> String {
+    format!("{}/scim/v2/Groups/{}", base_url(req), id)
+}
+
+fn resource(req: &HttpRequest, group: &ScimGroup, members: Option<&[ScimGroupMember]>) -> Value {
+    let members = members.map(|members| {
+        members
+            .iter()
+            .filter(|member| member.group_id == group.id)
+            .map(|member| (member.user_id.clone(), member.email.clone()))
+            .collect()
+    });
+    group_resource(
+        &group.id,
+        &group.display_name,
+        group.external_id.as_deref(),
+        members,
+        &location(req, &group.id),
+    )
+}
+
+fn unknown_group(id: &str) -> HttpResponse {
+    error_response(ScimError::not_found(format!("Unknown group {}", id)))
+}
+
+fn name_taken(display_name: &str) -> HttpResponse {
+    error_response(ScimError::conflict(format!(
+        "A group is already named {}",
+        display_name
+    )))
+}
+
+/// Errors of a change of the group or of its members.
+fn group_error(err: sqlx::Error, display_name: &str) -> HttpResponse {
+    match err {
+        sqlx::Error::Database(err) if err.is_unique_violation() => name_taken(display_name),
+        sqlx::Error::RowNotFound => error_response(ScimError::bad_request(
+            "invalidValue",
+            "A member is not a known user",
+        )),
+        err => database_error(err),
+    }
+}
+
+async fn find_group(
+    state: &AppState,
+    client: &ScimClient,
+    id: &str,
+) -> Result<ScimGroup, HttpResponse> {
+    match state.repository.find_scim_group(client.name(), id).await {
+        Ok(group) => Ok(group),
+        Err(sqlx::Error::RowNotFound) => Err(unknown_group(id)),
+        Err(err) => Err(database_error(err)),
+    }
+}
+
+async fn member_ids(state: &AppState, group_id: &str) -> Result<Vec<String>, HttpResponse> {
+    match state
+        .repository
+        .list_scim_group_members(&[group_id.to_owned()])
+        .await
+    {
+        Ok(members) => Ok(members.into_iter().map(|member| member.user_id).collect()),
+        Err(err) => Err(database_error(err)),
+    }
+}
+
+/// Give the users the roles mapped from the groups of the client they are a member of,
+/// once their memberships changed.
+async fn sync_roles(
+    state: &AppState,
+    client: &ScimClient,
+    user_ids: impl IntoIterator<Item = String>,
+) -> Result<(), HttpResponse> {
+    let user_ids: BTreeSet<String> = user_ids.into_iter().collect();
+    for user_id in user_ids {
+        let user = match state.repository.find_scim_user(&user_id).await {
+            Ok(user) => user,
+            Err(sqlx::Error::RowNotFound) => continue,
+            Err(err) => return Err(database_error(err)),
+        };
+        let groups = state
+            .repository
+            .list_user_scim_groups(client.name(), &user.id)
+            .await
+            .map_err(database_error)?;
+
+        let roles = client.roles(&user.role, groups.iter().map(String::as_str));
+        if roles != user.role {
+            if let Err(err) = state.repository.update_user_roles(&user.id, &roles).await {
+                log::error!(
+                    "Roles {:?} of {} could not be granted : {:?}",
+                    roles,
+                    client.name(),
+                    err
+                );
+                return Err(database_error(err));
+            }
+            state.access_control.invalidate_cache().await;
+        }
+    }
+    Ok(())
+}
+
+/// Group with its members, once changed.
+async fn group_response(
+    state: &AppState,
+    client: &ScimClient,
+    req: &HttpRequest,
+    id: &str,
+    status: StatusCode,
+) -> HttpResponse {
+    let group = match find_group(state, client, id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    match state
+        .repository
+        .list_scim_group_members(std::slice::from_ref(&group.id))
+        .await
+    {
+        Ok(members) => scim_response(status, resource(req, &group, Some(&members))),
+        Err(err) => database_error(err),
+    }
+}
+
+/// Groups of the client, filtered by `displayName` or `externalId`.
+#[get("/Groups")]
+pub async fn list_scim_groups(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(err) => return error_response(err),
+    };
+    let (display_name, external_id) = match &filter {
+        None => (None, None),
+        Some(filter) => match filter.attribute.as_str() {
+            "displayname" => (Some(filter.value.as_str()), None),
+            "externalid" => (None, Some(filter.value.as_str())),
+            _ => {
+                return error_response(ScimError::bad_request(
+                    "invalidFilter",
+                    format!("Groups are not filtered by {}", filter.attribute),
+                ))
+            }
+        },
+    };
+
+    let (groups, total) = match state
+        .repository
+        .list_scim_groups(
+            client.name(),
+            display_name,
+            external_id,
+            query.offset(),
+            query.limit(),
+        )
+        .await
+    {
+        Ok(groups) => groups,
+        Err(err) => return database_error(err),
+    };
+    // Members of large groups are left out by clients listing groups.
+    let members = if query.excludes("members") {
+        None
+    } else {
+        let ids: Vec<String> = groups.iter().map(|group| group.id.clone()).collect();
+        match state.repository.list_scim_group_members(&ids).await {
+            Ok(members) => Some(members),
+            Err(err) => return database_error(err),
+        }
+    };
+
+    let resources = groups
+        .iter()
+        .map(|group| resource(&req, group, members.as_deref()))
+        .collect();
+    scim_response(StatusCode::OK, query.response(resources, total))
+}
+
+#[get("/Groups/{id}")]
+pub async fn get_scim_group(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    if !query.excludes("members") {
+        return group_response(&state, client, &req, &id, StatusCode::OK).await;
+    }
+
+    match find_group(&state, client, &id).await {
+        Ok(group) => scim_response(StatusCode::OK, resource(&req, &group, None)),
+        Err(response) => response,
+    }
+}
+
+#[post("/Groups")]
+pub async fn create_scim_group(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<GroupResource>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    if let Err(err) = body.validate() {
+        return error_response(err);
+    }
+
+    let members = body.member_ids();
+    let saved = state
+        .repository
+        .save_scim_group(
+            client.name(),
+            &body.display_name,
+            body.external_id.as_deref(),
+            &members,
+        )
+        .await;
+    let group = match saved {
+        Ok(group) => group,
+        Err(err) => return group_error(err, &body.display_name),
+    };
+    if let Err(response) = sync_roles(&state, client, members).await {
+        return response;
+    }
+
+    let mut response = group_response(&state, client, &req, &group.id, StatusCode::CREATED).await;
+    if let Ok(location) = location(&req, &group.id).parse() {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+#[put("/Groups/{id}")]
+pub async fn replace_scim_group(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<GroupResource>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    if let Err(err) = body.validate() {
+        return error_response(err);
+    }
+    let group = match find_group(&state, client, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let previous_members = match member_ids(&state, &group.id).await {
+        Ok(members) => members,
+        Err(response) => return response,
+    };
+
+    let members = body.member_ids();
+    let replaced = state
+        .repository
+        .update_scim_group(
+            client.name(),
+            &group.id,
+            &body.display_name,
+            body.external_id.as_deref(),
+        )
+        .await;
+    let replaced = match replaced {
+        Ok(()) => {
+            state
+                .repository
+                .replace_scim_group_members(&group.id, &members)
+                .await
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = replaced {
+        return group_error(err, &body.display_name);
+    }
+    if let Err(response) =
+        sync_roles(&state, client, previous_members.into_iter().chain(members)).await
+    {
+        return response;
+    }
+
+    group_response(&state, client, &req, &group.id, StatusCode::OK).await
+}
+
+#[patch("/Groups/{id}")]
+pub async fn patch_scim_group(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<PatchRequest>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let patch = match GroupPatch::from_request(&body) {
+        Ok(patch) => patch,
+        Err(err) => return error_response(err),
+    };
+    let group = match find_group(&state, client, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let previous_members = match member_ids(&state, &group.id).await {
+        Ok(members) => members,
+        Err(response) => return response,
+    };
+
+    let display_name = patch
+        .display_name
+        .clone()
+        .unwrap_or_else(|| group.display_name.clone());
+    if patch.display_name.is_some() || patch.external_id.is_some() {
+        let external_id = match &patch.external_id {
+            Some(external_id) => external_id.as_deref(),
+            None => group.external_id.as_deref(),
+        };
+        if let Err(err) = state
+            .repository
+            .update_scim_group(client.name(), &group.id, &display_name, external_id)
+            .await
+        {
+            return group_error(err, &display_name);
+        }
+    }
+
+    let mut changed_members: Vec<String> = Vec::new();
+    for change in &patch.members {
+        let (changed, ids) = match change {
+            MembersChange::Add(ids) => (
+                state
+                    .repository
+                    .add_scim_group_members(&group.id, ids)
+                    .await,
+                ids,
+            ),
+            MembersChange::Remove(ids) => (
+                state
+                    .repository
+                    .remove_scim_group_members(&group.id, ids)
+                    .await,
+                ids,
+            ),
+            MembersChange::Replace(ids) => (
+                state
+                    .repository
+                    .replace_scim_group_members(&group.id, ids)
+                    .await,
+                ids,
+            ),
+        };
+        if let Err(err) = changed {
+            return group_error(err, &display_name);
+        }
+        changed_members.extend(ids.iter().cloned());
+    }
+    if let Err(response) = sync_roles(
+        &state,
+        client,
+        previous_members.into_iter().chain(changed_members),
+    )
+    .await
+    {
+        return response;
+    }
+
+    group_response(&state, client, &req, &group.id, StatusCode::OK).await
+}
+
+/// Delete the group, its members losing the roles it gave them.
+#[delete("/Groups/{id}")]
+pub async fn delete_scim_group(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let group = match find_group(&state, client, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+    let members = match member_ids(&state, &group.id).await {
+        Ok(members) => members,
+        Err(response) => return response,
+    };
+
+    match state
+        .repository
+        .delete_scim_group(client.name(), &group.id)
+        .await
+    {
+        Ok(()) => {}
+        Err(sqlx::Error::RowNotFound) => return unknown_group(&group.id),
+        Err(err) => return database_error(err),
+    }
+    if let Err(response) = sync_roles(&state, client, members).await {
+        return response;
+    }
+    HttpResponse::NoContent().finish()
+}
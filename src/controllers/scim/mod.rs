@@ -0,0 +1,99 @@
+use crate::controllers::{public_base_url, AppState};
+use crate::services::crypto::TokenConfig;
+use crate::services::scim::{ScimClient, ScimError, SCIM_CONTENT_TYPE};
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{error, web, HttpRequest, HttpResponse, Scope};
+use discovery_controller::{
+    get_scim_resource_type, get_scim_schema, list_scim_resource_types, list_scim_schemas,
+    scim_service_provider_config,
+};
+use group_controller::{
+    create_scim_group, delete_scim_group, get_scim_group, list_scim_groups, patch_scim_group,
+    replace_scim_group,
+};
+use serde_json::Value;
+use user_controller::{
+    create_scim_user, delete_scim_user, get_scim_user, list_scim_users, patch_scim_user,
+    replace_scim_user,
+};
+
+pub mod discovery_controller;
+pub mod group_controller;
+pub mod user_controller;
+
+/// SCIM 2.0 provisioning endpoints, for identity providers to push users and groups.
+pub fn get_scim_service() -> Scope {
+    web::scope("/scim/v2")
+        // `application/scim+json` bodies are JSON ones, errors are answered as SCIM errors.
+        .app_data(web::JsonConfig::default().error_handler(|err, _| {
+            let response = error_response(ScimError::bad_request("invalidSyntax", err.to_string()));
+            error::InternalError::from_response(err, response).into()
+        }))
+        .service(scim_service_provider_config)
+        .service(list_scim_resource_types)
+        .service(get_scim_resource_type)
+        .service(list_scim_schemas)
+        .service(get_scim_schema)
+        .service(list_scim_users)
+        .service(get_scim_user)
+        .service(create_scim_user)
+        .service(replace_scim_user)
+        .service(patch_scim_user)
+        .service(delete_scim_user)
+        .service(list_scim_groups)
+        .service(get_scim_group)
+        .service(create_scim_group)
+        .service(replace_scim_group)
+        .service(patch_scim_group)
+        .service(delete_scim_group)
+}
+
+pub(crate) fn scim_response(status: StatusCode, body: Value) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(SCIM_CONTENT_TYPE)
+        .json(body)
+}
+
+pub(crate) fn error_response(err: ScimError) -> HttpResponse {
+    let status = StatusCode::from_u16(err.status).unwrap_or(StatusCode::BAD_REQUEST);
+    scim_response(status, err.body())
+}
+
+pub(crate) fn database_error(err: sqlx::Error) -> HttpResponse {
+    log::error!("{:?}", err);
+    error_response(ScimError {
+        status: 500,
+        scim_type: None,
+        detail: String::from("Internal server error"),
+    })
+}
+
+/// Provisioning client the request is authenticated as.
+pub(crate) fn scim_client<'a>(
+    state: &'a AppState,
+    req: &HttpRequest,
+) -> Result<&'a ScimClient, HttpResponse> {
+    let authorization = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    state
+        .scim_clients
+        .authenticate(authorization)
+        .ok_or_else(|| {
+            let mut response = error_response(ScimError {
+                status: 401,
+                scim_type: None,
+                detail: String::from("Unauthorized"),
+            });
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            response
+        })
+}
+
+pub(crate) fn base_url(req: &HttpRequest) -> String {
+    public_base_url(&TokenConfig::from_env(), req)
+}
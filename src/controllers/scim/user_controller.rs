@@ -0,0 +1,317 @@
+use crate::config::roles::Role;
+use crate::controllers::scim::{
+    base_url, database_error, error_response, scim_client, scim_response,
+};
+use crate::controllers::AppState;
+use crate::repository::scim_repository::{NewScimUser, ScimUser};
+use crate::services::crypto::{Hash, HashService};
+use crate::services::scim::{
+    user_resource, ListQuery, PatchRequest, ScimClient, ScimError, UserAttributes, UserResource,
+};
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+
+fn location(req: &HttpRequest, id: &str) -> String {
+    format!("{}/scim/v2/Users/{}", base_url(req), id)
+}
+
+fn attributes(user: &ScimUser) -> UserAttributes {
+    UserAttributes {
+        email: user.email.clone(),
+        name: user.name.clone(),
+        external_id: user.external_id.clone(),
+        active: user.active,
+    }
+}
+
+fn resource(req: &HttpRequest, user: &ScimUser) -> serde_json::Value {
+    user_resource(&user.id, &attributes(user), &location(req, &user.id))
+}
+
+fn unknown_user(id: &str) -> HttpResponse {
+    error_response(ScimError::not_found(format!("Unknown user {}", id)))
+}
+
+fn email_taken(email: &str) -> HttpResponse {
+    error_response(ScimError::conflict(format!(
+        "A user already has the email {}",
+        email
+    )))
+}
+
+fn hashed_password(password: Option<&str>) -> Result<String, HttpResponse> {
+    match password.filter(|password| !password.is_empty()) {
+        Some(password) => HashService::hash_password(password).map_err(|err| {
+            log::error!("{:?}", err);
+            error_response(ScimError::bad_request("invalidValue", "Invalid password"))
+        }),
+        // The empty hash never matches a password.
+        None => Ok(String::new()),
+    }
+}
+
+/// User of the request, which clients may manage : super administrators are not.
+async fn managed_user(state: &AppState, id: &str) -> Result<ScimUser, HttpResponse> {
+    match state.repository.find_scim_user(id).await {
+        Ok(user) if user.role.contains(&Role::SUPER_ADMIN.to_string()) => {
+            Err(error_response(ScimError {
+                status: 403,
+                scim_type: None,
+                detail: String::from("Super administrators are not managed by provisioning"),
+            }))
+        }
+        Ok(user) => Ok(user),
+        Err(sqlx::Error::RowNotFound) => Err(unknown_user(id)),
+        Err(err) => Err(database_error(err)),
+    }
+}
+
+/// Save the attributes of `user`, deactivating them with a soft deletion.
+async fn save_user(
+    state: &AppState,
+    client: &ScimClient,
+    user: &ScimUser,
+    changes: &UserAttributes,
+) -> Result<(), HttpResponse> {
+    let saved = state
+        .repository
+        .update_scim_user(
+            client.name(),
+            &user.id,
+            &changes.email,
+            changes.name.as_deref(),
+            changes.external_id.as_deref(),
+        )
+        .await;
+    match saved {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            return Err(email_taken(&changes.email))
+        }
+        Err(err) => return Err(database_error(err)),
+    }
+
+    let activation = match (user.active, changes.active) {
+        (true, false) => state.repository.soft_delete_user(&user.id).await,
+        (false, true) => state.repository.remove_soft_deletion_user(&user.id).await,
+        _ => return Ok(()),
+    };
+    activation.map_err(database_error)?;
+    // Tokens of a deactivated user must not keep their cached grants.
+    state.access_control.invalidate_cache().await;
+    Ok(())
+}
+
+/// Users, filtered by `userName` or `externalId`.
+#[get("/Users")]
+pub async fn list_scim_users(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
+    if let Err(response) = scim_client(&state, &req) {
+        return response;
+    }
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(err) => return error_response(err),
+    };
+    let (email, external_id) = match &filter {
+        None => (None, None),
+        Some(filter) => match filter.attribute.as_str() {
+            "username" | "emails" | "emails.value" => (Some(filter.value.as_str()), None),
+            "externalid" => (None, Some(filter.value.as_str())),
+            _ => {
+                return error_response(ScimError::bad_request(
+                    "invalidFilter",
+                    format!("Users are not filtered by {}", filter.attribute),
+                ))
+            }
+        },
+    };
+
+    match state
+        .repository
+        .list_scim_users(email, external_id, query.offset(), query.limit())
+        .await
+    {
+        Ok((users, total)) => {
+            let resources = users.iter().map(|user| resource(&req, user)).collect();
+            scim_response(StatusCode::OK, query.response(resources, total))
+        }
+        Err(err) => database_error(err),
+    }
+}
+
+#[get("/Users/{id}")]
+pub async fn get_scim_user(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = scim_client(&state, &req) {
+        return response;
+    }
+
+    match state.repository.find_scim_user(&id).await {
+        Ok(user) => scim_response(StatusCode::OK, resource(&req, &user)),
+        Err(sqlx::Error::RowNotFound) => unknown_user(&id),
+        Err(err) => database_error(err),
+    }
+}
+
+#[post("/Users")]
+pub async fn create_scim_user(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<UserResource>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let attributes = match body.attributes() {
+        Ok(attributes) => attributes,
+        Err(err) => return error_response(err),
+    };
+    let password = match hashed_password(body.password.as_deref()) {
+        Ok(password) => password,
+        Err(response) => return response,
+    };
+
+    let saved = state
+        .repository
+        .save_scim_user(
+            client.name(),
+            NewScimUser {
+                email: &attributes.email,
+                password: &password,
+                name: attributes.name.as_deref(),
+                external_id: attributes.external_id.as_deref(),
+                role: vec![Role::USER.to_string()],
+            },
+        )
+        .await;
+    let mut user = match saved {
+        Ok(user) => user,
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            return email_taken(&attributes.email)
+        }
+        Err(err) => return database_error(err),
+    };
+    if !attributes.active {
+        if let Err(err) = state.repository.soft_delete_user(&user.id).await {
+            return database_error(err);
+        }
+        state.access_control.invalidate_cache().await;
+        user.active = false;
+    }
+
+    let mut response = scim_response(StatusCode::CREATED, resource(&req, &user));
+    if let Ok(location) = location(&req, &user.id).parse() {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+#[put("/Users/{id}")]
+pub async fn replace_scim_user(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<UserResource>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let user = match managed_user(&state, &id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let changes = match body.attributes() {
+        Ok(changes) => changes,
+        Err(err) => return error_response(err),
+    };
+
+    if let Err(response) = save_user(&state, client, &user, &changes).await {
+        return response;
+    }
+    if body
+        .password
+        .as_deref()
+        .is_some_and(|password| !password.is_empty())
+    {
+        let password = match hashed_password(body.password.as_deref()) {
+            Ok(password) => password,
+            Err(response) => return response,
+        };
+        if let Err(err) = state
+            .repository
+            .update_user_password(&user.id, &password)
+            .await
+        {
+            return database_error(err);
+        }
+    }
+
+    scim_response(
+        StatusCode::OK,
+        user_resource(&user.id, &changes, &location(&req, &user.id)),
+    )
+}
+
+#[patch("/Users/{id}")]
+pub async fn patch_scim_user(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<PatchRequest>,
+) -> impl Responder {
+    let client = match scim_client(&state, &req) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let user = match managed_user(&state, &id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let mut changes = attributes(&user);
+    if let Err(err) = changes.patch(&body) {
+        return error_response(err);
+    }
+
+    if let Err(response) = save_user(&state, client, &user, &changes).await {
+        return response;
+    }
+    scim_response(
+        StatusCode::OK,
+        user_resource(&user.id, &changes, &location(&req, &user.id)),
+    )
+}
+
+/// Soft delete the user, who is no longer shown to clients.
+#[delete("/Users/{id}")]
+pub async fn delete_scim_user(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = scim_client(&state, &req) {
+        return response;
+    }
+    let user = match managed_user(&state, &id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if let Err(err) = state.repository.soft_delete_user(&user.id).await {
+        return database_error(err);
+    }
+    state.access_control.invalidate_cache().await;
+    if let Err(err) = state.repository.forget_scim_user(&user.id).await {
+        return database_error(err);
+    }
+    HttpResponse::NoContent().finish()
+}
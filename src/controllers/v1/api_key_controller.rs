@@ -0,0 +1,115 @@
+use crate::controllers::v1::grant_controller::session_claims;
+use crate::controllers::{AppState, CustomResponse};
+use crate::repository::api_key_repository::ApiKey;
+use crate::services::authenticator::generate_api_key;
+use crate::services::identity_provider::recently_authenticated;
+use crate::services::oauth::hash_token;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const MAX_API_KEY_NAME_LENGTH: usize = 64;
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiKeyBody {
+    name: String,
+    /// The key never expires when not set.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewApiKeyResponse {
+    /// Only shown once.
+    key: String,
+    #[serde(flatten)]
+    api_key: ApiKey,
+}
+
+fn internal_error(err: sqlx::Error) -> HttpResponse {
+    log::error!("{:?}", err);
+    HttpResponse::InternalServerError().json(CustomResponse {
+        message: String::from("Internal server error"),
+    })
+}
+
+/// API keys of the user, they sign in with them on `login`.
+#[get("/me/api-keys")]
+pub async fn list_api_keys(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    match state.repository.list_api_keys(&user_id).await {
+        Ok(api_keys) => HttpResponse::Ok().json(api_keys),
+        Err(err) => internal_error(err),
+    }
+}
+
+/// Create an API key, the session must be recent.
+#[post("/me/api-keys")]
+pub async fn save_api_key(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ApiKeyBody>,
+) -> impl Responder {
+    let claims = match session_claims(&req) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let recent = DateTime::from_timestamp(claims.iat as i64, 0)
+        .is_some_and(|auth_time| recently_authenticated(auth_time, Utc::now()));
+    if !recent {
+        return HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Sign in again to create an API key"),
+        });
+    }
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_API_KEY_NAME_LENGTH {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: format!(
+                "The name must have between 1 and {} characters",
+                MAX_API_KEY_NAME_LENGTH
+            ),
+        });
+    }
+    if body.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("The expiration must be in the future"),
+        });
+    }
+
+    let key = generate_api_key();
+    match state
+        .repository
+        .save_api_key(&claims.sub, name, &hash_token(&key), body.expires_at)
+        .await
+    {
+        Ok(api_key) => HttpResponse::Created().json(NewApiKeyResponse { key, api_key }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[delete("/me/api-keys/{id}")]
+pub async fn delete_api_key(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    match state.repository.delete_api_key(&user_id, &id).await {
+        Ok(()) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("API key deleted successfully!"),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("API key not found"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
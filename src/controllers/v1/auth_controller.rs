@@ -1,17 +1,65 @@
+use crate::controllers::oauth::session::session_cookie;
+use crate::controllers::v1::phone_controller::send_sms_code;
 use crate::controllers::{AppState, CustomResponse};
-use crate::services::crypto::Hash;
+use crate::repository::phone_repository::{NewSmsCode, UserPhone};
+use crate::repository::user_repository::User;
+use crate::services::authenticator::Credentials;
+use crate::services::claims::ClaimsSubject;
 use crate::services::crypto::Jwt;
-use crate::services::crypto::{CSRFTokenService, HashService, JwtService};
+use crate::services::crypto::{CSRFTokenService, JwtService, TokenConfig, TokenOptions};
+use crate::services::identity_provider::EXTERNAL_LOGIN_TTL_SECONDS;
+use crate::services::oauth::{generate_token, hash_token};
+use crate::services::sms_code::{
+    code_matches, hash_code, login_sms, masked_phone_number, PendingLoginChallenge,
+    CODE_TTL_SECONDS, MAX_CODE_ATTEMPTS, PURPOSE_LOGIN,
+};
 use actix_web::http::header::{HeaderValue, SET_COOKIE};
 use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
 use cookie::time::{Duration, OffsetDateTime};
-use cookie::{Cookie, Expiration, SameSite};
+use cookie::{Cookie, SameSite};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct LoginBody {
+    #[serde(default)]
     email: String,
+    #[serde(default)]
     password: String,
+    /// Instead of the email and the password.
+    #[serde(default)]
+    api_key: Option<String>,
+    /// ID token issued by `provider` for a `nonce` of `login/nonce`, instead of the email and
+    /// the password.
+    #[serde(default)]
+    provider: Option<String>,
+    #[serde(default)]
+    id_token: Option<String>,
+    #[serde(default)]
+    nonce: Option<String>,
+    /// Realm whose authenticators check the credentials, the default one when not set.
+    #[serde(default)]
+    realm: Option<String>,
+    /// Audience of the token, the default one when not set.
+    #[serde(default)]
+    audience: Option<String>,
+}
+
+impl LoginBody {
+    fn credentials(&self) -> Credentials<'_> {
+        match (&self.api_key, &self.id_token) {
+            (Some(api_key), _) => Credentials::ApiKey(api_key),
+            (None, Some(id_token)) => Credentials::IdToken {
+                provider: self.provider.as_deref().unwrap_or_default(),
+                id_token,
+                nonce: self.nonce.as_deref().unwrap_or_default(),
+            },
+            (None, None) => Credentials::Password {
+                email: &self.email,
+                password: &self.password,
+            },
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -21,51 +69,286 @@ pub struct LoginResponse {
     role: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct MfaChallengeResponse {
+    mfa_required: bool,
+    /// Sent with the code to `login/mfa`.
+    challenge: String,
+    channel: String,
+    /// Masked, only its first and last characters are shown.
+    phone_number: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MfaBody {
+    challenge: String,
+    code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginNonceBody {
+    provider: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginNonceResponse {
+    nonce: String,
+    expires_in: i64,
+}
+
+/// Audience asked on `login` and the lifetime of its tokens, the default audience when none
+/// is asked. Tokens of the OAuth audience are only delegated to clients, with consent.
+pub fn login_audience(
+    config: &TokenConfig,
+    audience: Option<&str>,
+) -> Result<(String, u64), HttpResponse> {
+    let audience = audience.unwrap_or(&config.audience);
+    let lifetime = match config.lifetime(audience) {
+        Some(lifetime) if audience == config.audience || audience != config.oauth_audience => {
+            lifetime
+        }
+        _ => {
+            return Err(HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("Unknown audience"),
+            }))
+        }
+    };
+
+    Ok((audience.to_owned(), lifetime))
+}
+
+/// Sign in. Users with a verified phone number who use a password also need the code sent
+/// to it : the answer is then a challenge, completed on `login/mfa`.
 #[post("/login")]
 pub async fn login(state: web::Data<AppState>, body: web::Json<LoginBody>) -> impl Responder {
-    let user = match state.repository.find_user_by_email(&body.email).await {
-        Ok(user) => user,
-        Err(err) => {
-            log::error!("{:?}", err);
+    if let Some(realm) = body.realm.as_deref() {
+        if !state.authenticators.has_realm(realm) {
             return HttpResponse::BadRequest().json(CustomResponse {
-                message: String::from("Check your information"),
+                message: String::from("Unknown realm"),
             });
         }
+    }
+
+    let config = TokenConfig::from_env();
+    let (audience, lifetime) = match login_audience(&config, body.audience.as_deref()) {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+
+    let credentials = body.credentials();
+    let user = match state
+        .authenticators
+        .authenticate(body.realm.as_deref(), &credentials)
+        .await
+    {
+        Some(user) => user,
+        None => {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("Check your information"),
+            })
+        }
     };
 
-    match HashService::check_password(&body.password, &user.password) {
-        Ok(valid) => {
-            if !valid {
-                return HttpResponse::BadRequest().json(CustomResponse {
-                    message: String::from("Check your information"),
+    // API keys and ID tokens are not asked for a second factor.
+    if let Credentials::Password { .. } = credentials {
+        match state.repository.find_user_phone(&user.id).await {
+            Ok(UserPhone {
+                phone_number,
+                verified_at: Some(_),
+            }) => return login_challenge(&state, &user.id, &phone_number, &audience).await,
+            Ok(_) | Err(sqlx::Error::RowNotFound) => {}
+            Err(err) => {
+                log::error!("{:?}", err);
+                return HttpResponse::InternalServerError().json(CustomResponse {
+                    message: String::from("Internal server error"),
                 });
             }
         }
+    }
+
+    login_response(&state, user, audience, lifetime).await
+}
+
+/// Save a login code and send it to the verified phone of the user, the challenge the code
+/// completes, the answer to give otherwise.
+pub(crate) async fn send_login_challenge(
+    state: &AppState,
+    user_id: &str,
+    phone_number: &str,
+    audience: &str,
+) -> Result<String, HttpResponse> {
+    let pending = PendingLoginChallenge::generate();
+    let code = NewSmsCode {
+        user_id,
+        purpose: PURPOSE_LOGIN,
+        phone_number,
+        code_hash: &hash_code(user_id, &pending.code),
+        challenge_hash: Some(&hash_token(&pending.challenge)),
+        audience: Some(audience),
+        expires_at: Utc::now() + chrono::Duration::seconds(CODE_TTL_SECONDS),
+    };
+    send_sms_code(state, code, &login_sms(phone_number, &pending.code)).await?;
+
+    Ok(pending.challenge)
+}
+
+/// Send a code to the verified phone of the user, the answer of `login` is the challenge
+/// the code completes on `login/mfa`.
+async fn login_challenge(
+    state: &AppState,
+    user_id: &str,
+    phone_number: &str,
+    audience: &str,
+) -> HttpResponse {
+    match send_login_challenge(state, user_id, phone_number, audience).await {
+        Ok(challenge) => HttpResponse::Accepted().json(MfaChallengeResponse {
+            mfa_required: true,
+            challenge,
+            channel: String::from("sms"),
+            phone_number: masked_phone_number(phone_number),
+        }),
+        Err(response) => response,
+    }
+}
+
+/// User of the login challenge the code completes, and the audience they asked for. `None`
+/// when the code is wrong, or the challenge expired or ran out of attempts.
+pub(crate) async fn complete_login_challenge(
+    state: &AppState,
+    challenge: &str,
+    code: &str,
+) -> Result<Option<(User, Option<String>)>, sqlx::Error> {
+    let sms_code = match state
+        .repository
+        .attempt_login_challenge(&hash_token(challenge), MAX_CODE_ATTEMPTS)
+        .await
+    {
+        Ok(sms_code) => sms_code,
+        Err(sqlx::Error::RowNotFound) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if !code_matches(&sms_code.code_hash, &sms_code.user_id, code) {
+        return Ok(None);
+    }
+    state
+        .repository
+        .delete_sms_code(&sms_code.user_id, PURPOSE_LOGIN)
+        .await?;
+
+    match state.repository.find_user_by_id(&sms_code.user_id).await {
+        Ok(user) => Ok(Some((user, sms_code.audience))),
+        Err(sqlx::Error::RowNotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Nonce to ask an ID token of the provider for, before a `login` with it. It is accepted
+/// once, for the provider only.
+#[post("/login/nonce")]
+pub async fn login_nonce(
+    state: web::Data<AppState>,
+    body: web::Json<LoginNonceBody>,
+) -> impl Responder {
+    match state.identity_providers.get(&body.provider) {
+        Some(provider) if provider.is_openid() => {}
+        _ => {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("Unknown provider"),
+            })
+        }
+    }
+
+    let nonce = generate_token();
+    let expires_at = Utc::now() + chrono::Duration::seconds(EXTERNAL_LOGIN_TTL_SECONDS);
+    match state
+        .repository
+        .save_login_nonce(&hash_token(&nonce), &body.provider, expires_at)
+        .await
+    {
+        Ok(()) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(LoginNonceResponse {
+                nonce,
+                expires_in: EXTERNAL_LOGIN_TTL_SECONDS,
+            }),
         Err(err) => {
             log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+/// Second step of a `login` which answered with a challenge : the code sent by SMS.
+#[post("/login/mfa")]
+pub async fn login_mfa(state: web::Data<AppState>, body: web::Json<MfaBody>) -> impl Responder {
+    let (user, audience) = match complete_login_challenge(&state, &body.challenge, &body.code).await
+    {
+        Ok(Some(completed)) => completed,
+        Ok(None) => {
             return HttpResponse::BadRequest().json(CustomResponse {
-                message: String::from("Check your information"),
+                message: String::from("Invalid or expired code"),
+            })
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
             });
         }
     };
+    let config = TokenConfig::from_env();
+    let (audience, lifetime) = match login_audience(&config, audience.as_deref()) {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
 
-    let token = JwtService::generate_jwt(&user.email)
-        .map_err(|err| {
+    login_response(&state, user, audience, lifetime).await
+}
+
+/// Token and session cookie of a user who signed in.
+async fn login_response(
+    state: &AppState,
+    user: User,
+    audience: String,
+    lifetime: u64,
+) -> HttpResponse {
+    let subject = ClaimsSubject {
+        user_id: user.id.clone(),
+        email: user.email.clone(),
+        roles: user.role.clone(),
+        audience: audience.clone(),
+    };
+    let claims = match state.claims_enricher.enrich(&subject).await {
+        Ok(claims) => claims,
+        Err(err) => {
             log::error!("{:?}", err);
-            HttpResponse::BadRequest().json(CustomResponse {
-                message: err.to_string(),
-            })
-        })
-        .unwrap();
+            return HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            });
+        }
+    };
 
-    let expiration_date = OffsetDateTime::now_utc() + Duration::days(20);
-    let cookie = cookie::Cookie::build(("Authorization", &token))
-        .path("/")
-        .secure(true)
-        .http_only(true)
-        .same_site(SameSite::Strict)
-        .expires(Expiration::DateTime(expiration_date))
-        .build();
+    let options = TokenOptions {
+        audience: Some(audience),
+        roles: user.role.clone(),
+        scope: None,
+        client_id: None,
+        claims,
+        lifetime: None,
+    };
+    let token = match JwtService::generate_jwt_with(&user.id, &options) {
+        Ok(token) => token,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            });
+        }
+    };
+
+    let cookie = session_cookie(&token, lifetime);
 
     HttpResponse::Ok()
         .append_header((SET_COOKIE, cookie.to_string()))
@@ -103,7 +386,7 @@ pub async fn check_token(state: web::Data<AppState>, req: HttpRequest) -> impl R
         }
     };
 
-    match state.repository.find_user_by_email(&claims.sub).await {
+    match state.repository.find_user_by_id(&claims.sub).await {
         Ok(_) => HttpResponse::Ok().json(CustomResponse {
             message: String::from("Authorized"),
         }),
@@ -146,7 +429,7 @@ pub async fn check_cookie(state: web::Data<AppState>, req: HttpRequest) -> impl
         })
         .unwrap();
 
-    match state.repository.find_user_by_email(&claims.sub).await {
+    match state.repository.find_user_by_id(&claims.sub).await {
         Ok(_) => HttpResponse::Ok().json(CustomResponse {
             message: String::from("Authorized"),
         }),
@@ -190,16 +473,16 @@ pub async fn logout(_: web::Data<AppState>, req: HttpRequest) -> impl Responder
             }
             Err(err) => {
                 log::error!("{:?}", err);
-                return HttpResponse::Unauthorized().json(CustomResponse {
+                HttpResponse::Unauthorized().json(CustomResponse {
                     message: err.to_string(),
-                });
+                })
             }
         },
         Err(err) => {
             log::error!("{:?}", err);
-            return HttpResponse::InternalServerError().json(CustomResponse {
+            HttpResponse::InternalServerError().json(CustomResponse {
                 message: err.to_string(),
-            });
+            })
         }
     }
 }
@@ -0,0 +1,302 @@
+use crate::config::permissions::{AUTHZ_CHECK, AUTHZ_WRITE};
+use crate::controllers::v1::user_controller::{policy_resource, user_policy_context};
+use crate::controllers::{AppState, CustomResponse};
+use crate::services::access_control::Authorization::{Authorized, Unauthorized};
+use crate::services::access_control::{AccessControl, Authorization, GrantAccess};
+use crate::services::authz::{ConsistencyToken, ExpandNode, ObjectRef, RelationTuple};
+use crate::services::policy::PolicyDecision;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct CheckBody {
+    #[serde(flatten)]
+    tuple: RelationTuple,
+    consistency_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CheckResponse {
+    allowed: bool,
+    consistency_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExpandBody {
+    object: ObjectRef,
+    relation: String,
+    consistency_token: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ExpandResponse {
+    tree: ExpandNode,
+    consistency_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WriteBody {
+    #[serde(default)]
+    writes: Vec<RelationTuple>,
+    #[serde(default)]
+    deletes: Vec<RelationTuple>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WriteResponse {
+    consistency_token: String,
+}
+
+const MAX_BATCH_ITEMS: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchCheckItem {
+    action: String,
+    /// Email of the user for the `user:*` actions, as their endpoints take it.
+    resource: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchCheckBody {
+    token: String,
+    items: Vec<BatchCheckItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchCheckDecision {
+    action: String,
+    resource: String,
+    allowed: bool,
+    reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchCheckResponse {
+    subject: String,
+    decisions: Vec<BatchCheckDecision>,
+}
+
+/// Revision to evaluate at : the latest one, which must not be older than the token.
+async fn resolve_revision(state: &AppState, token: Option<&str>) -> Result<i64, HttpResponse> {
+    let requested = match token.map(ConsistencyToken::decode).transpose() {
+        Ok(requested) => requested,
+        Err(err) => {
+            return Err(HttpResponse::BadRequest().json(CustomResponse {
+                message: err.to_string(),
+            }))
+        }
+    };
+
+    let current = state
+        .repository
+        .current_authz_revision()
+        .await
+        .map_err(|err| {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        })?;
+
+    match requested {
+        Some(requested) if requested > current => {
+            Err(HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("Unknown consistency token"),
+            }))
+        }
+        _ => Ok(current),
+    }
+}
+
+#[post("/authz/check")]
+pub async fn check(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<CheckBody>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), AUTHZ_CHECK)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    let revision = match resolve_revision(&state, body.consistency_token.as_deref()).await {
+        Ok(revision) => revision,
+        Err(res) => return res,
+    };
+
+    match state
+        .relation_authz
+        .check(state.repository.as_ref(), &body.tuple, revision)
+        .await
+    {
+        Ok(allowed) => HttpResponse::Ok().json(CheckResponse {
+            allowed,
+            consistency_token: ConsistencyToken::encode(revision),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[post("/authz/expand")]
+pub async fn expand(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ExpandBody>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), AUTHZ_CHECK)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    let revision = match resolve_revision(&state, body.consistency_token.as_deref()).await {
+        Ok(revision) => revision,
+        Err(res) => return res,
+    };
+
+    match state
+        .relation_authz
+        .expand(state.repository.as_ref(), &body.object, &body.relation, revision)
+        .await
+    {
+        Ok(tree) => HttpResponse::Ok().json(ExpandResponse {
+            tree,
+            consistency_token: ConsistencyToken::encode(revision),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[post("/authz/write")]
+pub async fn write(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<WriteBody>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), AUTHZ_WRITE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    for tuple in body.writes.iter().chain(body.deletes.iter()) {
+        if let Err(err) = state.relation_authz.validate_tuple(tuple) {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: err.to_string(),
+            });
+        }
+    }
+
+    match state
+        .repository
+        .write_tuples(&body.writes, &body.deletes)
+        .await
+    {
+        Ok(revision) => HttpResponse::Ok().json(WriteResponse {
+            consistency_token: ConsistencyToken::encode(revision),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[post("/authz/batch-check")]
+pub async fn batch_check(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<BatchCheckBody>,
+) -> impl Responder {
+    if body.items.len() > MAX_BATCH_ITEMS {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: format!("At most {} items can be checked at once", MAX_BATCH_ITEMS),
+        });
+    }
+
+    let token = body.token.strip_prefix("Bearer ").unwrap_or(&body.token);
+    let grants = match state.access_control.grants_for_token(token).await {
+        Ok(grants) => grants,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            });
+        }
+    };
+
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let now = chrono::Utc::now();
+    // Service accounts have no user, the policies deny them as the endpoints do.
+    let subject = state.repository.find_user_by_id(&grants.user_id).await.ok();
+
+    let mut decisions = Vec::with_capacity(body.items.len());
+    for item in &body.items {
+        let (allowed, reason) =
+            match AccessControl::from_permissions(&grants.permissions, &item.action) {
+                Authorization::Unauthorized(_) => (false, String::from("Missing permission")),
+                Authorization::Authorized => {
+                    match policy_resource(&state, &item.action, &item.resource).await {
+                        None => (true, String::from("Permission granted")),
+                        Some(Err(_)) => (false, String::from("Unknown resource")),
+                        Some(Ok(resource)) => match &subject {
+                            None => (false, String::from("Unknown subject")),
+                            Some(subject) => {
+                                let context = user_policy_context(ip, now, subject, &resource);
+                                match state.policy_engine.evaluate(&item.action, &context) {
+                                    PolicyDecision::Allow(reason) => (true, reason),
+                                    PolicyDecision::Deny(reason) => (false, reason),
+                                }
+                            }
+                        },
+                    }
+                }
+            };
+
+        decisions.push(BatchCheckDecision {
+            action: item.action.clone(),
+            resource: item.resource.clone(),
+            allowed,
+            reason,
+        });
+    }
+
+    HttpResponse::Ok().json(BatchCheckResponse {
+        subject: grants.subject,
+        decisions,
+    })
+}
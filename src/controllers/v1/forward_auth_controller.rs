@@ -0,0 +1,78 @@
+use crate::config::roles::Role;
+use crate::controllers::{AppState, CustomResponse};
+use crate::services::access_control::AccessControl;
+use crate::services::forward_auth::ForwardAuthDecision;
+use actix_web::{route, web, HttpRequest, HttpResponse, Responder};
+use std::str::FromStr;
+
+fn header<'a>(req: &'a HttpRequest, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|name| req.headers().get(*name))
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Authentication endpoint for nginx `auth_request` and Traefik `ForwardAuth`.
+/// The proxy forwards the original request headers, this only answers with a status
+/// and identity headers to inject upstream.
+#[route(
+    "/forward-auth",
+    method = "GET",
+    method = "HEAD",
+    method = "POST",
+    method = "PUT",
+    method = "PATCH",
+    method = "DELETE",
+    method = "OPTIONS"
+)]
+pub async fn forward_auth(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let method = header(&req, &["X-Forwarded-Method", "X-Original-Method"])
+        .unwrap_or(req.method().as_str())
+        .to_owned();
+    let uri = header(&req, &["X-Forwarded-Uri", "X-Original-URI"])
+        .unwrap_or("/")
+        .to_owned();
+
+    if state.forward_auth_rules.is_public(&method, &uri) {
+        return HttpResponse::Ok().finish();
+    }
+
+    let user_id = match AccessControl::subject_from_request(req.headers()) {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    };
+
+    let user = match state.repository.find_user_by_id(&user_id).await {
+        Ok(user) => user,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            });
+        }
+    };
+
+    let roles: Vec<Role> = user
+        .role
+        .iter()
+        .filter_map(|role| Role::from_str(role).ok())
+        .collect();
+
+    match state.forward_auth_rules.evaluate(&method, &uri, &roles) {
+        ForwardAuthDecision::Forbidden => HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Forbidden"),
+        }),
+        ForwardAuthDecision::InvalidPath => HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("Invalid path"),
+        }),
+        ForwardAuthDecision::Public | ForwardAuthDecision::Allowed => HttpResponse::Ok()
+            .insert_header(("X-Auth-User-Id", user.id))
+            .insert_header(("X-Auth-Email", user.email))
+            .insert_header(("X-Auth-Roles", user.role.join(",")))
+            .finish(),
+    }
+}
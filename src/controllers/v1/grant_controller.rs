@@ -0,0 +1,66 @@
+use crate::controllers::{AppState, CustomResponse};
+use crate::services::access_control::AccessControl;
+use crate::services::crypto::Claims;
+use actix_web::{delete, get, web, HttpRequest, HttpResponse, Responder};
+
+/// Claims of a first-party session, tokens issued to OAuth clients and service accounts may
+/// not manage how the user signs in nor their grants.
+pub(crate) fn session_claims(req: &HttpRequest) -> Result<Claims, HttpResponse> {
+    match AccessControl::claims_from_request(req.headers()) {
+        Ok(claims) if claims.client_id.is_none() => Ok(claims),
+        Ok(_) => Err(HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Only available from a session of the user"),
+        })),
+        Err(_) => Err(HttpResponse::Unauthorized().json(CustomResponse {
+            message: String::from("Unauthorized"),
+        })),
+    }
+}
+
+/// Clients the user granted access to, and the scopes granted.
+#[get("/me/grants")]
+pub async fn list_grants(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    match state.repository.list_consents(&user_id).await {
+        Ok(grants) => HttpResponse::Ok().json(grants),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+/// Revoke the grant of a client and its refresh tokens, the user is asked again next time.
+/// Access tokens already issued stay valid until they expire.
+#[delete("/me/grants/{client_id}")]
+pub async fn revoke_grant(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    client_id: web::Path<String>,
+) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    match state.repository.revoke_consent(&user_id, &client_id).await {
+        Ok(()) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("Grant revoked successfully!"),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("Grant not found"),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::controllers::v1::grant_controller::session_claims;
+use crate::controllers::{AppState, CustomResponse};
+use crate::repository::identity_repository::Unlink;
+use crate::services::identity_provider::recently_authenticated;
+use actix_web::{delete, get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+
+/// External identities the user signs in with. Identities are linked from the browser, on
+/// `/oauth/providers/{provider}/link`.
+#[get("/me/identities")]
+pub async fn list_identities(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    match state.repository.list_identities(&user_id).await {
+        Ok(identities) => HttpResponse::Ok().json(identities),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+/// Unlink the identity of a provider. The session must be recent, and the user keeps at
+/// least a password or another identity to sign in with.
+#[delete("/me/identities/{provider}")]
+pub async fn unlink_identity(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    provider: web::Path<String>,
+) -> impl Responder {
+    let claims = match session_claims(&req) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let recent = DateTime::from_timestamp(claims.iat as i64, 0)
+        .is_some_and(|auth_time| recently_authenticated(auth_time, Utc::now()));
+    if !recent {
+        return HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Sign in again to unlink an identity"),
+        });
+    }
+
+    match state.repository.unlink_identity(&claims.sub, &provider).await {
+        Ok(Unlink::Unlinked) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("Identity unlinked successfully!"),
+        }),
+        Ok(Unlink::LastCredential) => HttpResponse::Conflict().json(CustomResponse {
+            message: String::from(
+                "This identity is the only way to sign in to your account, set a password or link another identity first",
+            ),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("Identity not found"),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::controllers::oauth::identity_provider_controller::internal_error;
+use crate::controllers::oauth::pages::{
+    external_login_done_page, external_login_error_page, html_response, sms_code_page,
+};
+use crate::controllers::oauth::session::{session_cookie, session_token};
+use crate::controllers::v1::auth_controller::{complete_login_challenge, send_login_challenge};
+use crate::controllers::{configured_base_url, AppState, CustomResponse};
+use crate::repository::magic_link_repository::NewMagicLink;
+use crate::repository::phone_repository::UserPhone;
+use crate::services::crypto::TokenConfig;
+use crate::services::identity_provider::safe_return_to;
+use crate::services::magic_link::{
+    magic_link_email, normalized_email, PendingMagicLink, MAGIC_LINK_TTL_SECONDS, RATE_LIMIT_WINDOW,
+};
+use crate::services::oauth::hash_token;
+use crate::services::sms_code::masked_phone_number;
+use actix_web::http::header::{HeaderValue, REFERRER_POLICY, RETRY_AFTER, SET_COOKIE};
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use cookie::time::Duration;
+use cookie::{Cookie, SameSite};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Binds a link to the browser which requested it. `Lax` as links are opened from emails.
+const NONCE_COOKIE: &str = "MAGIC-LINK-NONCE";
+const NONCE_COOKIE_PATH: &str = "/api/v1/login/magic-link";
+
+#[derive(Serialize, Deserialize)]
+pub struct MagicLinkBody {
+    email: String,
+    /// Local path to go to once signed in.
+    #[serde(default)]
+    return_to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MagicLinkQuery {
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MagicLinkMfaForm {
+    challenge: String,
+    code: String,
+    #[serde(default)]
+    return_to: Option<String>,
+}
+
+fn nonce_cookie(value: &str, max_age: Duration) -> Cookie<'static> {
+    Cookie::build((NONCE_COOKIE, value.to_owned()))
+        .path(NONCE_COOKIE_PATH)
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .build()
+}
+
+/// Save the link of the user of `email`, if any, and send it to them.
+async fn send_magic_link(
+    state: &AppState,
+    email: &str,
+    token_hash: &str,
+    nonce_hash: &str,
+    return_to: &str,
+    url: &str,
+) {
+    let user = match state.repository.find_user_by_email(email).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return;
+        }
+    };
+
+    let saved = state
+        .repository
+        .save_magic_link(NewMagicLink {
+            token_hash,
+            user_id: &user.id,
+            nonce_hash,
+            return_to,
+            expires_at: Utc::now() + chrono::Duration::seconds(MAGIC_LINK_TTL_SECONDS),
+        })
+        .await;
+    if let Err(err) = saved {
+        log::error!("{:?}", err);
+        return;
+    }
+    if let Err(err) = state.mailer.send(&magic_link_email(&user.email, url)).await {
+        log::error!("Sign-in link of {} could not be sent : {:?}", user.id, err);
+    }
+}
+
+/// Email a sign-in link to the user of the email. The answer is the same whether there is
+/// such a user or not.
+#[post("/login/magic-link")]
+pub async fn request_magic_link(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<MagicLinkBody>,
+) -> impl Responder {
+    let limited_email = match normalized_email(&body.email) {
+        Some(email) => email,
+        None => {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("Invalid email"),
+            })
+        }
+    };
+    let ip = req.peer_addr().map(|addr| addr.ip().to_string());
+    if !state
+        .magic_link_limiter
+        .allow(&limited_email, ip.as_deref(), Instant::now())
+    {
+        return HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, RATE_LIMIT_WINDOW.as_secs().to_string()))
+            .json(CustomResponse {
+                message: String::from("Too many sign-in links requested, try again later"),
+            });
+    }
+
+    // The host of the request is chosen by whoever sends it, the link would go there.
+    let base_url = match configured_base_url(&TokenConfig::from_env()) {
+        Some(base_url) => base_url,
+        None => {
+            log::error!("Sign-in links need PUBLIC_BASE_URL, or JWT_ISSUER, to be a URL");
+            return HttpResponse::ServiceUnavailable().json(CustomResponse {
+                message: String::from("Sign-in links are not available"),
+            });
+        }
+    };
+    let pending = PendingMagicLink::generate();
+    let url = format!(
+        "{}/api/v1/login/magic-link/callback?token={}",
+        base_url, pending.token
+    );
+    let email = body.email.trim().to_owned();
+    let return_to = safe_return_to(body.return_to.as_deref());
+    let token_hash = hash_token(&pending.token);
+    let nonce_hash = hash_token(&pending.nonce);
+    let state = state.get_ref().clone();
+    // The user is looked up once answered, so that the answer does not tell whether they exist.
+    tokio::spawn(async move {
+        send_magic_link(&state, &email, &token_hash, &nonce_hash, &return_to, &url).await;
+    });
+
+    let cookie = nonce_cookie(&pending.nonce, Duration::seconds(MAGIC_LINK_TTL_SECONDS));
+    HttpResponse::Accepted()
+        .append_header((SET_COOKIE, cookie.to_string()))
+        .json(CustomResponse {
+            message: String::from("If an account has this email, a sign-in link was sent to it"),
+        })
+}
+
+/// Sign in with a link, in the browser which requested it.
+#[get("/login/magic-link/callback")]
+pub async fn magic_link_callback(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MagicLinkQuery>,
+) -> impl Responder {
+    let invalid_link = || {
+        external_login_error_page(
+            StatusCode::BAD_REQUEST,
+            "This sign-in link is invalid or expired, or it was requested from another browser",
+        )
+    };
+    let nonce = req.cookie(NONCE_COOKIE);
+    let (token, nonce) = match (&query.token, &nonce) {
+        (Some(token), Some(nonce)) if !nonce.value().is_empty() => (token, nonce.value()),
+        _ => return invalid_link(),
+    };
+
+    let link = match state
+        .repository
+        .consume_magic_link(&hash_token(token), &hash_token(nonce))
+        .await
+    {
+        Ok(link) => link,
+        Err(sqlx::Error::RowNotFound) => return invalid_link(),
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+    let user = match state.repository.find_user_by_id(&link.user_id).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return invalid_link(),
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+    let phone_number = match state.repository.find_user_phone(&user.id).await {
+        Ok(UserPhone {
+            phone_number,
+            verified_at: Some(_),
+        }) => Some(phone_number),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => None,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+
+    let mut cookies = vec![nonce_cookie("", Duration::ZERO)];
+    let mut response = match phone_number {
+        // Like `login` with a password, users with a verified phone also need the code sent
+        // to it : the session is issued by `magic_link_mfa`.
+        Some(phone_number) => {
+            let audience = TokenConfig::from_env().audience;
+            match send_login_challenge(&state, &user.id, &phone_number, &audience).await {
+                Ok(challenge) => html_response(
+                    StatusCode::OK,
+                    sms_code_page(
+                        &challenge,
+                        &link.return_to,
+                        Some(&masked_phone_number(&phone_number)),
+                        None,
+                    ),
+                ),
+                Err(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    external_login_error_page(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Too many codes were sent, try again later",
+                    )
+                }
+                Err(response) => external_login_error_page(
+                    response.status(),
+                    "The code could not be sent, try again later",
+                ),
+            }
+        }
+        None => {
+            match session_token(&state, &user).await {
+                Some((token, lifetime)) => cookies.push(session_cookie(&token, lifetime)),
+                None => return internal_error(),
+            }
+            html_response(
+                StatusCode::OK,
+                external_login_done_page("Signed in", &link.return_to),
+            )
+        }
+    };
+    // The token of the link must not leak to the page signed in users go to.
+    response
+        .headers_mut()
+        .insert(REFERRER_POLICY, HeaderValue::from_static("no-referrer"));
+    for cookie in cookies {
+        if let Ok(value) = cookie.to_string().parse() {
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+/// Sign in with a link, second step for users with a verified phone : the code sent to it.
+#[post("/login/magic-link/mfa")]
+pub async fn magic_link_mfa(
+    state: web::Data<AppState>,
+    form: web::Form<MagicLinkMfaForm>,
+) -> impl Responder {
+    let return_to = safe_return_to(form.return_to.as_deref());
+    let user = match complete_login_challenge(&state, &form.challenge, &form.code).await {
+        Ok(Some((user, _))) => user,
+        Ok(None) => {
+            return html_response(
+                StatusCode::BAD_REQUEST,
+                sms_code_page(
+                    &form.challenge,
+                    &return_to,
+                    None,
+                    Some("Invalid or expired code"),
+                ),
+            )
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return internal_error();
+        }
+    };
+    let session = match session_token(&state, &user).await {
+        Some((token, lifetime)) => session_cookie(&token, lifetime),
+        None => return internal_error(),
+    };
+
+    let mut response = html_response(
+        StatusCode::OK,
+        external_login_done_page("Signed in", &return_to),
+    );
+    if let Ok(value) = session.to_string().parse() {
+        response.headers_mut().append(SET_COOKIE, value);
+    }
+    response
+}
@@ -1,17 +1,50 @@
 use actix_web::{web, Scope};
-use auth_controller::{check_cookie, check_token, login, logout};
+use api_key_controller::{delete_api_key, list_api_keys, save_api_key};
+use auth_controller::{check_cookie, check_token, login, login_mfa, login_nonce, logout};
+use authz_controller::{batch_check, check, expand, write};
+use forward_auth_controller::forward_auth;
+use grant_controller::{list_grants, revoke_grant};
+use identity_controller::{list_identities, unlink_identity};
+use magic_link_controller::{magic_link_callback, magic_link_mfa, request_magic_link};
+use oauth_client_controller::{
+    delete_oauth_client, get_oauth_client, list_oauth_clients, rotate_oauth_client_secret,
+    save_oauth_client, update_oauth_client,
+};
+use phone_controller::{delete_phone, get_phone, save_phone, verify_phone};
+use role_controller::{
+    delete_permission, delete_role, get_role, list_permissions, list_roles, save_permission,
+    save_role, update_role, update_user_roles,
+};
+use service_account_controller::{
+    delete_service_account, list_service_accounts, save_service_account,
+};
 use user_controller::{
     get_user_by_email, get_user_progression, hard_delete_user, remove_soft_deletion_user,
     save_user, soft_delete_user,
 };
 
+pub mod api_key_controller;
 pub mod auth_controller;
+pub mod authz_controller;
+pub mod forward_auth_controller;
+pub mod grant_controller;
+pub mod identity_controller;
+pub mod magic_link_controller;
+pub mod oauth_client_controller;
+pub mod phone_controller;
+pub mod role_controller;
+pub mod service_account_controller;
 pub mod user_controller;
 
 #[allow(dead_code)]
 pub fn get_v1_service() -> Scope {
     web::scope("/api/v1")
         .service(login)
+        .service(login_mfa)
+        .service(login_nonce)
+        .service(request_magic_link)
+        .service(magic_link_callback)
+        .service(magic_link_mfa)
         .service(logout)
         .service(check_cookie)
         .service(check_token)
@@ -21,4 +54,38 @@ pub fn get_v1_service() -> Scope {
         .service(soft_delete_user)
         .service(remove_soft_deletion_user)
         .service(hard_delete_user)
+        .service(update_user_roles)
+        .service(list_roles)
+        .service(get_role)
+        .service(save_role)
+        .service(update_role)
+        .service(delete_role)
+        .service(list_permissions)
+        .service(save_permission)
+        .service(delete_permission)
+        .service(check)
+        .service(expand)
+        .service(write)
+        .service(batch_check)
+        .service(forward_auth)
+        .service(save_oauth_client)
+        .service(list_oauth_clients)
+        .service(get_oauth_client)
+        .service(update_oauth_client)
+        .service(rotate_oauth_client_secret)
+        .service(delete_oauth_client)
+        .service(save_service_account)
+        .service(list_service_accounts)
+        .service(delete_service_account)
+        .service(list_grants)
+        .service(revoke_grant)
+        .service(list_identities)
+        .service(unlink_identity)
+        .service(list_api_keys)
+        .service(save_api_key)
+        .service(delete_api_key)
+        .service(get_phone)
+        .service(save_phone)
+        .service(verify_phone)
+        .service(delete_phone)
 }
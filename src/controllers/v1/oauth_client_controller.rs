@@ -0,0 +1,411 @@
+use crate::config::permissions::OAUTH_CLIENT_MANAGE;
+use crate::controllers::{AppState, CustomResponse};
+use crate::repository::oauth_repository::{ClientMetadata, OAuthClient, OAuthClientUpdate};
+use crate::services::access_control::Authorization::{Authorized, Unauthorized};
+use crate::services::access_control::GrantAccess;
+use crate::services::client_registration::{
+    uses_secret, ClientRegistration, DEFAULT_SECRET_GRACE_PERIOD_SECONDS,
+    MAX_SECRET_GRACE_PERIOD_SECONDS,
+};
+use crate::services::crypto::{Hash, HashService};
+use crate::services::oauth::{
+    generate_token, AUTH_METHOD_NONE, AUTH_METHOD_PRIVATE_KEY_JWT, AUTH_METHOD_SECRET_BASIC,
+    GRANT_AUTHORIZATION_CODE,
+};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use serde::{Deserialize, Serialize};
+
+fn default_grant_types() -> Vec<String> {
+    vec![String::from(GRANT_AUTHORIZATION_CODE)]
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OAuthClientBody {
+    name: String,
+    #[serde(default)]
+    redirect_uris: Vec<String>,
+    #[serde(default)]
+    scopes: Vec<String>,
+    #[serde(default = "default_grant_types")]
+    grant_types: Vec<String>,
+    /// Public clients (SPAs, native apps) have no secret and must use PKCE.
+    #[serde(default)]
+    public: bool,
+    /// Public keys of clients authenticating with `private_key_jwt` instead of a secret.
+    #[serde(default)]
+    jwks: Option<JwkSet>,
+    /// Clients of the organization itself, users are not asked for consent.
+    #[serde(default)]
+    first_party: bool,
+    #[serde(flatten)]
+    metadata: ClientMetadata,
+}
+
+impl OAuthClientBody {
+    fn into_registration(self) -> ClientRegistration {
+        let token_endpoint_auth_method = match (self.public, &self.jwks) {
+            (true, _) => AUTH_METHOD_NONE,
+            (false, Some(_)) => AUTH_METHOD_PRIVATE_KEY_JWT,
+            (false, None) => AUTH_METHOD_SECRET_BASIC,
+        };
+
+        ClientRegistration {
+            name: self.name,
+            redirect_uris: self.redirect_uris,
+            scopes: self.scopes,
+            grant_types: self.grant_types,
+            token_endpoint_auth_method: String::from(token_endpoint_auth_method),
+            jwks: self.jwks,
+            first_party: self.first_party,
+            metadata: self.metadata,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OAuthClientCreated {
+    #[serde(flatten)]
+    client: OAuthClient,
+    /// Only returned when generated, it is stored hashed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SecretRotation {
+    /// How long the current secret stays valid, to roll the new one out.
+    #[serde(default)]
+    grace_period_seconds: Option<i64>,
+}
+
+/// New secret and its hash.
+fn generate_secret() -> Result<(String, String), argon2::password_hash::Error> {
+    let secret = generate_token();
+    let hash = HashService::hash_password(&secret)?;
+    Ok((secret, hash))
+}
+
+#[post("/oauth/clients")]
+pub async fn save_oauth_client(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<OAuthClientBody>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), OAUTH_CLIENT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    let registration = body.into_inner().into_registration();
+    if let Err(err) = registration.validate() {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: err.error_description,
+        });
+    }
+
+    let (client_secret, client_secret_hash) =
+        match uses_secret(&registration.token_endpoint_auth_method) {
+            true => match generate_secret() {
+                Ok((secret, hash)) => (Some(secret), Some(hash)),
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    return HttpResponse::InternalServerError().json(CustomResponse {
+                        message: String::from("Internal server error"),
+                    });
+                }
+            },
+            false => (None, None),
+        };
+
+    match state
+        .repository
+        .save_oauth_client(registration.into_new_client(client_secret_hash))
+        .await
+    {
+        Ok(client) => HttpResponse::Created().json(OAuthClientCreated {
+            client,
+            client_secret,
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[get("/oauth/clients")]
+pub async fn list_oauth_clients(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), OAUTH_CLIENT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    match state.repository.list_oauth_clients().await {
+        Ok(clients) => HttpResponse::Ok().json(clients),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[get("/oauth/clients/{client_id}")]
+pub async fn get_oauth_client(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    client_id: web::Path<String>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), OAUTH_CLIENT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    match state.repository.find_oauth_client(&client_id).await {
+        Ok(client) => HttpResponse::Ok().json(client),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("Client not found"),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+/// Replace the settings of a client. A client becoming confidential is given a secret,
+/// returned once, and the secrets of a client no longer using one are dropped.
+#[put("/oauth/clients/{client_id}")]
+pub async fn update_oauth_client(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    client_id: web::Path<String>,
+    body: web::Json<OAuthClientBody>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), OAUTH_CLIENT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    let registration = body.into_inner().into_registration();
+    if let Err(err) = registration.validate() {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: err.error_description,
+        });
+    }
+
+    let current = match state.repository.find_oauth_client(&client_id).await {
+        Ok(client) => client,
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(CustomResponse {
+                message: String::from("Client not found"),
+            })
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            });
+        }
+    };
+
+    let with_secret = uses_secret(&registration.token_endpoint_auth_method);
+    let (client_secret, client_secret_hash) =
+        match with_secret && current.client_secret_hash.is_none() {
+            true => match generate_secret() {
+                Ok((secret, hash)) => (Some(secret), Some(hash)),
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    return HttpResponse::InternalServerError().json(CustomResponse {
+                        message: String::from("Internal server error"),
+                    });
+                }
+            },
+            false => (None, None),
+        };
+
+    let update = OAuthClientUpdate {
+        client: registration.into_new_client(client_secret_hash),
+        clear_secret: !with_secret,
+    };
+    match state
+        .repository
+        .update_oauth_client(&client_id, update)
+        .await
+    {
+        Ok(client) => HttpResponse::Ok().json(OAuthClientCreated {
+            client,
+            client_secret,
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from(
+                "Client not found, service accounts are managed on /service_accounts",
+            ),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+/// Generate a new secret. The current one stays valid for the grace period, one day by
+/// default, so that the client can be reconfigured without downtime.
+#[post("/oauth/clients/{client_id}/secret")]
+pub async fn rotate_oauth_client_secret(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    client_id: web::Path<String>,
+    body: Option<web::Json<SecretRotation>>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), OAUTH_CLIENT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    let grace_period = body
+        .and_then(|body| body.grace_period_seconds)
+        .unwrap_or(DEFAULT_SECRET_GRACE_PERIOD_SECONDS);
+    if !(0..=MAX_SECRET_GRACE_PERIOD_SECONDS).contains(&grace_period) {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: format!(
+                "grace_period_seconds must be between 0 and {}",
+                MAX_SECRET_GRACE_PERIOD_SECONDS
+            ),
+        });
+    }
+
+    match state.repository.find_oauth_client(&client_id).await {
+        Ok(client) if uses_secret(&client.token_endpoint_auth_method) => {}
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("The client does not authenticate with a secret"),
+            })
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(CustomResponse {
+                message: String::from("Client not found"),
+            })
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            });
+        }
+    }
+
+    let (client_secret, client_secret_hash) = match generate_secret() {
+        Ok(secret) => secret,
+        Err(err) => {
+            log::error!("{:?}", err);
+            return HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            });
+        }
+    };
+    let grace_until = Utc::now() + chrono::Duration::seconds(grace_period);
+    match state
+        .repository
+        .rotate_oauth_client_secret(&client_id, &client_secret_hash, grace_until)
+        .await
+    {
+        Ok(client) => HttpResponse::Ok().json(OAuthClientCreated {
+            client,
+            client_secret: Some(client_secret),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("Client not found"),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[delete("/oauth/clients/{client_id}")]
+pub async fn delete_oauth_client(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    client_id: web::Path<String>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), OAUTH_CLIENT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    match state.repository.delete_oauth_client(&client_id).await {
+        Ok(()) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("Client deleted successfully!"),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("Client not found"),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::controllers::v1::grant_controller::session_claims;
+use crate::controllers::{AppState, CustomResponse};
+use crate::repository::phone_repository::NewSmsCode;
+use crate::services::crypto::Claims;
+use crate::services::identity_provider::recently_authenticated;
+use crate::services::sms::Sms;
+use crate::services::sms_code::{
+    code_matches, generate_code, hash_code, normalized_phone_number, verification_sms,
+    CODE_TTL_SECONDS, MAX_CODE_ATTEMPTS, PURPOSE_PHONE_VERIFICATION, RATE_LIMIT_WINDOW,
+};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize)]
+pub struct PhoneBody {
+    /// E.164 form, with the country code : `+33612345678`.
+    phone_number: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PhoneCodeBody {
+    code: String,
+}
+
+fn internal_error(err: sqlx::Error) -> HttpResponse {
+    log::error!("{:?}", err);
+    HttpResponse::InternalServerError().json(CustomResponse {
+        message: String::from("Internal server error"),
+    })
+}
+
+fn invalid_code() -> HttpResponse {
+    HttpResponse::BadRequest().json(CustomResponse {
+        message: String::from("Invalid or expired code"),
+    })
+}
+
+fn is_recent(claims: &Claims) -> bool {
+    DateTime::from_timestamp(claims.iat as i64, 0)
+        .is_some_and(|auth_time| recently_authenticated(auth_time, Utc::now()))
+}
+
+/// Save a code and send its SMS, the answer to give otherwise. Codes sent to a user are
+/// rate limited.
+pub(crate) async fn send_sms_code(
+    state: &AppState,
+    code: NewSmsCode<'_>,
+    sms: &Sms,
+) -> Result<(), HttpResponse> {
+    if !state.sms_code_limiter.allow(code.user_id, Instant::now()) {
+        return Err(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, RATE_LIMIT_WINDOW.as_secs().to_string()))
+            .json(CustomResponse {
+                message: String::from("Too many codes requested, try again later"),
+            }));
+    }
+
+    let user_id = code.user_id;
+    state
+        .repository
+        .save_sms_code(code)
+        .await
+        .map_err(internal_error)?;
+    state.sms_sender.send(sms).await.map_err(|err| {
+        log::error!("SMS code of {} could not be sent : {:?}", user_id, err);
+        HttpResponse::ServiceUnavailable().json(CustomResponse {
+            message: String::from("The code could not be sent, try again later"),
+        })
+    })
+}
+
+/// Phone number of the user, once verified `login` also asks for a code sent to it.
+#[get("/me/phone")]
+pub async fn get_phone(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    match state.repository.find_user_phone(&user_id).await {
+        Ok(phone) => HttpResponse::Ok().json(phone),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("No phone number"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+/// Set the phone number of the user and send a code to verify it, the session must be
+/// recent.
+#[put("/me/phone")]
+pub async fn save_phone(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PhoneBody>,
+) -> impl Responder {
+    let claims = match session_claims(&req) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    if !is_recent(&claims) {
+        return HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Sign in again to change your phone number"),
+        });
+    }
+    let phone_number = match normalized_phone_number(&body.phone_number) {
+        Some(phone_number) => phone_number,
+        None => {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from(
+                    "Invalid phone number, use the international form such as +33612345678",
+                ),
+            })
+        }
+    };
+
+    let phone = match state
+        .repository
+        .save_user_phone(&claims.sub, &phone_number)
+        .await
+    {
+        Ok(phone) => phone,
+        Err(err) => return internal_error(err),
+    };
+    if phone.verified_at.is_some() {
+        return HttpResponse::Ok().json(phone);
+    }
+
+    let code = generate_code();
+    let new_code = NewSmsCode {
+        user_id: &claims.sub,
+        purpose: PURPOSE_PHONE_VERIFICATION,
+        phone_number: &phone_number,
+        code_hash: &hash_code(&claims.sub, &code),
+        challenge_hash: None,
+        audience: None,
+        expires_at: Utc::now() + chrono::Duration::seconds(CODE_TTL_SECONDS),
+    };
+    match send_sms_code(&state, new_code, &verification_sms(&phone_number, &code)).await {
+        Ok(()) => HttpResponse::Accepted().json(phone),
+        Err(response) => response,
+    }
+}
+
+/// Verify the phone number of the user with the code sent to it.
+#[post("/me/phone/verify")]
+pub async fn verify_phone(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PhoneCodeBody>,
+) -> impl Responder {
+    let user_id = match session_claims(&req) {
+        Ok(claims) => claims.sub,
+        Err(response) => return response,
+    };
+
+    let code = match state
+        .repository
+        .attempt_sms_code(&user_id, PURPOSE_PHONE_VERIFICATION, MAX_CODE_ATTEMPTS)
+        .await
+    {
+        Ok(code) => code,
+        Err(sqlx::Error::RowNotFound) => return invalid_code(),
+        Err(err) => return internal_error(err),
+    };
+    if !code_matches(&code.code_hash, &user_id, &body.code) {
+        return invalid_code();
+    }
+    if let Err(err) = state
+        .repository
+        .delete_sms_code(&user_id, PURPOSE_PHONE_VERIFICATION)
+        .await
+    {
+        return internal_error(err);
+    }
+
+    match state
+        .repository
+        .verify_user_phone(&user_id, &code.phone_number)
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("Phone number verified successfully!"),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::Conflict().json(CustomResponse {
+            message: String::from("The phone number changed since the code was sent"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+/// Remove the phone number of the user, and the codes `login` asks for. The session must be
+/// recent.
+#[delete("/me/phone")]
+pub async fn delete_phone(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let claims = match session_claims(&req) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    if !is_recent(&claims) {
+        return HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Sign in again to remove your phone number"),
+        });
+    }
+
+    match state.repository.delete_user_phone(&claims.sub).await {
+        Ok(()) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("Phone number deleted successfully!"),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("No phone number"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
@@ -0,0 +1,310 @@
+use crate::config::permissions::{is_valid_permission, ROLE_MANAGE};
+use crate::config::roles::Role;
+use crate::controllers::{AppState, CustomResponse};
+use crate::services::access_control::Authorization::{Authorized, Unauthorized};
+use crate::services::access_control::{AccessControl, GrantAccess};
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize)]
+pub struct RoleBody {
+    name: Role,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateRoleBody {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PermissionBody {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserRolesBody {
+    email: String,
+    roles: Vec<Role>,
+}
+
+/// 401 without a valid session, 403 when its user may not manage roles.
+async fn check_role_manager(state: &AppState, req: &HttpRequest) -> Result<(), HttpResponse> {
+    let user_id =
+        AccessControl::subject_from_cookie(req.headers().get("cookie")).map_err(|_| {
+            HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        })?;
+
+    match state
+        .access_control
+        .with_user_permission(&user_id, ROLE_MANAGE)
+        .await
+    {
+        Authorized => Ok(()),
+        Unauthorized(_) => Err(HttpResponse::Forbidden().json(CustomResponse {
+            message: String::from("Forbidden"),
+        })),
+    }
+}
+
+fn invalid_permissions(permissions: &[String]) -> Option<HttpResponse> {
+    permissions
+        .iter()
+        .find(|permission| !is_valid_permission(permission))
+        .map(|permission| {
+            HttpResponse::BadRequest().json(CustomResponse {
+                message: format!("Invalid permission: {}", permission),
+            })
+        })
+}
+
+#[get("/roles")]
+pub async fn list_roles(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    match state.repository.list_roles().await {
+        Ok(roles) => HttpResponse::Ok().json(roles),
+        Err(err) => HttpResponse::InternalServerError().json(CustomResponse {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[get("/roles/{name}")]
+pub async fn get_role(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    match state.repository.find_role(&name).await {
+        Ok(role) => HttpResponse::Ok().json(role),
+        Err(_) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("This role doesn't exist"),
+        }),
+    }
+}
+
+#[post("/roles")]
+pub async fn save_role(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<RoleBody>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    if let Some(res) = invalid_permissions(&body.permissions) {
+        return res;
+    }
+
+    if let Err(err) = state
+        .repository
+        .save_role(body.name.to_str(), &body.description, &body.permissions)
+        .await
+    {
+        log::error!("{:?}", err);
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("Unable to create the role"),
+        });
+    }
+    state.access_control.invalidate_cache().await;
+
+    HttpResponse::Created().json(CustomResponse {
+        message: String::from("Role created successfully!"),
+    })
+}
+
+/// Built-in roles cannot be changed, removing a permission of `SUPER_ADMIN` would lock
+/// administration out.
+#[put("/roles/{name}")]
+pub async fn update_role(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    name: web::Path<String>,
+    body: web::Json<UpdateRoleBody>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    if let Some(res) = invalid_permissions(&body.permissions) {
+        return res;
+    }
+
+    if let Err(err) = state
+        .repository
+        .update_role(&name, &body.description, &body.permissions)
+        .await
+    {
+        log::error!("{:?}", err);
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("This role doesn't exist or is built-in"),
+        });
+    }
+    state.access_control.invalidate_cache().await;
+
+    HttpResponse::Ok().json(CustomResponse {
+        message: String::from("Role updated successfully!"),
+    })
+}
+
+#[delete("/roles/{name}")]
+pub async fn delete_role(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    if let Err(err) = state.repository.delete_role(&name).await {
+        log::error!("{:?}", err);
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("This role doesn't exist or is built-in"),
+        });
+    }
+    state.access_control.invalidate_cache().await;
+
+    HttpResponse::Ok().json(CustomResponse {
+        message: String::from("Role deleted successfully!"),
+    })
+}
+
+#[get("/permissions")]
+pub async fn list_permissions(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    match state.repository.list_permissions().await {
+        Ok(permissions) => HttpResponse::Ok().json(permissions),
+        Err(err) => HttpResponse::InternalServerError().json(CustomResponse {
+            message: err.to_string(),
+        }),
+    }
+}
+
+#[post("/permissions")]
+pub async fn save_permission(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PermissionBody>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    if !is_valid_permission(&body.name) {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: format!("Invalid permission: {}", body.name),
+        });
+    }
+
+    if let Err(err) = state
+        .repository
+        .save_permission(&body.name, &body.description)
+        .await
+    {
+        log::error!("{:?}", err);
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("Unable to create the permission"),
+        });
+    }
+
+    HttpResponse::Created().json(CustomResponse {
+        message: String::from("Permission created successfully!"),
+    })
+}
+
+#[delete("/permissions/{name}")]
+pub async fn delete_permission(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    if let Err(err) = state.repository.delete_permission(&name).await {
+        log::error!("{:?}", err);
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("This permission doesn't exist"),
+        });
+    }
+    state.access_control.invalidate_cache().await;
+
+    HttpResponse::Ok().json(CustomResponse {
+        message: String::from("Permission deleted successfully!"),
+    })
+}
+
+#[patch("/user/roles")]
+pub async fn update_user_roles(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<UserRolesBody>,
+) -> impl Responder {
+    if let Err(response) = check_role_manager(&state, &req).await {
+        return response;
+    }
+
+    let user = match state.repository.find_user_by_email(&body.email).await {
+        Ok(user) => user,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("This user doesn't exist"),
+            })
+        }
+    };
+
+    let mut roles: Vec<String> = body.roles.iter().map(|role| role.to_string()).collect();
+    roles.sort();
+    roles.dedup();
+
+    // A super admin cannot be demoted through the API, it would lock the platform out.
+    if user
+        .role
+        .iter()
+        .any(|role| Role::from_str(role).ok() == Some(Role::SUPER_ADMIN))
+        && !roles.contains(&Role::SUPER_ADMIN.to_string())
+    {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("Super admin role cannot be removed"),
+        });
+    }
+
+    match state.repository.update_user_roles(&user.id, &roles).await {
+        Ok(_) => {
+            state.access_control.invalidate_cache().await;
+            HttpResponse::Ok().json(CustomResponse {
+                message: String::from("User roles updated successfully!"),
+            })
+        }
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::BadRequest().json(CustomResponse {
+                message: String::from("Unknown role"),
+            })
+        }
+    }
+}
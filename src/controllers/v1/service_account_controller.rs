@@ -0,0 +1,205 @@
+use crate::config::permissions::{is_valid_permission, SERVICE_ACCOUNT_MANAGE};
+use crate::controllers::{AppState, CustomResponse};
+use crate::repository::oauth_repository::{ClientMetadata, NewOAuthClient};
+use crate::repository::service_account_repository::ServiceAccount;
+use crate::services::access_control::Authorization::{Authorized, Unauthorized};
+use crate::services::access_control::{AccessControl, GrantAccess};
+use crate::services::crypto::{Hash, HashService};
+use crate::services::oauth::{
+    generate_token, validate_client_jwks, AUTH_METHOD_PRIVATE_KEY_JWT, AUTH_METHOD_SECRET_BASIC,
+    GRANT_CLIENT_CREDENTIALS,
+};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use jsonwebtoken::jwk::JwkSet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct ServiceAccountBody {
+    name: String,
+    #[serde(default)]
+    scopes: Vec<String>,
+    /// Public keys for `private_key_jwt`, a secret is generated when not set.
+    #[serde(default)]
+    jwks: Option<JwkSet>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ServiceAccountCreated {
+    #[serde(flatten)]
+    account: ServiceAccount,
+    /// Only returned on creation, it is stored hashed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<String>,
+}
+
+/// Service accounts are owned by the user creating them, and can only be given the
+/// permissions of their owner.
+#[post("/service_accounts")]
+pub async fn save_service_account(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ServiceAccountBody>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), SERVICE_ACCOUNT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+    let owner = match AccessControl::claims_from_request(req.headers()) {
+        Ok(claims) if !claims.is_service_account() => claims.sub,
+        _ => {
+            return HttpResponse::Forbidden().json(CustomResponse {
+                message: String::from("Service accounts must be owned by a user"),
+            })
+        }
+    };
+
+    let body = body.into_inner();
+    if body.name.trim().is_empty() {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("name is required"),
+        });
+    }
+    if body
+        .scopes
+        .iter()
+        .any(|scope| scope.is_empty() || scope.contains(' '))
+    {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: String::from("Scopes must be non-empty and without spaces"),
+        });
+    }
+    for scope in body
+        .scopes
+        .iter()
+        .filter(|scope| is_valid_permission(scope))
+    {
+        if let Unauthorized(_) = state
+            .access_control
+            .with_user_permission(&owner, scope)
+            .await
+        {
+            return HttpResponse::Forbidden().json(CustomResponse {
+                message: format!("You do not have the permission {}", scope),
+            });
+        }
+    }
+    if let Some(err) = body
+        .jwks
+        .as_ref()
+        .and_then(|jwks| validate_client_jwks(jwks).err())
+    {
+        return HttpResponse::BadRequest().json(CustomResponse {
+            message: err.error_description,
+        });
+    }
+
+    let (client_secret, token_endpoint_auth_method) = match body.jwks {
+        Some(_) => (None, AUTH_METHOD_PRIVATE_KEY_JWT),
+        None => (Some(generate_token()), AUTH_METHOD_SECRET_BASIC),
+    };
+    let client_secret_hash = match &client_secret {
+        Some(secret) => match HashService::hash_password(secret) {
+            Ok(hash) => Some(hash),
+            Err(err) => {
+                log::error!("{:?}", err);
+                return HttpResponse::InternalServerError().json(CustomResponse {
+                    message: String::from("Internal server error"),
+                });
+            }
+        },
+        None => None,
+    };
+
+    let client = NewOAuthClient {
+        client_secret_hash,
+        name: body.name,
+        redirect_uris: vec![],
+        scopes: body.scopes,
+        grant_types: vec![String::from(GRANT_CLIENT_CREDENTIALS)],
+        token_endpoint_auth_method: String::from(token_endpoint_auth_method),
+        jwks: body.jwks,
+        first_party: false,
+        metadata: ClientMetadata::default(),
+    };
+    match state.repository.save_service_account(client, &owner).await {
+        Ok(account) => HttpResponse::Created().json(ServiceAccountCreated {
+            account,
+            client_secret,
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[get("/service_accounts")]
+pub async fn list_service_accounts(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), SERVICE_ACCOUNT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    match state.repository.list_service_accounts().await {
+        Ok(accounts) => HttpResponse::Ok().json(accounts),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
+
+#[delete("/service_accounts/{client_id}")]
+pub async fn delete_service_account(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    client_id: web::Path<String>,
+) -> impl Responder {
+    match state
+        .access_control
+        .with_request_permission(req.headers(), SERVICE_ACCOUNT_MANAGE)
+        .await
+    {
+        Authorized => {}
+        Unauthorized(_) => {
+            return HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        }
+    }
+
+    match state.repository.delete_service_account(&client_id).await {
+        Ok(()) => HttpResponse::Ok().json(CustomResponse {
+            message: String::from("Service account deleted successfully!"),
+        }),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(CustomResponse {
+            message: String::from("Service account not found"),
+        }),
+        Err(err) => {
+            log::error!("{:?}", err);
+            HttpResponse::InternalServerError().json(CustomResponse {
+                message: String::from("Internal server error"),
+            })
+        }
+    }
+}
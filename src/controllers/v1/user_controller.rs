@@ -1,11 +1,86 @@
+use crate::config::permissions::{STATS_READ, USER_DELETE, USER_HARD_DELETE, USER_UNDELETE};
 use crate::config::roles::Role;
 use crate::controllers::{AppState, CustomResponse};
-use crate::repository::user_repository::NewUser;
+use crate::repository::user_repository::{NewUser, User};
 use crate::services::access_control::Authorization::{Authorized, Unauthorized};
-use crate::services::access_control::GrantAccess;
+use crate::services::access_control::{AccessControl, GrantAccess};
 use crate::services::crypto::{Hash, HashService};
+use crate::services::policy::{PolicyContext, PolicyDecision};
 use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Evaluate the attribute-based policies for an action of the logged user on `resource`.
+async fn check_policy(
+    state: &AppState,
+    req: &HttpRequest,
+    action: &str,
+    resource: &User,
+) -> Result<(), HttpResponse> {
+    let subject = AccessControl::subject_from_cookie(req.headers().get("cookie"))
+        .map_err(|_| {
+            HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        })?;
+    let subject = state
+        .repository
+        .find_user_by_id(&subject)
+        .await
+        .map_err(|_| {
+            HttpResponse::Unauthorized().json(CustomResponse {
+                message: String::from("Unauthorized"),
+            })
+        })?;
+
+    let context = user_policy_context(
+        req.peer_addr().map(|addr| addr.ip()),
+        chrono::Utc::now(),
+        &subject,
+        resource,
+    );
+
+    match state.policy_engine.evaluate(action, &context) {
+        PolicyDecision::Allow(_) => Ok(()),
+        PolicyDecision::Deny(reason) => {
+            log::info!("{} denied for {} on {}: {}", action, subject.email, resource.email, reason);
+            Err(HttpResponse::Forbidden().json(CustomResponse { message: reason }))
+        }
+    }
+}
+
+/// Attributes the policies of an action of `subject` on `resource` are evaluated with.
+pub(crate) fn user_policy_context(
+    ip: Option<IpAddr>,
+    now: DateTime<Utc>,
+    subject: &User,
+    resource: &User,
+) -> PolicyContext {
+    PolicyContext::new(ip, now)
+        .with_subject("id", subject.id.as_str())
+        .with_subject("email", subject.email.as_str())
+        .with_subject("roles", subject.role.clone())
+        .with_subject("organization", subject.organization.clone())
+        .with_resource("id", resource.id.as_str())
+        .with_resource("email", resource.email.as_str())
+        .with_resource("roles", resource.role.clone())
+        .with_resource("organization", resource.organization.clone())
+}
+
+/// User an action checked against the policies applies to, found by email as its endpoint
+/// finds it. `None` for the actions the endpoints do not check against the policies.
+pub(crate) async fn policy_resource(
+    state: &AppState,
+    action: &str,
+    email: &str,
+) -> Option<Result<User, sqlx::Error>> {
+    match action {
+        USER_DELETE | USER_HARD_DELETE => Some(state.repository.find_user_by_email(email).await),
+        USER_UNDELETE => Some(state.repository.find_banned_user_by_email(email).await),
+        _ => None,
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct NewUserBody {
@@ -158,7 +233,7 @@ pub async fn soft_delete_user(
 ) -> impl Responder {
     match state
         .access_control
-        .with_cookie(req.headers().get("cookie"), vec![Role::ADMIN])
+        .with_cookie_permission(req.headers().get("cookie"), USER_DELETE)
         .await
     {
         Authorized => {}
@@ -180,6 +255,10 @@ pub async fn soft_delete_user(
         })
         .unwrap();
 
+    if let Err(res) = check_policy(&state, &req, USER_DELETE, &user).await {
+        return res;
+    }
+
     state
         .repository
         .soft_delete_user(&user.id)
@@ -190,6 +269,7 @@ pub async fn soft_delete_user(
             })
         })
         .unwrap();
+    state.access_control.invalidate_cache().await;
 
     HttpResponse::Ok().json(CustomResponse {
         message: String::from("User deleted successfully!"),
@@ -204,7 +284,7 @@ pub async fn remove_soft_deletion_user(
 ) -> impl Responder {
     match state
         .access_control
-        .with_cookie(req.headers().get("cookie"), vec![Role::ADMIN])
+        .with_cookie_permission(req.headers().get("cookie"), USER_UNDELETE)
         .await
     {
         Authorized => {}
@@ -226,6 +306,10 @@ pub async fn remove_soft_deletion_user(
         })
         .unwrap();
 
+    if let Err(res) = check_policy(&state, &req, USER_UNDELETE, &user).await {
+        return res;
+    }
+
     state
         .repository
         .remove_soft_deletion_user(&user.id)
@@ -250,7 +334,7 @@ pub async fn hard_delete_user(
 ) -> impl Responder {
     match state
         .access_control
-        .with_cookie(req.headers().get("cookie"), vec![Role::ADMIN])
+        .with_cookie_permission(req.headers().get("cookie"), USER_HARD_DELETE)
         .await
     {
         Authorized => {}
@@ -272,6 +356,10 @@ pub async fn hard_delete_user(
         })
         .unwrap();
 
+    if let Err(res) = check_policy(&state, &req, USER_HARD_DELETE, &user).await {
+        return res;
+    }
+
     state
         .repository
         .hard_delete_user(&user.id)
@@ -282,6 +370,7 @@ pub async fn hard_delete_user(
             })
         })
         .unwrap();
+    state.access_control.invalidate_cache().await;
 
     HttpResponse::Ok().json(CustomResponse {
         message: String::from("User deleted successfully!"),
@@ -292,7 +381,7 @@ pub async fn hard_delete_user(
 pub async fn get_user_progression(state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
     match state
         .access_control
-        .with_cookie(req.headers().get("cookie"), vec![Role::ADMIN])
+        .with_cookie_permission(req.headers().get("cookie"), STATS_READ)
         .await
     {
         Authorized => {}
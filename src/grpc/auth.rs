@@ -0,0 +1,289 @@
+//! `auth.v1.AuthService`, the typed counterpart of the token and user HTTP endpoints.
+//! Messages mirror `proto/auth/v1/auth.proto`.
+
+include!(concat!(env!("OUT_DIR"), "/auth.v1.AuthService.rs"));
+
+use crate::config::permissions::USER_READ;
+use crate::config::roles::Role;
+use crate::repository::{user_repository, Repository};
+use crate::services::access_control::{
+    AccessControl, Authorization, GrantAccess, SubjectGrants, TokenAuthority,
+};
+use auth_service_server::AuthService;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct User {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub email: String,
+    #[prost(string, repeated, tag = "3")]
+    pub roles: Vec<String>,
+    #[prost(string, tag = "4")]
+    pub organization: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VerifyTokenRequest {
+    #[prost(string, tag = "1")]
+    pub token: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VerifyTokenResponse {
+    #[prost(bool, tag = "1")]
+    pub valid: bool,
+    #[prost(message, optional, tag = "2")]
+    pub user: Option<User>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetUserRequest {
+    #[prost(oneof = "Lookup", tags = "1, 2")]
+    pub lookup: Option<Lookup>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum Lookup {
+    #[prost(string, tag = "1")]
+    Id(String),
+    #[prost(string, tag = "2")]
+    Email(String),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckRolesRequest {
+    #[prost(string, tag = "1")]
+    pub token: String,
+    #[prost(string, repeated, tag = "2")]
+    pub roles: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckRolesResponse {
+    #[prost(bool, tag = "1")]
+    pub allowed: bool,
+    /// Roles of the user.
+    #[prost(string, repeated, tag = "2")]
+    pub roles: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListUsersRequest {
+    #[prost(uint32, tag = "1")]
+    pub page_size: u32,
+    #[prost(string, tag = "2")]
+    pub page_token: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListUsersResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub users: Vec<User>,
+    #[prost(string, tag = "2")]
+    pub next_page_token: String,
+}
+
+pub type DirectoryFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, sqlx::Error>> + Send + 'a>>;
+
+/// User lookups needed by the gRPC API, implemented by [`Repository`].
+pub trait UserDirectory: Send + Sync + 'static {
+    fn find_by_id<'a>(&'a self, id: &'a str) -> DirectoryFuture<'a, User>;
+    fn find_by_email<'a>(&'a self, email: &'a str) -> DirectoryFuture<'a, User>;
+    fn list<'a>(&'a self, limit: i64, after_id: Option<&'a str>) -> DirectoryFuture<'a, Vec<User>>;
+}
+
+impl From<user_repository::User> for User {
+    fn from(user: user_repository::User) -> User {
+        User {
+            id: user.id,
+            email: user.email,
+            roles: user.role,
+            organization: user.organization.unwrap_or_default(),
+        }
+    }
+}
+
+impl UserDirectory for Repository {
+    fn find_by_id<'a>(&'a self, id: &'a str) -> DirectoryFuture<'a, User> {
+        Box::pin(async move { self.find_user_by_id(id).await.map(User::from) })
+    }
+
+    fn find_by_email<'a>(&'a self, email: &'a str) -> DirectoryFuture<'a, User> {
+        Box::pin(async move { self.find_user_by_email(email).await.map(User::from) })
+    }
+
+    fn list<'a>(&'a self, limit: i64, after_id: Option<&'a str>) -> DirectoryFuture<'a, Vec<User>> {
+        Box::pin(async move {
+            self.list_users(limit, after_id)
+                .await
+                .map(|users| users.into_iter().map(User::from).collect())
+        })
+    }
+}
+
+pub struct AuthGrpcServer<A: TokenAuthority, D: UserDirectory> {
+    authority: Arc<A>,
+    directory: Arc<D>,
+}
+
+impl<A: TokenAuthority, D: UserDirectory> AuthGrpcServer<A, D> {
+    pub fn new(authority: Arc<A>, directory: Arc<D>) -> AuthGrpcServer<A, D> {
+        AuthGrpcServer {
+            authority,
+            directory,
+        }
+    }
+
+    fn bearer<T>(request: &Request<T>) -> Option<&str> {
+        let header = request.metadata().get("authorization")?.to_str().ok()?;
+        Some(header.strip_prefix("Bearer ").unwrap_or(header).trim())
+    }
+
+    /// The caller must send a token granting `user:read` in the `authorization` metadata.
+    async fn authorize_read<T>(&self, request: &Request<T>) -> Result<SubjectGrants, Status> {
+        let token = Self::bearer(request)
+            .ok_or_else(|| Status::unauthenticated("Missing authorization metadata"))?;
+        let grants = self
+            .authority
+            .resolve_token(token)
+            .await
+            .map_err(|_| Status::unauthenticated("Unauthorized"))?;
+
+        match AccessControl::from_permissions(&grants.permissions, USER_READ) {
+            Authorization::Authorized => Ok(grants),
+            Authorization::Unauthorized(_) => Err(Status::permission_denied("Forbidden")),
+        }
+    }
+}
+
+/// User of a token, its organization being the one of the user record as `GetUser` returns it.
+fn user_from_grants(grants: SubjectGrants, organization: String) -> User {
+    User {
+        id: grants.user_id,
+        email: grants.subject,
+        roles: grants.roles.iter().map(|role| role.to_string()).collect(),
+        organization,
+    }
+}
+
+fn lookup_error(err: sqlx::Error) -> Status {
+    match err {
+        sqlx::Error::RowNotFound => Status::not_found("User not found"),
+        err => {
+            log::error!("{:?}", err);
+            Status::internal("Internal server error")
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<A: TokenAuthority, D: UserDirectory> AuthService for AuthGrpcServer<A, D> {
+    async fn verify_token(
+        &self,
+        request: Request<VerifyTokenRequest>,
+    ) -> Result<Response<VerifyTokenResponse>, Status> {
+        let response = match self.authority.resolve_token(&request.get_ref().token).await {
+            Ok(grants) => {
+                let organization = match self.directory.find_by_id(&grants.user_id).await {
+                    Ok(user) => user.organization,
+                    // Service accounts are not users and have no organization.
+                    Err(sqlx::Error::RowNotFound) => String::new(),
+                    Err(err) => return Err(lookup_error(err)),
+                };
+                VerifyTokenResponse {
+                    valid: true,
+                    user: Some(user_from_grants(grants, organization)),
+                }
+            }
+            Err(_) => VerifyTokenResponse {
+                valid: false,
+                user: None,
+            },
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_user(&self, request: Request<GetUserRequest>) -> Result<Response<User>, Status> {
+        self.authorize_read(&request).await?;
+
+        let user = match &request.get_ref().lookup {
+            Some(Lookup::Id(id)) => self.directory.find_by_id(id).await,
+            Some(Lookup::Email(email)) => self.directory.find_by_email(email).await,
+            None => return Err(Status::invalid_argument("id or email is required")),
+        };
+
+        user.map(Response::new).map_err(lookup_error)
+    }
+
+    async fn check_roles(
+        &self,
+        request: Request<CheckRolesRequest>,
+    ) -> Result<Response<CheckRolesResponse>, Status> {
+        let request = request.into_inner();
+        let granted_roles = request
+            .roles
+            .iter()
+            .map(|role| Role::from_str(role))
+            .collect::<Result<Vec<Role>, _>>()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let grants = self
+            .authority
+            .resolve_token(&request.token)
+            .await
+            .map_err(|_| Status::unauthenticated("Unauthorized"))?;
+
+        let allowed = matches!(
+            AccessControl::from_role(grants.roles.clone(), granted_roles),
+            Authorization::Authorized
+        );
+
+        Ok(Response::new(CheckRolesResponse {
+            allowed,
+            roles: grants.roles.iter().map(|role| role.to_string()).collect(),
+        }))
+    }
+
+    async fn list_users(
+        &self,
+        request: Request<ListUsersRequest>,
+    ) -> Result<Response<ListUsersResponse>, Status> {
+        self.authorize_read(&request).await?;
+
+        let request = request.into_inner();
+        let page_size = match request.page_size {
+            0 => DEFAULT_PAGE_SIZE,
+            size => size.min(MAX_PAGE_SIZE),
+        };
+        let after_id = Some(request.page_token.as_str()).filter(|token| !token.is_empty());
+
+        // One extra row tells whether there is a next page.
+        let mut users = self
+            .directory
+            .list(page_size as i64 + 1, after_id)
+            .await
+            .map_err(lookup_error)?;
+
+        let next_page_token = if users.len() > page_size as usize {
+            users.truncate(page_size as usize);
+            users.last().map(|user| user.id.clone()).unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(Response::new(ListUsersResponse {
+            users,
+            next_page_token,
+        }))
+    }
+}
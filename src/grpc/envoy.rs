@@ -0,0 +1,131 @@
+//! Subset of the `envoy.service.auth.v3` messages used by the ext_authz server.
+//!
+//! Field numbers follow the upstream definitions
+//! (envoy/service/auth/v3/{attribute_context,external_auth}.proto), fields we do not
+//! read are left out and skipped by the decoder.
+
+include!(concat!(env!("OUT_DIR"), "/envoy.service.auth.v3.Authorization.rs"));
+
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckRequest {
+    #[prost(message, optional, tag = "1")]
+    pub attributes: Option<AttributeContext>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AttributeContext {
+    #[prost(message, optional, tag = "4")]
+    pub request: Option<Request>,
+    #[prost(map = "string, string", tag = "10")]
+    pub context_extensions: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Request {
+    #[prost(message, optional, tag = "2")]
+    pub http: Option<HttpRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HttpRequest {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub method: String,
+    /// Header names are lower-cased by Envoy.
+    #[prost(map = "string, string", tag = "3")]
+    pub headers: HashMap<String, String>,
+    #[prost(string, tag = "4")]
+    pub path: String,
+    #[prost(string, tag = "5")]
+    pub host: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckResponse {
+    #[prost(message, optional, tag = "1")]
+    pub status: Option<Status>,
+    #[prost(oneof = "HttpResponse", tags = "2, 3")]
+    pub http_response: Option<HttpResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum HttpResponse {
+    #[prost(message, tag = "2")]
+    DeniedResponse(DeniedHttpResponse),
+    #[prost(message, tag = "3")]
+    OkResponse(OkHttpResponse),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeniedHttpResponse {
+    #[prost(message, optional, tag = "1")]
+    pub status: Option<HttpStatus>,
+    #[prost(message, repeated, tag = "2")]
+    pub headers: Vec<HeaderValueOption>,
+    #[prost(string, tag = "3")]
+    pub body: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OkHttpResponse {
+    #[prost(message, repeated, tag = "2")]
+    pub headers: Vec<HeaderValueOption>,
+    #[prost(string, repeated, tag = "5")]
+    pub headers_to_remove: Vec<String>,
+}
+
+/// `google.rpc.Status`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Status {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+/// `envoy.type.v3.HttpStatus`, `code` holds the HTTP status code.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HttpStatus {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+}
+
+/// `envoy.config.core.v3.HeaderValueOption`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HeaderValueOption {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<HeaderValue>,
+    /// `HeaderAppendAction`, 2 is `OVERWRITE_IF_EXISTS_OR_ADD`.
+    #[prost(int32, tag = "3")]
+    pub append_action: i32,
+}
+
+/// `envoy.config.core.v3.HeaderValue`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HeaderValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+pub const RPC_OK: i32 = 0;
+pub const RPC_INVALID_ARGUMENT: i32 = 3;
+pub const RPC_PERMISSION_DENIED: i32 = 7;
+pub const RPC_UNAUTHENTICATED: i32 = 16;
+pub const OVERWRITE_IF_EXISTS_OR_ADD: i32 = 2;
+
+impl HeaderValueOption {
+    pub fn overwrite(key: &str, value: &str) -> HeaderValueOption {
+        HeaderValueOption {
+            header: Some(HeaderValue {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            }),
+            append_action: OVERWRITE_IF_EXISTS_OR_ADD,
+        }
+    }
+}
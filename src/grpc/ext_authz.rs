@@ -0,0 +1,118 @@
+use crate::grpc::envoy::authorization_server::Authorization;
+use crate::grpc::envoy::{
+    CheckRequest, CheckResponse, DeniedHttpResponse, HeaderValueOption, HttpRequest,
+    HttpResponse, HttpStatus, OkHttpResponse, Status, RPC_INVALID_ARGUMENT, RPC_OK,
+    RPC_PERMISSION_DENIED, RPC_UNAUTHENTICATED,
+};
+use crate::services::access_control::{AccessControl, TokenAuthority};
+use crate::services::forward_auth::{ForwardAuthDecision, ForwardAuthRules};
+use actix_web::http::header::HeaderValue;
+use std::sync::Arc;
+
+/// Injected upstream on allowed requests, stripped from public ones so clients cannot forge them.
+const IDENTITY_HEADERS: [&str; 3] = ["x-auth-user-id", "x-auth-email", "x-auth-roles"];
+
+/// Envoy `ext_authz` server. Applies the same per-path rules as the forward-auth endpoint,
+/// to the canonical path as Envoy does not normalize it by default, and injects the identity
+/// headers on allowed requests.
+pub struct ExtAuthzServer<A: TokenAuthority> {
+    authority: Arc<A>,
+    rules: Arc<ForwardAuthRules>,
+}
+
+impl<A: TokenAuthority> ExtAuthzServer<A> {
+    pub fn new(authority: Arc<A>, rules: Arc<ForwardAuthRules>) -> ExtAuthzServer<A> {
+        ExtAuthzServer { authority, rules }
+    }
+
+    /// Token from the `authorization` header, with or without `Bearer`, or from the cookie.
+    fn token(http: &HttpRequest) -> Option<String> {
+        if let Some(header) = http.headers.get("authorization") {
+            return Some(header.strip_prefix("Bearer ").unwrap_or(header).trim().to_owned());
+        }
+
+        let cookie = http
+            .headers
+            .get("cookie")
+            .and_then(|cookie| HeaderValue::from_str(cookie).ok())?;
+        AccessControl::token_from_cookie(Some(&cookie)).ok()
+    }
+
+    pub async fn decide(&self, request: CheckRequest) -> CheckResponse {
+        let http = request
+            .attributes
+            .and_then(|attributes| attributes.request)
+            .and_then(|request| request.http)
+            .unwrap_or_default();
+
+        if self.rules.is_public(&http.method, &http.path) {
+            return ok_response(vec![], IDENTITY_HEADERS.map(String::from).to_vec());
+        }
+
+        let grants = match Self::token(&http) {
+            Some(token) => self.authority.resolve_token(&token).await,
+            None => return denied_response(RPC_UNAUTHENTICATED, 401, "Unauthorized"),
+        };
+        let grants = match grants {
+            Ok(grants) => grants,
+            Err(_) => return denied_response(RPC_UNAUTHENTICATED, 401, "Unauthorized"),
+        };
+
+        match self.rules.evaluate(&http.method, &http.path, &grants.roles) {
+            ForwardAuthDecision::Forbidden => {
+                denied_response(RPC_PERMISSION_DENIED, 403, "Forbidden")
+            }
+            ForwardAuthDecision::InvalidPath => {
+                denied_response(RPC_INVALID_ARGUMENT, 400, "Invalid path")
+            }
+            ForwardAuthDecision::Public | ForwardAuthDecision::Allowed => {
+                let roles: Vec<String> = grants.roles.iter().map(|role| role.to_string()).collect();
+                ok_response(
+                    vec![
+                        HeaderValueOption::overwrite(IDENTITY_HEADERS[0], &grants.user_id),
+                        HeaderValueOption::overwrite(IDENTITY_HEADERS[1], &grants.subject),
+                        HeaderValueOption::overwrite(IDENTITY_HEADERS[2], &roles.join(",")),
+                    ],
+                    vec![],
+                )
+            }
+        }
+    }
+}
+
+fn ok_response(headers: Vec<HeaderValueOption>, headers_to_remove: Vec<String>) -> CheckResponse {
+    CheckResponse {
+        status: Some(Status {
+            code: RPC_OK,
+            message: String::new(),
+        }),
+        http_response: Some(HttpResponse::OkResponse(OkHttpResponse {
+            headers,
+            headers_to_remove,
+        })),
+    }
+}
+
+fn denied_response(rpc_code: i32, http_code: i32, message: &str) -> CheckResponse {
+    CheckResponse {
+        status: Some(Status {
+            code: rpc_code,
+            message: message.to_owned(),
+        }),
+        http_response: Some(HttpResponse::DeniedResponse(DeniedHttpResponse {
+            status: Some(HttpStatus { code: http_code }),
+            headers: vec![HeaderValueOption::overwrite("content-type", "application/json")],
+            body: format!("{{\"message\":\"{}\"}}", message),
+        })),
+    }
+}
+
+#[tonic::async_trait]
+impl<A: TokenAuthority> Authorization for ExtAuthzServer<A> {
+    async fn check(
+        &self,
+        request: tonic::Request<CheckRequest>,
+    ) -> Result<tonic::Response<CheckResponse>, tonic::Status> {
+        Ok(tonic::Response::new(self.decide(request.into_inner()).await))
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::{Error, ErrorKind};
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+pub mod auth;
+pub mod envoy;
+pub mod ext_authz;
+
+/// TLS settings of the gRPC API. `GRPC_TLS_CERT` and `GRPC_TLS_KEY` enable TLS,
+/// `GRPC_TLS_CLIENT_CA` additionally requires client certificates signed by that CA (mTLS).
+pub fn tls_config_from_env() -> Result<Option<ServerTlsConfig>, Error> {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+    let (cert, key) = match (var("GRPC_TLS_CERT"), var("GRPC_TLS_KEY")) {
+        (Some(cert), Some(key)) => (std::fs::read(cert)?, std::fs::read(key)?),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "GRPC_TLS_CERT and GRPC_TLS_KEY must be set together",
+            ))
+        }
+    };
+
+    let mut config = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    if let Some(client_ca) = var("GRPC_TLS_CLIENT_CA") {
+        config = config.client_ca_root(Certificate::from_pem(std::fs::read(client_ca)?));
+    }
+
+    Ok(Some(config))
+}
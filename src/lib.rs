@@ -2,4 +2,5 @@ pub mod services;
 pub mod repository;
 pub mod controllers;
 pub mod database;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod grpc;
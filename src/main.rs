@@ -1,40 +1,213 @@
 use std::sync::Arc;
 
-use crate::database::{Database, DatabaseService};
 use actix_web::{web, App, HttpServer};
-use auth_api::{config, controllers::ping};
-use controllers::{v1::get_v1_service, AppState};
+use auth_api::config;
+use auth_api::controllers::{
+    configured_base_url, jwks, oauth::get_oauth_service, openid_configuration, ping,
+    saml::get_saml_service, scim::get_scim_service, v1::get_v1_service, AppState,
+};
+use auth_api::database::{Database, DatabaseService};
+use auth_api::grpc;
+use auth_api::grpc::auth::auth_service_server::AuthServiceServer;
+use auth_api::grpc::auth::AuthGrpcServer;
+use auth_api::grpc::envoy::authorization_server::AuthorizationServer;
+use auth_api::grpc::ext_authz::ExtAuthzServer;
+use auth_api::repository::Repository;
+use auth_api::services::access_control::AccessControl;
+use auth_api::services::authenticator::Authenticators;
+use auth_api::services::authz::RelationAuthz;
+use auth_api::services::claims::ClaimsEnricher;
+use auth_api::services::crypto::{check_signing_config, TokenConfig};
+use auth_api::services::forward_auth::ForwardAuthRules;
+use auth_api::services::identity_provider::IdentityProviders;
+use auth_api::services::ldap::LdapDirectory;
+use auth_api::services::mailer::mailer_from_env;
+use auth_api::services::policy::PolicyEngine;
+use auth_api::services::revocation::RevocationList;
+use auth_api::services::saml::SamlProviders;
+use auth_api::services::scim::ScimClients;
+use auth_api::services::sms::sms_sender_from_env;
 use log::info;
-use repository::Repository;
-use services::access_control::AccessControl;
+use std::time::Duration;
 
-mod controllers;
-mod database;
-mod repository;
-mod services;
+/// How often expired authorization codes, device codes, tokens, external logins, SAML
+/// requests and magic links are deleted.
+const OAUTH_CLEANUP_INTERVAL: Duration = Duration::from_secs(300);
+/// How often the revocations made by other instances are loaded.
+const REVOCATION_SYNC_INTERVAL: Duration = Duration::from_secs(30);
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
+    check_signing_config();
 
     DatabaseService::new().migrations_migrate().await;
 
     config::account::create_super_admin_account().await;
 
-    let state = AppState {
-        repository: Arc::from(Repository::new().await),
-        access_control: Arc::from(AccessControl::new().await),
-    };
+    let policy_engine = PolicyEngine::from_env()
+        .unwrap_or_else(|err| panic!("Failed to load policies : {:?}", err));
+    let relation_authz = RelationAuthz::from_env()
+        .unwrap_or_else(|err| panic!("Failed to load authz namespaces : {:?}", err));
+    let forward_auth_rules = ForwardAuthRules::from_env()
+        .unwrap_or_else(|err| panic!("Failed to load forward-auth rules : {:?}", err));
+
+    let claims_pool = DatabaseService::new().database_connection().await;
+    let claims_enricher = ClaimsEnricher::from_env(claims_pool)
+        .unwrap_or_else(|err| panic!("Failed to load claims providers : {:?}", err));
+    let identity_providers = Arc::new(
+        IdentityProviders::from_env()
+            .unwrap_or_else(|err| panic!("Failed to load identity providers : {:?}", err)),
+    );
+    let saml_providers = SamlProviders::from_env()
+        .unwrap_or_else(|err| panic!("Failed to load SAML providers : {:?}", err));
+    let ldap_directory = LdapDirectory::from_env()
+        .unwrap_or_else(|err| panic!("Failed to load the LDAP directory : {:?}", err));
+    let scim_clients = ScimClients::from_env()
+        .unwrap_or_else(|err| panic!("Failed to load SCIM clients : {:?}", err));
+    let mailer =
+        mailer_from_env().unwrap_or_else(|err| panic!("Failed to load the mailer : {:?}", err));
+    let sms_sender = sms_sender_from_env();
+    if configured_base_url(&TokenConfig::from_env()).is_none() {
+        log::warn!("Neither PUBLIC_BASE_URL nor JWT_ISSUER is a URL, sign-in links are disabled");
+    }
+
+    let access_control = AccessControl::new().await;
+    let ext_authz = ExtAuthzServer::new(
+        Arc::new(access_control.clone()),
+        Arc::new(forward_auth_rules.clone()),
+    );
+
+    let repository = Repository::new().await;
+    let cleanup_repository = repository.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(OAUTH_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let codes = cleanup_repository.delete_expired_oauth_codes().await;
+            let tokens = cleanup_repository.delete_expired_oauth_tokens().await;
+            match codes.and_then(|codes| tokens.map(|tokens| codes + tokens)) {
+                Ok(0) => {}
+                Ok(deleted) => info!("🧹 Deleted {} expired OAuth codes and tokens", deleted),
+                Err(err) => log::error!("Failed to delete expired OAuth codes : {:?}", err),
+            }
+            if let Err(err) = cleanup_repository.delete_expired_external_logins().await {
+                log::error!("Failed to delete expired external logins : {:?}", err);
+            }
+            if let Err(err) = cleanup_repository.delete_expired_saml_requests().await {
+                log::error!("Failed to delete expired SAML requests : {:?}", err);
+            }
+            if let Err(err) = cleanup_repository.delete_expired_magic_links().await {
+                log::error!("Failed to delete expired magic links : {:?}", err);
+            }
+            if let Err(err) = cleanup_repository.delete_expired_sms_codes().await {
+                log::error!("Failed to delete expired SMS codes : {:?}", err);
+            }
+        }
+    });
+
+    let revocation_repository = repository.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REVOCATION_SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            match revocation_repository.list_revoked_access_tokens().await {
+                Ok(revoked) => RevocationList::sync(
+                    revoked
+                        .into_iter()
+                        .map(|(jti, expires_at)| (jti, expires_at.timestamp() as u64)),
+                ),
+                Err(err) => log::error!("Failed to load revoked tokens : {:?}", err),
+            }
+        }
+    });
+
+    let authenticators = Authenticators::from_env(
+        repository.clone(),
+        access_control.clone(),
+        ldap_directory.map(Arc::new),
+        identity_providers.clone(),
+    )
+    .unwrap_or_else(|err| panic!("Failed to load authenticators : {:?}", err));
+
+    let auth_grpc = AuthGrpcServer::new(
+        Arc::new(access_control.clone()),
+        Arc::new(repository.clone()),
+    );
+
+    let state = AppState::new(
+        repository,
+        access_control,
+        policy_engine,
+        relation_authz,
+        forward_auth_rules,
+        claims_enricher,
+        identity_providers,
+        saml_providers,
+        authenticators,
+        scim_clients,
+        mailer,
+        sms_sender,
+    );
 
     let port = std::env::var("PORT").unwrap_or_else(|_| String::from("4000"));
     let ipv4 = "0.0.0.0";
 
+    let ext_authz_port = std::env::var("EXT_AUTHZ_PORT").unwrap_or_else(|_| String::from("9001"));
+    let ext_authz_addr = format!("{}:{}", ipv4, ext_authz_port)
+        .parse()
+        .unwrap_or_else(|err| panic!("Invalid EXT_AUTHZ_PORT : {:?}", err));
+
+    info!("🛡️ Envoy ext_authz server listening on {}", ext_authz_addr);
+    tokio::spawn(async move {
+        if let Err(err) = tonic::transport::Server::builder()
+            .add_service(AuthorizationServer::new(ext_authz))
+            .serve(ext_authz_addr)
+            .await
+        {
+            log::error!("ext_authz server stopped : {:?}", err);
+        }
+    });
+
+    let grpc_port = std::env::var("GRPC_PORT").unwrap_or_else(|_| String::from("50051"));
+    let grpc_addr = format!("{}:{}", ipv4, grpc_port)
+        .parse()
+        .unwrap_or_else(|err| panic!("Invalid GRPC_PORT : {:?}", err));
+    let grpc_tls = grpc::tls_config_from_env()
+        .unwrap_or_else(|err| panic!("Invalid gRPC TLS configuration : {:?}", err));
+
+    info!(
+        "🔌 gRPC API listening on {} (TLS: {})",
+        grpc_addr,
+        grpc_tls.is_some()
+    );
+    tokio::spawn(async move {
+        let mut builder = tonic::transport::Server::builder();
+        if let Some(tls) = grpc_tls {
+            builder = builder
+                .tls_config(tls)
+                .unwrap_or_else(|err| panic!("Invalid gRPC TLS configuration : {:?}", err));
+        }
+        if let Err(err) = builder
+            .add_service(AuthServiceServer::new(auth_grpc))
+            .serve(grpc_addr)
+            .await
+        {
+            log::error!("gRPC server stopped : {:?}", err);
+        }
+    });
+
     info!("📡 Server started ! Listening on {}:{}", ipv4, port);
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(state.clone()))
             .service(ping)
+            .service(jwks)
+            .service(openid_configuration)
+            .service(get_oauth_service())
+            .service(get_saml_service())
+            .service(get_scim_service())
             .service(get_v1_service())
     })
     .bind((ipv4, port.parse::<u16>().unwrap()))?
@@ -0,0 +1,85 @@
+use crate::repository::user_repository::User;
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+/// API key of a user, as listed to them : the key itself is only shown on creation.
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl Repository {
+    pub async fn save_api_key(
+        &self,
+        user_id: &str,
+        name: &str,
+        key_hash: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, Error> {
+        sqlx::query_as::<_, ApiKey>(
+            "\
+            INSERT INTO public.api_keys (user_id, name, key_hash, expires_at) \
+            VALUES ($1, $2, $3, $4) \
+            RETURNING id, name, created_at, expires_at, last_used_at\
+            ",
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(key_hash)
+        .bind(expires_at)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn list_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        sqlx::query_as::<_, ApiKey>(
+            "\
+            SELECT id, name, created_at, expires_at, last_used_at \
+            FROM public.api_keys \
+            WHERE user_id=$1 \
+            ORDER BY created_at DESC\
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Delete a key of the user, `RowNotFound` when they have no such key.
+    pub async fn delete_api_key(&self, user_id: &str, id: &str) -> Result<(), Error> {
+        let res = sqlx::query("DELETE FROM public.api_keys WHERE user_id=$1 AND id=$2")
+            .bind(user_id)
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Active user of an unexpired key, its last use is recorded.
+    pub async fn find_user_by_api_key(&self, key_hash: &str) -> Result<User, Error> {
+        sqlx::query_as::<_, User>(
+            "\
+        WITH api_key AS ( \
+            UPDATE public.api_keys SET last_used_at=now() \
+            WHERE key_hash=$1 \
+            AND (expires_at IS NULL OR expires_at > now()) \
+            RETURNING user_id \
+        ) \
+        SELECT u.id, u.email, u.password, u.role, u.organization, u.name, u.email_verified \
+        FROM public.user u \
+        JOIN api_key ON api_key.user_id = u.id \
+        WHERE u.deleted_at IS NULL\
+        ",
+        )
+        .bind(key_hash)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+}
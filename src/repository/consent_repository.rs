@@ -0,0 +1,98 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+/// Scopes granted by the user to a client, as listed to the user.
+#[derive(FromRow, Serialize, Deserialize, Clone)]
+pub struct Consent {
+    pub client_id: String,
+    pub client_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Repository {
+    /// Scopes granted by the user to the client, `RowNotFound` without consent.
+    pub async fn find_consent_scopes(
+        &self,
+        user_id: &str,
+        client_id: &str,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar(
+            "SELECT scopes FROM public.oauth_consents WHERE user_id=$1 AND client_id=$2",
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Record the consent of the user, adding the scopes to those already granted.
+    pub async fn save_consent(
+        &self,
+        user_id: &str,
+        client_id: &str,
+        scopes: &[String],
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.oauth_consents (user_id, client_id, scopes) VALUES ($1, $2, $3) \
+            ON CONFLICT (user_id, client_id) DO UPDATE SET \
+            scopes = ARRAY(SELECT DISTINCT unnest(oauth_consents.scopes || EXCLUDED.scopes) ORDER BY 1), \
+            updated_at = now()\
+            ",
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(scopes)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_consents(&self, user_id: &str) -> Result<Vec<Consent>, Error> {
+        sqlx::query_as::<_, Consent>(
+            "\
+            SELECT g.client_id, c.name AS client_name, c.logo_uri, g.scopes, g.created_at, g.updated_at \
+            FROM public.oauth_consents g \
+            JOIN public.oauth_clients c ON c.client_id = g.client_id \
+            WHERE g.user_id=$1 \
+            ORDER BY g.updated_at DESC\
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Delete the consent and revoke the refresh tokens the user delegated to the client.
+    pub async fn revoke_consent(&self, user_id: &str, client_id: &str) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let res =
+            sqlx::query("DELETE FROM public.oauth_consents WHERE user_id=$1 AND client_id=$2")
+                .bind(user_id)
+                .bind(client_id)
+                .execute(&mut *tx)
+                .await?;
+        self.is_row_affected(res.rows_affected(), 1)?;
+
+        sqlx::query(
+            "\
+            UPDATE public.oauth_refresh_tokens SET revoked_at = now() \
+            WHERE user_id=$1 AND client_id=$2 AND revoked_at IS NULL\
+            ",
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+}
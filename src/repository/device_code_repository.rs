@@ -0,0 +1,166 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, FromRow};
+
+#[derive(FromRow)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub client_id: String,
+    pub scope: String,
+    pub status: String,
+    pub user_id: Option<String>,
+    pub auth_time: Option<DateTime<Utc>>,
+    pub interval_seconds: i32,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// State of a device code when the device polls, with the time of the previous poll.
+#[derive(FromRow)]
+pub struct DeviceCodePoll {
+    pub client_id: String,
+    pub status: String,
+    pub interval_seconds: i32,
+    pub expires_at: DateTime<Utc>,
+    pub previous_poll: Option<DateTime<Utc>>,
+}
+
+pub struct NewDeviceCode<'a> {
+    pub device_code_hash: &'a str,
+    pub user_code: &'a str,
+    pub client_id: &'a str,
+    pub scope: &'a str,
+    pub interval_seconds: i32,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Repository {
+    pub async fn save_device_code(&self, code: NewDeviceCode<'_>) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.oauth_device_codes \
+            (device_code_hash, user_code, client_id, scope, interval_seconds, expires_at) \
+            VALUES ($1, $2, $3, $4, $5, $6)\
+            ",
+        )
+        .bind(code.device_code_hash)
+        .bind(code.user_code)
+        .bind(code.client_id)
+        .bind(code.scope)
+        .bind(code.interval_seconds)
+        .bind(code.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Pending, unexpired, device code of a user code.
+    pub async fn find_pending_device_code(&self, user_code: &str) -> Result<DeviceCode, Error> {
+        sqlx::query_as::<_, DeviceCode>(
+            "\
+            SELECT user_code, client_id, scope, status, user_id, auth_time, interval_seconds, expires_at \
+            FROM public.oauth_device_codes \
+            WHERE user_code=$1 AND status='pending' AND expires_at > now()\
+            ",
+        )
+        .bind(user_code)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Approve a pending device code on behalf of the user.
+    pub async fn approve_device_code(
+        &self,
+        user_code: &str,
+        user_id: &str,
+        auth_time: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            UPDATE public.oauth_device_codes SET status='approved', user_id=$2, auth_time=$3 \
+            WHERE user_code=$1 AND status='pending' AND expires_at > now()\
+            ",
+        )
+        .bind(user_code)
+        .bind(user_id)
+        .bind(auth_time)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    pub async fn deny_device_code(&self, user_code: &str) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            UPDATE public.oauth_device_codes SET status='denied' \
+            WHERE user_code=$1 AND status='pending' AND expires_at > now()\
+            ",
+        )
+        .bind(user_code)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Record a poll of the device and return the state it found.
+    pub async fn poll_device_code(&self, device_code_hash: &str) -> Result<DeviceCodePoll, Error> {
+        sqlx::query_as::<_, DeviceCodePoll>(
+            "\
+            UPDATE public.oauth_device_codes d SET last_polled_at = now() \
+            FROM ( \
+                SELECT last_polled_at AS previous_poll FROM public.oauth_device_codes \
+                WHERE device_code_hash=$1 FOR UPDATE \
+            ) previous \
+            WHERE d.device_code_hash=$1 \
+            RETURNING d.client_id, d.status, d.interval_seconds, d.expires_at, previous.previous_poll\
+            ",
+        )
+        .bind(device_code_hash)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// RFC 8628 section 3.5 : the interval is increased by 5 seconds on `slow_down`.
+    pub async fn slow_down_device_code(&self, device_code_hash: &str) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            UPDATE public.oauth_device_codes SET interval_seconds = interval_seconds + 5 \
+            WHERE device_code_hash=$1\
+            ",
+        )
+        .bind(device_code_hash)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark an approved device code as used and return it, a device code grants tokens once.
+    pub async fn consume_device_code(&self, device_code_hash: &str) -> Result<DeviceCode, Error> {
+        sqlx::query_as::<_, DeviceCode>(
+            "\
+            UPDATE public.oauth_device_codes SET status='consumed' \
+            WHERE device_code_hash=$1 AND status='approved' AND expires_at > now() \
+            RETURNING user_code, client_id, scope, status, user_id, auth_time, interval_seconds, expires_at\
+            ",
+        )
+        .bind(device_code_hash)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Delete expired device and authorization codes, returns how many were deleted.
+    pub async fn delete_expired_oauth_codes(&self) -> Result<u64, Error> {
+        let devices = sqlx::query("DELETE FROM public.oauth_device_codes WHERE expires_at < now()")
+            .execute(&self.db_pool)
+            .await?;
+        let codes =
+            sqlx::query("DELETE FROM public.oauth_authorization_codes WHERE expires_at < now()")
+                .execute(&self.db_pool)
+                .await?;
+
+        Ok(devices.rows_affected() + codes.rows_affected())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, FromRow};
+
+pub struct NewExternalLogin<'a> {
+    pub state_hash: &'a str,
+    pub provider: &'a str,
+    pub nonce: &'a str,
+    pub code_verifier: &'a str,
+    pub return_to: &'a str,
+    /// User linking the identity, `None` for a login.
+    pub link_user_id: Option<&'a str>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct ExternalLogin {
+    pub nonce: String,
+    pub code_verifier: String,
+    pub return_to: String,
+    pub link_user_id: Option<String>,
+}
+
+impl Repository {
+    pub async fn save_external_login(&self, login: NewExternalLogin<'_>) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.external_login_states \
+            (state_hash, provider, nonce, code_verifier, return_to, link_user_id, expires_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7)\
+            ",
+        )
+        .bind(login.state_hash)
+        .bind(login.provider)
+        .bind(login.nonce)
+        .bind(login.code_verifier)
+        .bind(login.return_to)
+        .bind(login.link_user_id)
+        .bind(login.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unexpired login of a state, deleted so that it can only be completed once.
+    pub async fn consume_external_login(
+        &self,
+        state_hash: &str,
+        provider: &str,
+    ) -> Result<ExternalLogin, Error> {
+        sqlx::query_as::<_, ExternalLogin>(
+            "\
+            DELETE FROM public.external_login_states \
+            WHERE state_hash=$1 \
+            AND provider=$2 \
+            AND expires_at > now() \
+            RETURNING nonce, code_verifier, return_to, link_user_id\
+            ",
+        )
+        .bind(state_hash)
+        .bind(provider)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Nonce for a `login` with an ID token of `provider`.
+    pub async fn save_login_nonce(
+        &self,
+        nonce_hash: &str,
+        provider: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO public.login_nonces (nonce_hash, provider, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(nonce_hash)
+        .bind(provider)
+        .bind(expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete an unexpired nonce so that it is only accepted once, `RowNotFound` when it is
+    /// unknown, expired, of another provider or already used.
+    pub async fn consume_login_nonce(&self, nonce_hash: &str, provider: &str) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            DELETE FROM public.login_nonces \
+            WHERE nonce_hash=$1 \
+            AND provider=$2 \
+            AND expires_at > now()\
+            ",
+        )
+        .bind(nonce_hash)
+        .bind(provider)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Delete the expired logins in progress and login nonces.
+    pub async fn delete_expired_external_logins(&self) -> Result<u64, Error> {
+        let res = sqlx::query(
+            "\
+            WITH nonces AS (DELETE FROM public.login_nonces WHERE expires_at < now()) \
+            DELETE FROM public.external_login_states WHERE expires_at < now()\
+            ",
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+}
@@ -0,0 +1,163 @@
+use crate::repository::user_repository::User;
+use crate::repository::Repository;
+use crate::services::identity_provider::ExternalIdentity;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+/// Constraint violated when a user links a second identity of a provider.
+pub const IDENTITY_USER_PROVIDER_CONSTRAINT: &str = "identities_user_provider_key";
+
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct Identity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Unlink {
+    Unlinked,
+    /// The identity is the only way left for the user to sign in, it is kept.
+    LastCredential,
+}
+
+impl Repository {
+    /// User of an identity, its last login is recorded.
+    pub async fn find_user_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<User, Error> {
+        sqlx::query_as::<_, User>(
+            "\
+        WITH identity AS ( \
+            UPDATE public.identities SET last_login_at=now() \
+            WHERE provider=$1 AND subject=$2 \
+            RETURNING user_id \
+        ) \
+        SELECT u.id, u.email, u.password, u.role, u.organization, u.name, u.email_verified \
+        FROM public.user u \
+        JOIN identity ON identity.user_id = u.id \
+        WHERE u.deleted_at IS NULL\
+        ",
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Provision the user of an identity on their first login. They have no password, the
+    /// empty hash never matches one.
+    pub async fn save_user_with_identity(
+        &self,
+        identity: &ExternalIdentity,
+        role: Vec<String>,
+    ) -> Result<User, Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let user = sqlx::query_as::<_, User>(
+            "\
+            INSERT INTO public.user (email, password, role, name, email_verified) \
+            VALUES ($1, '', $2, $3, $4) \
+            RETURNING id, email, password, role, organization, name, email_verified\
+            ",
+        )
+        .bind(&identity.email)
+        .bind(role)
+        .bind(&identity.name)
+        .bind(identity.email_verified)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "\
+            INSERT INTO public.identities (provider, subject, user_id, email, last_login_at) \
+            VALUES ($1, $2, $3, $4, now())\
+            ",
+        )
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(&user.id)
+        .bind(&identity.email)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(user)
+    }
+
+    /// Fails with a unique violation when the identity is linked to a user already, or when
+    /// the user has an identity of this provider (see [`IDENTITY_USER_PROVIDER_CONSTRAINT`]).
+    pub async fn link_identity(
+        &self,
+        user_id: &str,
+        identity: &ExternalIdentity,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.identities (provider, subject, user_id, email, last_login_at) \
+            VALUES ($1, $2, $3, $4, now())\
+            ",
+        )
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(user_id)
+        .bind(&identity.email)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_identities(&self, user_id: &str) -> Result<Vec<Identity>, Error> {
+        sqlx::query_as::<_, Identity>(
+            "\
+            SELECT provider, subject, email, created_at, last_login_at \
+            FROM public.identities \
+            WHERE user_id=$1 \
+            ORDER BY created_at\
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Unlink the identity of a provider, unless the user would have no password nor
+    /// identity left to sign in with. The user row is locked so that concurrent unlinks
+    /// cannot remove both of the last two credentials.
+    pub async fn unlink_identity(&self, user_id: &str, provider: &str) -> Result<Unlink, Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let password: String = sqlx::query_scalar(
+            "SELECT password FROM public.user WHERE id=$1 AND deleted_at IS NULL FOR UPDATE",
+        )
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let res = sqlx::query("DELETE FROM public.identities WHERE user_id=$1 AND provider=$2")
+            .bind(user_id)
+            .bind(provider)
+            .execute(&mut *tx)
+            .await?;
+        self.is_row_affected(res.rows_affected(), 1)?;
+
+        let identities: i64 =
+            sqlx::query_scalar("SELECT count(*) FROM public.identities WHERE user_id=$1")
+                .bind(user_id)
+                .fetch_one(&mut *tx)
+                .await?;
+        if password.is_empty() && identities == 0 {
+            tx.rollback().await?;
+            return Ok(Unlink::LastCredential);
+        }
+
+        tx.commit().await?;
+        Ok(Unlink::Unlinked)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, FromRow};
+
+pub struct NewMagicLink<'a> {
+    pub token_hash: &'a str,
+    pub user_id: &'a str,
+    pub nonce_hash: &'a str,
+    pub return_to: &'a str,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct MagicLink {
+    pub user_id: String,
+    pub return_to: String,
+}
+
+impl Repository {
+    pub async fn save_magic_link(&self, link: NewMagicLink<'_>) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.magic_links (token_hash, user_id, nonce_hash, return_to, expires_at) \
+            VALUES ($1, $2, $3, $4, $5)\
+            ",
+        )
+        .bind(link.token_hash)
+        .bind(link.user_id)
+        .bind(link.nonce_hash)
+        .bind(link.return_to)
+        .bind(link.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unexpired link of a token opened in the browser with the nonce, deleted so that it can
+    /// only be used once. Opened in another browser, the link is kept.
+    pub async fn consume_magic_link(
+        &self,
+        token_hash: &str,
+        nonce_hash: &str,
+    ) -> Result<MagicLink, Error> {
+        sqlx::query_as::<_, MagicLink>(
+            "\
+            DELETE FROM public.magic_links \
+            WHERE token_hash=$1 \
+            AND nonce_hash=$2 \
+            AND expires_at > now() \
+            RETURNING user_id, return_to\
+            ",
+        )
+        .bind(token_hash)
+        .bind(nonce_hash)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn delete_expired_magic_links(&self) -> Result<u64, Error> {
+        let res = sqlx::query("DELETE FROM public.magic_links WHERE expires_at < now()")
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(res.rows_affected())
+    }
+}
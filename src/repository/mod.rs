@@ -1,6 +1,20 @@
 use sqlx::{Error, Pool, Postgres};
 use crate::database::{Database, DatabaseService};
 
+pub mod api_key_repository;
+pub mod consent_repository;
+pub mod device_code_repository;
+pub mod external_login_repository;
+pub mod identity_repository;
+pub mod magic_link_repository;
+pub mod oauth_repository;
+pub mod phone_repository;
+pub mod role_repository;
+pub mod saml_repository;
+pub mod scim_repository;
+pub mod service_account_repository;
+pub mod token_repository;
+pub mod tuple_repository;
 pub mod user_repository;
 
 #[derive(Clone)]
@@ -0,0 +1,280 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{Error, FromRow, PgExecutor};
+
+#[derive(FromRow, Serialize, Deserialize, Clone)]
+pub struct OAuthClient {
+    pub client_id: String,
+    #[serde(skip_serializing)]
+    pub client_secret_hash: Option<String>,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub token_endpoint_auth_method: String,
+    /// Public keys verifying the `private_key_jwt` assertions of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<Json<JwkSet>>,
+    /// Users are not asked to consent to the scopes of first-party clients.
+    pub first_party: bool,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub metadata: ClientMetadata,
+    #[serde(skip_serializing)]
+    pub previous_client_secret_hash: Option<String>,
+    /// End of the grace period of the secret replaced by the last rotation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_secret_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Optional settings of a client, with the names of RFC 7591 section 2.
+#[derive(FromRow, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ClientMetadata {
+    /// Lifetime of the access tokens issued to the client, the one of the audience otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token_lifetime_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token_lifetime_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tos_uri: Option<String>,
+}
+
+pub struct NewOAuthClient {
+    pub client_secret_hash: Option<String>,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub token_endpoint_auth_method: String,
+    pub jwks: Option<JwkSet>,
+    pub first_party: bool,
+    pub metadata: ClientMetadata,
+}
+
+/// New settings of a client. The secret is only replaced when `client_secret_hash` is
+/// set, and dropped with `clear_secret` when the client no longer authenticates with one.
+pub struct OAuthClientUpdate {
+    pub client: NewOAuthClient,
+    pub clear_secret: bool,
+}
+
+const OAUTH_CLIENT_COLUMNS: &str = "\
+    client_id, client_secret_hash, name, redirect_uris, scopes, grant_types, \
+    token_endpoint_auth_method, jwks, first_party, access_token_lifetime_seconds, \
+    refresh_token_lifetime_seconds, logo_uri, client_uri, policy_uri, tos_uri, \
+    previous_client_secret_hash, previous_secret_expires_at, created_at";
+
+/// Insert a client, within the transaction of a service account for instance.
+pub(crate) async fn insert_oauth_client<'e, E: PgExecutor<'e>>(
+    executor: E,
+    client: NewOAuthClient,
+) -> Result<OAuthClient, Error> {
+    sqlx::query_as::<_, OAuthClient>(&format!(
+        "\
+        INSERT INTO public.oauth_clients \
+        (client_secret_hash, name, redirect_uris, scopes, grant_types, token_endpoint_auth_method, jwks, \
+        access_token_lifetime_seconds, refresh_token_lifetime_seconds, logo_uri, client_uri, policy_uri, tos_uri, \
+        first_party) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) \
+        RETURNING {}\
+        ",
+        OAUTH_CLIENT_COLUMNS
+    ))
+    .bind(client.client_secret_hash)
+    .bind(client.name)
+    .bind(client.redirect_uris)
+    .bind(client.scopes)
+    .bind(client.grant_types)
+    .bind(client.token_endpoint_auth_method)
+    .bind(client.jwks.map(Json))
+    .bind(client.metadata.access_token_lifetime_seconds)
+    .bind(client.metadata.refresh_token_lifetime_seconds)
+    .bind(client.metadata.logo_uri)
+    .bind(client.metadata.client_uri)
+    .bind(client.metadata.policy_uri)
+    .bind(client.metadata.tos_uri)
+    .bind(client.first_party)
+    .fetch_one(executor)
+    .await
+}
+
+#[derive(FromRow)]
+pub struct AuthorizationCode {
+    pub client_id: String,
+    pub user_id: String,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub code_challenge: String,
+    pub code_challenge_method: String,
+    pub nonce: Option<String>,
+    pub auth_time: DateTime<Utc>,
+}
+
+pub struct NewAuthorizationCode<'a> {
+    pub code_hash: &'a str,
+    pub client_id: &'a str,
+    pub user_id: &'a str,
+    pub redirect_uri: &'a str,
+    pub scope: &'a str,
+    pub code_challenge: &'a str,
+    pub code_challenge_method: &'a str,
+    pub nonce: Option<&'a str>,
+    /// When the user authenticated, the `auth_time` of the ID token.
+    pub auth_time: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Repository {
+    pub async fn save_oauth_client(&self, client: NewOAuthClient) -> Result<OAuthClient, Error> {
+        insert_oauth_client(&self.db_pool, client).await
+    }
+
+    pub async fn find_oauth_client(&self, client_id: &str) -> Result<OAuthClient, Error> {
+        sqlx::query_as::<_, OAuthClient>(&format!(
+            "SELECT {} FROM public.oauth_clients WHERE client_id=$1",
+            OAUTH_CLIENT_COLUMNS
+        ))
+        .bind(client_id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn list_oauth_clients(&self) -> Result<Vec<OAuthClient>, Error> {
+        sqlx::query_as::<_, OAuthClient>(&format!(
+            "SELECT {} FROM public.oauth_clients ORDER BY created_at",
+            OAUTH_CLIENT_COLUMNS
+        ))
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Replace the settings of a client, service accounts are managed on their own.
+    pub async fn update_oauth_client(
+        &self,
+        client_id: &str,
+        update: OAuthClientUpdate,
+    ) -> Result<OAuthClient, Error> {
+        let client = update.client;
+        sqlx::query_as::<_, OAuthClient>(&format!(
+            "\
+            UPDATE public.oauth_clients SET \
+            name=$2, redirect_uris=$3, scopes=$4, grant_types=$5, token_endpoint_auth_method=$6, jwks=$7, \
+            access_token_lifetime_seconds=$8, refresh_token_lifetime_seconds=$9, \
+            logo_uri=$10, client_uri=$11, policy_uri=$12, tos_uri=$13, first_party=$16, \
+            client_secret_hash = CASE WHEN $15 THEN NULL ELSE COALESCE($14, client_secret_hash) END, \
+            previous_client_secret_hash = CASE WHEN $15 THEN NULL ELSE previous_client_secret_hash END, \
+            previous_secret_expires_at = CASE WHEN $15 THEN NULL ELSE previous_secret_expires_at END \
+            WHERE client_id=$1 AND client_id NOT IN (SELECT client_id FROM public.service_accounts) \
+            RETURNING {}\
+            ",
+            OAUTH_CLIENT_COLUMNS
+        ))
+        .bind(client_id)
+        .bind(client.name)
+        .bind(client.redirect_uris)
+        .bind(client.scopes)
+        .bind(client.grant_types)
+        .bind(client.token_endpoint_auth_method)
+        .bind(client.jwks.map(Json))
+        .bind(client.metadata.access_token_lifetime_seconds)
+        .bind(client.metadata.refresh_token_lifetime_seconds)
+        .bind(client.metadata.logo_uri)
+        .bind(client.metadata.client_uri)
+        .bind(client.metadata.policy_uri)
+        .bind(client.metadata.tos_uri)
+        .bind(client.client_secret_hash)
+        .bind(update.clear_secret)
+        .bind(client.first_party)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Replace the secret of a client. The current one stays valid until `grace_until`,
+    /// or is dropped right away when it is not in the future.
+    pub async fn rotate_oauth_client_secret(
+        &self,
+        client_id: &str,
+        client_secret_hash: &str,
+        grace_until: DateTime<Utc>,
+    ) -> Result<OAuthClient, Error> {
+        sqlx::query_as::<_, OAuthClient>(&format!(
+            "\
+            UPDATE public.oauth_clients SET \
+            previous_client_secret_hash = CASE WHEN $3 > now() THEN client_secret_hash END, \
+            previous_secret_expires_at = CASE WHEN $3 > now() THEN $3 END, \
+            client_secret_hash=$2 \
+            WHERE client_id=$1 AND client_secret_hash IS NOT NULL \
+            RETURNING {}\
+            ",
+            OAUTH_CLIENT_COLUMNS
+        ))
+        .bind(client_id)
+        .bind(client_secret_hash)
+        .bind(grace_until)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn delete_oauth_client(&self, client_id: &str) -> Result<(), Error> {
+        let res = sqlx::query("DELETE FROM public.oauth_clients WHERE client_id=$1")
+            .bind(client_id)
+            .execute(&self.db_pool)
+            .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    pub async fn save_authorization_code(
+        &self,
+        code: NewAuthorizationCode<'_>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.oauth_authorization_codes \
+            (code_hash, client_id, user_id, redirect_uri, scope, code_challenge, code_challenge_method, nonce, auth_time, expires_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)\
+            ",
+        )
+        .bind(code.code_hash)
+        .bind(code.client_id)
+        .bind(code.user_id)
+        .bind(code.redirect_uri)
+        .bind(code.scope)
+        .bind(code.code_challenge)
+        .bind(code.code_challenge_method)
+        .bind(code.nonce)
+        .bind(code.auth_time)
+        .bind(code.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark the code as used and return it. A code can only be consumed once, before it expires.
+    pub async fn consume_authorization_code(
+        &self,
+        code_hash: &str,
+    ) -> Result<AuthorizationCode, Error> {
+        sqlx::query_as::<_, AuthorizationCode>(
+            "\
+            UPDATE public.oauth_authorization_codes SET consumed_at = now() \
+            WHERE code_hash=$1 AND consumed_at IS NULL AND expires_at > now() \
+            RETURNING client_id, user_id, redirect_uri, scope, code_challenge, code_challenge_method, nonce, auth_time\
+            ",
+        )
+        .bind(code_hash)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+}
@@ -0,0 +1,190 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct UserPhone {
+    pub phone_number: String,
+    /// Not set until the user typed the code sent to the number.
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+pub struct NewSmsCode<'a> {
+    pub user_id: &'a str,
+    pub purpose: &'a str,
+    pub phone_number: &'a str,
+    pub code_hash: &'a str,
+    pub challenge_hash: Option<&'a str>,
+    pub audience: Option<&'a str>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct SmsCode {
+    pub user_id: String,
+    pub phone_number: String,
+    pub code_hash: String,
+    pub audience: Option<String>,
+}
+
+impl Repository {
+    pub async fn find_user_phone(&self, user_id: &str) -> Result<UserPhone, Error> {
+        sqlx::query_as::<_, UserPhone>(
+            "SELECT phone_number, verified_at FROM public.user_phones WHERE user_id=$1",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Set the number of the user, unverified unless it is the one they verified.
+    pub async fn save_user_phone(
+        &self,
+        user_id: &str,
+        phone_number: &str,
+    ) -> Result<UserPhone, Error> {
+        sqlx::query_as::<_, UserPhone>(
+            "\
+            INSERT INTO public.user_phones (user_id, phone_number) \
+            VALUES ($1, $2) \
+            ON CONFLICT (user_id) DO UPDATE SET \
+            phone_number=EXCLUDED.phone_number, \
+            verified_at=CASE WHEN user_phones.phone_number=EXCLUDED.phone_number \
+                THEN user_phones.verified_at END \
+            RETURNING phone_number, verified_at\
+            ",
+        )
+        .bind(user_id)
+        .bind(phone_number)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Mark the number of the user as verified, `RowNotFound` when they changed it since.
+    pub async fn verify_user_phone(&self, user_id: &str, phone_number: &str) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            UPDATE public.user_phones SET verified_at=now() \
+            WHERE user_id=$1 AND phone_number=$2\
+            ",
+        )
+        .bind(user_id)
+        .bind(phone_number)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Delete the number of the user and their pending codes, `RowNotFound` when they have
+    /// no number.
+    pub async fn delete_user_phone(&self, user_id: &str) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            WITH codes AS (DELETE FROM public.sms_codes WHERE user_id=$1) \
+            DELETE FROM public.user_phones WHERE user_id=$1\
+            ",
+        )
+        .bind(user_id)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Save a code, replacing the pending one of the user for the same purpose.
+    pub async fn save_sms_code(&self, code: NewSmsCode<'_>) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.sms_codes \
+            (user_id, purpose, phone_number, code_hash, challenge_hash, audience, expires_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7) \
+            ON CONFLICT (user_id, purpose) DO UPDATE SET \
+            phone_number=EXCLUDED.phone_number, \
+            code_hash=EXCLUDED.code_hash, \
+            challenge_hash=EXCLUDED.challenge_hash, \
+            audience=EXCLUDED.audience, \
+            attempts=0, \
+            created_at=now(), \
+            expires_at=EXCLUDED.expires_at\
+            ",
+        )
+        .bind(code.user_id)
+        .bind(code.purpose)
+        .bind(code.phone_number)
+        .bind(code.code_hash)
+        .bind(code.challenge_hash)
+        .bind(code.audience)
+        .bind(code.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unexpired code of the user for the purpose, counting an attempt. `RowNotFound` once
+    /// `max_attempts` were made.
+    pub async fn attempt_sms_code(
+        &self,
+        user_id: &str,
+        purpose: &str,
+        max_attempts: i32,
+    ) -> Result<SmsCode, Error> {
+        sqlx::query_as::<_, SmsCode>(
+            "\
+            UPDATE public.sms_codes SET attempts=attempts + 1 \
+            WHERE user_id=$1 \
+            AND purpose=$2 \
+            AND attempts < $3 \
+            AND expires_at > now() \
+            RETURNING user_id, phone_number, code_hash, audience\
+            ",
+        )
+        .bind(user_id)
+        .bind(purpose)
+        .bind(max_attempts)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Unexpired code of a login challenge, counting an attempt. `RowNotFound` once
+    /// `max_attempts` were made.
+    pub async fn attempt_login_challenge(
+        &self,
+        challenge_hash: &str,
+        max_attempts: i32,
+    ) -> Result<SmsCode, Error> {
+        sqlx::query_as::<_, SmsCode>(
+            "\
+            UPDATE public.sms_codes SET attempts=attempts + 1 \
+            WHERE challenge_hash=$1 \
+            AND attempts < $2 \
+            AND expires_at > now() \
+            RETURNING user_id, phone_number, code_hash, audience\
+            ",
+        )
+        .bind(challenge_hash)
+        .bind(max_attempts)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn delete_sms_code(&self, user_id: &str, purpose: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM public.sms_codes WHERE user_id=$1 AND purpose=$2")
+            .bind(user_id)
+            .bind(purpose)
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_expired_sms_codes(&self) -> Result<u64, Error> {
+        let res = sqlx::query("DELETE FROM public.sms_codes WHERE expires_at < now()")
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(res.rows_affected())
+    }
+}
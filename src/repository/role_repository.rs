@@ -0,0 +1,178 @@
+use crate::repository::Repository;
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct RoleDefinition {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) is_builtin: bool,
+    pub(crate) permissions: Vec<String>,
+}
+
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct Permission {
+    pub(crate) name: String,
+    pub(crate) description: String,
+}
+
+impl Repository {
+    pub async fn list_roles(&self) -> Result<Vec<RoleDefinition>, Error> {
+        sqlx::query_as::<_, RoleDefinition>(
+            "\
+            SELECT r.name, r.description, r.is_builtin, \
+            COALESCE(array_agg(rp.permission_name) FILTER (WHERE rp.permission_name IS NOT NULL), '{}') AS permissions \
+            FROM public.roles r \
+            LEFT JOIN public.role_permissions rp ON rp.role_name = r.name \
+            GROUP BY r.name \
+            ORDER BY r.name\
+            ",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn find_role(&self, name: &str) -> Result<RoleDefinition, Error> {
+        sqlx::query_as::<_, RoleDefinition>(
+            "\
+            SELECT r.name, r.description, r.is_builtin, \
+            COALESCE(array_agg(rp.permission_name) FILTER (WHERE rp.permission_name IS NOT NULL), '{}') AS permissions \
+            FROM public.roles r \
+            LEFT JOIN public.role_permissions rp ON rp.role_name = r.name \
+            WHERE r.name=$1 \
+            GROUP BY r.name\
+            ",
+        )
+        .bind(name)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Create the role then attach its permissions, in a single transaction.
+    pub async fn save_role(
+        &self,
+        name: &str,
+        description: &str,
+        permissions: &[String],
+    ) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        sqlx::query("INSERT INTO public.roles (name, description) VALUES ($1, $2)")
+            .bind(name)
+            .bind(description)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO public.role_permissions (role_name, permission_name) SELECT $1, unnest($2::varchar[])",
+        )
+        .bind(name)
+        .bind(permissions)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Update the description and replace the whole permission set of a non built-in role.
+    pub async fn update_role(
+        &self,
+        name: &str,
+        description: &str,
+        permissions: &[String],
+    ) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let res = sqlx::query(
+            "UPDATE public.roles SET description=$1 WHERE name=$2 AND is_builtin=false",
+        )
+        .bind(description)
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        self.is_row_affected(res.rows_affected(), 1)?;
+
+        sqlx::query("DELETE FROM public.role_permissions WHERE role_name=$1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO public.role_permissions (role_name, permission_name) SELECT $1, unnest($2::varchar[])",
+        )
+        .bind(name)
+        .bind(permissions)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Delete a non built-in role and remove it from every user holding it.
+    pub async fn delete_role(&self, name: &str) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let res = sqlx::query("DELETE FROM public.roles WHERE name=$1 AND is_builtin=false")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        self.is_row_affected(res.rows_affected(), 1)?;
+
+        sqlx::query("UPDATE public.user SET role=array_remove(role, $1::varchar) WHERE $1 = ANY(role)")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    pub async fn list_permissions(&self) -> Result<Vec<Permission>, Error> {
+        sqlx::query_as::<_, Permission>(
+            "SELECT name, description FROM public.permissions ORDER BY name",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn save_permission(&self, name: &str, description: &str) -> Result<(), Error> {
+        sqlx::query("INSERT INTO public.permissions (name, description) VALUES ($1, $2)")
+            .bind(name)
+            .bind(description)
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_permission(&self, name: &str) -> Result<(), Error> {
+        let res = sqlx::query("DELETE FROM public.permissions WHERE name=$1")
+            .bind(name)
+            .execute(&self.db_pool)
+            .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Replace the roles of a user. Every role must exist in `public.roles` : they are locked
+    /// until the user is updated, so that a role deleted meanwhile is not assigned.
+    pub async fn update_user_roles(&self, user_id: &str, roles: &[String]) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let known: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM public.roles WHERE name = ANY($1::varchar[]) FOR SHARE",
+        )
+        .bind(roles)
+        .fetch_all(&mut *tx)
+        .await?;
+        self.is_row_affected(known.len() as u64, roles.len() as u64)?;
+
+        let res = sqlx::query("UPDATE public.user SET role=$1 WHERE id=$2")
+            .bind(roles)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        self.is_row_affected(res.rows_affected(), 1)?;
+
+        tx.commit().await
+    }
+}
@@ -0,0 +1,93 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, FromRow};
+
+pub struct NewSamlRequest<'a> {
+    pub request_id: &'a str,
+    pub provider: &'a str,
+    pub return_to: &'a str,
+    /// User linking the identity, `None` for a login.
+    pub link_user_id: Option<&'a str>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct SamlRequest {
+    pub return_to: String,
+    pub link_user_id: Option<String>,
+}
+
+impl Repository {
+    pub async fn save_saml_request(&self, request: NewSamlRequest<'_>) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.saml_requests \
+            (request_id, provider, return_to, link_user_id, expires_at) \
+            VALUES ($1, $2, $3, $4, $5)\
+            ",
+        )
+        .bind(request.request_id)
+        .bind(request.provider)
+        .bind(request.return_to)
+        .bind(request.link_user_id)
+        .bind(request.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unexpired request, deleted so that it is only answered once.
+    pub async fn consume_saml_request(
+        &self,
+        request_id: &str,
+        provider: &str,
+    ) -> Result<SamlRequest, Error> {
+        sqlx::query_as::<_, SamlRequest>(
+            "\
+            DELETE FROM public.saml_requests \
+            WHERE request_id=$1 \
+            AND provider=$2 \
+            AND expires_at > now() \
+            RETURNING return_to, link_user_id\
+            ",
+        )
+        .bind(request_id)
+        .bind(provider)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Fails with a unique violation when the assertion was consumed already.
+    pub async fn save_saml_assertion(
+        &self,
+        provider: &str,
+        assertion_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.saml_assertions (provider, assertion_id, expires_at) \
+            VALUES ($1, $2, $3)\
+            ",
+        )
+        .bind(provider)
+        .bind(assertion_id)
+        .bind(expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_expired_saml_requests(&self) -> Result<u64, Error> {
+        let requests = sqlx::query("DELETE FROM public.saml_requests WHERE expires_at < now()")
+            .execute(&self.db_pool)
+            .await?;
+        let assertions = sqlx::query("DELETE FROM public.saml_assertions WHERE expires_at < now()")
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(requests.rows_affected() + assertions.rows_affected())
+    }
+}
@@ -0,0 +1,418 @@
+use crate::repository::Repository;
+use sqlx::{Error, FromRow, PgConnection};
+
+/// User as seen by SCIM clients : active ones, and those a client deactivated.
+#[derive(FromRow)]
+pub struct ScimUser {
+    pub id: String,
+    pub email: String,
+    pub name: Option<String>,
+    pub external_id: Option<String>,
+    pub role: Vec<String>,
+    pub active: bool,
+}
+
+pub struct NewScimUser<'a> {
+    pub email: &'a str,
+    /// Hash of the password, empty when the client did not set one.
+    pub password: &'a str,
+    pub name: Option<&'a str>,
+    pub external_id: Option<&'a str>,
+    pub role: Vec<String>,
+}
+
+#[derive(FromRow)]
+pub struct ScimGroup {
+    pub id: String,
+    pub display_name: String,
+    pub external_id: Option<String>,
+}
+
+#[derive(FromRow)]
+pub struct ScimGroupMember {
+    pub group_id: String,
+    pub user_id: String,
+    pub email: String,
+}
+
+const SCIM_USER_COLUMNS: &str =
+    "u.id, u.email, u.name, s.external_id, u.role, (u.deleted_at IS NULL) AS active";
+
+/// Users SCIM clients see, filtered by email and external id when set.
+const SCIM_USER_FILTER: &str = "\
+    FROM public.user u \
+    LEFT JOIN public.scim_users s ON s.user_id = u.id \
+    WHERE (u.deleted_at IS NULL OR s.user_id IS NOT NULL) \
+    AND ($1::text IS NULL OR lower(u.email) = lower($1)) \
+    AND ($2::text IS NULL OR s.external_id = $2)";
+
+const SCIM_GROUP_FILTER: &str = "\
+    FROM public.scim_groups \
+    WHERE client=$1 \
+    AND ($2::text IS NULL OR display_name = $2) \
+    AND ($3::text IS NULL OR external_id = $3)";
+
+/// Add users to a group, failing with `RowNotFound` when one of them does not exist.
+async fn insert_members(
+    conn: &mut PgConnection,
+    group_id: &str,
+    user_ids: &[String],
+) -> Result<(), Error> {
+    let mut user_ids = user_ids.to_vec();
+    user_ids.sort();
+    user_ids.dedup();
+
+    let known: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM public.user WHERE id = ANY($1::text[])")
+            .bind(&user_ids)
+            .fetch_one(&mut *conn)
+            .await?;
+    if known as usize != user_ids.len() {
+        return Err(Error::RowNotFound);
+    }
+
+    sqlx::query(
+        "\
+        INSERT INTO public.scim_group_members (group_id, user_id) \
+        SELECT $1, unnest($2::text[]) \
+        ON CONFLICT DO NOTHING\
+        ",
+    )
+    .bind(group_id)
+    .bind(&user_ids)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+impl Repository {
+    pub async fn find_scim_user(&self, id: &str) -> Result<ScimUser, Error> {
+        sqlx::query_as::<_, ScimUser>(&format!(
+            "\
+            SELECT {} \
+            FROM public.user u \
+            LEFT JOIN public.scim_users s ON s.user_id = u.id \
+            WHERE u.id=$1 \
+            AND (u.deleted_at IS NULL OR s.user_id IS NOT NULL)\
+            ",
+            SCIM_USER_COLUMNS
+        ))
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Page of users ordered by id, with the number of users matching the filter.
+    pub async fn list_scim_users(
+        &self,
+        email: Option<&str>,
+        external_id: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<ScimUser>, i64), Error> {
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) {}", SCIM_USER_FILTER))
+            .bind(email)
+            .bind(external_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+        let users = sqlx::query_as::<_, ScimUser>(&format!(
+            "SELECT {} {} ORDER BY u.id OFFSET $3 LIMIT $4",
+            SCIM_USER_COLUMNS, SCIM_USER_FILTER
+        ))
+        .bind(email)
+        .bind(external_id)
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok((users, total))
+    }
+
+    /// Fails with a unique violation when a user, maybe deleted, has the email.
+    pub async fn save_scim_user(
+        &self,
+        client: &str,
+        user: NewScimUser<'_>,
+    ) -> Result<ScimUser, Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        // Emails of the identity provider of the organization are verified ones.
+        let id: String = sqlx::query_scalar(
+            "\
+            INSERT INTO public.user (email, password, role, name, email_verified) \
+            VALUES ($1, $2, $3, $4, true) \
+            RETURNING id\
+            ",
+        )
+        .bind(user.email)
+        .bind(user.password)
+        .bind(&user.role)
+        .bind(user.name)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "\
+            INSERT INTO public.scim_users (user_id, client, external_id) \
+            VALUES ($1, $2, $3)\
+            ",
+        )
+        .bind(&id)
+        .bind(client)
+        .bind(user.external_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(ScimUser {
+            id,
+            email: user.email.to_owned(),
+            name: user.name.map(str::to_owned),
+            external_id: user.external_id.map(str::to_owned),
+            role: user.role,
+            active: true,
+        })
+    }
+
+    /// Update the email and the name of a user, who becomes managed by `client`.
+    pub async fn update_scim_user(
+        &self,
+        client: &str,
+        id: &str,
+        email: &str,
+        name: Option<&str>,
+        external_id: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let res = sqlx::query("UPDATE public.user SET email=$1, name=$2 WHERE id=$3")
+            .bind(email)
+            .bind(name)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        self.is_row_affected(res.rows_affected(), 1)?;
+
+        sqlx::query(
+            "\
+            INSERT INTO public.scim_users (user_id, client, external_id) \
+            VALUES ($1, $2, $3) \
+            ON CONFLICT (user_id) DO UPDATE SET client=$2, external_id=$3\
+            ",
+        )
+        .bind(id)
+        .bind(client)
+        .bind(external_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Stop showing a deleted user to SCIM clients, out of their groups.
+    pub async fn forget_scim_user(&self, id: &str) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        sqlx::query("DELETE FROM public.scim_group_members WHERE user_id=$1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM public.scim_users WHERE user_id=$1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    pub async fn find_scim_group(&self, client: &str, id: &str) -> Result<ScimGroup, Error> {
+        sqlx::query_as::<_, ScimGroup>(
+            "\
+            SELECT id, display_name, external_id \
+            FROM public.scim_groups \
+            WHERE client=$1 AND id=$2\
+            ",
+        )
+        .bind(client)
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Page of the groups of a client ordered by id, with the number of groups matching
+    /// the filter.
+    pub async fn list_scim_groups(
+        &self,
+        client: &str,
+        display_name: Option<&str>,
+        external_id: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<ScimGroup>, i64), Error> {
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) {}", SCIM_GROUP_FILTER))
+            .bind(client)
+            .bind(display_name)
+            .bind(external_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+        let groups = sqlx::query_as::<_, ScimGroup>(&format!(
+            "SELECT id, display_name, external_id {} ORDER BY id OFFSET $4 LIMIT $5",
+            SCIM_GROUP_FILTER
+        ))
+        .bind(client)
+        .bind(display_name)
+        .bind(external_id)
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok((groups, total))
+    }
+
+    pub async fn list_scim_group_members(
+        &self,
+        group_ids: &[String],
+    ) -> Result<Vec<ScimGroupMember>, Error> {
+        sqlx::query_as::<_, ScimGroupMember>(
+            "\
+            SELECT m.group_id, m.user_id, u.email \
+            FROM public.scim_group_members m \
+            JOIN public.user u ON u.id = m.user_id \
+            WHERE m.group_id = ANY($1::text[]) \
+            ORDER BY m.group_id, m.user_id\
+            ",
+        )
+        .bind(group_ids)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Display names of the groups of a client the user is a member of.
+    pub async fn list_user_scim_groups(
+        &self,
+        client: &str,
+        user_id: &str,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar(
+            "\
+            SELECT g.display_name \
+            FROM public.scim_groups g \
+            JOIN public.scim_group_members m ON m.group_id = g.id \
+            WHERE g.client=$1 AND m.user_id=$2\
+            ",
+        )
+        .bind(client)
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Fails with a unique violation when the client has a group of this name, and with
+    /// `RowNotFound` when a member does not exist.
+    pub async fn save_scim_group(
+        &self,
+        client: &str,
+        display_name: &str,
+        external_id: Option<&str>,
+        members: &[String],
+    ) -> Result<ScimGroup, Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let group = sqlx::query_as::<_, ScimGroup>(
+            "\
+            INSERT INTO public.scim_groups (client, display_name, external_id) \
+            VALUES ($1, $2, $3) \
+            RETURNING id, display_name, external_id\
+            ",
+        )
+        .bind(client)
+        .bind(display_name)
+        .bind(external_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        insert_members(&mut tx, &group.id, members).await?;
+
+        tx.commit().await?;
+        Ok(group)
+    }
+
+    pub async fn update_scim_group(
+        &self,
+        client: &str,
+        id: &str,
+        display_name: &str,
+        external_id: Option<&str>,
+    ) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            UPDATE public.scim_groups SET display_name=$1, external_id=$2 \
+            WHERE client=$3 AND id=$4\
+            ",
+        )
+        .bind(display_name)
+        .bind(external_id)
+        .bind(client)
+        .bind(id)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Fails with `RowNotFound` when a member does not exist.
+    pub async fn add_scim_group_members(
+        &self,
+        group_id: &str,
+        user_ids: &[String],
+    ) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+        insert_members(&mut tx, group_id, user_ids).await?;
+        tx.commit().await
+    }
+
+    pub async fn remove_scim_group_members(
+        &self,
+        group_id: &str,
+        user_ids: &[String],
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "DELETE FROM public.scim_group_members WHERE group_id=$1 AND user_id = ANY($2::text[])",
+        )
+        .bind(group_id)
+        .bind(user_ids)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the members of a group, failing with `RowNotFound` when one does not exist.
+    pub async fn replace_scim_group_members(
+        &self,
+        group_id: &str,
+        user_ids: &[String],
+    ) -> Result<(), Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        sqlx::query("DELETE FROM public.scim_group_members WHERE group_id=$1")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_members(&mut tx, group_id, user_ids).await?;
+
+        tx.commit().await
+    }
+
+    pub async fn delete_scim_group(&self, client: &str, id: &str) -> Result<(), Error> {
+        let res = sqlx::query("DELETE FROM public.scim_groups WHERE client=$1 AND id=$2")
+            .bind(client)
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+}
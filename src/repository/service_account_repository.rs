@@ -0,0 +1,96 @@
+use crate::repository::oauth_repository::{insert_oauth_client, NewOAuthClient};
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub client_id: String,
+    pub name: String,
+    pub owner_id: String,
+    pub scopes: Vec<String>,
+    pub token_endpoint_auth_method: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Repository {
+    /// Create the OAuth client of the service account and link it to its owner.
+    pub async fn save_service_account(
+        &self,
+        client: NewOAuthClient,
+        owner_id: &str,
+    ) -> Result<ServiceAccount, Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let client = insert_oauth_client(&mut *tx, client).await?;
+        let created_at: DateTime<Utc> = sqlx::query_scalar(
+            "\
+            INSERT INTO public.service_accounts (client_id, owner_id) \
+            VALUES ($1, $2) \
+            RETURNING created_at\
+            ",
+        )
+        .bind(&client.client_id)
+        .bind(owner_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(ServiceAccount {
+            client_id: client.client_id,
+            name: client.name,
+            owner_id: owner_id.to_owned(),
+            scopes: client.scopes,
+            token_endpoint_auth_method: client.token_endpoint_auth_method,
+            created_at,
+        })
+    }
+
+    /// Service account of an active owner.
+    pub async fn find_service_account(&self, client_id: &str) -> Result<ServiceAccount, Error> {
+        sqlx::query_as::<_, ServiceAccount>(
+            "\
+            SELECT s.client_id, c.name, s.owner_id, c.scopes, c.token_endpoint_auth_method, s.created_at \
+            FROM public.service_accounts s \
+            JOIN public.oauth_clients c ON c.client_id = s.client_id \
+            JOIN public.user u ON u.id = s.owner_id \
+            WHERE s.client_id=$1 \
+            AND u.deleted_at IS NULL\
+            ",
+        )
+        .bind(client_id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn list_service_accounts(&self) -> Result<Vec<ServiceAccount>, Error> {
+        sqlx::query_as::<_, ServiceAccount>(
+            "\
+            SELECT s.client_id, c.name, s.owner_id, c.scopes, c.token_endpoint_auth_method, s.created_at \
+            FROM public.service_accounts s \
+            JOIN public.oauth_clients c ON c.client_id = s.client_id \
+            ORDER BY s.created_at\
+            ",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Delete the service account with its client, tokens it obtained stop being accepted.
+    pub async fn delete_service_account(&self, client_id: &str) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            DELETE FROM public.oauth_clients \
+            WHERE client_id=$1 \
+            AND client_id IN (SELECT client_id FROM public.service_accounts)\
+            ",
+        )
+        .bind(client_id)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+}
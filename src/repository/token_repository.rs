@@ -0,0 +1,146 @@
+use crate::repository::Repository;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, FromRow};
+
+#[derive(FromRow)]
+pub struct RefreshToken {
+    pub client_id: String,
+    pub user_id: String,
+    pub scope: String,
+    pub auth_time: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl RefreshToken {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+}
+
+pub struct NewRefreshToken<'a> {
+    pub token_hash: &'a str,
+    pub client_id: &'a str,
+    pub user_id: &'a str,
+    pub scope: &'a str,
+    pub auth_time: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Repository {
+    pub async fn save_refresh_token(&self, token: NewRefreshToken<'_>) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.oauth_refresh_tokens \
+            (token_hash, client_id, user_id, scope, auth_time, expires_at) \
+            VALUES ($1, $2, $3, $4, $5, $6)\
+            ",
+        )
+        .bind(token.token_hash)
+        .bind(token.client_id)
+        .bind(token.user_id)
+        .bind(token.scope)
+        .bind(token.auth_time)
+        .bind(token.expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Refresh token in any state, for introspection.
+    pub async fn find_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error> {
+        sqlx::query_as::<_, RefreshToken>(
+            "\
+            SELECT client_id, user_id, scope, auth_time, created_at, expires_at, revoked_at \
+            FROM public.oauth_refresh_tokens WHERE token_hash=$1\
+            ",
+        )
+        .bind(token_hash)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Revoke an active refresh token of the client and return it, a refresh token is
+    /// exchanged once for a new one.
+    pub async fn consume_refresh_token(
+        &self,
+        token_hash: &str,
+        client_id: &str,
+    ) -> Result<RefreshToken, Error> {
+        sqlx::query_as::<_, RefreshToken>(
+            "\
+            UPDATE public.oauth_refresh_tokens SET revoked_at = now() \
+            WHERE token_hash=$1 AND client_id=$2 AND revoked_at IS NULL AND expires_at > now() \
+            RETURNING client_id, user_id, scope, auth_time, created_at, expires_at, revoked_at\
+            ",
+        )
+        .bind(token_hash)
+        .bind(client_id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn revoke_refresh_token(
+        &self,
+        token_hash: &str,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        let res = sqlx::query(
+            "\
+            UPDATE public.oauth_refresh_tokens SET revoked_at = now() \
+            WHERE token_hash=$1 AND client_id=$2 AND revoked_at IS NULL\
+            ",
+        )
+        .bind(token_hash)
+        .bind(client_id)
+        .execute(&self.db_pool)
+        .await?;
+
+        self.is_row_affected(res.rows_affected(), 1)
+    }
+
+    /// Revoke an access token by `jti` until it expires.
+    pub async fn revoke_access_token(
+        &self,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            INSERT INTO public.oauth_revoked_tokens (jti, expires_at) VALUES ($1, $2) \
+            ON CONFLICT (jti) DO NOTHING\
+            ",
+        )
+        .bind(jti)
+        .bind(expires_at)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// `jti` and `exp` of the revoked access tokens which did not expire yet.
+    pub async fn list_revoked_access_tokens(&self) -> Result<Vec<(String, DateTime<Utc>)>, Error> {
+        sqlx::query_as::<_, (String, DateTime<Utc>)>(
+            "SELECT jti, expires_at FROM public.oauth_revoked_tokens WHERE expires_at > now()",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Delete expired refresh tokens and revocations, returns how many were deleted.
+    pub async fn delete_expired_oauth_tokens(&self) -> Result<u64, Error> {
+        let refresh_tokens =
+            sqlx::query("DELETE FROM public.oauth_refresh_tokens WHERE expires_at < now()")
+                .execute(&self.db_pool)
+                .await?;
+        let revocations =
+            sqlx::query("DELETE FROM public.oauth_revoked_tokens WHERE expires_at < now()")
+                .execute(&self.db_pool)
+                .await?;
+
+        Ok(refresh_tokens.rows_affected() + revocations.rows_affected())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::repository::Repository;
+use crate::services::authz::{ObjectRef, RelationTuple, StoreFuture, SubjectRef, TupleStore};
+use sqlx::{Error, FromRow};
+
+#[derive(FromRow)]
+struct TupleSubject {
+    subject_namespace: String,
+    subject_id: String,
+    subject_relation: String,
+}
+
+impl Repository {
+    pub async fn current_authz_revision(&self) -> Result<i64, Error> {
+        sqlx::query_scalar("SELECT revision FROM public.authz_revision")
+            .fetch_one(&self.db_pool)
+            .await
+    }
+
+    /// Apply the writes and deletes atomically and return the new revision.
+    /// Locking the revision row serializes writers, so revisions are committed in order.
+    pub async fn write_tuples(
+        &self,
+        writes: &[RelationTuple],
+        deletes: &[RelationTuple],
+    ) -> Result<i64, Error> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let revision: i64 = sqlx::query_scalar(
+            "UPDATE public.authz_revision SET revision = revision + 1 RETURNING revision",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for tuple in deletes {
+            sqlx::query(
+                "\
+                UPDATE public.relation_tuples SET deleted_revision=$1 \
+                WHERE namespace=$2 AND object_id=$3 AND relation=$4 \
+                AND subject_namespace=$5 AND subject_id=$6 AND subject_relation=$7 \
+                AND deleted_revision IS NULL\
+                ",
+            )
+            .bind(revision)
+            .bind(&tuple.object.namespace)
+            .bind(&tuple.object.id)
+            .bind(&tuple.relation)
+            .bind(&tuple.subject.namespace)
+            .bind(&tuple.subject.id)
+            .bind(tuple.subject.relation.as_deref().unwrap_or(""))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for tuple in writes {
+            sqlx::query(
+                "\
+                INSERT INTO public.relation_tuples \
+                (namespace, object_id, relation, subject_namespace, subject_id, subject_relation, created_revision) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7) \
+                ON CONFLICT (namespace, object_id, relation, subject_namespace, subject_id, subject_relation) \
+                WHERE deleted_revision IS NULL DO NOTHING\
+                ",
+            )
+            .bind(&tuple.object.namespace)
+            .bind(&tuple.object.id)
+            .bind(&tuple.relation)
+            .bind(&tuple.subject.namespace)
+            .bind(&tuple.subject.id)
+            .bind(tuple.subject.relation.as_deref().unwrap_or(""))
+            .bind(revision)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(revision)
+    }
+}
+
+impl TupleStore for Repository {
+    fn read_tuples<'a>(
+        &'a self,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        revision: i64,
+    ) -> StoreFuture<'a, Vec<SubjectRef>> {
+        Box::pin(async move {
+            let rows = sqlx::query_as::<_, TupleSubject>(
+                "\
+                SELECT subject_namespace, subject_id, subject_relation \
+                FROM public.relation_tuples \
+                WHERE namespace=$1 AND object_id=$2 AND relation=$3 \
+                AND created_revision <= $4 \
+                AND (deleted_revision IS NULL OR deleted_revision > $4)\
+                ",
+            )
+            .bind(&object.namespace)
+            .bind(&object.id)
+            .bind(relation)
+            .bind(revision)
+            .fetch_all(&self.db_pool)
+            .await?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| SubjectRef {
+                    namespace: row.subject_namespace,
+                    id: row.subject_id,
+                    relation: Some(row.subject_relation).filter(|r| !r.is_empty()),
+                })
+                .collect())
+        })
+    }
+}
@@ -9,6 +9,14 @@ pub struct User {
     pub(crate) email: String,
     pub(crate) password: String,
     pub(crate) role: Vec<String>,
+    #[sqlx(default)]
+    pub(crate) organization: Option<String>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub(crate) email_verified: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,7 +58,7 @@ impl Repository {
     pub async fn find_user_by_email(&self, email: &str) -> Result<User, Error> {
         sqlx::query_as::<_, User>(
             "\
-        SELECT id, email, password, role \
+        SELECT id, email, password, role, organization, name, email_verified \
         FROM public.user \
         WHERE email=$1 \
         AND deleted_at IS NULL\
@@ -61,10 +69,42 @@ impl Repository {
         .await
     }
 
+    pub async fn find_user_by_id(&self, id: &str) -> Result<User, Error> {
+        sqlx::query_as::<_, User>(
+            "\
+        SELECT id, email, password, role, organization, name, email_verified \
+        FROM public.user \
+        WHERE id=$1 \
+        AND deleted_at IS NULL\
+        ",
+        )
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    /// Active users ordered by id, starting after `after_id` when set.
+    pub async fn list_users(&self, limit: i64, after_id: Option<&str>) -> Result<Vec<User>, Error> {
+        sqlx::query_as::<_, User>(
+            "\
+        SELECT id, email, password, role, organization, name, email_verified \
+        FROM public.user \
+        WHERE deleted_at IS NULL \
+        AND ($2::text IS NULL OR id > $2) \
+        ORDER BY id \
+        LIMIT $1\
+        ",
+        )
+        .bind(limit)
+        .bind(after_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
     pub async fn find_banned_user_by_email(&self, email: &str) -> Result<User, Error> {
         sqlx::query_as::<_, User>(
             "\
-        SELECT id, email, password \
+        SELECT id, email, password, role, organization, name, email_verified \
         FROM public.user \
         WHERE email=$1 \
         AND deleted_at IS NOT NULL\
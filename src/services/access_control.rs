@@ -1,25 +1,55 @@
+use crate::config::permissions::permission_matches;
 use crate::config::roles::Role;
 use crate::controllers::v1::auth_controller::extract_auth_cookie;
 use crate::database::{Database, DatabaseService};
-use crate::services::crypto::JwtService;
-use actix_web::http::header::HeaderValue;
+use crate::services::crypto::{Claims, JwtService};
+use actix_web::http::header::{HeaderMap, HeaderValue};
 use cookie::Cookie;
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::io::{Error, ErrorKind};
+use std::pin::Pin;
 use std::str::FromStr;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
 
 use super::crypto::Jwt;
 
 #[allow(async_fn_in_trait)]
 pub trait GrantAccess {
     fn from_role(role: Vec<Role>, granted_roles: Vec<Role>) -> Authorization;
+    fn from_permissions(permissions: &HashSet<String>, permission: &str) -> Authorization;
     async fn with_email(&self, email: &str, granted_roles: Vec<Role>) -> Authorization;
     async fn with_cookie(
         &self,
         cookie_header: Option<&HeaderValue>,
         granted_roles: Vec<Role>,
     ) -> Authorization;
+    async fn with_email_permission(&self, email: &str, permission: &str) -> Authorization;
+    async fn with_user_permission(&self, user_id: &str, permission: &str) -> Authorization;
+    async fn with_cookie_permission(
+        &self,
+        cookie_header: Option<&HeaderValue>,
+        permission: &str,
+    ) -> Authorization;
+    async fn with_request_permission(&self, headers: &HeaderMap, permission: &str)
+        -> Authorization;
+    fn from_scopes(scopes: &HashSet<String>, scope: &str) -> Authorization;
+    async fn with_request_scope(&self, headers: &HeaderMap, scope: &str) -> Authorization;
+}
+
+/// Resolution of a bearer token into [`SubjectGrants`], implemented by [`AccessControl`].
+/// Servers other than the actix one (e.g. gRPC) depend on this rather than on the database.
+pub trait TokenAuthority: Send + Sync + 'static {
+    fn resolve_token<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<SubjectGrants, Error>> + Send + 'a>>;
 }
 
 pub enum Authorization {
@@ -29,18 +59,352 @@ pub enum Authorization {
 
 #[derive(FromRow, Serialize, Deserialize)]
 struct User {
+    #[sqlx(default)]
+    id: String,
+    #[sqlx(default)]
+    email: String,
     role: Option<Vec<String>>,
 }
 
+#[derive(FromRow)]
+struct RolePermission {
+    role_name: String,
+    permission_name: String,
+}
+
+type PermissionCache = HashMap<Role, HashSet<String>>;
+
+/// What a token grants : its subject, the subject roles and the resulting permissions.
+/// Service accounts have no role, their scopes are their permissions.
+#[derive(Debug, Clone)]
+pub struct SubjectGrants {
+    pub user_id: String,
+    /// Email of the user, client id of a service account.
+    pub subject: String,
+    pub roles: Vec<Role>,
+    pub permissions: HashSet<String>,
+    /// Scopes of the token, empty for login tokens.
+    pub scopes: HashSet<String>,
+}
+
+const MAX_CACHED_DECISIONS: usize = 10_000;
+
+/// Short-lived cache of [`SubjectGrants`] keyed by token id and role version, so a change
+/// on roles or permissions is never served from the cache.
+pub struct DecisionCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<(String, u64), (Instant, SubjectGrants)>>,
+}
+
+impl DecisionCache {
+    pub fn new(ttl: Duration) -> DecisionCache {
+        DecisionCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, token_id: &str, role_version: u64) -> Option<SubjectGrants> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (token_id.to_owned(), role_version);
+        match entries.get(&key) {
+            Some((expires_at, grants)) if *expires_at > Instant::now() => Some(grants.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, token_id: &str, role_version: u64, grants: SubjectGrants) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_DECISIONS {
+            let now = Instant::now();
+            entries.retain(|(_, version), (expires_at, _)| {
+                *version == role_version && *expires_at > now
+            });
+            if entries.len() >= MAX_CACHED_DECISIONS {
+                entries.clear();
+            }
+        }
+        entries.insert(
+            (token_id.to_owned(), role_version),
+            (Instant::now() + self.ttl, grants),
+        );
+    }
+}
+
 #[derive(Clone)]
 pub struct AccessControl {
     db_pool: Pool<Postgres>,
+    permission_cache: Arc<RwLock<Option<PermissionCache>>>,
+    role_version: Arc<AtomicU64>,
+    decision_cache: Arc<DecisionCache>,
 }
 
 impl AccessControl {
     pub async fn new() -> AccessControl {
         let pool = DatabaseService::new().database_connection().await;
-        AccessControl { db_pool: pool }
+        let ttl = std::env::var("DECISION_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|ttl| ttl.parse::<u64>().ok())
+            .unwrap_or(30);
+
+        AccessControl::with_pool(pool, Duration::from_secs(ttl))
+    }
+
+    /// Access control on an existing pool, decisions being cached for `decision_ttl`.
+    pub fn with_pool(db_pool: Pool<Postgres>, decision_ttl: Duration) -> AccessControl {
+        AccessControl {
+            db_pool,
+            permission_cache: Arc::new(RwLock::new(None)),
+            role_version: Arc::new(AtomicU64::new(0)),
+            decision_cache: Arc::new(DecisionCache::new(decision_ttl)),
+        }
+    }
+
+    /// Identifier of a token used as cache key, tokens themselves are never kept in memory.
+    pub fn token_id(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Verify the token and resolve what it grants, served from the decision cache
+    /// while the roles did not change.
+    pub async fn grants_for_token(&self, token: &str) -> Result<SubjectGrants, Error> {
+        let claims = <JwtService as Jwt>::verify_jwt(token)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Unauthorized"))?;
+
+        let token_id = Self::token_id(token);
+        let role_version = self.role_version();
+        if let Some(grants) = self.decision_cache.get(&token_id, role_version) {
+            return Ok(grants);
+        }
+
+        let scopes = Self::token_scopes(&claims);
+        let grants = if claims.is_service_account() {
+            self.check_service_account(&claims.sub).await?;
+            SubjectGrants {
+                subject: claims.sub.clone(),
+                user_id: claims.sub,
+                roles: vec![],
+                permissions: scopes.clone(),
+                scopes,
+            }
+        } else {
+            let (email, roles) = self.find_identity(&claims.sub).await?;
+            let mut permissions = self.permissions_for(&roles).await.map_err(|err| {
+                log::error!("{:?}", err);
+                Error::new(ErrorKind::InvalidData, "Unauthorized")
+            })?;
+            if claims.client_id.is_some() {
+                permissions = Self::delegated_permissions(&permissions, &scopes);
+            }
+
+            SubjectGrants {
+                user_id: claims.sub,
+                subject: email,
+                roles,
+                permissions,
+                scopes,
+            }
+        };
+        self.decision_cache
+            .insert(&token_id, role_version, grants.clone());
+
+        Ok(grants)
+    }
+
+    /// Drop the cached role → permissions mapping. Must be called after any change
+    /// on roles, permissions, their association or the roles of a user.
+    pub async fn invalidate_cache(&self) {
+        *self.permission_cache.write().await = None;
+        self.role_version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Incremented on each invalidation, can be used to key caches derived from roles.
+    pub fn role_version(&self) -> u64 {
+        self.role_version.load(Ordering::SeqCst)
+    }
+
+    /// Permissions granted by the given roles, loading the mapping from the database
+    /// when the cache is empty.
+    pub async fn permissions_for(&self, roles: &[Role]) -> Result<HashSet<String>, sqlx::Error> {
+        if let Some(cache) = self.permission_cache.read().await.as_ref() {
+            return Ok(Self::collect_permissions(cache, roles));
+        }
+
+        let mut guard = self.permission_cache.write().await;
+        if guard.is_none() {
+            let rows = sqlx::query_as::<_, RolePermission>(
+                "SELECT role_name, permission_name FROM public.role_permissions",
+            )
+            .fetch_all(&self.db_pool)
+            .await?;
+
+            let mut cache = PermissionCache::new();
+            for row in rows {
+                if let Ok(role) = Role::from_str(&row.role_name) {
+                    cache.entry(role).or_default().insert(row.permission_name);
+                }
+            }
+            *guard = Some(cache);
+        }
+
+        Ok(guard
+            .as_ref()
+            .map(|cache| Self::collect_permissions(cache, roles))
+            .unwrap_or_default())
+    }
+
+    fn collect_permissions(cache: &PermissionCache, roles: &[Role]) -> HashSet<String> {
+        roles
+            .iter()
+            .filter_map(|role| cache.get(role))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    async fn find_roles(&self, email: &str) -> Result<Vec<Role>, Error> {
+        let res = sqlx::query_as::<_, User>(
+            "SELECT id, email, role FROM public.user WHERE email=$1 AND deleted_at IS NULL",
+        )
+        .bind(email)
+        .fetch_one(&self.db_pool)
+        .await;
+
+        match res {
+            Ok(User {
+                role: Some(roles), ..
+            }) => Ok(Self::parse_roles(&roles)),
+            _ => Err(Error::new(ErrorKind::InvalidData, "User not found")),
+        }
+    }
+
+    /// Email and roles of an active user.
+    async fn find_identity(&self, user_id: &str) -> Result<(String, Vec<Role>), Error> {
+        let res = sqlx::query_as::<_, User>(
+            "SELECT id, email, role FROM public.user WHERE id=$1 AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await;
+
+        match res {
+            Ok(User {
+                email,
+                role: Some(roles),
+                ..
+            }) => Ok((email, Self::parse_roles(&roles))),
+            _ => Err(Error::new(ErrorKind::InvalidData, "User not found")),
+        }
+    }
+
+    /// The service account exists and its owner is active.
+    async fn check_service_account(&self, client_id: &str) -> Result<(), Error> {
+        sqlx::query(
+            "\
+            SELECT s.client_id FROM public.service_accounts s \
+            JOIN public.user u ON u.id = s.owner_id \
+            WHERE s.client_id=$1 AND u.deleted_at IS NULL\
+            ",
+        )
+        .bind(client_id)
+        .fetch_one(&self.db_pool)
+        .await
+        .map(|_| ())
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "Service account not found"))
+    }
+
+    pub fn token_scopes(claims: &Claims) -> HashSet<String> {
+        claims
+            .scope
+            .as_deref()
+            .unwrap_or_default()
+            .split(' ')
+            .filter(|scope| !scope.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Permissions of a token delegated to a client : those of the user which a scope of the
+    /// token also grants.
+    pub fn delegated_permissions(
+        permissions: &HashSet<String>,
+        scopes: &HashSet<String>,
+    ) -> HashSet<String> {
+        let granted = |by: &HashSet<String>, permission: &str| {
+            by.iter()
+                .any(|granted| permission_matches(granted, permission))
+        };
+        permissions
+            .iter()
+            .filter(|permission| granted(scopes, permission))
+            .chain(scopes.iter().filter(|scope| granted(permissions, scope)))
+            .cloned()
+            .collect()
+    }
+
+    fn parse_roles(roles: &[String]) -> Vec<Role> {
+        roles
+            .iter()
+            .filter_map(|role| Role::from_str(role).ok())
+            .collect()
+    }
+
+    /// Verify the auth cookie and return the subject (user id) of its JWT.
+    pub fn subject_from_cookie(cookie_header: Option<&HeaderValue>) -> Result<String, Error> {
+        Self::claims_from_cookie(cookie_header).map(|claims| claims.sub)
+    }
+
+    /// Verify the auth cookie and return the claims of its JWT.
+    pub fn claims_from_cookie(cookie_header: Option<&HeaderValue>) -> Result<Claims, Error> {
+        let token = Self::token_from_cookie(cookie_header)?;
+
+        <JwtService as Jwt>::verify_jwt(&token)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Unauthorized"))
+    }
+
+    /// Raw, unverified, token of the auth cookie.
+    pub fn token_from_cookie(cookie_header: Option<&HeaderValue>) -> Result<String, Error> {
+        let unauthorized = || Error::new(ErrorKind::InvalidData, "Unauthorized");
+
+        let cookie = extract_auth_cookie(cookie_header).map_err(|_| unauthorized())?;
+        let token = Cookie::parse(cookie).map_err(|_| unauthorized())?;
+
+        Ok(token.value().to_owned())
+    }
+
+    /// Verify a token sent in the `Authorization` header, with or without the `Bearer` scheme,
+    /// and return its subject (user id).
+    pub fn subject_from_token(authorization_header: Option<&HeaderValue>) -> Result<String, Error> {
+        Self::claims_from_token(authorization_header).map(|claims| claims.sub)
+    }
+
+    /// Verify a token sent in the `Authorization` header and return its claims.
+    pub fn claims_from_token(authorization_header: Option<&HeaderValue>) -> Result<Claims, Error> {
+        let unauthorized = || Error::new(ErrorKind::InvalidData, "Unauthorized");
+
+        let header = authorization_header
+            .and_then(|header| header.to_str().ok())
+            .ok_or_else(unauthorized)?;
+        let token = header.strip_prefix("Bearer ").unwrap_or(header).trim();
+
+        <JwtService as Jwt>::verify_jwt(token).map_err(|_| unauthorized())
+    }
+
+    /// Subject of the request, from the `Authorization` header first, then from the cookie.
+    pub fn subject_from_request(headers: &HeaderMap) -> Result<String, Error> {
+        Self::claims_from_request(headers).map(|claims| claims.sub)
+    }
+
+    /// Claims of the request token, from the `Authorization` header first, then from the cookie.
+    pub fn claims_from_request(headers: &HeaderMap) -> Result<Claims, Error> {
+        match headers.get("Authorization") {
+            Some(header) => Self::claims_from_token(Some(header)),
+            None => Self::claims_from_cookie(headers.get("cookie")),
+        }
     }
 }
 
@@ -54,6 +418,16 @@ impl GrantAccess for AccessControl {
         Authorization::Unauthorized(Error::new(ErrorKind::InvalidData, "Unauthorized"))
     }
 
+    fn from_permissions(permissions: &HashSet<String>, permission: &str) -> Authorization {
+        if permissions
+            .iter()
+            .any(|granted| permission_matches(granted, permission))
+        {
+            return Authorization::Authorized;
+        }
+        Authorization::Unauthorized(Error::new(ErrorKind::InvalidData, "Unauthorized"))
+    }
+
     async fn with_email(&self, email: &str, granted_roles: Vec<Role>) -> Authorization {
         let res = sqlx::query_as::<_, User>("SELECT role FROM public.user WHERE email=$1")
             .bind(email)
@@ -117,6 +491,111 @@ impl GrantAccess for AccessControl {
                 ))
             }
         };
-        self.with_email(&claims.sub, granted_roles).await
+        match self.find_identity(&claims.sub).await {
+            Ok((_, roles)) => Self::from_role(roles, granted_roles),
+            Err(err) => Authorization::Unauthorized(err),
+        }
+    }
+
+    async fn with_email_permission(&self, email: &str, permission: &str) -> Authorization {
+        let roles = match self.find_roles(email).await {
+            Ok(roles) => roles,
+            Err(err) => return Authorization::Unauthorized(err),
+        };
+
+        match self.permissions_for(&roles).await {
+            Ok(permissions) => Self::from_permissions(&permissions, permission),
+            Err(err) => {
+                log::error!("{:?}", err);
+                Authorization::Unauthorized(Error::new(ErrorKind::InvalidData, "Unauthorized"))
+            }
+        }
+    }
+
+    async fn with_user_permission(&self, user_id: &str, permission: &str) -> Authorization {
+        let roles = match self.find_identity(user_id).await {
+            Ok((_, roles)) => roles,
+            Err(err) => return Authorization::Unauthorized(err),
+        };
+
+        match self.permissions_for(&roles).await {
+            Ok(permissions) => Self::from_permissions(&permissions, permission),
+            Err(err) => {
+                log::error!("{:?}", err);
+                Authorization::Unauthorized(Error::new(ErrorKind::InvalidData, "Unauthorized"))
+            }
+        }
+    }
+
+    async fn with_cookie_permission(
+        &self,
+        cookie_header: Option<&HeaderValue>,
+        permission: &str,
+    ) -> Authorization {
+        match Self::subject_from_cookie(cookie_header) {
+            Ok(user_id) => self.with_user_permission(&user_id, permission).await,
+            Err(err) => Authorization::Unauthorized(err),
+        }
+    }
+
+    async fn with_request_permission(
+        &self,
+        headers: &HeaderMap,
+        permission: &str,
+    ) -> Authorization {
+        let claims = match Self::claims_from_request(headers) {
+            Ok(claims) => claims,
+            Err(err) => return Authorization::Unauthorized(err),
+        };
+        if claims.is_service_account() {
+            return match self.check_service_account(&claims.sub).await {
+                Ok(()) => Self::from_permissions(&Self::token_scopes(&claims), permission),
+                Err(err) => Authorization::Unauthorized(err),
+            };
+        }
+
+        // Delegated to a client, as in `grant_controller::session_claims` : the token also
+        // needs a scope granting the permission.
+        if claims.client_id.is_some()
+            && !Self::token_scopes(&claims)
+                .iter()
+                .any(|scope| permission_matches(scope, permission))
+        {
+            return Authorization::Unauthorized(Error::new(
+                ErrorKind::InvalidData,
+                "Insufficient scope",
+            ));
+        }
+        self.with_user_permission(&claims.sub, permission).await
+    }
+
+    fn from_scopes(scopes: &HashSet<String>, scope: &str) -> Authorization {
+        if scopes.contains(scope) {
+            return Authorization::Authorized;
+        }
+        Authorization::Unauthorized(Error::new(ErrorKind::InvalidData, "Insufficient scope"))
+    }
+
+    async fn with_request_scope(&self, headers: &HeaderMap, scope: &str) -> Authorization {
+        let claims = match Self::claims_from_request(headers) {
+            Ok(claims) => claims,
+            Err(err) => return Authorization::Unauthorized(err),
+        };
+        if claims.is_service_account() {
+            if let Err(err) = self.check_service_account(&claims.sub).await {
+                return Authorization::Unauthorized(err);
+            }
+        }
+
+        Self::from_scopes(&Self::token_scopes(&claims), scope)
+    }
+}
+
+impl TokenAuthority for AccessControl {
+    fn resolve_token<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<SubjectGrants, Error>> + Send + 'a>> {
+        Box::pin(self.grants_for_token(token))
     }
 }
@@ -0,0 +1,513 @@
+//! Authentication of users by their credentials, for `login` and the `password` grant of
+//! the token endpoint.
+//!
+//! Authenticators are tried in the order of the realm of the request. Each one skips the
+//! credentials it does not handle, the first one to authenticate or reject them decides.
+//! Realms are declared in a JSON file (`AUTHENTICATORS_FILE`, `authenticators.json` by
+//! default) :
+//!
+//! ```json
+//! {
+//!   "realms": {
+//!     "default": ["ldap", "local"],
+//!     "partners": ["external_idp", "api_key"]
+//!   }
+//! }
+//! ```
+//!
+//! - `local` : email and password of the user, skipped for users without a password
+//! - `ldap` : email and password checked by the LDAP directory, see
+//!   [`LocalFallback`](crate::services::ldap::LocalFallback) for the users it skips
+//! - `external_idp` : ID token issued to this service by an OpenID Connect provider of
+//!   `IDENTITY_PROVIDERS_FILE`, for a nonce handed out by `/api/v1/login/nonce`. Each nonce
+//!   is accepted once, so a captured ID token cannot be replayed.
+//! - `api_key` : key created by the user from `/api/v1/me/api-keys`
+//!
+//! Requests without a realm use the `default` one, which must be declared. Without the
+//! file, the `default` realm tries `ldap` when a directory is configured, then `local`,
+//! `external_idp` and `api_key`.
+
+use crate::repository::user_repository::User;
+use crate::repository::Repository;
+use crate::services::access_control::AccessControl;
+use crate::services::crypto::{Hash, HashService};
+use crate::services::identity_provider::{
+    provision_user, ExternalIdentity, IdentityProviders, ProvisioningError,
+};
+use crate::services::ldap::{DirectoryLogin, DirectoryUser, LdapDirectory};
+use crate::services::oauth::{generate_token, hash_token};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub const DEFAULT_REALM: &str = "default";
+/// API keys are told apart from other secrets by this prefix.
+pub const API_KEY_PREFIX: &str = "ak_";
+
+pub const AUTHENTICATOR_LOCAL: &str = "local";
+pub const AUTHENTICATOR_LDAP: &str = "ldap";
+pub const AUTHENTICATOR_EXTERNAL_IDP: &str = "external_idp";
+pub const AUTHENTICATOR_API_KEY: &str = "api_key";
+
+const MAX_REALM_NAME_LENGTH: usize = 64;
+
+/// New API key, only its [`hash_token`] is stored.
+pub fn generate_api_key() -> String {
+    format!("{}{}", API_KEY_PREFIX, generate_token())
+}
+
+pub enum Credentials<'a> {
+    Password {
+        email: &'a str,
+        password: &'a str,
+    },
+    IdToken {
+        provider: &'a str,
+        id_token: &'a str,
+        nonce: &'a str,
+    },
+    ApiKey(&'a str),
+}
+
+pub enum AuthOutcome {
+    Authenticated(User),
+    /// The credentials are wrong, the next authenticators are not tried.
+    Rejected,
+    /// The credentials are not handled by the authenticator.
+    Skipped,
+}
+
+pub type AuthFuture<'a> = Pin<Box<dyn Future<Output = AuthOutcome> + Send + 'a>>;
+
+pub trait Authenticator: Send + Sync {
+    fn authenticate<'a>(&'a self, credentials: &'a Credentials<'a>) -> AuthFuture<'a>;
+}
+
+/// Failures of the authenticators reject the credentials, they are only logged.
+fn rejected<E: std::fmt::Debug>(err: E) -> AuthOutcome {
+    log::error!("{:?}", err);
+    AuthOutcome::Rejected
+}
+
+/// User of an external identity, provisioned on their first login.
+async fn provisioned(repository: &Repository, identity: &ExternalIdentity) -> Option<User> {
+    match provision_user(repository, identity).await {
+        Ok(user) => Some(user),
+        Err(ProvisioningError::Database(err)) => {
+            log::error!("{:?}", err);
+            None
+        }
+        Err(_) => {
+            log::warn!(
+                "The {} identity {} cannot be linked to an account",
+                identity.provider,
+                identity.subject
+            );
+            None
+        }
+    }
+}
+
+/// Email and local password of the user.
+pub struct LocalPasswordAuthenticator {
+    repository: Repository,
+}
+
+impl LocalPasswordAuthenticator {
+    pub fn new(repository: Repository) -> LocalPasswordAuthenticator {
+        LocalPasswordAuthenticator { repository }
+    }
+}
+
+impl Authenticator for LocalPasswordAuthenticator {
+    fn authenticate<'a>(&'a self, credentials: &'a Credentials<'a>) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let Credentials::Password { email, password } = credentials else {
+                return AuthOutcome::Skipped;
+            };
+            let user = match self.repository.find_user_by_email(email).await {
+                Ok(user) => user,
+                Err(sqlx::Error::RowNotFound) => return AuthOutcome::Skipped,
+                Err(err) => return rejected(err),
+            };
+            // Users provisioned by a directory or a provider have no password.
+            if user.password.is_empty() {
+                return AuthOutcome::Skipped;
+            }
+
+            match HashService::check_password(password, &user.password) {
+                Ok(true) => AuthOutcome::Authenticated(user),
+                _ => AuthOutcome::Rejected,
+            }
+        })
+    }
+}
+
+/// Email and password checked by the LDAP directory. Users are provisioned on their first
+/// login, roles mapped from groups follow the directory : the decisions cached by
+/// `access_control` are invalidated when they change.
+pub struct LdapAuthenticator {
+    directory: Arc<LdapDirectory>,
+    repository: Repository,
+    access_control: AccessControl,
+}
+
+impl LdapAuthenticator {
+    pub fn new(
+        directory: Arc<LdapDirectory>,
+        repository: Repository,
+        access_control: AccessControl,
+    ) -> LdapAuthenticator {
+        LdapAuthenticator {
+            directory,
+            repository,
+            access_control,
+        }
+    }
+
+    async fn directory_user(&self, entry: &DirectoryUser) -> AuthOutcome {
+        let mut user = match provisioned(&self.repository, &self.directory.identity(entry)).await {
+            Some(user) => user,
+            None => return AuthOutcome::Rejected,
+        };
+
+        let roles = self.directory.roles(&user.role, entry);
+        if roles != user.role {
+            if let Err(err) = self.repository.update_user_roles(&user.id, &roles).await {
+                log::error!(
+                    "Roles {:?} of {} could not be granted : {:?}",
+                    roles,
+                    entry.dn,
+                    err
+                );
+                return AuthOutcome::Rejected;
+            }
+            self.access_control.invalidate_cache().await;
+            user.role = roles;
+        }
+        AuthOutcome::Authenticated(user)
+    }
+}
+
+impl Authenticator for LdapAuthenticator {
+    fn authenticate<'a>(&'a self, credentials: &'a Credentials<'a>) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let Credentials::Password { email, password } = credentials else {
+                return AuthOutcome::Skipped;
+            };
+            let login = self.directory.authenticate(email, password).await;
+            match &login {
+                Ok(DirectoryLogin::Authenticated(entry)) => {
+                    return self.directory_user(entry).await
+                }
+                Ok(_) => {}
+                Err(err) => log::error!("{:?}", err),
+            }
+
+            match self.directory.local_fallback().applies(&login) {
+                true => AuthOutcome::Skipped,
+                false => AuthOutcome::Rejected,
+            }
+        })
+    }
+}
+
+/// ID token of an OpenID Connect provider, for a nonce this service handed out and which
+/// was not used yet. Users are provisioned on their first login.
+pub struct IdentityProviderAuthenticator {
+    providers: Arc<IdentityProviders>,
+    repository: Repository,
+}
+
+impl IdentityProviderAuthenticator {
+    pub fn new(
+        providers: Arc<IdentityProviders>,
+        repository: Repository,
+    ) -> IdentityProviderAuthenticator {
+        IdentityProviderAuthenticator {
+            providers,
+            repository,
+        }
+    }
+}
+
+impl Authenticator for IdentityProviderAuthenticator {
+    fn authenticate<'a>(&'a self, credentials: &'a Credentials<'a>) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let Credentials::IdToken {
+                provider,
+                id_token,
+                nonce,
+            } = credentials
+            else {
+                return AuthOutcome::Skipped;
+            };
+            let provider = match self.providers.get(provider) {
+                Some(provider) if provider.is_openid() => provider,
+                _ => return AuthOutcome::Rejected,
+            };
+            if nonce.is_empty() {
+                return AuthOutcome::Rejected;
+            }
+
+            let identity = match provider.verify_id_token(id_token, nonce).await {
+                Ok(claims) => provider.identity(&claims),
+                Err(err) => Err(err),
+            };
+            let identity = match identity {
+                Ok(identity) => identity,
+                Err(err) => {
+                    log::warn!("ID token of {} refused : {}", provider.name(), err);
+                    return AuthOutcome::Rejected;
+                }
+            };
+            match self
+                .repository
+                .consume_login_nonce(&hash_token(nonce), provider.name())
+                .await
+            {
+                Ok(()) => {}
+                Err(sqlx::Error::RowNotFound) => {
+                    log::warn!(
+                        "ID token of {} with an unknown or used nonce",
+                        provider.name()
+                    );
+                    return AuthOutcome::Rejected;
+                }
+                Err(err) => return rejected(err),
+            }
+            match provisioned(&self.repository, &identity).await {
+                Some(user) => AuthOutcome::Authenticated(user),
+                None => AuthOutcome::Rejected,
+            }
+        })
+    }
+}
+
+/// API key of the user, which stops working once it expired.
+pub struct ApiKeyAuthenticator {
+    repository: Repository,
+}
+
+impl ApiKeyAuthenticator {
+    pub fn new(repository: Repository) -> ApiKeyAuthenticator {
+        ApiKeyAuthenticator { repository }
+    }
+}
+
+impl Authenticator for ApiKeyAuthenticator {
+    fn authenticate<'a>(&'a self, credentials: &'a Credentials<'a>) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let Credentials::ApiKey(key) = credentials else {
+                return AuthOutcome::Skipped;
+            };
+            if !key.starts_with(API_KEY_PREFIX) {
+                return AuthOutcome::Rejected;
+            }
+
+            match self.repository.find_user_by_api_key(&hash_token(key)).await {
+                Ok(user) => AuthOutcome::Authenticated(user),
+                Err(sqlx::Error::RowNotFound) => AuthOutcome::Rejected,
+                Err(err) => rejected(err),
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthenticatorsConfig {
+    realms: HashMap<String, Vec<String>>,
+}
+
+/// Registered authenticators, and the order each realm tries them in.
+#[derive(Default)]
+pub struct Authenticators {
+    registered: Vec<(String, Arc<dyn Authenticator>)>,
+    realms: HashMap<String, Vec<Arc<dyn Authenticator>>>,
+}
+
+impl Authenticators {
+    pub fn register(
+        &mut self,
+        name: &str,
+        authenticator: impl Authenticator + 'static,
+    ) -> Result<(), Error> {
+        if self
+            .registered
+            .iter()
+            .any(|(registered, _)| registered == name)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Duplicated authenticator: {}", name),
+            ));
+        }
+        self.registered
+            .push((name.to_owned(), Arc::new(authenticator)));
+        Ok(())
+    }
+
+    /// Realm trying the authenticators named `names`, in this order.
+    pub fn set_realm(&mut self, realm: &str, names: &[String]) -> Result<(), Error> {
+        let valid = !realm.is_empty()
+            && realm.len() <= MAX_REALM_NAME_LENGTH
+            && realm
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid realm name: {}", realm),
+            ));
+        }
+        if names.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("The realm {} has no authenticator", realm),
+            ));
+        }
+
+        let mut authenticators = Vec::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("The realm {} lists {} twice", realm, name),
+                ));
+            }
+            let authenticator = self
+                .registered
+                .iter()
+                .find(|(registered, _)| registered == name)
+                .map(|(_, authenticator)| authenticator.clone())
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Unknown authenticator {} in the realm {}", name, realm),
+                    )
+                })?;
+            authenticators.push(authenticator);
+        }
+        self.realms.insert(realm.to_owned(), authenticators);
+        Ok(())
+    }
+
+    /// The authenticators of this service, `ldap` being registered when a directory is
+    /// configured. Realms are still to be set.
+    pub fn builtin(
+        repository: Repository,
+        access_control: AccessControl,
+        directory: Option<Arc<LdapDirectory>>,
+        providers: Arc<IdentityProviders>,
+    ) -> Authenticators {
+        let mut registered: Vec<(String, Arc<dyn Authenticator>)> = vec![];
+        if let Some(directory) = directory {
+            registered.push((
+                AUTHENTICATOR_LDAP.to_owned(),
+                Arc::new(LdapAuthenticator::new(
+                    directory,
+                    repository.clone(),
+                    access_control,
+                )),
+            ));
+        }
+        registered.push((
+            AUTHENTICATOR_LOCAL.to_owned(),
+            Arc::new(LocalPasswordAuthenticator::new(repository.clone())),
+        ));
+        registered.push((
+            AUTHENTICATOR_EXTERNAL_IDP.to_owned(),
+            Arc::new(IdentityProviderAuthenticator::new(
+                providers,
+                repository.clone(),
+            )),
+        ));
+        registered.push((
+            AUTHENTICATOR_API_KEY.to_owned(),
+            Arc::new(ApiKeyAuthenticator::new(repository)),
+        ));
+
+        Authenticators {
+            registered,
+            realms: HashMap::new(),
+        }
+    }
+
+    /// The `default` realm tries every registered authenticator, in the order they were
+    /// registered.
+    pub fn with_default_realm(mut self) -> Result<Authenticators, Error> {
+        let names: Vec<String> = self
+            .registered
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.set_realm(DEFAULT_REALM, &names)?;
+        Ok(self)
+    }
+
+    pub fn with_realms_json(mut self, json: &str) -> Result<Authenticators, Error> {
+        let config: AuthenticatorsConfig =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        if !config.realms.contains_key(DEFAULT_REALM) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("The {} realm is required", DEFAULT_REALM),
+            ));
+        }
+
+        for (realm, names) in &config.realms {
+            self.set_realm(realm, names)?;
+        }
+        Ok(self)
+    }
+
+    /// Load the realms from `AUTHENTICATORS_FILE`, only the `default` one when the default
+    /// file is missing.
+    pub fn with_realms_from_env(self) -> Result<Authenticators, Error> {
+        match std::env::var("AUTHENTICATORS_FILE") {
+            Ok(path) => self.with_realms_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("authenticators.json") {
+                Ok(json) => self.with_realms_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => self.with_default_realm(),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    /// The [`builtin`](Authenticators::builtin) authenticators and the realms of
+    /// `AUTHENTICATORS_FILE`.
+    pub fn from_env(
+        repository: Repository,
+        access_control: AccessControl,
+        directory: Option<Arc<LdapDirectory>>,
+        providers: Arc<IdentityProviders>,
+    ) -> Result<Authenticators, Error> {
+        Authenticators::builtin(repository, access_control, directory, providers)
+            .with_realms_from_env()
+    }
+
+    pub fn has_realm(&self, realm: &str) -> bool {
+        self.realms.contains_key(realm)
+    }
+
+    /// User of the credentials, as decided by the first authenticator of the realm which
+    /// does not skip them. `None` for unknown realms.
+    pub async fn authenticate(
+        &self,
+        realm: Option<&str>,
+        credentials: &Credentials<'_>,
+    ) -> Option<User> {
+        let authenticators = self.realms.get(realm.unwrap_or(DEFAULT_REALM))?;
+        for authenticator in authenticators {
+            match authenticator.authenticate(credentials).await {
+                AuthOutcome::Authenticated(user) => return Some(user),
+                AuthOutcome::Rejected => return None,
+                AuthOutcome::Skipped => {}
+            }
+        }
+        None
+    }
+}
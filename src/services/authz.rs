@@ -0,0 +1,611 @@
+//! Relationship-based authorization, in the spirit of Google Zanzibar.
+//!
+//! Relations are stored as tuples `namespace:object#relation@subject` where the subject is
+//! either a plain object (`user:alice`) or a userset (`group:eng#member`). The namespace
+//! configuration (`AUTHZ_NAMESPACE_FILE`, `authz_namespaces.json` by default) describes how
+//! each relation is computed :
+//!
+//! ```json
+//! {
+//!   "namespaces": [{
+//!     "name": "document",
+//!     "relations": {
+//!       "parent": null,
+//!       "owner": null,
+//!       "viewer": { "union": ["this", { "computed_userset": { "relation": "owner" } },
+//!                   { "tuple_to_userset": { "tupleset": "parent", "computed_userset": "viewer" } }] }
+//!     }
+//!   }]
+//! }
+//! ```
+//!
+//! A `null` relation only holds its direct tuples. Every evaluation happens at a store
+//! revision, which is also what consistency tokens carry.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+const MAX_DEPTH: u8 = 25;
+const MAX_CACHED_CHECKS: usize = 10_000;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, sqlx::Error>> + Send + 'a>>;
+
+/// Read access to the tuples as they were at a given revision.
+pub trait TupleStore: Sync {
+    fn read_tuples<'a>(
+        &'a self,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        revision: i64,
+    ) -> StoreFuture<'a, Vec<SubjectRef>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectRef {
+    pub namespace: String,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubjectRef {
+    pub namespace: String,
+    pub id: String,
+    pub relation: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RelationTuple {
+    pub object: ObjectRef,
+    pub relation: String,
+    pub subject: SubjectRef,
+}
+
+fn is_identifier(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 64
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_object_id(value: &str) -> bool {
+    !value.is_empty() && value.len() <= 255 && !value.contains(['#', '@', ' '])
+}
+
+impl FromStr for ObjectRef {
+    type Err = Error;
+
+    fn from_str(object: &str) -> Result<Self, Self::Err> {
+        match object.split_once(':') {
+            Some((namespace, id)) if is_identifier(namespace) && is_object_id(id) => Ok(ObjectRef {
+                namespace: namespace.to_owned(),
+                id: id.to_owned(),
+            }),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid object, expected namespace:id")),
+        }
+    }
+}
+
+impl Display for ObjectRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.id)
+    }
+}
+
+impl SubjectRef {
+    pub fn object(&self) -> ObjectRef {
+        ObjectRef {
+            namespace: self.namespace.clone(),
+            id: self.id.clone(),
+        }
+    }
+}
+
+impl FromStr for SubjectRef {
+    type Err = Error;
+
+    fn from_str(subject: &str) -> Result<Self, Self::Err> {
+        let (object, relation) = match subject.split_once('#') {
+            Some((object, relation)) if is_identifier(relation) => (object, Some(relation)),
+            Some(_) => {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid subject relation"))
+            }
+            None => (subject, None),
+        };
+        let object = ObjectRef::from_str(object)?;
+
+        Ok(SubjectRef {
+            namespace: object.namespace,
+            id: object.id,
+            relation: relation.map(str::to_owned),
+        })
+    }
+}
+
+impl Display for SubjectRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.relation {
+            Some(relation) => write!(f, "{}:{}#{}", self.namespace, self.id, relation),
+            None => write!(f, "{}:{}", self.namespace, self.id),
+        }
+    }
+}
+
+impl FromStr for RelationTuple {
+    type Err = Error;
+
+    /// Parse `namespace:object#relation@subject`.
+    fn from_str(tuple: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "Invalid tuple");
+        let (object, subject) = tuple.split_once('@').ok_or_else(invalid)?;
+        let (object, relation) = object.split_once('#').ok_or_else(invalid)?;
+        if !is_identifier(relation) {
+            return Err(invalid());
+        }
+
+        Ok(RelationTuple {
+            object: ObjectRef::from_str(object)?,
+            relation: relation.to_owned(),
+            subject: SubjectRef::from_str(subject)?,
+        })
+    }
+}
+
+impl Display for RelationTuple {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}@{}", self.object, self.relation, self.subject)
+    }
+}
+
+macro_rules! string_serde {
+    ($type:ty) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = String::deserialize(deserializer)?;
+                <$type>::from_str(&value).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+string_serde!(ObjectRef);
+string_serde!(SubjectRef);
+
+/// Opaque token returned by writes and checks. Passing it back to a check guarantees the
+/// evaluation happens at a revision at least as fresh as the one it encodes.
+pub struct ConsistencyToken;
+
+impl ConsistencyToken {
+    pub fn encode(revision: i64) -> String {
+        format!("zk_{}", revision)
+    }
+
+    pub fn decode(token: &str) -> Result<i64, Error> {
+        token
+            .strip_prefix("zk_")
+            .and_then(|revision| revision.parse::<i64>().ok())
+            .filter(|revision| *revision >= 0)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid consistency token"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsersetRewrite {
+    /// Direct tuples of the relation.
+    This,
+    /// Subjects of another relation of the same object.
+    ComputedUserset { relation: String },
+    /// For each object of `tupleset`, subjects of its `computed_userset` relation.
+    TupleToUserset {
+        tupleset: String,
+        computed_userset: String,
+    },
+    Union(Vec<UsersetRewrite>),
+    Intersection(Vec<UsersetRewrite>),
+    Exclusion {
+        base: Box<UsersetRewrite>,
+        subtract: Box<UsersetRewrite>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Namespace {
+    pub name: String,
+    pub relations: HashMap<String, Option<UsersetRewrite>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NamespaceFile {
+    #[serde(default)]
+    namespaces: Vec<Namespace>,
+}
+
+/// Userset tree returned by `expand`. Leaves hold direct subjects, usersets among them
+/// are not expanded further.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExpandNode {
+    Leaf {
+        userset: String,
+        subjects: Vec<SubjectRef>,
+    },
+    Union {
+        userset: String,
+        children: Vec<ExpandNode>,
+    },
+    Intersection {
+        userset: String,
+        children: Vec<ExpandNode>,
+    },
+    Exclusion {
+        userset: String,
+        children: Vec<ExpandNode>,
+    },
+}
+
+#[derive(Default)]
+pub struct RelationAuthz {
+    namespaces: HashMap<String, Namespace>,
+    check_cache: Mutex<HashMap<(i64, RelationTuple), bool>>,
+}
+
+impl RelationAuthz {
+    pub fn new(namespaces: Vec<Namespace>) -> RelationAuthz {
+        RelationAuthz {
+            namespaces: namespaces
+                .into_iter()
+                .map(|namespace| (namespace.name.clone(), namespace))
+                .collect(),
+            check_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<RelationAuthz, Error> {
+        let file: NamespaceFile =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        let authz = RelationAuthz::new(file.namespaces);
+        authz.validate_config()?;
+        Ok(authz)
+    }
+
+    /// Load the namespaces from `AUTHZ_NAMESPACE_FILE`, no namespaces when the default
+    /// file is missing.
+    pub fn from_env() -> Result<RelationAuthz, Error> {
+        match std::env::var("AUTHZ_NAMESPACE_FILE") {
+            Ok(path) => RelationAuthz::from_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("authz_namespaces.json") {
+                Ok(json) => RelationAuthz::from_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(RelationAuthz::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    fn validate_config(&self) -> Result<(), Error> {
+        fn referenced<'a>(rewrite: &'a UsersetRewrite, relations: &mut Vec<&'a str>) {
+            match rewrite {
+                UsersetRewrite::This => {}
+                UsersetRewrite::ComputedUserset { relation } => relations.push(relation),
+                UsersetRewrite::TupleToUserset { tupleset, .. } => relations.push(tupleset),
+                UsersetRewrite::Union(children) | UsersetRewrite::Intersection(children) => {
+                    children.iter().for_each(|child| referenced(child, relations))
+                }
+                UsersetRewrite::Exclusion { base, subtract } => {
+                    referenced(base, relations);
+                    referenced(subtract, relations);
+                }
+            }
+        }
+
+        for namespace in self.namespaces.values() {
+            for rewrite in namespace.relations.values().flatten() {
+                let mut relations = vec![];
+                referenced(rewrite, &mut relations);
+                if let Some(unknown) = relations
+                    .into_iter()
+                    .find(|relation| !namespace.relations.contains_key(*relation))
+                {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unknown relation {} in namespace {}", unknown, namespace.name),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A tuple can only be written on a relation declared in its namespace.
+    pub fn validate_tuple(&self, tuple: &RelationTuple) -> Result<(), Error> {
+        match self.namespaces.get(&tuple.object.namespace) {
+            Some(namespace) if namespace.relations.contains_key(&tuple.relation) => Ok(()),
+            Some(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown relation {}", tuple.relation),
+            )),
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown namespace {}", tuple.object.namespace),
+            )),
+        }
+    }
+
+    fn rewrite(&self, object: &ObjectRef, relation: &str) -> Option<UsersetRewrite> {
+        self.namespaces
+            .get(&object.namespace)
+            .and_then(|namespace| namespace.relations.get(relation))
+            .map(|rewrite| rewrite.clone().unwrap_or(UsersetRewrite::This))
+    }
+
+    /// Whether `subject` has `relation` on `object` at `revision`.
+    pub async fn check<S: TupleStore>(
+        &self,
+        store: &S,
+        tuple: &RelationTuple,
+        revision: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let key = (revision, tuple.clone());
+        if let Some(allowed) = self.check_cache.lock().unwrap().get(&key) {
+            return Ok(*allowed);
+        }
+
+        let allowed = self
+            .check_relation(store, &tuple.object, &tuple.relation, &tuple.subject, revision, 0)
+            .await?;
+
+        let mut cache = self.check_cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_CHECKS {
+            cache.clear();
+        }
+        cache.insert(key, allowed);
+
+        Ok(allowed)
+    }
+
+    fn check_relation<'a, S: TupleStore>(
+        &'a self,
+        store: &'a S,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        subject: &'a SubjectRef,
+        revision: i64,
+        depth: u8,
+    ) -> StoreFuture<'a, bool> {
+        Box::pin(async move {
+            if depth > MAX_DEPTH {
+                log::warn!("authz check depth exceeded on {}#{}", object, relation);
+                return Ok(false);
+            }
+
+            match self.rewrite(object, relation) {
+                Some(rewrite) => {
+                    self.check_rewrite(store, &rewrite, object, relation, subject, revision, depth)
+                        .await
+                }
+                None => Ok(false),
+            }
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn check_rewrite<'a, S: TupleStore>(
+        &'a self,
+        store: &'a S,
+        rewrite: &'a UsersetRewrite,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        subject: &'a SubjectRef,
+        revision: i64,
+        depth: u8,
+    ) -> StoreFuture<'a, bool> {
+        Box::pin(async move {
+            match rewrite {
+                UsersetRewrite::This => {
+                    for direct in store.read_tuples(object, relation, revision).await? {
+                        if &direct == subject {
+                            return Ok(true);
+                        }
+                        if let Some(userset_relation) = &direct.relation {
+                            let userset = direct.object();
+                            if self
+                                .check_relation(
+                                    store,
+                                    &userset,
+                                    userset_relation,
+                                    subject,
+                                    revision,
+                                    depth + 1,
+                                )
+                                .await?
+                            {
+                                return Ok(true);
+                            }
+                        }
+                    }
+                    Ok(false)
+                }
+                UsersetRewrite::ComputedUserset { relation } => {
+                    self.check_relation(store, object, relation, subject, revision, depth + 1)
+                        .await
+                }
+                UsersetRewrite::TupleToUserset {
+                    tupleset,
+                    computed_userset,
+                } => {
+                    for parent in store.read_tuples(object, tupleset, revision).await? {
+                        let parent = parent.object();
+                        if self
+                            .check_relation(
+                                store,
+                                &parent,
+                                computed_userset,
+                                subject,
+                                revision,
+                                depth + 1,
+                            )
+                            .await?
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                UsersetRewrite::Union(children) => {
+                    for child in children {
+                        if self
+                            .check_rewrite(store, child, object, relation, subject, revision, depth)
+                            .await?
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                UsersetRewrite::Intersection(children) => {
+                    for child in children {
+                        if !self
+                            .check_rewrite(store, child, object, relation, subject, revision, depth)
+                            .await?
+                        {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(!children.is_empty())
+                }
+                UsersetRewrite::Exclusion { base, subtract } => Ok(self
+                    .check_rewrite(store, base, object, relation, subject, revision, depth)
+                    .await?
+                    && !self
+                        .check_rewrite(store, subtract, object, relation, subject, revision, depth)
+                        .await?),
+            }
+        })
+    }
+
+    /// Userset tree of `relation` on `object` at `revision`.
+    pub fn expand<'a, S: TupleStore>(
+        &'a self,
+        store: &'a S,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        revision: i64,
+    ) -> StoreFuture<'a, ExpandNode> {
+        Box::pin(async move {
+            match self.rewrite(object, relation) {
+                Some(rewrite) => {
+                    self.expand_rewrite(store, &rewrite, object, relation, revision, 0)
+                        .await
+                }
+                None => Ok(ExpandNode::Leaf {
+                    userset: format!("{}#{}", object, relation),
+                    subjects: vec![],
+                }),
+            }
+        })
+    }
+
+    fn expand_rewrite<'a, S: TupleStore>(
+        &'a self,
+        store: &'a S,
+        rewrite: &'a UsersetRewrite,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        revision: i64,
+        depth: u8,
+    ) -> StoreFuture<'a, ExpandNode> {
+        Box::pin(async move {
+            let userset = format!("{}#{}", object, relation);
+            if depth > MAX_DEPTH {
+                return Ok(ExpandNode::Leaf {
+                    userset,
+                    subjects: vec![],
+                });
+            }
+
+            let expand_children = |children: &'a [UsersetRewrite]| async move {
+                let mut nodes = vec![];
+                for child in children {
+                    nodes.push(
+                        self.expand_rewrite(store, child, object, relation, revision, depth + 1)
+                            .await?,
+                    );
+                }
+                Ok::<_, sqlx::Error>(nodes)
+            };
+
+            match rewrite {
+                UsersetRewrite::This => Ok(ExpandNode::Leaf {
+                    userset,
+                    subjects: store.read_tuples(object, relation, revision).await?,
+                }),
+                UsersetRewrite::ComputedUserset { relation } => match self.rewrite(object, relation)
+                {
+                    Some(rewrite) => {
+                        self.expand_rewrite(store, &rewrite, object, relation, revision, depth + 1)
+                            .await
+                    }
+                    None => Ok(ExpandNode::Leaf {
+                        userset,
+                        subjects: vec![],
+                    }),
+                },
+                UsersetRewrite::TupleToUserset {
+                    tupleset,
+                    computed_userset,
+                } => {
+                    let mut children = vec![];
+                    for parent in store.read_tuples(object, tupleset, revision).await? {
+                        let parent = parent.object();
+                        if let Some(rewrite) = self.rewrite(&parent, computed_userset) {
+                            children.push(
+                                self.expand_rewrite(
+                                    store,
+                                    &rewrite,
+                                    &parent,
+                                    computed_userset,
+                                    revision,
+                                    depth + 1,
+                                )
+                                .await?,
+                            );
+                        }
+                    }
+                    Ok(ExpandNode::Union { userset, children })
+                }
+                UsersetRewrite::Union(children) => Ok(ExpandNode::Union {
+                    userset,
+                    children: expand_children(children).await?,
+                }),
+                UsersetRewrite::Intersection(children) => Ok(ExpandNode::Intersection {
+                    userset,
+                    children: expand_children(children).await?,
+                }),
+                UsersetRewrite::Exclusion { base, subtract } => {
+                    let base = self
+                        .expand_rewrite(store, base, object, relation, revision, depth + 1)
+                        .await?;
+                    let subtract = self
+                        .expand_rewrite(store, subtract, object, relation, revision, depth + 1)
+                        .await?;
+                    Ok(ExpandNode::Exclusion {
+                        userset,
+                        children: vec![base, subtract],
+                    })
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,385 @@
+//! Custom claims added to access tokens at issuance time.
+//!
+//! Each provider owns a namespace, the object it returns is added to the token under that
+//! key. Providers are declared in a JSON file (`CLAIMS_PROVIDERS_FILE`,
+//! `claims_providers.json` by default) :
+//!
+//! ```json
+//! {
+//!   "max_total_bytes": 4096,
+//!   "providers": [
+//!     { "type": "static", "namespace": "app", "claims": { "plan": "free" },
+//!       "by_role": { "ROLE_ADMIN": { "plan": "internal" } } },
+//!     { "type": "sql", "namespace": "tenant", "required": true,
+//!       "query": "SELECT tenant_id, plan FROM public.user_metadata WHERE user_id = $1" },
+//!     { "type": "http", "namespace": "flags", "url": "http://localhost:8080/claims", "timeout_ms": 500 }
+//!   ]
+//! }
+//! ```
+//!
+//! - `static` : fixed claims, merged with the claims of each role of the user
+//! - `sql` : first row of the query, `$1` being the user id, columns become claims
+//! - `http` : the subject is POSTed as JSON, the JSON object answered becomes the claims
+//!
+//! A provider failing or answering more than `max_bytes` (1024 by default) is skipped, unless
+//! it is `required`, in which case no token is issued.
+
+use crate::services::crypto::REGISTERED_CLAIMS;
+use auth_client::HttpClient;
+use bytes::Bytes;
+use http_body_util::Full;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::time::Duration;
+
+const DEFAULT_MAX_BYTES: usize = 1024;
+const DEFAULT_MAX_TOTAL_BYTES: usize = 4096;
+const MAX_CLAIM_NAME_LENGTH: usize = 64;
+
+/// The user a token is issued for, as seen by the providers.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaimsSubject {
+    #[serde(rename = "sub")]
+    pub user_id: String,
+    pub email: String,
+    pub roles: Vec<String>,
+    #[serde(rename = "aud")]
+    pub audience: String,
+}
+
+pub type ClaimsFuture<'a> = Pin<Box<dyn Future<Output = Result<Map<String, Value>, Error>> + Send + 'a>>;
+
+pub trait ClaimsProvider: Send + Sync {
+    fn claims<'a>(&'a self, subject: &'a ClaimsSubject) -> ClaimsFuture<'a>;
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StaticClaimsProvider {
+    #[serde(default)]
+    claims: Map<String, Value>,
+    /// Claims added for each role of the user, overriding the default ones.
+    #[serde(default)]
+    by_role: HashMap<String, Map<String, Value>>,
+}
+
+impl StaticClaimsProvider {
+    pub fn new(claims: Map<String, Value>, by_role: HashMap<String, Map<String, Value>>) -> Self {
+        StaticClaimsProvider { claims, by_role }
+    }
+}
+
+impl ClaimsProvider for StaticClaimsProvider {
+    fn claims<'a>(&'a self, subject: &'a ClaimsSubject) -> ClaimsFuture<'a> {
+        Box::pin(async move {
+            let mut claims = self.claims.clone();
+            for role in &subject.roles {
+                if let Some(role_claims) = self.by_role.get(role) {
+                    claims.extend(role_claims.clone());
+                }
+            }
+            Ok(claims)
+        })
+    }
+}
+
+pub struct SqlClaimsProvider {
+    query: String,
+    db_pool: Pool<Postgres>,
+}
+
+impl SqlClaimsProvider {
+    pub fn new(query: &str, db_pool: Pool<Postgres>) -> SqlClaimsProvider {
+        SqlClaimsProvider {
+            query: query.trim().trim_end_matches(';').to_owned(),
+            db_pool,
+        }
+    }
+}
+
+impl ClaimsProvider for SqlClaimsProvider {
+    fn claims<'a>(&'a self, subject: &'a ClaimsSubject) -> ClaimsFuture<'a> {
+        Box::pin(async move {
+            let query = format!(
+                "SELECT row_to_json(claims)::text FROM ({}) AS claims LIMIT 1",
+                self.query
+            );
+            let row = sqlx::query_scalar::<_, String>(&query)
+                .bind(&subject.user_id)
+                .fetch_optional(&self.db_pool)
+                .await
+                .map_err(Error::other)?;
+
+            match row {
+                Some(json) => parse_object(json.as_bytes()),
+                None => Ok(Map::new()),
+            }
+        })
+    }
+}
+
+/// HTTP callback, meant for a sidecar or a service on the same network : plain HTTP is
+/// allowed to any host. Answers are read up to `max_bytes`.
+pub struct HttpClaimsProvider {
+    uri: http::Uri,
+    client: HttpClient,
+}
+
+impl HttpClaimsProvider {
+    pub fn new(
+        url: &str,
+        timeout: Duration,
+        max_bytes: usize,
+    ) -> Result<HttpClaimsProvider, Error> {
+        let uri: http::Uri = url
+            .parse()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        let client = HttpClient::default()
+            .with_plain_http()
+            .with_timeout(timeout)
+            .with_max_response_bytes(max_bytes);
+        if !client.supports(&uri) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unsupported claims callback url: {}", url),
+            ));
+        }
+
+        Ok(HttpClaimsProvider { uri, client })
+    }
+}
+
+impl ClaimsProvider for HttpClaimsProvider {
+    fn claims<'a>(&'a self, subject: &'a ClaimsSubject) -> ClaimsFuture<'a> {
+        Box::pin(async move {
+            let request = http::Request::post(self.uri.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Full::new(Bytes::from(serde_json::to_vec(subject)?)))
+                .map_err(Error::other)?;
+
+            let response = self.client.send(request).await?;
+            if !response.status().is_success() {
+                return Err(Error::other(format!(
+                    "Claims callback answered {}",
+                    response.status()
+                )));
+            }
+            parse_object(response.body())
+        })
+    }
+}
+
+fn parse_object(json: &[u8]) -> Result<Map<String, Value>, Error> {
+    match serde_json::from_slice(json)? {
+        Value::Object(claims) => Ok(claims),
+        _ => Err(Error::new(ErrorKind::InvalidData, "Claims must be a JSON object")),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ProviderKind {
+    Static(StaticClaimsProvider),
+    Sql { query: String },
+    Http {
+        url: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ProviderConfig {
+    namespace: String,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    max_bytes: Option<usize>,
+    #[serde(flatten)]
+    kind: ProviderKind,
+}
+
+#[derive(Deserialize)]
+struct EnricherConfig {
+    #[serde(default)]
+    max_total_bytes: Option<usize>,
+    #[serde(default)]
+    providers: Vec<ProviderConfig>,
+}
+
+struct RegisteredProvider {
+    namespace: String,
+    required: bool,
+    max_bytes: usize,
+    provider: Box<dyn ClaimsProvider>,
+}
+
+/// Collects the namespaced claims of the registered providers.
+pub struct ClaimsEnricher {
+    providers: Vec<RegisteredProvider>,
+    max_total_bytes: usize,
+}
+
+impl Default for ClaimsEnricher {
+    fn default() -> ClaimsEnricher {
+        ClaimsEnricher::new(DEFAULT_MAX_TOTAL_BYTES)
+    }
+}
+
+impl ClaimsEnricher {
+    pub fn new(max_total_bytes: usize) -> ClaimsEnricher {
+        ClaimsEnricher {
+            providers: vec![],
+            max_total_bytes,
+        }
+    }
+
+    /// Namespaces are lower-case identifiers which must not shadow a registered claim.
+    pub fn validate_namespace(namespace: &str) -> Result<(), Error> {
+        let valid = namespace.len() <= 32
+            && namespace.starts_with(|c: char| c.is_ascii_lowercase())
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+
+        if !valid || REGISTERED_CLAIMS.contains(&namespace) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid claims namespace: {}", namespace),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn register(
+        &mut self,
+        namespace: &str,
+        provider: impl ClaimsProvider + 'static,
+        required: bool,
+        max_bytes: usize,
+    ) -> Result<(), Error> {
+        Self::validate_namespace(namespace)?;
+        if self.providers.iter().any(|p| p.namespace == namespace) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Duplicated claims namespace: {}", namespace),
+            ));
+        }
+
+        self.providers.push(RegisteredProvider {
+            namespace: namespace.to_owned(),
+            required,
+            max_bytes,
+            provider: Box::new(provider),
+        });
+        Ok(())
+    }
+
+    /// `db_pool` is used by the `sql` providers.
+    pub fn from_json(json: &str, db_pool: Pool<Postgres>) -> Result<ClaimsEnricher, Error> {
+        let config: EnricherConfig =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        let mut enricher =
+            ClaimsEnricher::new(config.max_total_bytes.unwrap_or(DEFAULT_MAX_TOTAL_BYTES));
+        for provider in config.providers {
+            let max_bytes = provider.max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+            let namespace = provider.namespace.as_str();
+            match provider.kind {
+                ProviderKind::Static(static_provider) => {
+                    enricher.register(namespace, static_provider, provider.required, max_bytes)?
+                }
+                ProviderKind::Sql { query } => enricher.register(
+                    namespace,
+                    SqlClaimsProvider::new(&query, db_pool.clone()),
+                    provider.required,
+                    max_bytes,
+                )?,
+                ProviderKind::Http { url, timeout_ms } => enricher.register(
+                    namespace,
+                    HttpClaimsProvider::new(
+                        &url,
+                        Duration::from_millis(timeout_ms.unwrap_or(1000)),
+                        max_bytes,
+                    )?,
+                    provider.required,
+                    max_bytes,
+                )?,
+            }
+        }
+
+        Ok(enricher)
+    }
+
+    /// Load the providers from `CLAIMS_PROVIDERS_FILE`, none when the default file is missing.
+    pub fn from_env(db_pool: Pool<Postgres>) -> Result<ClaimsEnricher, Error> {
+        match std::env::var("CLAIMS_PROVIDERS_FILE") {
+            Ok(path) => ClaimsEnricher::from_json(&std::fs::read_to_string(path)?, db_pool),
+            Err(_) => match std::fs::read_to_string("claims_providers.json") {
+                Ok(json) => ClaimsEnricher::from_json(&json, db_pool),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(ClaimsEnricher::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    /// Claims of every provider, keyed by namespace. Fails only when a required provider
+    /// fails or its claims are invalid.
+    pub async fn enrich(&self, subject: &ClaimsSubject) -> Result<Map<String, Value>, Error> {
+        let mut enriched = Map::new();
+        let mut total_bytes = 0;
+
+        for registered in &self.providers {
+            let claims = registered
+                .provider
+                .claims(subject)
+                .await
+                .and_then(|claims| {
+                    validate_claims(&claims)?;
+                    let size = serde_json::to_vec(&claims)?.len();
+                    if size > registered.max_bytes || total_bytes + size > self.max_total_bytes {
+                        return Err(Error::new(ErrorKind::InvalidData, "Claims are too large"));
+                    }
+                    Ok((claims, size))
+                });
+
+            match claims {
+                Ok((claims, _)) if claims.is_empty() => {}
+                Ok((claims, size)) => {
+                    total_bytes += size;
+                    enriched.insert(registered.namespace.clone(), Value::Object(claims));
+                }
+                Err(err) if registered.required => {
+                    return Err(Error::new(
+                        err.kind(),
+                        format!("Claims provider {} failed: {}", registered.namespace, err),
+                    ))
+                }
+                Err(err) => log::warn!(
+                    "Claims provider {} skipped : {}",
+                    registered.namespace,
+                    err
+                ),
+            }
+        }
+
+        Ok(enriched)
+    }
+}
+
+fn validate_claims(claims: &Map<String, Value>) -> Result<(), Error> {
+    match claims
+        .keys()
+        .find(|name| name.is_empty() || name.len() > MAX_CLAIM_NAME_LENGTH)
+    {
+        Some(name) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid claim name: {}", name),
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,333 @@
+//! Registration of OAuth clients, by administrators or dynamically (RFC 7591).
+//!
+//! Dynamic registration is enabled by setting `OAUTH_INITIAL_ACCESS_TOKEN`, the bearer
+//! token that registering parties must present. Dynamically registered clients may only
+//! request the OpenID Connect scopes and use the default token lifetimes.
+
+use crate::repository::oauth_repository::{ClientMetadata, NewOAuthClient, OAuthClient};
+use crate::services::oauth::{
+    parse_scope, validate_client_jwks, validate_redirect_uri, OAuthError, OAuthErrorCode,
+    AUTH_METHOD_NONE, AUTH_METHOD_PRIVATE_KEY_JWT, AUTH_METHOD_SECRET_BASIC,
+    AUTH_METHOD_SECRET_POST, GRANT_AUTHORIZATION_CODE, GRANT_CLIENT_CREDENTIALS, GRANT_PASSWORD,
+    SUPPORTED_GRANT_TYPES,
+};
+use crate::services::oidc::SUPPORTED_SCOPES;
+use jsonwebtoken::jwk::JwkSet;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+pub const MAX_TOKEN_LIFETIME_SECONDS: i32 = 3600 * 24 * 365;
+pub const DEFAULT_SECRET_GRACE_PERIOD_SECONDS: i64 = 3600 * 24;
+pub const MAX_SECRET_GRACE_PERIOD_SECONDS: i64 = 3600 * 24 * 30;
+
+pub const SUPPORTED_AUTH_METHODS: &[&str] = &[
+    AUTH_METHOD_SECRET_BASIC,
+    AUTH_METHOD_SECRET_POST,
+    AUTH_METHOD_PRIVATE_KEY_JWT,
+    AUTH_METHOD_NONE,
+];
+
+/// Whether clients with this authentication method are given a secret.
+pub fn uses_secret(token_endpoint_auth_method: &str) -> bool {
+    matches!(
+        token_endpoint_auth_method,
+        AUTH_METHOD_SECRET_BASIC | AUTH_METHOD_SECRET_POST
+    )
+}
+
+/// Settings of a client to register or update, checked by [`ClientRegistration::validate`].
+#[derive(Debug, Clone)]
+pub struct ClientRegistration {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub token_endpoint_auth_method: String,
+    pub jwks: Option<JwkSet>,
+    /// Only set by administrators, never by dynamic registration.
+    pub first_party: bool,
+    pub metadata: ClientMetadata,
+}
+
+fn invalid_metadata(description: &str) -> OAuthError {
+    OAuthError::new(OAuthErrorCode::InvalidClientMetadata, description)
+}
+
+/// Pages link to these URIs and show the logo, only `https` is accepted.
+fn validate_metadata_uri(name: &str, uri: &Option<String>) -> Result<(), OAuthError> {
+    match uri {
+        Some(uri)
+            if !uri.starts_with("https://")
+                || uri.len() <= "https://".len()
+                || uri.chars().any(|c| c.is_whitespace()) =>
+        {
+            Err(invalid_metadata(&format!("{} must be an https URI", name)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_lifetime(name: &str, lifetime: Option<i32>) -> Result<(), OAuthError> {
+    match lifetime {
+        Some(lifetime) if lifetime <= 0 || lifetime > MAX_TOKEN_LIFETIME_SECONDS => {
+            Err(invalid_metadata(&format!(
+                "{} must be between 1 and {}",
+                name, MAX_TOKEN_LIFETIME_SECONDS
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl ClientRegistration {
+    pub fn validate(&self) -> Result<(), OAuthError> {
+        if self.name.trim().is_empty() {
+            return Err(invalid_metadata("name is required"));
+        }
+
+        if let Some(grant_type) = self
+            .grant_types
+            .iter()
+            .find(|grant_type| !SUPPORTED_GRANT_TYPES.contains(&grant_type.as_str()))
+        {
+            return Err(invalid_metadata(&format!(
+                "Unsupported grant type: {}",
+                grant_type
+            )));
+        }
+        if self.grant_types.is_empty() {
+            return Err(invalid_metadata("At least one grant type is required"));
+        }
+        if self.has_grant(GRANT_CLIENT_CREDENTIALS) {
+            return Err(invalid_metadata(
+                "The client_credentials grant is reserved to service accounts",
+            ));
+        }
+        // Third parties never see the passwords of the users.
+        if self.has_grant(GRANT_PASSWORD) && !self.first_party {
+            return Err(invalid_metadata(
+                "The password grant is reserved to first-party clients",
+            ));
+        }
+
+        // Only the authorization code grant redirects, devices poll the token endpoint.
+        if self.has_grant(GRANT_AUTHORIZATION_CODE) && self.redirect_uris.is_empty() {
+            return Err(OAuthError::new(
+                OAuthErrorCode::InvalidRedirectUri,
+                "redirect_uris are required for the authorization_code grant",
+            ));
+        }
+        if let Some(err) = self
+            .redirect_uris
+            .iter()
+            .find_map(|uri| validate_redirect_uri(uri).err())
+        {
+            return Err(OAuthError::new(
+                OAuthErrorCode::InvalidRedirectUri,
+                &err.error_description,
+            ));
+        }
+
+        if self
+            .scopes
+            .iter()
+            .any(|scope| scope.is_empty() || scope.contains(' '))
+        {
+            return Err(invalid_metadata(
+                "Scopes must be non-empty and without spaces",
+            ));
+        }
+
+        if !SUPPORTED_AUTH_METHODS.contains(&self.token_endpoint_auth_method.as_str()) {
+            return Err(invalid_metadata(&format!(
+                "Unsupported token_endpoint_auth_method: {}",
+                self.token_endpoint_auth_method
+            )));
+        }
+        match (&self.jwks, self.token_endpoint_auth_method.as_str()) {
+            (Some(jwks), AUTH_METHOD_PRIVATE_KEY_JWT) => validate_client_jwks(jwks)
+                .map_err(|err| invalid_metadata(&err.error_description))?,
+            (None, AUTH_METHOD_PRIVATE_KEY_JWT) => {
+                return Err(invalid_metadata("jwks is required for private_key_jwt"))
+            }
+            (Some(_), _) => return Err(invalid_metadata("jwks is only used with private_key_jwt")),
+            (None, _) => {}
+        }
+
+        validate_lifetime(
+            "access_token_lifetime_seconds",
+            self.metadata.access_token_lifetime_seconds,
+        )?;
+        validate_lifetime(
+            "refresh_token_lifetime_seconds",
+            self.metadata.refresh_token_lifetime_seconds,
+        )?;
+        validate_metadata_uri("logo_uri", &self.metadata.logo_uri)?;
+        validate_metadata_uri("client_uri", &self.metadata.client_uri)?;
+        validate_metadata_uri("policy_uri", &self.metadata.policy_uri)?;
+        validate_metadata_uri("tos_uri", &self.metadata.tos_uri)?;
+
+        Ok(())
+    }
+
+    pub fn has_grant(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|grant| grant == grant_type)
+    }
+
+    pub fn into_new_client(self, client_secret_hash: Option<String>) -> NewOAuthClient {
+        NewOAuthClient {
+            client_secret_hash,
+            name: self.name,
+            redirect_uris: self.redirect_uris,
+            scopes: self.scopes,
+            grant_types: self.grant_types,
+            token_endpoint_auth_method: self.token_endpoint_auth_method,
+            jwks: self.jwks,
+            first_party: self.first_party,
+            metadata: self.metadata,
+        }
+    }
+}
+
+fn default_grant_types() -> Vec<String> {
+    vec![String::from(GRANT_AUTHORIZATION_CODE)]
+}
+
+fn default_auth_method() -> String {
+    String::from(AUTH_METHOD_SECRET_BASIC)
+}
+
+/// Client metadata of a dynamic registration request (RFC 7591 section 2).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationRequest {
+    #[serde(default)]
+    pub client_name: Option<String>,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    #[serde(default = "default_grant_types")]
+    pub grant_types: Vec<String>,
+    #[serde(default)]
+    pub response_types: Option<Vec<String>>,
+    /// Space separated.
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default = "default_auth_method")]
+    pub token_endpoint_auth_method: String,
+    #[serde(default)]
+    pub jwks: Option<JwkSet>,
+    #[serde(default)]
+    pub logo_uri: Option<String>,
+    #[serde(default)]
+    pub client_uri: Option<String>,
+    #[serde(default)]
+    pub policy_uri: Option<String>,
+    #[serde(default)]
+    pub tos_uri: Option<String>,
+}
+
+impl RegistrationRequest {
+    pub fn into_registration(self) -> Result<ClientRegistration, OAuthError> {
+        if let Some(response_type) = self
+            .response_types
+            .iter()
+            .flatten()
+            .find(|response_type| *response_type != "code")
+        {
+            return Err(invalid_metadata(&format!(
+                "Unsupported response type: {}",
+                response_type
+            )));
+        }
+
+        let scopes = parse_scope(self.scope.as_deref());
+        if let Some(scope) = scopes
+            .iter()
+            .find(|scope| !SUPPORTED_SCOPES.contains(&scope.as_str()))
+        {
+            return Err(OAuthError::new(
+                OAuthErrorCode::InvalidClientMetadata,
+                &format!("Scope {} may not be registered dynamically", scope),
+            ));
+        }
+
+        let registration = ClientRegistration {
+            name: self.client_name.unwrap_or_default(),
+            redirect_uris: self.redirect_uris,
+            scopes,
+            grant_types: self.grant_types,
+            token_endpoint_auth_method: self.token_endpoint_auth_method,
+            jwks: self.jwks,
+            first_party: false,
+            metadata: ClientMetadata {
+                logo_uri: self.logo_uri,
+                client_uri: self.client_uri,
+                policy_uri: self.policy_uri,
+                tos_uri: self.tos_uri,
+                ..ClientMetadata::default()
+            },
+        };
+        registration.validate()?;
+
+        Ok(registration)
+    }
+}
+
+/// Client information response (RFC 7591 section 3.2.1).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationResponse {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub client_id_issued_at: i64,
+    /// `0`, secrets do not expire.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret_expires_at: Option<i64>,
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+    pub token_endpoint_auth_method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<JwkSet>,
+    #[serde(flatten)]
+    pub metadata: ClientMetadata,
+}
+
+impl RegistrationResponse {
+    pub fn new(client: OAuthClient, client_secret: Option<String>) -> RegistrationResponse {
+        RegistrationResponse {
+            client_secret_expires_at: client_secret.as_ref().map(|_| 0),
+            client_secret,
+            client_id: client.client_id,
+            client_id_issued_at: client.created_at.timestamp(),
+            client_name: client.name,
+            redirect_uris: client.redirect_uris,
+            grant_types: client.grant_types,
+            scope: client.scopes.join(" "),
+            token_endpoint_auth_method: client.token_endpoint_auth_method,
+            jwks: client.jwks.map(|jwks| jwks.0),
+            metadata: client.metadata,
+        }
+    }
+}
+
+/// Token expected from registering parties, registration is disabled when not set.
+pub fn initial_access_token() -> Option<String> {
+    env::var("OAUTH_INITIAL_ACCESS_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// The `Authorization` header bears the initial access token.
+pub fn initial_access_token_valid(authorization: Option<&str>, expected: Option<&str>) -> bool {
+    let token = authorization.and_then(|header| header.strip_prefix("Bearer "));
+    match (token, expected) {
+        (Some(token), Some(expected)) => ring::constant_time::verify_slices_are_equal(
+            token.trim().as_bytes(),
+            expected.as_bytes(),
+        )
+        .is_ok(),
+        _ => false,
+    }
+}
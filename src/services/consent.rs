@@ -0,0 +1,21 @@
+//! Consent of users to the scopes requested by OAuth clients.
+//!
+//! Users are asked once per client : the consent page is skipped while the scopes they
+//! granted cover the request, and always for first-party clients. Users list and revoke
+//! their grants on `/api/v1/me/grants`.
+
+/// Whether the user must be asked before the client gets `requested`.
+pub fn consent_required(
+    first_party: bool,
+    granted: Option<&[String]>,
+    requested: &[String],
+) -> bool {
+    if first_party {
+        return false;
+    }
+
+    match granted {
+        Some(granted) => requested.iter().any(|scope| !granted.contains(scope)),
+        None => true,
+    }
+}
@@ -1,14 +1,28 @@
+use crate::services::revocation::RevocationList;
 use argon2::password_hash::rand_core::OsRng;
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::{Local, Utc};
 use jsonwebtoken::errors::ErrorKind;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{Ed25519KeyPair, KeyPair};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use sha2::digest::block_buffer::Error;
 use sha2::Digest;
 use sha2::Sha256;
+use std::collections::HashMap;
 use std::env;
+use std::sync::OnceLock;
 
 pub struct HashService;
 pub struct JwtService;
@@ -43,52 +57,411 @@ pub trait Hash {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
+    pub iss: String,
+    /// Id of the user, stable across email changes.
     pub sub: String,
+    pub aud: String,
     pub exp: u64,
+    pub iat: u64,
+    pub nbf: u64,
+    pub jti: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// OAuth client the token was issued to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Namespaced claims of the [`ClaimsEnricher`](crate::services::claims::ClaimsEnricher).
+    #[serde(flatten)]
+    pub custom: Map<String, Value>,
+}
+
+impl Claims {
+    /// Tokens of the client credentials grant are about the client itself.
+    pub fn is_service_account(&self) -> bool {
+        self.client_id.as_deref() == Some(self.sub.as_str())
+    }
+}
+
+/// Claims set by the service itself, never overridden by custom claims.
+pub const REGISTERED_CLAIMS: &[&str] = &[
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "nbf",
+    "jti",
+    "roles",
+    "scope",
+    "client_id",
+    "azp",
+    "nonce",
+    "auth_time",
+    "email",
+    "email_verified",
+    "name",
+];
+
+/// What to put in a token besides the subject.
+#[derive(Debug, Default, Clone)]
+pub struct TokenOptions {
+    /// Audience of the token, the default one when not set.
+    pub audience: Option<String>,
+    pub roles: Vec<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub claims: Map<String, Value>,
+    /// Lifetime in seconds, the one of the audience when not set.
+    pub lifetime: Option<u64>,
+}
+
+const DEFAULT_ISSUER: &str = "auth_api";
+const DEFAULT_LIFETIME_SECONDS: u64 = 3600 * 24 * 20;
+const DEFAULT_LEEWAY_SECONDS: u64 = 60;
+const DEFAULT_OAUTH_AUDIENCE: &str = "oauth";
+
+/// Issuance and validation settings of the tokens.
+///
+/// - `JWT_ISSUER` : `iss` of issued tokens, `auth_api` by default
+/// - `JWT_AUDIENCE` : default `aud`, the issuer by default
+/// - `JWT_LIFETIME_SECONDS` : lifetime of tokens for the default audience, 20 days by default
+/// - `JWT_AUDIENCE_LIFETIMES` : other audiences tokens are issued for and their lifetime,
+///   e.g. `mobile=2592000,cli=3600`. This service only accepts them where stated, e.g. on
+///   introspection, as they are meant for other services.
+/// - `JWT_OAUTH_AUDIENCE` : `aud` of the access tokens users delegate to OAuth clients,
+///   `oauth` by default, with the lifetime of the default audience unless listed below. The
+///   API of this service does not accept them, only the resource servers of the clients and
+///   the UserInfo endpoint do.
+/// - `JWT_LEEWAY_SECONDS` : clock skew tolerated on `exp` and `nbf`, 60 by default
+#[derive(Debug, Clone)]
+pub struct TokenConfig {
+    pub issuer: String,
+    pub audience: String,
+    pub oauth_audience: String,
+    pub lifetimes: HashMap<String, u64>,
+    pub leeway: u64,
+}
+
+impl TokenConfig {
+    pub fn new(issuer: &str, audience: &str, lifetime: u64) -> TokenConfig {
+        TokenConfig {
+            issuer: issuer.to_owned(),
+            audience: audience.to_owned(),
+            oauth_audience: String::from(DEFAULT_OAUTH_AUDIENCE),
+            lifetimes: HashMap::from([
+                (audience.to_owned(), lifetime),
+                (String::from(DEFAULT_OAUTH_AUDIENCE), lifetime),
+            ]),
+            leeway: DEFAULT_LEEWAY_SECONDS,
+        }
+    }
+
+    pub fn from_env() -> TokenConfig {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let issuer = var("JWT_ISSUER").unwrap_or_else(|| String::from(DEFAULT_ISSUER));
+        let audience = var("JWT_AUDIENCE").unwrap_or_else(|| issuer.clone());
+        let lifetime = var("JWT_LIFETIME_SECONDS")
+            .and_then(|lifetime| lifetime.parse().ok())
+            .unwrap_or(DEFAULT_LIFETIME_SECONDS);
+
+        let mut config = TokenConfig::new(&issuer, &audience, lifetime);
+        if let Some(oauth_audience) = var("JWT_OAUTH_AUDIENCE") {
+            if audience != DEFAULT_OAUTH_AUDIENCE {
+                config.lifetimes.remove(DEFAULT_OAUTH_AUDIENCE);
+            }
+            config.add_audience(&oauth_audience, lifetime);
+            config.oauth_audience = oauth_audience;
+        }
+        for entry in var("JWT_AUDIENCE_LIFETIMES").unwrap_or_default().split(',') {
+            match entry.split_once('=') {
+                Some((audience, lifetime)) => match lifetime.trim().parse() {
+                    Ok(lifetime) => config.add_audience(audience.trim(), lifetime),
+                    Err(_) => log::warn!("Invalid lifetime in JWT_AUDIENCE_LIFETIMES : {}", entry),
+                },
+                None if entry.trim().is_empty() => {}
+                None => log::warn!("Invalid entry in JWT_AUDIENCE_LIFETIMES : {}", entry),
+            }
+        }
+        if let Some(leeway) = var("JWT_LEEWAY_SECONDS").and_then(|leeway| leeway.parse().ok()) {
+            config.leeway = leeway;
+        }
+
+        config
+    }
+
+    pub fn add_audience(&mut self, audience: &str, lifetime: u64) {
+        self.lifetimes.insert(audience.to_owned(), lifetime);
+    }
+
+    /// Lifetime in seconds of tokens for `audience`, `None` for an unknown audience.
+    pub fn lifetime(&self, audience: &str) -> Option<u64> {
+        self.lifetimes.get(audience).copied()
+    }
+
+    /// Claims of a new token for `user_id`, fails when the audience is unknown.
+    pub fn claims(
+        &self,
+        user_id: &str,
+        options: &TokenOptions,
+    ) -> Result<Claims, jsonwebtoken::errors::Error> {
+        if user_id.is_empty() {
+            return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidSubject));
+        }
+
+        let audience = options.audience.as_deref().unwrap_or(&self.audience);
+        let lifetime = self
+            .lifetime(audience)
+            .ok_or_else(|| jsonwebtoken::errors::Error::from(ErrorKind::InvalidAudience))?;
+        let lifetime = options.lifetime.unwrap_or(lifetime);
+        let now = Utc::now().timestamp() as u64;
+
+        Ok(Claims {
+            iss: self.issuer.clone(),
+            sub: user_id.to_owned(),
+            aud: audience.to_owned(),
+            exp: now + lifetime,
+            iat: now,
+            nbf: now,
+            jti: generate_token_id(),
+            roles: options.roles.clone(),
+            scope: options.scope.clone(),
+            client_id: options.client_id.clone(),
+            custom: options
+                .claims
+                .iter()
+                .filter(|(name, _)| !REGISTERED_CLAIMS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        })
+    }
+
+    /// Every audience tokens are issued for.
+    pub fn audiences(&self) -> Vec<&str> {
+        self.lifetimes.keys().map(String::as_str).collect()
+    }
+
+    /// Validation of issuer, `exp` and `nbf` with the configured leeway, for tokens of the
+    /// default audience : those of the other audiences are not meant for this service.
+    pub fn validation(&self, algorithm: Algorithm) -> Validation {
+        self.validation_for(algorithm, &[&self.audience])
+    }
+
+    /// Same as [`TokenConfig::validation`], for tokens of any of `audiences`.
+    pub fn validation_for(&self, algorithm: Algorithm, audiences: &[&str]) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(audiences);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation
+    }
+}
+
+/// 128 random bits, hex encoded, used as `jti`.
+fn generate_token_id() -> String {
+    let mut id = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut id)
+        .expect("Unable to generate a token id");
+    hex::encode(id)
+}
+
+/// Ed25519 key signing access tokens, published on `/.well-known/jwks.json` so that other
+/// services can verify tokens offline. Without one, tokens are signed with `JWT_SECRET`.
+pub struct SigningKey {
+    kid: String,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    public_key: Vec<u8>,
+}
+
+static SIGNING_KEY: OnceLock<Option<SigningKey>> = OnceLock::new();
+
+impl SigningKey {
+    /// Load a PKCS#8 Ed25519 private key (`openssl genpkey -algorithm ed25519`).
+    pub fn from_pem(pem: &[u8]) -> Result<SigningKey, jsonwebtoken::errors::Error> {
+        let invalid_key = || jsonwebtoken::errors::Error::from(ErrorKind::InvalidEcdsaKey);
+        let der = pem::parse(pem).map_err(|_| invalid_key())?;
+        let key_pair =
+            Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents()).map_err(|_| invalid_key())?;
+        let public_key = key_pair.public_key().as_ref().to_vec();
+
+        Ok(SigningKey {
+            kid: SigningKey::thumbprint(&public_key),
+            encoding_key: EncodingKey::from_ed_der(der.contents()),
+            decoding_key: DecodingKey::from_ed_der(&public_key),
+            public_key,
+        })
+    }
+
+    /// Key read once from `JWT_SIGNING_KEY_FILE`, if set.
+    pub fn from_env() -> Option<&'static SigningKey> {
+        SIGNING_KEY
+            .get_or_init(|| {
+                let path = env::var("JWT_SIGNING_KEY_FILE")
+                    .ok()
+                    .filter(|path| !path.is_empty())?;
+                let pem = std::fs::read(&path)
+                    .unwrap_or_else(|err| panic!("Unable to read {} : {:?}", path, err));
+                Some(
+                    SigningKey::from_pem(&pem)
+                        .unwrap_or_else(|err| panic!("Invalid JWT signing key : {:?}", err)),
+                )
+            })
+            .as_ref()
+    }
+
+    /// RFC 7638 thumbprint, used as `kid`.
+    fn thumbprint(public_key: &[u8]) -> String {
+        let canonical = format!(
+            r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#,
+            URL_SAFE_NO_PAD.encode(public_key)
+        );
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(self.kid.clone());
+        encode(&header, claims, &self.encoding_key)
+    }
+
+    pub fn verify(
+        &self,
+        token: &str,
+        config: &TokenConfig,
+        audiences: &[&str],
+    ) -> Result<Claims, jsonwebtoken::errors::Error> {
+        let validation = config.validation_for(Algorithm::EdDSA, audiences);
+        decode::<Claims>(token, &self.decoding_key, &validation).map(|data| data.claims)
+    }
+
+    pub fn jwk(&self) -> Jwk {
+        Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(KeyAlgorithm::EdDSA),
+                key_id: Some(self.kid.clone()),
+                ..Default::default()
+            },
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(&self.public_key),
+            }),
+        }
+    }
+}
+
+/// Check at startup that tokens can be signed : with the key of `JWT_SIGNING_KEY_FILE`, or
+/// with `JWT_SECRET`.
+pub fn check_signing_config() {
+    let secret = env::var("JWT_SECRET").unwrap_or_default();
+    if SigningKey::from_env().is_none() && secret.is_empty() {
+        panic!("JWT_SECRET env variable is required");
+    }
 }
 
 pub trait Jwt {
-    fn generate_jwt(email: &str) -> Result<String, jsonwebtoken::errors::Error> {
-        if email.is_empty() {
-            return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidIssuer));
+    fn generate_jwt(user_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
+        Self::generate_jwt_with(user_id, &TokenOptions::default())
+    }
+
+    fn generate_jwt_with(
+        user_id: &str,
+        options: &TokenOptions,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        if user_id.is_empty() {
+            return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidSubject));
+        }
+
+        let my_claims = TokenConfig::from_env().claims(user_id, options)?;
+
+        Self::sign_claims(&my_claims)
+    }
+
+    /// Sign any set of claims, e.g. ID tokens, with the key of the access tokens.
+    fn sign_claims<T: Serialize>(claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        if let Some(key) = SigningKey::from_env() {
+            return key.sign(claims);
         }
 
         let secret = env::var("JWT_SECRET")
             .unwrap_or_else(|_| panic!("JWT_SECRET env variable is required"));
-        let my_claims = Claims {
-            sub: email.to_owned(),
-            exp: (Utc::now().timestamp() + (3600 * 24 * 20)) as u64,
-        };
-
-        /* let header = Header {
-            kid: Some("signing_key".to_owned()),
-            alg: Algorithm::HS512,
-            ..Default::default()
-        }; */
 
         encode(
             &Header::default(),
-            &my_claims,
+            claims,
             &EncodingKey::from_secret(secret.as_bytes()),
         )
     }
 
+    /// Algorithm of the issued tokens.
+    fn signing_algorithm() -> Algorithm {
+        match SigningKey::from_env() {
+            Some(_) => Algorithm::EdDSA,
+            None => Algorithm::HS256,
+        }
+    }
+
+    /// Verify the signature and validity of a token of the default audience, and that it was
+    /// not revoked.
     fn verify_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        let config = TokenConfig::from_env();
+        Self::verify_jwt_for(token, &config, &[&config.audience])
+    }
+
+    /// Same as [`Jwt::verify_jwt`], for a token of any of `audiences`.
+    fn verify_jwt_for(
+        token: &str,
+        config: &TokenConfig,
+        audiences: &[&str],
+    ) -> Result<Claims, jsonwebtoken::errors::Error> {
         if token.is_empty() {
             return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken));
         }
 
-        let secret = env::var("JWT_SECRET")
-            .unwrap_or_else(|_| panic!("JWT_SECRET env variable is required"));
+        let claims = match SigningKey::from_env() {
+            Some(key) if decode_header(token)?.kid.as_deref() == Some(key.kid()) => {
+                key.verify(token, config, audiences)?
+            }
+            _ => {
+                // Tokens are only signed with the secret when it is set, any other key id is
+                // an invalid token rather than a configuration error.
+                let secret = env::var("JWT_SECRET")
+                    .ok()
+                    .filter(|secret| !secret.is_empty())
+                    .ok_or_else(|| jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken))?;
 
-        decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(secret.as_bytes()),
-            &Validation::default(),
-        )
-        .map(|data| data.claims)
+                decode::<Claims>(
+                    token,
+                    &DecodingKey::from_secret(secret.as_bytes()),
+                    &config.validation_for(Algorithm::HS256, audiences),
+                )?
+                .claims
+            }
+        };
+
+        match RevocationList::is_revoked(&claims.jti) {
+            true => Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken)),
+            false => Ok(claims),
+        }
+    }
+
+    /// Public keys to publish, empty when tokens are signed with the shared secret.
+    fn jwks() -> JwkSet {
+        JwkSet {
+            keys: SigningKey::from_env().map(SigningKey::jwk).into_iter().collect(),
+        }
     }
 }
 
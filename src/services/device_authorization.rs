@@ -0,0 +1,102 @@
+//! OAuth 2.0 device authorization grant (RFC 8628), for CLIs and devices without browser.
+//!
+//! The device gets a device code, which it polls the token endpoint with, and a short user
+//! code that the user enters on `/oauth/device` from another device where they are logged in.
+
+use crate::services::oauth::{OAuthError, OAuthErrorCode};
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+
+pub const DEVICE_CODE_TTL_SECONDS: i64 = 600;
+pub const DEVICE_POLL_INTERVAL_SECONDS: i32 = 5;
+
+/// Consonants only, no word can be spelled and no character is ambiguous (RFC 8628 section 6.1).
+const USER_CODE_CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_APPROVED: &str = "approved";
+pub const STATUS_DENIED: &str = "denied";
+
+/// 8 random characters of [`USER_CODE_CHARSET`], about 34 bits of entropy.
+pub fn generate_user_code() -> String {
+    let rng = SystemRandom::new();
+    let mut code = String::with_capacity(USER_CODE_LENGTH);
+    let mut byte = [0u8; 1];
+
+    while code.len() < USER_CODE_LENGTH {
+        rng.fill(&mut byte)
+            .expect("Unable to generate a random user code");
+        // Rejection sampling keeps every character equally likely.
+        let limit = 256 - 256 % USER_CODE_CHARSET.len();
+        if (byte[0] as usize) < limit {
+            code.push(USER_CODE_CHARSET[byte[0] as usize % USER_CODE_CHARSET.len()] as char);
+        }
+    }
+    code
+}
+
+/// User code as stored, from what the user typed : case and separators do not matter.
+pub fn normalize_user_code(input: &str) -> Option<String> {
+    let code: String = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    let valid =
+        code.len() == USER_CODE_LENGTH && code.bytes().all(|c| USER_CODE_CHARSET.contains(&c));
+    valid.then_some(code)
+}
+
+/// User code as displayed, `BCDF-GHJK`.
+pub fn format_user_code(code: &str) -> String {
+    match code.len() {
+        USER_CODE_LENGTH => format!("{}-{}", &code[..4], &code[4..]),
+        _ => code.to_owned(),
+    }
+}
+
+/// Answer to a poll of the device : `Ok` once the user approved, the RFC 8628 error otherwise.
+/// Polling again before the interval elapsed is answered with `slow_down`.
+pub fn check_poll(
+    status: &str,
+    previous_poll: Option<DateTime<Utc>>,
+    interval_seconds: i32,
+    expires_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<(), OAuthError> {
+    if expires_at <= now {
+        return Err(OAuthError::new(
+            OAuthErrorCode::ExpiredToken,
+            "The device code has expired",
+        ));
+    }
+
+    match status {
+        STATUS_APPROVED => Ok(()),
+        STATUS_DENIED => Err(OAuthError::new(
+            OAuthErrorCode::AccessDenied,
+            "The user denied the request",
+        )),
+        STATUS_PENDING => {
+            let too_fast = previous_poll.is_some_and(|previous| {
+                now < previous + chrono::Duration::seconds(interval_seconds as i64)
+            });
+            match too_fast {
+                true => Err(OAuthError::new(
+                    OAuthErrorCode::SlowDown,
+                    "Polling too fast, increase the interval by 5 seconds",
+                )),
+                false => Err(OAuthError::new(
+                    OAuthErrorCode::AuthorizationPending,
+                    "The user has not approved the request yet",
+                )),
+            }
+        }
+        _ => Err(OAuthError::new(
+            OAuthErrorCode::InvalidGrant,
+            "The device code was already used",
+        )),
+    }
+}
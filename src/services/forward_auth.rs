@@ -0,0 +1,172 @@
+//! Per-path access rules for the forward-auth endpoint used by reverse proxies.
+//!
+//! Rules are read from a JSON file (`FORWARD_AUTH_RULES_FILE`, `forward_auth.json` by
+//! default) and the first matching rule wins :
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     { "path_prefix": "/health", "public": true },
+//!     { "path_prefix": "/admin", "roles": ["ROLE_ADMIN", "ROLE_SUPER_ADMIN"] },
+//!     { "path_prefix": "/api", "methods": ["DELETE"], "roles": ["ROLE_ADMIN"] }
+//!   ]
+//! }
+//! ```
+//!
+//! A path matching no rule only requires an authenticated user.
+//!
+//! Rules match the canonical form of the path, so that `//admin`, `/%61dmin` or
+//! `/health/../admin` are not mistaken for other paths : unreserved characters are
+//! percent-decoded, repeated slashes collapsed and dot segments resolved. Paths whose meaning
+//! depends on the upstream, with an encoded slash, a backslash or `;` path parameters, which
+//! servlet containers strip from `/admin;x/users`, are invalid.
+
+use crate::config::roles::Role;
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardAuthRule {
+    pub path_prefix: String,
+    /// Methods the rule applies to, every method when empty.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// No authentication required.
+    #[serde(default)]
+    pub public: bool,
+    /// At least one of these roles is required, any authenticated user when empty.
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ForwardAuthDecision {
+    Public,
+    Allowed,
+    Forbidden,
+    /// The path has no canonical form, see [`canonical_path`].
+    InvalidPath,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ForwardAuthRules {
+    #[serde(default)]
+    rules: Vec<ForwardAuthRule>,
+}
+
+impl ForwardAuthRule {
+    fn matches(&self, method: &str, path: &str) -> bool {
+        let prefix = self.path_prefix.trim_end_matches('/');
+        let path_matches = match path.strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.is_empty(),
+            None => false,
+        };
+
+        path_matches
+            && (self.methods.is_empty()
+                || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+    }
+}
+
+impl ForwardAuthRules {
+    pub fn new(rules: Vec<ForwardAuthRule>) -> ForwardAuthRules {
+        ForwardAuthRules { rules }
+    }
+
+    pub fn from_json(json: &str) -> Result<ForwardAuthRules, Error> {
+        serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    /// Load the rules from `FORWARD_AUTH_RULES_FILE`, no rules when the default file is missing.
+    pub fn from_env() -> Result<ForwardAuthRules, Error> {
+        match std::env::var("FORWARD_AUTH_RULES_FILE") {
+            Ok(path) => ForwardAuthRules::from_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("forward_auth.json") {
+                Ok(json) => ForwardAuthRules::from_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(ForwardAuthRules::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    /// Rule of the canonical path of `uri`, `None` when it has none or no rule matches.
+    pub fn find(&self, method: &str, uri: &str) -> Option<&ForwardAuthRule> {
+        let path = canonical_path(uri)?;
+        self.rules.iter().find(|rule| rule.matches(method, &path))
+    }
+
+    /// Whether the request needs authentication at all, never for an invalid path.
+    pub fn is_public(&self, method: &str, uri: &str) -> bool {
+        self.find(method, uri).map(|rule| rule.public).unwrap_or(false)
+    }
+
+    /// Decision for an authenticated user holding `roles`.
+    pub fn evaluate(&self, method: &str, uri: &str, roles: &[Role]) -> ForwardAuthDecision {
+        if canonical_path(uri).is_none() {
+            return ForwardAuthDecision::InvalidPath;
+        }
+
+        match self.find(method, uri) {
+            Some(rule) if rule.public => ForwardAuthDecision::Public,
+            Some(rule) if !rule.roles.is_empty() => {
+                if rule.roles.iter().any(|role| roles.contains(role)) {
+                    ForwardAuthDecision::Allowed
+                } else {
+                    ForwardAuthDecision::Forbidden
+                }
+            }
+            _ => ForwardAuthDecision::Allowed,
+        }
+    }
+}
+
+/// Path of `uri` as the rules match it : unreserved characters percent-decoded, repeated
+/// slashes collapsed and dot segments resolved (RFC 3986 sections 6.2.2 and 5.2.4). `None`
+/// for a path not starting with `/`, with an invalid escape, an encoded slash, a backslash or
+/// a `;`.
+pub fn canonical_path(uri: &str) -> Option<String> {
+    let raw = uri.split(['?', '#']).next().unwrap_or_default();
+    if !raw.starts_with('/') || raw.contains(['\\', ';']) {
+        return None;
+    }
+
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = bytes.get(i + 1..i + 3)?;
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+        match byte {
+            b'/' | b'\\' | b';' => return None,
+            b if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') => {
+                decoded.push(b)
+            }
+            _ => {
+                decoded.push(b'%');
+                decoded.extend(hex.to_ascii_uppercase());
+            }
+        }
+        i += 3;
+    }
+    let decoded = String::from_utf8(decoded).ok()?;
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(format!("/{}", segments.join("/")))
+}
@@ -0,0 +1,686 @@
+//! Sign in with external identity providers, such as Google or GitHub.
+//!
+//! Providers are declared in a JSON file (`IDENTITY_PROVIDERS_FILE`,
+//! `identity_providers.json` by default) :
+//!
+//! ```json
+//! {
+//!   "providers": [
+//!     { "name": "google", "display_name": "Google", "issuer": "https://accounts.google.com",
+//!       "client_id": "...", "client_secret": "..." },
+//!     { "name": "github", "display_name": "GitHub", "client_id": "...", "client_secret": "...",
+//!       "authorization_endpoint": "https://github.com/login/oauth/authorize",
+//!       "token_endpoint": "https://github.com/login/oauth/access_token",
+//!       "userinfo_endpoint": "https://api.github.com/user",
+//!       "scopes": ["read:user", "user:email"], "subject_claim": "id", "trust_email": true }
+//!   ]
+//! }
+//! ```
+//!
+//! - with an `issuer`, the provider is an OpenID Connect one : its endpoints come from its
+//!   discovery document and users are identified by the ID token, verified against its JWKS
+//! - without, it is a plain OAuth 2.0 provider, users are identified by its userinfo endpoint
+//!
+//! The authorization code flow always uses PKCE. Emails are only trusted when the provider
+//! flags them as verified (`email_verified`), or when the provider is configured with
+//! `trust_email`.
+//!
+//! Identities are linked to users : on the first login with a provider a user is created, or
+//! the identity is linked to the user with the same email when both emails are verified.
+//! Logged users also link and unlink identities themselves, after signing in again.
+
+use crate::config::roles::Role;
+use crate::repository::user_repository::User;
+use crate::repository::Repository;
+use crate::services::oauth::{generate_token, redirect_with, PKCE_METHOD_S256};
+use crate::services::oidc::{SCOPE_EMAIL, SCOPE_OPENID, SCOPE_PROFILE};
+use auth_client::HttpClient;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http_body_util::Full;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How long the user has to come back from the provider.
+pub const EXTERNAL_LOGIN_TTL_SECONDS: i64 = 600;
+/// Identities are linked and unlinked by users who signed in at most this long ago.
+pub const REAUTHENTICATION_MAX_AGE_SECONDS: i64 = 300;
+
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+const MAX_PROVIDER_NAME_LENGTH: usize = 64;
+/// Keys of a provider are reloaded on an unknown `kid`, at most this often.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const ID_TOKEN_LEEWAY_SECONDS: u64 = 60;
+/// Asymmetric algorithms only, the client secret is never used to verify ID tokens.
+const ID_TOKEN_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderConfig {
+    /// Identifies the provider in URLs and in the users it provisioned.
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Issuer of an OpenID Connect provider, its endpoints are discovered.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// `openid email profile` for OpenID Connect providers by default.
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+    #[serde(default)]
+    pub authorization_endpoint: Option<String>,
+    #[serde(default)]
+    pub token_endpoint: Option<String>,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
+    /// Claim identifying the user, `sub` by default.
+    #[serde(default)]
+    pub subject_claim: Option<String>,
+    /// Emails of the provider are verified even without an `email_verified` claim.
+    #[serde(default)]
+    pub trust_email: bool,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Endpoints of a provider, configured or read from its discovery document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderEndpoints {
+    #[serde(default)]
+    pub issuer: Option<String>,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
+    #[serde(default)]
+    pub jwks_uri: Option<String>,
+}
+
+/// User as known by an external provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// Secrets of a login in progress, kept until the user comes back from the provider.
+pub struct PendingLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl PendingLogin {
+    pub fn generate() -> PendingLogin {
+        PendingLogin {
+            state: generate_token(),
+            nonce: generate_token(),
+            code_verifier: generate_token(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    access_token: Option<String>,
+    #[serde(default)]
+    id_token: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+fn invalid_data(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// Provider names appear in URLs : lowercase letters, digits, `-` and `_`.
+pub(crate) fn valid_provider_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_PROVIDER_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_'))
+}
+
+pub struct IdentityProvider {
+    config: ProviderConfig,
+    endpoints: RwLock<Option<ProviderEndpoints>>,
+    jwks: RwLock<Option<(JwkSet, Instant)>>,
+}
+
+impl IdentityProvider {
+    pub fn new(config: ProviderConfig) -> Result<IdentityProvider, Error> {
+        if !valid_provider_name(&config.name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid identity provider name: {}", config.name),
+            ));
+        }
+
+        let endpoints = match (
+            &config.issuer,
+            &config.authorization_endpoint,
+            &config.token_endpoint,
+            &config.userinfo_endpoint,
+        ) {
+            (Some(_), _, _, _) => None,
+            (None, Some(authorization_endpoint), Some(token_endpoint), Some(userinfo_endpoint)) => {
+                Some(ProviderEndpoints {
+                    issuer: None,
+                    authorization_endpoint: authorization_endpoint.clone(),
+                    token_endpoint: token_endpoint.clone(),
+                    userinfo_endpoint: Some(userinfo_endpoint.clone()),
+                    jwks_uri: None,
+                })
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Identity provider {} needs an issuer, or its authorization, token and userinfo endpoints",
+                        config.name
+                    ),
+                ))
+            }
+        };
+
+        Ok(IdentityProvider {
+            config,
+            endpoints: RwLock::new(endpoints),
+            jwks: RwLock::new(None),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.config
+            .display_name
+            .as_deref()
+            .unwrap_or(&self.config.name)
+    }
+
+    pub fn is_openid(&self) -> bool {
+        self.config.issuer.is_some()
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS))
+    }
+
+    fn scope(&self) -> String {
+        match &self.config.scopes {
+            Some(scopes) => scopes.join(" "),
+            None if self.is_openid() => [SCOPE_OPENID, SCOPE_EMAIL, SCOPE_PROFILE].join(" "),
+            None => String::new(),
+        }
+    }
+
+    /// Endpoints of the provider, its discovery document is fetched once.
+    pub async fn endpoints(&self) -> Result<ProviderEndpoints, Error> {
+        if let Some(endpoints) = self.endpoints.read().await.as_ref() {
+            return Ok(endpoints.clone());
+        }
+
+        let issuer = self.config.issuer.as_deref().unwrap_or_default();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let endpoints: ProviderEndpoints = serde_json::from_slice(&self.get(&url, None).await?)?;
+        if endpoints.issuer.as_deref() != Some(issuer) {
+            return Err(invalid_data(format!(
+                "Discovery document of {} is for another issuer",
+                self.config.name
+            )));
+        }
+
+        *self.endpoints.write().await = Some(endpoints.clone());
+        Ok(endpoints)
+    }
+
+    /// Where the user is sent to log in with the provider.
+    pub async fn authorization_url(
+        &self,
+        redirect_uri: &str,
+        login: &PendingLogin,
+    ) -> Result<String, Error> {
+        let endpoints = self.endpoints().await?;
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(login.code_verifier.as_bytes()));
+        let scope = self.scope();
+
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("state", login.state.as_str()),
+            ("code_challenge", code_challenge.as_str()),
+            ("code_challenge_method", PKCE_METHOD_S256),
+        ];
+        if !scope.is_empty() {
+            params.push(("scope", scope.as_str()));
+        }
+        if self.is_openid() {
+            params.push(("nonce", login.nonce.as_str()));
+        }
+
+        Ok(redirect_with(&endpoints.authorization_endpoint, &params))
+    }
+
+    /// Redeem the code the user came back with, and identify them.
+    pub async fn authenticate(
+        &self,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, Error> {
+        let endpoints = self.endpoints().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let body = self.post_form(&endpoints.token_endpoint, &form).await?;
+        let tokens: TokenResponse = serde_json::from_slice(&body)?;
+        if let Some(error) = tokens.error {
+            return Err(invalid_data(format!(
+                "Token request refused by {} : {} {}",
+                self.config.name,
+                error,
+                tokens.error_description.unwrap_or_default()
+            )));
+        }
+        let access_token = tokens
+            .access_token
+            .ok_or_else(|| invalid_data("No access token in the token response"))?;
+
+        let claims = match self.is_openid() {
+            true => {
+                let id_token = tokens
+                    .id_token
+                    .ok_or_else(|| invalid_data("No ID token in the token response"))?;
+                let mut claims = self.verify_id_token(&id_token, nonce).await?;
+                if !claims.contains_key("email") {
+                    if let Some(userinfo_endpoint) = &endpoints.userinfo_endpoint {
+                        let userinfo = self.userinfo(userinfo_endpoint, &access_token).await?;
+                        // The userinfo must be about the user of the ID token.
+                        if userinfo.get("sub") != claims.get("sub") {
+                            return Err(invalid_data("Userinfo is about another subject"));
+                        }
+                        for (name, value) in userinfo {
+                            claims.entry(name).or_insert(value);
+                        }
+                    }
+                }
+                claims
+            }
+            false => {
+                let userinfo_endpoint = endpoints.userinfo_endpoint.as_deref().unwrap_or_default();
+                self.userinfo(userinfo_endpoint, &access_token).await?
+            }
+        };
+
+        self.identity(&claims)
+    }
+
+    /// Claims of an ID token issued by the provider to this service for `nonce`.
+    pub async fn verify_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<Map<String, Value>, Error> {
+        let header = decode_header(id_token).map_err(|err| invalid_data(err.to_string()))?;
+        if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+            return Err(invalid_data(format!(
+                "ID token algorithm {:?} is not accepted",
+                header.alg
+            )));
+        }
+        let jwk = self.signing_key(header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|err| invalid_data(err.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[self.config.issuer.as_deref().unwrap_or_default()]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = ID_TOKEN_LEEWAY_SECONDS;
+
+        let claims = decode::<Map<String, Value>>(id_token, &key, &validation)
+            .map_err(|err| invalid_data(format!("Invalid ID token : {}", err)))?
+            .claims;
+
+        let nonce_valid = claims
+            .get("nonce")
+            .and_then(Value::as_str)
+            .is_some_and(|claimed| {
+                ring::constant_time::verify_slices_are_equal(claimed.as_bytes(), nonce.as_bytes())
+                    .is_ok()
+            });
+        if !nonce_valid {
+            return Err(invalid_data("Invalid ID token nonce"));
+        }
+        if claims
+            .get("azp")
+            .and_then(Value::as_str)
+            .is_some_and(|azp| azp != self.config.client_id)
+        {
+            return Err(invalid_data("ID token issued to another client"));
+        }
+
+        Ok(claims)
+    }
+
+    /// Key of the provider with the `kid` of a token, the JWKS is reloaded when the key
+    /// is unknown as the provider may have rotated its keys.
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, Error> {
+        let find = |jwks: &JwkSet| match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+            None => None,
+        };
+
+        let fetched_at = match self.jwks.read().await.as_ref() {
+            Some((jwks, fetched_at)) => match find(jwks) {
+                Some(jwk) => return Ok(jwk),
+                None => Some(*fetched_at),
+            },
+            None => None,
+        };
+        if fetched_at.is_some_and(|fetched_at| fetched_at.elapsed() < JWKS_MIN_REFRESH_INTERVAL) {
+            return Err(invalid_data("Unknown ID token key"));
+        }
+
+        let jwks_uri = self
+            .endpoints()
+            .await?
+            .jwks_uri
+            .ok_or_else(|| invalid_data("The provider does not publish its keys"))?;
+        let jwks: JwkSet = serde_json::from_slice(&self.get(&jwks_uri, None).await?)?;
+        let jwk = find(&jwks);
+        *self.jwks.write().await = Some((jwks, Instant::now()));
+
+        jwk.ok_or_else(|| invalid_data("Unknown ID token key"))
+    }
+
+    async fn userinfo(&self, url: &str, access_token: &str) -> Result<Map<String, Value>, Error> {
+        match serde_json::from_slice(&self.get(url, Some(access_token)).await?)? {
+            Value::Object(claims) => Ok(claims),
+            _ => Err(invalid_data("Userinfo is not a JSON object")),
+        }
+    }
+
+    /// The identity in the claims of the provider, subjects may be numbers (GitHub).
+    pub fn identity(&self, claims: &Map<String, Value>) -> Result<ExternalIdentity, Error> {
+        let subject_claim = self.config.subject_claim.as_deref().unwrap_or("sub");
+        let subject = match claims.get(subject_claim) {
+            Some(Value::String(subject)) if !subject.is_empty() => subject.clone(),
+            Some(Value::Number(subject)) => subject.to_string(),
+            _ => return Err(invalid_data(format!("No {} claim", subject_claim))),
+        };
+        let email = claims
+            .get("email")
+            .and_then(Value::as_str)
+            .filter(|email| !email.is_empty())
+            .map(str::to_lowercase);
+        let email_verified = email.is_some()
+            && (self.config.trust_email
+                || matches!(claims.get("email_verified"), Some(Value::Bool(true)))
+                || matches!(claims.get("email_verified"), Some(Value::String(verified)) if verified == "true"));
+        let name = claims
+            .get("name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .map(String::from);
+
+        Ok(ExternalIdentity {
+            provider: self.config.name.clone(),
+            subject,
+            email,
+            email_verified,
+            name,
+        })
+    }
+
+    async fn get(&self, url: &str, bearer: Option<&str>) -> Result<Bytes, Error> {
+        let mut request = http::Request::get(url);
+        if let Some(token) = bearer {
+            request = request.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request
+            .body(Full::new(Bytes::new()))
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        self.send(request).await
+    }
+
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Bytes, Error> {
+        let body = serde_urlencoded::to_string(form).map_err(Error::other)?;
+        let request = http::Request::post(url)
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        self.send(request).await
+    }
+
+    /// Providers are reached over HTTPS, plain HTTP is only allowed on the loopback, for
+    /// local development and tests.
+    async fn send(&self, mut request: http::Request<Full<Bytes>>) -> Result<Bytes, Error> {
+        let url = request.uri().to_string();
+        let headers = request.headers_mut();
+        headers.insert(
+            http::header::ACCEPT,
+            http::HeaderValue::from_static("application/json"),
+        );
+        headers.insert(
+            http::header::USER_AGENT,
+            http::HeaderValue::from_static("auth_api"),
+        );
+
+        let client = HttpClient::default()
+            .with_timeout(self.timeout())
+            .with_max_response_bytes(MAX_RESPONSE_BYTES);
+        let response = client.send(request).await?;
+        if !response.status().is_success() {
+            return Err(Error::other(format!("{} answered {}", url, response.status())));
+        }
+
+        Ok(response.into_body())
+    }
+}
+
+#[derive(Deserialize)]
+struct ProvidersConfig {
+    #[serde(default)]
+    providers: Vec<ProviderConfig>,
+}
+
+/// The configured providers, in the order they are offered to users.
+#[derive(Default)]
+pub struct IdentityProviders {
+    providers: Vec<IdentityProvider>,
+}
+
+impl IdentityProviders {
+    pub fn from_json(json: &str) -> Result<IdentityProviders, Error> {
+        let config: ProvidersConfig =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        let mut providers: Vec<IdentityProvider> = Vec::new();
+        for provider in config.providers {
+            if providers.iter().any(|p| p.name() == provider.name) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Duplicated identity provider: {}", provider.name),
+                ));
+            }
+            providers.push(IdentityProvider::new(provider)?);
+        }
+
+        Ok(IdentityProviders { providers })
+    }
+
+    /// Load the providers from `IDENTITY_PROVIDERS_FILE`, none when the default file is missing.
+    pub fn from_env() -> Result<IdentityProviders, Error> {
+        match std::env::var("IDENTITY_PROVIDERS_FILE") {
+            Ok(path) => IdentityProviders::from_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("identity_providers.json") {
+                Ok(json) => IdentityProviders::from_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(IdentityProviders::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&IdentityProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &IdentityProvider> {
+        self.providers.iter()
+    }
+}
+
+/// What to do with an identity no user is linked to yet, `user` being the one with the same
+/// email, if any.
+#[derive(Debug, PartialEq)]
+pub enum Provisioning {
+    /// Provision a new user.
+    Create,
+    /// Link the identity to the user of the same email.
+    Link(String),
+    /// The email cannot be trusted to be the one of the user.
+    Refuse,
+}
+
+impl Provisioning {
+    /// Both emails must be verified before linking, otherwise whoever registered an email
+    /// they do not own would get the account of its owner, or the other way around.
+    pub fn of(identity: &ExternalIdentity, user: Option<&User>) -> Provisioning {
+        match (identity.email.is_some() && identity.email_verified, user) {
+            (false, _) => Provisioning::Refuse,
+            (true, None) => Provisioning::Create,
+            (true, Some(user)) if user.email_verified => Provisioning::Link(user.id.clone()),
+            (true, Some(_)) => Provisioning::Refuse,
+        }
+    }
+}
+
+/// Why no user could be provisioned for an external identity.
+#[derive(Debug)]
+pub enum ProvisioningError {
+    /// A user has the email of the identity, without both emails being verified.
+    AccountExists,
+    /// The identity has no verified email.
+    UnverifiedEmail,
+    Database(sqlx::Error),
+}
+
+/// User of an external identity. On the first login with it, the identity is linked to the
+/// user of the same email or a user is provisioned, see [`Provisioning`].
+pub async fn provision_user(
+    repository: &Repository,
+    identity: &ExternalIdentity,
+) -> Result<User, ProvisioningError> {
+    match repository
+        .find_user_by_identity(&identity.provider, &identity.subject)
+        .await
+    {
+        Ok(user) => return Ok(user),
+        Err(sqlx::Error::RowNotFound) => {}
+        Err(err) => return Err(ProvisioningError::Database(err)),
+    }
+
+    let email = identity.email.as_deref().unwrap_or_default();
+    let user_with_email = match repository.find_user_by_email(email).await {
+        Ok(user) => Some(user),
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(err) => return Err(ProvisioningError::Database(err)),
+    };
+
+    let provisioned = match Provisioning::of(identity, user_with_email.as_ref()) {
+        Provisioning::Create => {
+            repository
+                .save_user_with_identity(identity, vec![Role::USER.to_string()])
+                .await
+        }
+        Provisioning::Link(user_id) => match repository.link_identity(&user_id, identity).await {
+            Ok(()) => repository.find_user_by_id(&user_id).await,
+            Err(err) => Err(err),
+        },
+        Provisioning::Refuse if user_with_email.is_some() => {
+            return Err(ProvisioningError::AccountExists)
+        }
+        Provisioning::Refuse => return Err(ProvisioningError::UnverifiedEmail),
+    };
+
+    match provisioned {
+        Ok(user) => Ok(user),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            Err(ProvisioningError::AccountExists)
+        }
+        Err(err) => Err(ProvisioningError::Database(err)),
+    }
+}
+
+/// The user signed in recently enough to change how they sign in.
+pub fn recently_authenticated(auth_time: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    now - auth_time <= chrono::Duration::seconds(REAUTHENTICATION_MAX_AGE_SECONDS)
+}
+
+/// Path to go back to after the login, only local paths are allowed so that the login
+/// cannot be used as an open redirect.
+pub fn safe_return_to(return_to: Option<&str>) -> String {
+    match return_to {
+        Some(path)
+            if path.starts_with('/')
+                && !path.starts_with("//")
+                && !path.starts_with("/\\")
+                && !path.chars().any(|c| c.is_control()) =>
+        {
+            path.to_owned()
+        }
+        _ => String::from("/"),
+    }
+}
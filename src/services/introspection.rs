@@ -0,0 +1,81 @@
+//! Token introspection (RFC 7662) and revocation (RFC 7009).
+//!
+//! Access tokens are JWTs, revoked by `jti` in the [`RevocationList`] until they expire.
+//! Refresh tokens are opaque and stored hashed, see
+//! [`RefreshToken`](crate::repository::token_repository::RefreshToken).
+//!
+//! [`RevocationList`]: crate::services::revocation::RevocationList
+
+use crate::repository::token_repository::RefreshToken;
+use crate::services::crypto::Claims;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Lifetime of refresh tokens, each use exchanges the token for a new one.
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 3600 * 24 * 30;
+
+pub const TOKEN_TYPE_REFRESH_TOKEN: &str = "refresh_token";
+
+/// Response of the introspection endpoint, only `active` is set for inactive tokens so
+/// that nothing is disclosed about them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Introspection {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+impl Introspection {
+    pub fn inactive() -> Introspection {
+        Introspection::default()
+    }
+
+    /// Access token, its claims were verified.
+    pub fn from_claims(claims: &Claims) -> Introspection {
+        Introspection {
+            active: true,
+            scope: claims.scope.clone(),
+            client_id: claims.client_id.clone(),
+            token_type: Some(String::from("Bearer")),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            sub: Some(claims.sub.clone()),
+            aud: Some(claims.aud.clone()),
+            iss: Some(claims.iss.clone()),
+            jti: Some(claims.jti.clone()),
+        }
+    }
+
+    pub fn from_refresh_token(token: &RefreshToken, now: DateTime<Utc>) -> Introspection {
+        if !token.is_active(now) {
+            return Introspection::inactive();
+        }
+
+        Introspection {
+            active: true,
+            scope: Some(token.scope.clone()).filter(|scope| !scope.is_empty()),
+            client_id: Some(token.client_id.clone()),
+            token_type: Some(String::from(TOKEN_TYPE_REFRESH_TOKEN)),
+            exp: Some(token.expires_at.timestamp() as u64),
+            iat: Some(token.created_at.timestamp() as u64),
+            sub: Some(token.user_id.clone()),
+            ..Introspection::default()
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! Password authentication against an LDAP directory, such as Active Directory.
+//!
+//! The directory is configured in a JSON file (`LDAP_CONFIG_FILE`, `ldap.json` by default),
+//! users only sign in with their local password without it :
+//!
+//! ```json
+//! {
+//!   "url": "ldaps://ad.example.com",
+//!   "bind_dn": "CN=auth-api,OU=Services,DC=example,DC=com", "bind_password": "...",
+//!   "base_dn": "OU=Users,DC=example,DC=com",
+//!   "user_filter": "(&(objectClass=user)(mail={email}))",
+//!   "subject_attribute": "objectGUID",
+//!   "group_mapping": { "CN=Administrators,OU=Groups,DC=example,DC=com": "ROLE_ADMIN" },
+//!   "local_fallback": "not_found"
+//! }
+//! ```
+//!
+//! - users are searched with the service account (`bind_dn`), then their password is checked
+//!   by binding as the entry found
+//! - the directory is reached with `ldaps://`, or `ldap://` and `starttls`. Plain `ldap://`
+//!   is only allowed on the loopback, for local development and tests
+//! - groups are the values of `group_attribute` (`memberOf` by default), or the entries of
+//!   `group_filter` (`{dn}` being the DN of the user) under `group_base_dn`
+//! - users are cached in `public.user`, linked to their `ldap` identity : the DN, or
+//!   `subject_attribute` as DNs change when users move. Roles of `group_mapping` follow
+//!   their groups at every login
+//! - `local_fallback` tells when local passwords are checked : `never`, when the directory
+//!   does not know the user (`not_found`, the default), or also when it is `unavailable`
+
+use crate::config::roles::{sync_mapped_roles, Role};
+use crate::services::identity_provider::ExternalIdentity;
+use auth_client::http_client::is_loopback;
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_native_tls::native_tls::{Certificate, TlsConnector};
+
+/// Identities of the users of the directory.
+pub const LDAP_IDENTITY_PROVIDER: &str = "ldap";
+
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_USER_FILTER: &str = "(mail={email})";
+/// Result code of a bind with a wrong password, or of an unknown DN.
+const INVALID_CREDENTIALS: u32 = 49;
+
+fn invalid_input(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidInput, message.into())
+}
+
+fn ldap_error(err: ldap3::LdapError) -> Error {
+    Error::other(err)
+}
+
+/// When users are authenticated by their local password instead of the directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalFallback {
+    Never,
+    /// Users the directory does not know.
+    #[default]
+    NotFound,
+    /// Users the directory does not know, and every user while it cannot be reached.
+    Unavailable,
+}
+
+impl LocalFallback {
+    /// The local password of the user is checked after the directory answered `login`.
+    pub fn applies(&self, login: &Result<DirectoryLogin, Error>) -> bool {
+        match (self, login) {
+            (LocalFallback::Never, _) => false,
+            (_, Ok(DirectoryLogin::NotFound)) => true,
+            (LocalFallback::Unavailable, Err(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LdapConfig {
+    pub url: String,
+    #[serde(default)]
+    pub starttls: bool,
+    /// PEM certificate of the CA of the directory, when it is not trusted by the system.
+    #[serde(default)]
+    pub ca_certificate_file: Option<String>,
+    /// Service account searching the users, searches are anonymous without it.
+    #[serde(default)]
+    pub bind_dn: Option<String>,
+    #[serde(default)]
+    pub bind_password: Option<String>,
+    pub base_dn: String,
+    /// `{email}` is replaced by the escaped email of the user.
+    #[serde(default)]
+    pub user_filter: Option<String>,
+    /// Attribute identifying users, their DN by default.
+    #[serde(default)]
+    pub subject_attribute: Option<String>,
+    #[serde(default)]
+    pub email_attribute: Option<String>,
+    #[serde(default)]
+    pub name_attribute: Option<String>,
+    #[serde(default)]
+    pub group_attribute: Option<String>,
+    #[serde(default)]
+    pub group_base_dn: Option<String>,
+    /// Search of the groups of a user, `{dn}` is replaced by its escaped DN.
+    #[serde(default)]
+    pub group_filter: Option<String>,
+    /// Role granted to the members of each group, by group DN.
+    #[serde(default)]
+    pub group_mapping: HashMap<String, String>,
+    #[serde(default)]
+    pub local_fallback: LocalFallback,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// User of the directory whose password was checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryUser {
+    pub dn: String,
+    pub subject: String,
+    pub email: String,
+    pub name: Option<String>,
+    /// DNs of the groups of the user, lowercased.
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DirectoryLogin {
+    Authenticated(DirectoryUser),
+    /// No entry of the directory has the email.
+    NotFound,
+    InvalidCredentials,
+}
+
+/// Host of an LDAP URL.
+fn url_host(url: &str) -> Option<&str> {
+    let authority = url.split_once("://")?.1.split('/').next()?;
+    match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6
+            .split_once(']')
+            .map(|(host, _)| &authority[..host.len() + 2]),
+        None => authority.split(':').next(),
+    }
+}
+
+pub struct LdapDirectory {
+    config: LdapConfig,
+    connector: Option<TlsConnector>,
+}
+
+impl LdapDirectory {
+    pub fn new(mut config: LdapConfig) -> Result<LdapDirectory, Error> {
+        let host = url_host(&config.url)
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| invalid_input(format!("Invalid LDAP url: {}", config.url)))?;
+        let secure = match config.url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("ldaps") => true,
+            Some("ldap") => config.starttls || is_loopback(host),
+            _ => false,
+        };
+        if !secure {
+            return Err(invalid_input(format!(
+                "The LDAP directory {} must be reached with ldaps:// or StartTLS",
+                config.url
+            )));
+        }
+        if !config
+            .user_filter
+            .as_deref()
+            .unwrap_or(DEFAULT_USER_FILTER)
+            .contains("{email}")
+        {
+            return Err(invalid_input("The LDAP user filter needs an {email}"));
+        }
+        if config
+            .group_filter
+            .as_deref()
+            .is_some_and(|filter| !filter.contains("{dn}"))
+        {
+            return Err(invalid_input("The LDAP group filter needs a {dn}"));
+        }
+        for role in config.group_mapping.values() {
+            Role::from_str(role)
+                .map_err(|_| invalid_input(format!("Invalid role {} of an LDAP group", role)))?;
+        }
+        // DNs are compared case-insensitively.
+        config.group_mapping = config
+            .group_mapping
+            .into_iter()
+            .map(|(group, role)| (group.to_lowercase(), role))
+            .collect();
+
+        let connector = match &config.ca_certificate_file {
+            Some(path) => {
+                let certificate = Certificate::from_pem(&std::fs::read(path)?)
+                    .map_err(|err| invalid_input(err.to_string()))?;
+                let connector = TlsConnector::builder()
+                    .add_root_certificate(certificate)
+                    .build()
+                    .map_err(|err| invalid_input(err.to_string()))?;
+                Some(connector)
+            }
+            None => None,
+        };
+
+        Ok(LdapDirectory { config, connector })
+    }
+
+    pub fn from_json(json: &str) -> Result<LdapDirectory, Error> {
+        let config: LdapConfig =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        LdapDirectory::new(config)
+    }
+
+    /// Load the directory from `LDAP_CONFIG_FILE`, none when the default file is missing.
+    pub fn from_env() -> Result<Option<LdapDirectory>, Error> {
+        let json = match std::env::var("LDAP_CONFIG_FILE") {
+            Ok(path) => std::fs::read_to_string(path)?,
+            Err(_) => match std::fs::read_to_string("ldap.json") {
+                Ok(json) => json,
+                Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err),
+            },
+        };
+        LdapDirectory::from_json(&json).map(Some)
+    }
+
+    pub fn local_fallback(&self) -> LocalFallback {
+        self.config.local_fallback
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS))
+    }
+
+    /// Check the password of the user of `email`, an error meaning that the directory
+    /// could not answer.
+    pub async fn authenticate(&self, email: &str, password: &str) -> Result<DirectoryLogin, Error> {
+        // Directories accept binds without password as anonymous ones.
+        if email.is_empty() || password.is_empty() {
+            return Ok(DirectoryLogin::InvalidCredentials);
+        }
+
+        match tokio::time::timeout(self.timeout(), self.search_then_bind(email, password)).await {
+            Ok(login) => login,
+            Err(_) => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("The LDAP directory {} did not answer", self.config.url),
+            )),
+        }
+    }
+
+    async fn connect(&self) -> Result<Ldap, Error> {
+        let mut settings = LdapConnSettings::new()
+            .set_conn_timeout(self.timeout())
+            .set_starttls(self.config.starttls);
+        if let Some(connector) = &self.connector {
+            settings = settings.set_connector(connector.clone());
+        }
+
+        let (conn, ldap) = LdapConnAsync::with_settings(settings, &self.config.url)
+            .await
+            .map_err(ldap_error)?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    fn attribute(&self, entry: &SearchEntry, name: &str) -> Option<String> {
+        match entry.attrs.get(name).and_then(|values| values.first()) {
+            Some(value) => Some(value.clone()),
+            // Binary identifiers, such as the `objectGUID` of Active Directory.
+            None => entry
+                .bin_attrs
+                .get(name)
+                .and_then(|values| values.first())
+                .map(hex::encode),
+        }
+    }
+
+    async fn search_then_bind(&self, email: &str, password: &str) -> Result<DirectoryLogin, Error> {
+        let mut ldap = self.connect().await?;
+        ldap.simple_bind(
+            self.config.bind_dn.as_deref().unwrap_or_default(),
+            self.config.bind_password.as_deref().unwrap_or_default(),
+        )
+        .await
+        .and_then(|result| result.success())
+        .map_err(ldap_error)?;
+
+        let email_attribute = self.config.email_attribute.as_deref().unwrap_or("mail");
+        let name_attribute = self
+            .config
+            .name_attribute
+            .as_deref()
+            .unwrap_or("displayName");
+        let group_attribute = self.config.group_attribute.as_deref().unwrap_or("memberOf");
+        let mut attributes = vec![email_attribute, name_attribute, group_attribute];
+        if let Some(subject_attribute) = &self.config.subject_attribute {
+            attributes.push(subject_attribute.as_str());
+        }
+        let filter = self
+            .config
+            .user_filter
+            .as_deref()
+            .unwrap_or(DEFAULT_USER_FILTER)
+            .replace("{email}", &ldap_escape(email));
+
+        let (entries, _) = ldap
+            .search(&self.config.base_dn, Scope::Subtree, &filter, attributes)
+            .await
+            .and_then(|result| result.success())
+            .map_err(ldap_error)?;
+        let mut entries: Vec<SearchEntry> = entries
+            .into_iter()
+            .filter(|entry| !entry.is_ref() && !entry.is_intermediate())
+            .map(SearchEntry::construct)
+            .collect();
+        let entry = match entries.len() {
+            0 => return Ok(DirectoryLogin::NotFound),
+            1 => entries.remove(0),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Several LDAP entries have the email {}", email),
+                ))
+            }
+        };
+
+        let bind = ldap
+            .simple_bind(&entry.dn, password)
+            .await
+            .map_err(ldap_error)?;
+        if bind.rc == INVALID_CREDENTIALS {
+            return Ok(DirectoryLogin::InvalidCredentials);
+        }
+        bind.success().map_err(ldap_error)?;
+
+        let groups = match &self.config.group_filter {
+            Some(group_filter) => {
+                // Searched as the user, who may read their groups.
+                let filter = group_filter.replace("{dn}", &ldap_escape(entry.dn.as_str()));
+                let base_dn = self
+                    .config
+                    .group_base_dn
+                    .as_deref()
+                    .unwrap_or(&self.config.base_dn);
+                let (groups, _) = ldap
+                    .search(base_dn, Scope::Subtree, &filter, vec!["1.1"])
+                    .await
+                    .and_then(|result| result.success())
+                    .map_err(ldap_error)?;
+                groups
+                    .into_iter()
+                    .filter(|group| !group.is_ref() && !group.is_intermediate())
+                    .map(|group| SearchEntry::construct(group).dn)
+                    .collect()
+            }
+            None => entry
+                .attrs
+                .get(group_attribute)
+                .cloned()
+                .unwrap_or_default(),
+        };
+        let _ = ldap.unbind().await;
+
+        let subject = match &self.config.subject_attribute {
+            Some(subject_attribute) => {
+                self.attribute(&entry, subject_attribute).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("The LDAP entry {} has no {}", entry.dn, subject_attribute),
+                    )
+                })?
+            }
+            None => entry.dn.clone(),
+        };
+        Ok(DirectoryLogin::Authenticated(DirectoryUser {
+            subject,
+            email: self
+                .attribute(&entry, email_attribute)
+                .unwrap_or_else(|| email.to_owned())
+                .to_lowercase(),
+            name: self.attribute(&entry, name_attribute),
+            groups: groups.iter().map(|group| group.to_lowercase()).collect(),
+            dn: entry.dn,
+        }))
+    }
+
+    /// Emails of the directory are managed by its administrators, they are verified ones.
+    pub fn identity(&self, user: &DirectoryUser) -> ExternalIdentity {
+        ExternalIdentity {
+            provider: String::from(LDAP_IDENTITY_PROVIDER),
+            subject: user.subject.clone(),
+            email: Some(user.email.clone()),
+            email_verified: true,
+            name: user.name.clone(),
+        }
+    }
+
+    /// Roles of a user who had `current` ones, once those of `group_mapping` follow their
+    /// groups.
+    pub fn roles(&self, current: &[String], user: &DirectoryUser) -> Vec<String> {
+        sync_mapped_roles(
+            current,
+            &self.config.group_mapping,
+            user.groups.iter().map(String::as_str),
+        )
+    }
+}
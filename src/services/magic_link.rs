@@ -0,0 +1,96 @@
+//! Passwordless sign-in with a link sent by email.
+//!
+//! A link is requested on `POST /api/v1/login/magic-link` and opened on its callback, which
+//! signs the user in as `login` does. Links are single use and expire after
+//! [`MAGIC_LINK_TTL_SECONDS`], only the hash of their token is stored. A link only works in
+//! the browser which requested it : the request sets a nonce cookie, whose hash is stored
+//! with the link.
+//!
+//! The answer of a request is the same whether an account has the email or not, the link
+//! being looked up and sent after answering. Requests are rate limited by email and by IP
+//! address.
+//!
+//! Links are built from `PUBLIC_BASE_URL`, or `JWT_ISSUER` when it is a URL. Without either,
+//! requests are refused.
+
+use crate::services::mailer::Email;
+use crate::services::oauth::generate_token;
+use crate::services::rate_limit::RateLimiter;
+use std::time::{Duration, Instant};
+
+pub const MAGIC_LINK_TTL_SECONDS: i64 = 900;
+/// Links requested for an email within [`RATE_LIMIT_WINDOW`].
+pub const MAX_LINKS_PER_EMAIL: usize = 3;
+/// Links requested from an IP address within [`RATE_LIMIT_WINDOW`].
+pub const MAX_LINKS_PER_IP: usize = 20;
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(900);
+const MAX_EMAIL_LENGTH: usize = 254;
+
+/// Link being requested : the token goes in the email, the nonce in the cookie of the
+/// browser.
+pub struct PendingMagicLink {
+    pub token: String,
+    pub nonce: String,
+}
+
+impl PendingMagicLink {
+    pub fn generate() -> PendingMagicLink {
+        PendingMagicLink {
+            token: generate_token(),
+            nonce: generate_token(),
+        }
+    }
+}
+
+/// Email the requests are limited by, `None` when it cannot be one.
+pub fn normalized_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let valid = email.len() <= MAX_EMAIL_LENGTH
+        && email
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control());
+    valid.then_some(email)
+}
+
+pub struct MagicLinkLimiter {
+    by_email: RateLimiter,
+    by_ip: RateLimiter,
+}
+
+impl Default for MagicLinkLimiter {
+    fn default() -> MagicLinkLimiter {
+        MagicLinkLimiter {
+            by_email: RateLimiter::new(MAX_LINKS_PER_EMAIL, RATE_LIMIT_WINDOW),
+            by_ip: RateLimiter::new(MAX_LINKS_PER_IP, RATE_LIMIT_WINDOW),
+        }
+    }
+}
+
+impl MagicLinkLimiter {
+    /// Whether a link may be sent to `email` for a request of `ip`.
+    pub fn allow(&self, email: &str, ip: Option<&str>, now: Instant) -> bool {
+        if let Some(ip) = ip {
+            if !self.by_ip.hit(ip, now) {
+                return false;
+            }
+        }
+        self.by_email.hit(email, now)
+    }
+}
+
+pub fn magic_link_email(to: &str, link: &str) -> Email {
+    Email {
+        to: to.to_owned(),
+        subject: String::from("Your sign-in link"),
+        body: format!(
+            "Hello,\n\n\
+            Open this link to sign in, in the browser you requested it from :\n\n\
+            {}\n\n\
+            The link works once and expires in {} minutes. If you did not ask to sign in, \
+            you can ignore this email.\n",
+            link,
+            MAGIC_LINK_TTL_SECONDS / 60
+        ),
+    }
+}
@@ -1,2 +1,24 @@
 pub mod crypto;
-pub mod access_control;
\ No newline at end of file
+pub mod access_control;
+pub mod policy;
+pub mod authz;
+pub mod forward_auth;
+pub mod claims;
+pub mod oauth;
+pub mod oidc;
+pub mod device_authorization;
+pub mod revocation;
+pub mod introspection;
+pub mod client_registration;
+pub mod consent;
+pub mod identity_provider;
+pub mod xml_dsig;
+pub mod saml;
+pub mod ldap;
+pub mod scim;
+pub mod authenticator;
+pub mod mailer;
+pub mod rate_limit;
+pub mod magic_link;
+pub mod sms;
+pub mod sms_code;
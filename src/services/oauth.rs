@@ -0,0 +1,514 @@
+//! OAuth 2.0 authorization server building blocks (RFC 6749, RFC 7636).
+//!
+//! Users delegate with the authorization code grant with PKCE `S256` or the device grant,
+//! redirect URIs are compared exactly against the registered ones. Refresh tokens rotate :
+//! each use exchanges them for a new one. Service accounts use
+//! the client credentials grant, authenticated by secret or `private_key_jwt` (RFC 7523).
+//! First-party clients may also use the password grant, the credentials being checked by the
+//! authenticators of a realm.
+
+use crate::repository::oauth_repository::OAuthClient;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+
+pub const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 60;
+pub const GRANT_AUTHORIZATION_CODE: &str = "authorization_code";
+pub const GRANT_CLIENT_CREDENTIALS: &str = "client_credentials";
+pub const GRANT_DEVICE_CODE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+pub const GRANT_REFRESH_TOKEN: &str = "refresh_token";
+pub const GRANT_PASSWORD: &str = "password";
+pub const PKCE_METHOD_S256: &str = "S256";
+pub const SUPPORTED_GRANT_TYPES: &[&str] = &[
+    GRANT_AUTHORIZATION_CODE,
+    GRANT_CLIENT_CREDENTIALS,
+    GRANT_DEVICE_CODE,
+    GRANT_REFRESH_TOKEN,
+    GRANT_PASSWORD,
+];
+
+pub const AUTH_METHOD_SECRET_BASIC: &str = "client_secret_basic";
+pub const AUTH_METHOD_SECRET_POST: &str = "client_secret_post";
+pub const AUTH_METHOD_NONE: &str = "none";
+pub const AUTH_METHOD_PRIVATE_KEY_JWT: &str = "private_key_jwt";
+
+pub const CLIENT_ASSERTION_TYPE_JWT_BEARER: &str =
+    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+/// Assertions are single use in practice : they must expire within this delay.
+pub const CLIENT_ASSERTION_MAX_LIFETIME_SECONDS: u64 = 300;
+const CLIENT_ASSERTION_LEEWAY_SECONDS: u64 = 60;
+
+/// Error codes of RFC 6749 sections 4.1.2.1 and 5.2, RFC 8628 section 3.5 and RFC 7591
+/// section 3.2.2.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OAuthErrorCode {
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    InvalidScope,
+    AccessDenied,
+    UnsupportedResponseType,
+    ServerError,
+    AuthorizationPending,
+    SlowDown,
+    ExpiredToken,
+    InvalidRedirectUri,
+    InvalidClientMetadata,
+}
+
+impl OAuthErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OAuthErrorCode::InvalidRequest => "invalid_request",
+            OAuthErrorCode::InvalidClient => "invalid_client",
+            OAuthErrorCode::InvalidGrant => "invalid_grant",
+            OAuthErrorCode::UnauthorizedClient => "unauthorized_client",
+            OAuthErrorCode::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthErrorCode::InvalidScope => "invalid_scope",
+            OAuthErrorCode::AccessDenied => "access_denied",
+            OAuthErrorCode::UnsupportedResponseType => "unsupported_response_type",
+            OAuthErrorCode::ServerError => "server_error",
+            OAuthErrorCode::AuthorizationPending => "authorization_pending",
+            OAuthErrorCode::SlowDown => "slow_down",
+            OAuthErrorCode::ExpiredToken => "expired_token",
+            OAuthErrorCode::InvalidRedirectUri => "invalid_redirect_uri",
+            OAuthErrorCode::InvalidClientMetadata => "invalid_client_metadata",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OAuthError {
+    pub error: OAuthErrorCode,
+    pub error_description: String,
+}
+
+impl OAuthError {
+    pub fn new(error: OAuthErrorCode, description: &str) -> OAuthError {
+        OAuthError {
+            error,
+            error_description: description.to_owned(),
+        }
+    }
+}
+
+impl Display for OAuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.error.as_str(), self.error_description)
+    }
+}
+
+/// Parameters of the authorization request, carried from the consent page to its submission.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthorizeParams {
+    #[serde(default)]
+    pub response_type: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub code_challenge: String,
+    #[serde(default)]
+    pub code_challenge_method: String,
+    /// OpenID Connect replay protection, echoed in the ID token.
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+/// A validated authorization request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationGrant {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+}
+
+/// Why an authorization request is rejected. Errors about the client or its redirect URI
+/// must be shown to the user, the others are sent back to the client.
+#[derive(Debug, PartialEq)]
+pub enum AuthorizeError {
+    Display(OAuthError),
+    Redirect {
+        redirect_uri: String,
+        state: Option<String>,
+        error: OAuthError,
+    },
+}
+
+impl AuthorizeParams {
+    pub fn validate(&self, client: &OAuthClient) -> Result<AuthorizationGrant, AuthorizeError> {
+        if !redirect_uri_matches(&client.redirect_uris, &self.redirect_uri) {
+            return Err(AuthorizeError::Display(OAuthError::new(
+                OAuthErrorCode::InvalidRequest,
+                "The redirect_uri is not registered for this client",
+            )));
+        }
+
+        let redirect = |error| AuthorizeError::Redirect {
+            redirect_uri: self.redirect_uri.clone(),
+            state: self.state.clone(),
+            error,
+        };
+
+        if self.response_type != "code" {
+            return Err(redirect(OAuthError::new(
+                OAuthErrorCode::UnsupportedResponseType,
+                "Only the code response type is supported",
+            )));
+        }
+        if !client
+            .grant_types
+            .iter()
+            .any(|g| g == GRANT_AUTHORIZATION_CODE)
+        {
+            return Err(redirect(OAuthError::new(
+                OAuthErrorCode::UnauthorizedClient,
+                "The client may not use the authorization code grant",
+            )));
+        }
+        validate_code_challenge(&self.code_challenge, &self.code_challenge_method)
+            .map_err(redirect)?;
+        let scopes = resolve_scope(self.scope.as_deref(), &client.scopes).map_err(redirect)?;
+
+        Ok(AuthorizationGrant {
+            client_id: client.client_id.clone(),
+            redirect_uri: self.redirect_uri.clone(),
+            scopes,
+            state: self.state.clone(),
+            code_challenge: self.code_challenge.clone(),
+            nonce: self.nonce.clone(),
+        })
+    }
+}
+
+/// Redirect URIs must be absolute, without fragment nor userinfo. Plain `http` is only
+/// accepted on loopback, private-use schemes (`com.example.app:/callback`) are accepted for native apps.
+pub fn validate_redirect_uri(uri: &str) -> Result<(), OAuthError> {
+    let invalid = || {
+        OAuthError::new(
+            OAuthErrorCode::InvalidRequest,
+            &format!("Invalid redirect_uri: {}", uri),
+        )
+    };
+
+    if uri.contains('#') || uri.chars().any(|c| c.is_whitespace()) {
+        return Err(invalid());
+    }
+    let (scheme, rest) = uri.split_once(':').ok_or_else(invalid)?;
+    let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !valid_scheme || rest.is_empty() {
+        return Err(invalid());
+    }
+
+    match scheme.to_ascii_lowercase().as_str() {
+        scheme @ ("https" | "http") => {
+            let uri: http::Uri = uri.parse().map_err(|_| invalid())?;
+            let authority = uri.authority().ok_or_else(invalid)?;
+            if authority.as_str().contains('@') {
+                return Err(invalid());
+            }
+            match (scheme, authority.host()) {
+                (_, "") => Err(invalid()),
+                ("https", _) | (_, "localhost" | "127.0.0.1" | "[::1]") => Ok(()),
+                _ => Err(invalid()),
+            }
+        }
+        "javascript" | "data" | "file" => Err(invalid()),
+        _ => Ok(()),
+    }
+}
+
+/// Exact string comparison, as required by the OAuth 2.0 Security BCP.
+pub fn redirect_uri_matches(registered: &[String], redirect_uri: &str) -> bool {
+    !redirect_uri.is_empty() && registered.iter().any(|uri| uri == redirect_uri)
+}
+
+/// Only `S256` is accepted, the challenge being the base64url SHA-256 of a 43 to 128
+/// characters verifier.
+pub fn validate_code_challenge(challenge: &str, method: &str) -> Result<(), OAuthError> {
+    if challenge.is_empty() {
+        return Err(OAuthError::new(
+            OAuthErrorCode::InvalidRequest,
+            "code_challenge is required",
+        ));
+    }
+    if method != PKCE_METHOD_S256 {
+        return Err(OAuthError::new(
+            OAuthErrorCode::InvalidRequest,
+            "code_challenge_method must be S256",
+        ));
+    }
+    match URL_SAFE_NO_PAD.decode(challenge) {
+        Ok(digest) if digest.len() == 32 => Ok(()),
+        _ => Err(OAuthError::new(
+            OAuthErrorCode::InvalidRequest,
+            "Invalid code_challenge",
+        )),
+    }
+}
+
+pub fn verify_pkce(verifier: &str, challenge: &str) -> bool {
+    let valid_verifier = (43..=128).contains(&verifier.len())
+        && verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'));
+    if !valid_verifier {
+        return false;
+    }
+
+    let computed = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    ring::constant_time::verify_slices_are_equal(computed.as_bytes(), challenge.as_bytes()).is_ok()
+}
+
+pub fn parse_scope(scope: Option<&str>) -> Vec<String> {
+    let mut scopes: Vec<String> = scope
+        .unwrap_or_default()
+        .split(' ')
+        .filter(|scope| !scope.is_empty())
+        .map(String::from)
+        .collect();
+    scopes.dedup();
+    scopes
+}
+
+/// Requested scopes, all of them allowed for the client, or every allowed scope when the
+/// request has none.
+pub fn resolve_scope(
+    requested: Option<&str>,
+    allowed: &[String],
+) -> Result<Vec<String>, OAuthError> {
+    let requested = parse_scope(requested);
+    if requested.is_empty() {
+        return Ok(allowed.to_vec());
+    }
+
+    match requested.iter().find(|scope| !allowed.contains(scope)) {
+        Some(scope) => Err(OAuthError::new(
+            OAuthErrorCode::InvalidScope,
+            &format!("Scope {} is not allowed for this client", scope),
+        )),
+        None => Ok(requested),
+    }
+}
+
+/// 256 random bits, base64url encoded, for codes and secrets.
+pub fn generate_token() -> String {
+    let mut token = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut token)
+        .expect("Unable to generate a random token");
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+/// Codes and other high entropy tokens are stored as their SHA-256.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// `redirect_uri` with the given parameters added to its query.
+pub fn redirect_with(redirect_uri: &str, params: &[(&str, &str)]) -> String {
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", redirect_uri, separator, query)
+}
+
+/// Client redirection carrying an error, and the state of the request.
+pub fn error_redirect(redirect_uri: &str, state: Option<&str>, error: &OAuthError) -> String {
+    let mut params = vec![
+        ("error", error.error.as_str()),
+        ("error_description", error.error_description.as_str()),
+    ];
+    if let Some(state) = state {
+        params.push(("state", state));
+    }
+    redirect_with(redirect_uri, &params)
+}
+
+/// Credentials presented by a client at the token endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// `private_key_jwt` assertion, verified with [`verify_client_assertion`].
+    pub client_assertion: Option<String>,
+}
+
+/// Client credentials from the `Authorization: Basic` header or the request body
+/// (`client_secret_post`), using both is rejected as RFC 6749 section 2.3 requires.
+pub fn client_credentials(
+    authorization: Option<&str>,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<ClientCredentials, OAuthError> {
+    let invalid = |description: &str| OAuthError::new(OAuthErrorCode::InvalidClient, description);
+
+    match authorization.and_then(|header| header.strip_prefix("Basic ")) {
+        Some(basic) => {
+            if client_secret.is_some() {
+                return Err(OAuthError::new(
+                    OAuthErrorCode::InvalidRequest,
+                    "Only one client authentication method may be used",
+                ));
+            }
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(basic.trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .ok_or_else(|| invalid("Malformed Basic credentials"))?;
+            let (id, secret) = decoded
+                .split_once(':')
+                .ok_or_else(|| invalid("Malformed Basic credentials"))?;
+            if client_id.is_some_and(|client_id| client_id != id) {
+                return Err(invalid("client_id does not match the credentials"));
+            }
+
+            Ok(ClientCredentials {
+                client_id: id.to_owned(),
+                client_secret: Some(secret.to_owned()),
+                client_assertion: None,
+            })
+        }
+        None => match client_id.filter(|id| !id.is_empty()) {
+            Some(id) => Ok(ClientCredentials {
+                client_id: id.to_owned(),
+                client_secret: client_secret.map(String::from),
+                client_assertion: None,
+            }),
+            None => Err(invalid("Client authentication is required")),
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct AssertionClaims {
+    sub: String,
+    exp: u64,
+}
+
+/// Client of a `private_key_jwt` assertion (RFC 7523 section 2.2), identified by its
+/// unverified `sub`, which must match `client_id` when the latter is sent.
+pub fn client_assertion(
+    assertion_type: &str,
+    assertion: &str,
+    client_id: Option<&str>,
+) -> Result<ClientCredentials, OAuthError> {
+    let invalid = || OAuthError::new(OAuthErrorCode::InvalidClient, "Invalid client_assertion");
+
+    if assertion_type != CLIENT_ASSERTION_TYPE_JWT_BEARER {
+        return Err(OAuthError::new(
+            OAuthErrorCode::InvalidRequest,
+            "Unsupported client_assertion_type",
+        ));
+    }
+
+    let payload = assertion.split('.').nth(1).ok_or_else(invalid)?;
+    let claims: AssertionClaims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|payload| serde_json::from_slice(&payload).ok())
+        .ok_or_else(invalid)?;
+    if client_id.is_some_and(|client_id| client_id != claims.sub) {
+        return Err(invalid());
+    }
+
+    Ok(ClientCredentials {
+        client_id: claims.sub,
+        client_secret: None,
+        client_assertion: Some(assertion.to_owned()),
+    })
+}
+
+/// Verify a `private_key_jwt` assertion : signed by one of the client keys, issued by and
+/// about the client, for one of `audiences` (the token endpoint or the issuer) and short-lived.
+pub fn verify_client_assertion(
+    assertion: &str,
+    client_id: &str,
+    jwks: &JwkSet,
+    audiences: &[String],
+) -> Result<(), OAuthError> {
+    let invalid = || OAuthError::new(OAuthErrorCode::InvalidClient, "Invalid client_assertion");
+
+    let header = decode_header(assertion).map_err(|_| invalid())?;
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(invalid)?;
+
+    // Shared secrets are not keys of the client, and the algorithm follows the key.
+    let key_matches = match &jwk.algorithm {
+        AlgorithmParameters::OctetKeyPair(_) => header.alg == Algorithm::EdDSA,
+        AlgorithmParameters::RSA(_) => matches!(
+            header.alg,
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+        ),
+        AlgorithmParameters::EllipticCurve(_) => {
+            matches!(header.alg, Algorithm::ES256 | Algorithm::ES384)
+        }
+        AlgorithmParameters::OctetKey(_) => false,
+    };
+    if !key_matches {
+        return Err(invalid());
+    }
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| invalid())?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.leeway = CLIENT_ASSERTION_LEEWAY_SECONDS;
+    validation.set_issuer(&[client_id]);
+    validation.sub = Some(client_id.to_owned());
+    validation.set_audience(audiences);
+    validation.set_required_spec_claims(&["exp", "iss", "sub", "aud"]);
+    let claims = decode::<AssertionClaims>(assertion, &key, &validation)
+        .map_err(|_| invalid())?
+        .claims;
+
+    let now = Utc::now().timestamp() as u64;
+    if claims.exp > now + CLIENT_ASSERTION_MAX_LIFETIME_SECONDS + CLIENT_ASSERTION_LEEWAY_SECONDS {
+        return Err(invalid());
+    }
+
+    Ok(())
+}
+
+/// Client keys usable for `private_key_jwt` : asymmetric and parseable.
+pub fn validate_client_jwks(jwks: &JwkSet) -> Result<(), OAuthError> {
+    let invalid = |description: &str| OAuthError::new(OAuthErrorCode::InvalidRequest, description);
+
+    if jwks.keys.is_empty() {
+        return Err(invalid("jwks must contain at least one key"));
+    }
+    for jwk in &jwks.keys {
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err(invalid("Symmetric keys are not accepted"));
+        }
+        DecodingKey::from_jwk(jwk).map_err(|_| invalid("Invalid key in jwks"))?;
+    }
+
+    Ok(())
+}
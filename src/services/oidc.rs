@@ -0,0 +1,173 @@
+//! OpenID Connect layer of the authorization server (OpenID Connect Core 1.0).
+//!
+//! An authorization code granted with the `openid` scope also yields an ID token, the
+//! `profile` and `email` scopes select which user fields are released as claims.
+
+use crate::repository::user_repository::User;
+use crate::services::crypto::TokenConfig;
+use crate::services::oauth::{PKCE_METHOD_S256, SUPPORTED_GRANT_TYPES};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+pub const SCOPE_OPENID: &str = "openid";
+pub const SCOPE_PROFILE: &str = "profile";
+pub const SCOPE_EMAIL: &str = "email";
+pub const SUPPORTED_SCOPES: &[&str] = &[SCOPE_OPENID, SCOPE_PROFILE, SCOPE_EMAIL];
+
+pub const ID_TOKEN_LIFETIME_SECONDS: u64 = 3600;
+
+/// Claims of an ID token. The audience is the client the token is issued to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    /// Authorized party, the client.
+    pub azp: String,
+    pub exp: u64,
+    pub iat: u64,
+    pub auth_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// Scoped user claims, see [`user_claims`].
+    #[serde(flatten)]
+    pub user: Map<String, Value>,
+}
+
+impl IdTokenClaims {
+    pub fn new(
+        config: &TokenConfig,
+        user: &User,
+        client_id: &str,
+        scopes: &[String],
+        nonce: Option<String>,
+        auth_time: u64,
+    ) -> IdTokenClaims {
+        let now = Utc::now().timestamp() as u64;
+
+        IdTokenClaims {
+            iss: config.issuer.clone(),
+            sub: user.id.clone(),
+            aud: client_id.to_owned(),
+            azp: client_id.to_owned(),
+            exp: now + ID_TOKEN_LIFETIME_SECONDS,
+            iat: now,
+            auth_time,
+            nonce,
+            user: user_claims(user, scopes),
+        }
+    }
+}
+
+pub fn has_scope(scopes: &[String], scope: &str) -> bool {
+    scopes.iter().any(|granted| granted == scope)
+}
+
+/// Standard claims of `user` released by the granted scopes, `sub` excepted.
+pub fn user_claims(user: &User, scopes: &[String]) -> Map<String, Value> {
+    let mut claims = Map::new();
+
+    if has_scope(scopes, SCOPE_PROFILE) {
+        if let Some(name) = &user.name {
+            claims.insert(String::from("name"), Value::from(name.as_str()));
+        }
+        claims.insert(
+            String::from("preferred_username"),
+            Value::from(user.email.as_str()),
+        );
+    }
+    if has_scope(scopes, SCOPE_EMAIL) {
+        claims.insert(String::from("email"), Value::from(user.email.as_str()));
+        claims.insert(
+            String::from("email_verified"),
+            Value::from(user.email_verified),
+        );
+    }
+
+    claims
+}
+
+/// Response of the userinfo endpoint.
+pub fn userinfo(user: &User, scopes: &[String]) -> Map<String, Value> {
+    let mut claims = user_claims(user, scopes);
+    claims.insert(String::from("sub"), Value::from(user.id.as_str()));
+    claims
+}
+
+/// OpenID Provider metadata, served on `/.well-known/openid-configuration`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub device_authorization_endpoint: String,
+    pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
+    /// Only when dynamic client registration is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_endpoint: Option<String>,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub token_endpoint_auth_signing_alg_values_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+impl ProviderMetadata {
+    /// Metadata of the provider reachable on `base_url`, `https://auth.example.com` for
+    /// instance. `issuer` must be that same URL for clients to accept the ID tokens.
+    pub fn new(issuer: &str, base_url: &str, signing_algorithm: &str) -> ProviderMetadata {
+        let base_url = base_url.trim_end_matches('/');
+
+        ProviderMetadata {
+            issuer: issuer.to_owned(),
+            authorization_endpoint: format!("{}/oauth/authorize", base_url),
+            token_endpoint: format!("{}/oauth/token", base_url),
+            userinfo_endpoint: format!("{}/oauth/userinfo", base_url),
+            device_authorization_endpoint: format!("{}/oauth/device_authorization", base_url),
+            introspection_endpoint: format!("{}/oauth/introspect", base_url),
+            revocation_endpoint: format!("{}/oauth/revoke", base_url),
+            registration_endpoint: None,
+            jwks_uri: format!("{}/.well-known/jwks.json", base_url),
+            scopes_supported: strings(SUPPORTED_SCOPES),
+            response_types_supported: strings(&["code"]),
+            grant_types_supported: strings(SUPPORTED_GRANT_TYPES),
+            subject_types_supported: strings(&["public"]),
+            id_token_signing_alg_values_supported: strings(&[signing_algorithm]),
+            token_endpoint_auth_methods_supported: strings(&[
+                "client_secret_basic",
+                "client_secret_post",
+                "private_key_jwt",
+                "none",
+            ]),
+            token_endpoint_auth_signing_alg_values_supported: strings(&[
+                "EdDSA", "RS256", "RS384", "RS512", "PS256", "PS384", "PS512", "ES256", "ES384",
+            ]),
+            code_challenge_methods_supported: strings(&[PKCE_METHOD_S256]),
+            claims_supported: strings(&[
+                "sub",
+                "iss",
+                "aud",
+                "exp",
+                "iat",
+                "auth_time",
+                "nonce",
+                "name",
+                "preferred_username",
+                "email",
+                "email_verified",
+            ]),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Attribute-based policies evaluated on top of the role/permission checks of
+//! [`AccessControl`](crate::services::access_control::AccessControl).
+//!
+//! Policies are declared in a JSON file (`POLICY_FILE`, `policies.json` by default) :
+//!
+//! ```json
+//! {
+//!   "policies": [{
+//!     "id": "no-hard-delete-super-admin",
+//!     "effect": "deny",
+//!     "actions": ["user:hard_delete"],
+//!     "conditions": [{ "attribute": "resource.roles", "operator": "contains", "value": "ROLE_SUPER_ADMIN" }],
+//!     "reason": "Super admin accounts cannot be hard deleted"
+//!   }]
+//! }
+//! ```
+//!
+//! A matching `deny` always wins. When at least one `allow` policy targets the action,
+//! one of them must match, otherwise the action is allowed.
+
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    /// Attribute equals `value`.
+    Equals,
+    NotEquals,
+    /// Attribute is one of the values of the `value` array.
+    In,
+    /// Attribute is an array containing `value`.
+    Contains,
+    /// Attribute equals the attribute named by `value`, e.g. `resource.organization`.
+    EqualsAttribute,
+    NotEqualsAttribute,
+    /// Attribute is an IP address inside the `value` CIDR, e.g. `10.0.0.0/8`.
+    Cidr,
+    /// Attribute is an hour within the `[start, end)` `value` array, e.g. `[9, 18]`.
+    HourBetween,
+    /// Attribute is present and not null.
+    Exists,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Condition {
+    pub attribute: String,
+    pub operator: Operator,
+    #[serde(default)]
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Policy {
+    pub id: String,
+    pub effect: Effect,
+    pub actions: Vec<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PolicyDecision {
+    Allow(String),
+    Deny(String),
+}
+
+/// Attributes of the subject, the resource and the environment of a request.
+/// Keys are looked up as `subject.<key>`, `resource.<key>` and `environment.<key>`.
+#[derive(Debug, Default, Clone)]
+pub struct PolicyContext {
+    pub subject: HashMap<String, Value>,
+    pub resource: HashMap<String, Value>,
+    pub environment: HashMap<String, Value>,
+}
+
+impl PolicyContext {
+    /// Context with the environment filled from the current time and the client IP.
+    pub fn new(ip: Option<IpAddr>, now: DateTime<Utc>) -> PolicyContext {
+        let mut environment = HashMap::new();
+        environment.insert(String::from("hour"), Value::from(now.hour()));
+        environment.insert(String::from("timestamp"), Value::from(now.timestamp()));
+        if let Some(ip) = ip {
+            environment.insert(String::from("ip"), Value::from(ip.to_string()));
+        }
+
+        PolicyContext {
+            environment,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_subject(mut self, key: &str, value: impl Into<Value>) -> PolicyContext {
+        self.subject.insert(key.to_owned(), value.into());
+        self
+    }
+
+    pub fn with_resource(mut self, key: &str, value: impl Into<Value>) -> PolicyContext {
+        self.resource.insert(key.to_owned(), value.into());
+        self
+    }
+
+    pub fn get(&self, attribute: &str) -> Option<&Value> {
+        let (namespace, key) = attribute.split_once('.')?;
+        let value = match namespace {
+            "subject" => self.subject.get(key),
+            "resource" => self.resource.get(key),
+            "environment" => self.environment.get(key),
+            _ => None,
+        };
+        value.filter(|v| !v.is_null())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PolicyEngine {
+    #[serde(default)]
+    policies: Vec<Policy>,
+}
+
+impl PolicyEngine {
+    pub fn new(policies: Vec<Policy>) -> PolicyEngine {
+        PolicyEngine { policies }
+    }
+
+    pub fn from_json(json: &str) -> Result<PolicyEngine, Error> {
+        serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    /// Load the policies from `POLICY_FILE`. A missing default file means no policies,
+    /// an explicitly configured file that cannot be read is an error.
+    pub fn from_env() -> Result<PolicyEngine, Error> {
+        match std::env::var("POLICY_FILE") {
+            Ok(path) => PolicyEngine::from_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("policies.json") {
+                Ok(json) => PolicyEngine::from_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(PolicyEngine::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    pub fn evaluate(&self, action: &str, context: &PolicyContext) -> PolicyDecision {
+        let applicable: Vec<&Policy> = self
+            .policies
+            .iter()
+            .filter(|policy| policy.actions.iter().any(|a| a == action || a == "*"))
+            .collect();
+
+        let matching = |effect: Effect| {
+            applicable
+                .iter()
+                .filter(move |policy| policy.effect == effect)
+                .find(|policy| policy.conditions.iter().all(|c| c.is_satisfied(context)))
+        };
+
+        if let Some(policy) = matching(Effect::Deny) {
+            return PolicyDecision::Deny(policy.describe());
+        }
+
+        if let Some(policy) = matching(Effect::Allow) {
+            return PolicyDecision::Allow(policy.describe());
+        }
+
+        if applicable.iter().any(|policy| policy.effect == Effect::Allow) {
+            return PolicyDecision::Deny(format!("No policy allows {}", action));
+        }
+
+        PolicyDecision::Allow(String::from("No policy applies"))
+    }
+}
+
+impl Policy {
+    fn describe(&self) -> String {
+        self.reason
+            .clone()
+            .unwrap_or_else(|| format!("Policy {}", self.id))
+    }
+}
+
+impl Condition {
+    fn is_satisfied(&self, context: &PolicyContext) -> bool {
+        let attribute = context.get(&self.attribute);
+
+        match self.operator {
+            Operator::Exists => attribute.is_some(),
+            Operator::Equals => attribute == Some(&self.value),
+            Operator::NotEquals => attribute != Some(&self.value),
+            Operator::In => match (&self.value, attribute) {
+                (Value::Array(values), Some(attribute)) => values.contains(attribute),
+                _ => false,
+            },
+            Operator::Contains => match attribute {
+                Some(Value::Array(values)) => values.contains(&self.value),
+                _ => false,
+            },
+            Operator::EqualsAttribute | Operator::NotEqualsAttribute => {
+                let other = self.value.as_str().and_then(|name| context.get(name));
+                let equals = attribute.is_some() && attribute == other;
+                equals == (self.operator == Operator::EqualsAttribute)
+            }
+            Operator::Cidr => match (attribute.and_then(Value::as_str), self.value.as_str()) {
+                (Some(ip), Some(cidr)) => ip_in_cidr(ip, cidr),
+                _ => false,
+            },
+            Operator::HourBetween => {
+                let hour = attribute.and_then(Value::as_u64);
+                let bounds = self
+                    .value
+                    .as_array()
+                    .map(|b| (b.first().and_then(Value::as_u64), b.get(1).and_then(Value::as_u64)));
+                match (hour, bounds) {
+                    (Some(hour), Some((Some(start), Some(end)))) if start <= end => {
+                        hour >= start && hour < end
+                    }
+                    // Range wrapping around midnight, e.g. [22, 6]
+                    (Some(hour), Some((Some(start), Some(end)))) => hour >= start || hour < end,
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+pub fn ip_in_cidr(ip: &str, cidr: &str) -> bool {
+    let (network, prefix) = match cidr.split_once('/') {
+        Some((network, prefix)) => (network, prefix.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+
+    match (ip.parse::<IpAddr>(), network.parse::<IpAddr>()) {
+        (Ok(IpAddr::V4(ip)), Ok(IpAddr::V4(network))) => {
+            let prefix = prefix.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (Ok(IpAddr::V6(ip)), Ok(IpAddr::V6(network))) => {
+            let prefix = prefix.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
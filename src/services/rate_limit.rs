@@ -0,0 +1,51 @@
+//! Limits of how often a key, such as an email or an IP address, may do something.
+//!
+//! Hits are counted in memory over a sliding window, each instance of the service limiting
+//! on its own.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keys are forgotten once their window is over, when there are more than this many.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+pub struct RateLimiter {
+    max_hits: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_hits: usize, window: Duration) -> RateLimiter {
+        RateLimiter {
+            max_hits,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a hit of `key` at `now`, `false` when the key already reached the limit : the
+    /// hit is then not counted.
+    pub fn hit(&self, key: &str, now: Instant) -> bool {
+        let mut hits = match self.hits.lock() {
+            Ok(hits) => hits,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let expired = |hit: &Instant| now.saturating_duration_since(*hit) >= self.window;
+
+        if hits.len() >= MAX_TRACKED_KEYS {
+            hits.retain(|_, key_hits| key_hits.back().is_some_and(|hit| !expired(hit)));
+        }
+        let key_hits = hits.entry(key.to_owned()).or_default();
+        while key_hits.front().is_some_and(expired) {
+            key_hits.pop_front();
+        }
+
+        if key_hits.len() >= self.max_hits {
+            return false;
+        }
+        key_hits.push_back(now);
+        true
+    }
+}
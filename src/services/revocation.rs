@@ -0,0 +1,45 @@
+//! Access tokens revoked before they expire.
+//!
+//! Revocations are stored in `public.oauth_revoked_tokens` and mirrored in memory, so that
+//! [`Jwt::verify_jwt`](crate::services::crypto::Jwt::verify_jwt) checks them without a
+//! query. The mirror is reloaded periodically to see the revocations of other instances.
+
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// `jti` of the revoked tokens and their `exp`, after which they are invalid anyway.
+static REVOKED_TOKENS: OnceLock<RwLock<HashMap<String, u64>>> = OnceLock::new();
+
+fn revoked_tokens() -> &'static RwLock<HashMap<String, u64>> {
+    REVOKED_TOKENS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+pub struct RevocationList;
+
+impl RevocationList {
+    pub fn revoke(jti: &str, exp: u64) {
+        let mut revoked = revoked_tokens()
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        revoked.insert(jti.to_owned(), exp);
+    }
+
+    pub fn is_revoked(jti: &str) -> bool {
+        let revoked = revoked_tokens()
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+        revoked.contains_key(jti)
+    }
+
+    /// Add the revocations read from the database and drop the expired ones. Revocations
+    /// are never undone, merging keeps those made locally while the database was read.
+    pub fn sync(entries: impl IntoIterator<Item = (String, u64)>) {
+        let now = Utc::now().timestamp() as u64;
+        let mut revoked = revoked_tokens()
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        revoked.extend(entries);
+        revoked.retain(|_, exp| *exp > now);
+    }
+}
@@ -0,0 +1,574 @@
+//! SAML 2.0 service provider, for the single sign-on of enterprise identity providers.
+//!
+//! Identity providers are declared in a JSON file (`SAML_PROVIDERS_FILE`,
+//! `saml_providers.json` by default) :
+//!
+//! ```json
+//! {
+//!   "entity_id": "https://auth.example.com/saml/metadata",
+//!   "providers": [
+//!     { "name": "acme", "display_name": "ACME", "entity_id": "http://www.okta.com/exk1",
+//!       "sso_url": "https://acme.okta.com/app/auth_api/exk1/sso/saml",
+//!       "certificate_file": "acme.pem", "email_attribute": "email",
+//!       "name_attribute": "displayName", "role_attribute": "groups",
+//!       "role_mapping": { "Administrators": "ROLE_ADMIN" }, "trust_email": true }
+//!   ]
+//! }
+//! ```
+//!
+//! - `entity_id` identifies this service provider, `{base_url}/saml/metadata` by default,
+//!   where its metadata is published
+//! - the certificate of a provider is given inline (`certificate`) or as a PEM file
+//!   (`certificate_file`), the keys embedded in the responses are never trusted
+//! - users are identified by the `NameID` of the assertions, or by `subject_attribute` when
+//!   the provider only sends transient ones
+//!
+//! Only SP-initiated logins are accepted : the request is sent with the HTTP-Redirect
+//! binding, the response comes back to the assertion consumer service with the HTTP-POST
+//! binding. Its assertion must be signed, by itself or by the response, be in response to
+//! the request, for this service provider and unexpired. Assertions are kept until they
+//! expire so that none is accepted twice.
+//!
+//! Roles of `role_mapping` follow the values of `role_attribute` at every login, the other
+//! roles of the users are left untouched.
+
+use crate::config::roles::{sync_mapped_roles, Role};
+use crate::services::identity_provider::{valid_provider_name, ExternalIdentity};
+use crate::services::oauth::{generate_token, redirect_with};
+use crate::services::xml_dsig::{has_unique_ids, verify_enveloped_signature, Element, NS_DSIG};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::x509::X509;
+use quick_xml::escape::escape;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Write};
+use std::str::FromStr;
+
+pub const NS_PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+pub const NS_ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+pub const NS_METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+pub const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+pub const CONFIRMATION_BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+pub const BINDING_HTTP_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+pub const NAME_ID_EMAIL: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+const NAME_ID_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified";
+
+/// How long the user has to come back from the identity provider.
+pub const SAML_REQUEST_TTL_SECONDS: i64 = 600;
+const CLOCK_SKEW_SECONDS: i64 = 60;
+/// Base64 encoded responses above this size are refused before being decoded.
+pub const MAX_RESPONSE_BYTES: usize = 256 * 1024;
+const MIN_RSA_KEY_BITS: u32 = 2048;
+
+fn invalid_data(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn invalid_input(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidInput, message.into())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SamlProviderConfig {
+    /// Identifies the provider in URLs, its users are linked to the `saml:{name}` identities.
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Issuer of the responses of the provider.
+    pub entity_id: String,
+    /// Where authentication requests are sent, with the HTTP-Redirect binding.
+    pub sso_url: String,
+    #[serde(default)]
+    pub certificate: Option<String>,
+    #[serde(default)]
+    pub certificate_file: Option<String>,
+    /// Attribute identifying the user, the `NameID` by default.
+    #[serde(default)]
+    pub subject_attribute: Option<String>,
+    /// Attribute of the email, the `NameID` when it is an email address by default.
+    #[serde(default)]
+    pub email_attribute: Option<String>,
+    #[serde(default)]
+    pub name_attribute: Option<String>,
+    #[serde(default)]
+    pub role_attribute: Option<String>,
+    /// Role granted for each value of `role_attribute`.
+    #[serde(default)]
+    pub role_mapping: HashMap<String, String>,
+    /// The emails of the provider are verified ones.
+    #[serde(default)]
+    pub trust_email: bool,
+}
+
+/// What the response to an authentication request must be.
+pub struct ResponseContext<'a> {
+    pub sp_entity_id: &'a str,
+    pub acs_url: &'a str,
+    /// ID of the request, the assertion must be in response to it.
+    pub request_id: &'a str,
+    pub now: DateTime<Utc>,
+}
+
+/// Content of a validated assertion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamlAssertion {
+    pub id: String,
+    pub name_id: String,
+    pub name_id_format: Option<String>,
+    pub attributes: HashMap<String, Vec<String>>,
+    /// The assertion is refused afterwards, it no longer needs to be remembered against
+    /// replays.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// ID of a new authentication request, an `xs:ID` must not start with a digit.
+pub fn new_request_id() -> String {
+    format!("_{}", generate_token())
+}
+
+fn timestamp(element: &Element, attribute: &str) -> Result<Option<DateTime<Utc>>, Error> {
+    match element.attribute(attribute) {
+        Some(value) => DateTime::parse_from_rfc3339(value.trim())
+            .map(|instant| Some(instant.with_timezone(&Utc)))
+            .map_err(|_| invalid_data(format!("Invalid {} : {}", attribute, value))),
+        None => Ok(None),
+    }
+}
+
+pub struct SamlProvider {
+    config: SamlProviderConfig,
+    public_key: PKey<Public>,
+}
+
+impl SamlProvider {
+    pub fn new(config: SamlProviderConfig) -> Result<SamlProvider, Error> {
+        if !valid_provider_name(&config.name) {
+            return Err(invalid_input(format!(
+                "Invalid SAML provider name: {}",
+                config.name
+            )));
+        }
+        for role in config.role_mapping.values() {
+            Role::from_str(role).map_err(|_| {
+                invalid_input(format!(
+                    "Invalid role {} of SAML provider {}",
+                    role, config.name
+                ))
+            })?;
+        }
+
+        let pem = match (&config.certificate, &config.certificate_file) {
+            (Some(certificate), _) => certificate.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)?,
+            (None, None) => {
+                return Err(invalid_input(format!(
+                    "SAML provider {} needs a certificate",
+                    config.name
+                )))
+            }
+        };
+        let public_key = X509::from_pem(pem.as_bytes())
+            .and_then(|certificate| certificate.public_key())
+            .map_err(|err| {
+                invalid_input(format!("Invalid certificate of {} : {}", config.name, err))
+            })?;
+        if public_key.id() != Id::RSA || public_key.bits() < MIN_RSA_KEY_BITS {
+            return Err(invalid_input(format!(
+                "SAML provider {} needs an RSA key of at least {} bits",
+                config.name, MIN_RSA_KEY_BITS
+            )));
+        }
+
+        Ok(SamlProvider { config, public_key })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.config
+            .display_name
+            .as_deref()
+            .unwrap_or(&self.config.name)
+    }
+
+    /// Provider of the identities of the users of this provider.
+    pub fn identity_provider(&self) -> String {
+        format!("saml:{}", self.config.name)
+    }
+
+    pub fn authn_request(
+        &self,
+        request_id: &str,
+        sp_entity_id: &str,
+        acs_url: &str,
+        now: DateTime<Utc>,
+    ) -> String {
+        format!(
+            "<samlp:AuthnRequest xmlns:samlp=\"{}\" xmlns:saml=\"{}\" ID=\"{}\" Version=\"2.0\" \
+            IssueInstant=\"{}\" Destination=\"{}\" AssertionConsumerServiceURL=\"{}\" \
+            ProtocolBinding=\"{}\"><saml:Issuer>{}</saml:Issuer>\
+            <samlp:NameIDPolicy Format=\"{}\" AllowCreate=\"true\"/></samlp:AuthnRequest>",
+            NS_PROTOCOL,
+            NS_ASSERTION,
+            escape(request_id),
+            now.format("%Y-%m-%dT%H:%M:%SZ"),
+            escape(&self.config.sso_url),
+            escape(acs_url),
+            BINDING_HTTP_POST,
+            escape(sp_entity_id),
+            NAME_ID_UNSPECIFIED,
+        )
+    }
+
+    /// Single sign-on URL carrying `authn_request` with the HTTP-Redirect binding : deflated,
+    /// then base64 encoded.
+    pub fn redirect_url(&self, authn_request: &str) -> Result<String, Error> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(authn_request.as_bytes())?;
+        let deflated = encoder.finish()?;
+
+        Ok(redirect_with(
+            &self.config.sso_url,
+            &[("SAMLRequest", &STANDARD.encode(deflated))],
+        ))
+    }
+
+    fn check_issuer(&self, issuer: Option<&Element>) -> Result<(), Error> {
+        match issuer {
+            Some(issuer) if issuer.text().trim() == self.config.entity_id => Ok(()),
+            Some(issuer) => Err(invalid_data(format!(
+                "Unexpected issuer {}",
+                issuer.text().trim()
+            ))),
+            None => Err(invalid_data("Missing issuer")),
+        }
+    }
+
+    /// Assertion of `saml_response`, the base64 encoded `SAMLResponse` of the HTTP-POST
+    /// binding. Only the assertion covered by a valid signature is read.
+    pub fn validate_response(
+        &self,
+        saml_response: &str,
+        context: &ResponseContext,
+    ) -> Result<SamlAssertion, Error> {
+        if saml_response.len() > MAX_RESPONSE_BYTES {
+            return Err(invalid_data("Response too large"));
+        }
+        let encoded: String = saml_response.split_whitespace().collect();
+        let xml = STANDARD
+            .decode(encoded)
+            .map_err(|err| invalid_data(err.to_string()))?;
+        let xml = String::from_utf8(xml).map_err(|err| invalid_data(err.to_string()))?;
+        let response = Element::parse(&xml)?;
+
+        if !response.is(NS_PROTOCOL, "Response") {
+            return Err(invalid_data("Not a SAML response"));
+        }
+        if !has_unique_ids(&response, "ID") {
+            return Err(invalid_data("Duplicated IDs in the response"));
+        }
+        if response.attribute("Version") != Some("2.0") {
+            return Err(invalid_data("Unsupported SAML version"));
+        }
+        if response
+            .attribute("Destination")
+            .is_some_and(|destination| destination != context.acs_url)
+        {
+            return Err(invalid_data("The response is for another destination"));
+        }
+        if response
+            .attribute("InResponseTo")
+            .is_some_and(|request_id| request_id != context.request_id)
+        {
+            return Err(invalid_data("The response is for another request"));
+        }
+
+        let status = response
+            .child(NS_PROTOCOL, "Status")
+            .and_then(|status| status.child(NS_PROTOCOL, "StatusCode"))
+            .and_then(|code| code.attribute("Value"));
+        if status != Some(STATUS_SUCCESS) {
+            return Err(invalid_data(format!(
+                "The identity provider answered {}",
+                status.unwrap_or("without status")
+            )));
+        }
+        if let Some(issuer) = response.child(NS_ASSERTION, "Issuer") {
+            self.check_issuer(Some(issuer))?;
+        }
+
+        if response.child(NS_ASSERTION, "EncryptedAssertion").is_some() {
+            return Err(invalid_data("Encrypted assertions are not supported"));
+        }
+        let mut assertions = response.children_named(NS_ASSERTION, "Assertion");
+        let assertion = assertions
+            .next()
+            .ok_or_else(|| invalid_data("No assertion in the response"))?;
+        if assertions.next().is_some() {
+            return Err(invalid_data("More than one assertion in the response"));
+        }
+
+        let response_signed = response.child(NS_DSIG, "Signature").is_some();
+        if response_signed {
+            verify_enveloped_signature(&response, "ID", &self.public_key)?;
+        }
+        if assertion.child(NS_DSIG, "Signature").is_some() {
+            verify_enveloped_signature(assertion, "ID", &self.public_key)?;
+        } else if !response_signed {
+            return Err(invalid_data("The assertion is not signed"));
+        }
+
+        self.read_assertion(assertion, context)
+    }
+
+    fn read_assertion(
+        &self,
+        assertion: &Element,
+        context: &ResponseContext,
+    ) -> Result<SamlAssertion, Error> {
+        if assertion.attribute("Version") != Some("2.0") {
+            return Err(invalid_data("Unsupported SAML version"));
+        }
+        let id = assertion
+            .attribute("ID")
+            .ok_or_else(|| invalid_data("Assertion without ID"))?;
+        self.check_issuer(assertion.child(NS_ASSERTION, "Issuer"))?;
+
+        let skew = Duration::seconds(CLOCK_SKEW_SECONDS);
+        let conditions = assertion
+            .child(NS_ASSERTION, "Conditions")
+            .ok_or_else(|| invalid_data("Assertion without conditions"))?;
+        if timestamp(conditions, "NotBefore")?
+            .is_some_and(|not_before| context.now + skew < not_before)
+        {
+            return Err(invalid_data("The assertion is not valid yet"));
+        }
+        let valid_until = timestamp(conditions, "NotOnOrAfter")?;
+        if valid_until.is_some_and(|not_on_or_after| context.now - skew >= not_on_or_after) {
+            return Err(invalid_data("The assertion expired"));
+        }
+        let restrictions: Vec<&Element> = conditions
+            .children_named(NS_ASSERTION, "AudienceRestriction")
+            .collect();
+        let for_this_sp = !restrictions.is_empty()
+            && restrictions.iter().all(|restriction| {
+                restriction
+                    .children_named(NS_ASSERTION, "Audience")
+                    .any(|audience| audience.text().trim() == context.sp_entity_id)
+            });
+        if !for_this_sp {
+            return Err(invalid_data(
+                "The assertion is for another service provider",
+            ));
+        }
+
+        let subject = assertion
+            .child(NS_ASSERTION, "Subject")
+            .ok_or_else(|| invalid_data("Assertion without subject"))?;
+        let name_id = subject
+            .child(NS_ASSERTION, "NameID")
+            .ok_or_else(|| invalid_data("Assertion without NameID"))?;
+        let confirmed_until = subject
+            .children_named(NS_ASSERTION, "SubjectConfirmation")
+            .filter(|confirmation| confirmation.attribute("Method") == Some(CONFIRMATION_BEARER))
+            .filter_map(|confirmation| confirmation.child(NS_ASSERTION, "SubjectConfirmationData"))
+            .find_map(|data| {
+                let not_on_or_after = timestamp(data, "NotOnOrAfter").ok()??;
+                let confirmed = data.attribute("Recipient") == Some(context.acs_url)
+                    && data.attribute("InResponseTo") == Some(context.request_id)
+                    && context.now - skew < not_on_or_after;
+                confirmed.then_some(not_on_or_after)
+            })
+            .ok_or_else(|| invalid_data("The subject of the assertion is not confirmed"))?;
+
+        if assertion.child(NS_ASSERTION, "AuthnStatement").is_none() {
+            return Err(invalid_data("Assertion without authentication statement"));
+        }
+
+        let mut attributes: HashMap<String, Vec<String>> = HashMap::new();
+        for attribute in assertion
+            .children_named(NS_ASSERTION, "AttributeStatement")
+            .flat_map(|statement| statement.children_named(NS_ASSERTION, "Attribute"))
+        {
+            if let Some(name) = attribute.attribute("Name") {
+                attributes.entry(name.to_owned()).or_default().extend(
+                    attribute
+                        .children_named(NS_ASSERTION, "AttributeValue")
+                        .map(|value| value.text().trim().to_owned()),
+                );
+            }
+        }
+
+        Ok(SamlAssertion {
+            id: id.to_owned(),
+            name_id: name_id.text().trim().to_owned(),
+            name_id_format: name_id.attribute("Format").map(str::to_owned),
+            attributes,
+            expires_at: valid_until.map_or(confirmed_until, |valid_until| {
+                valid_until.min(confirmed_until)
+            }),
+        })
+    }
+
+    fn first_value<'a>(
+        &self,
+        assertion: &'a SamlAssertion,
+        attribute: &Option<String>,
+    ) -> Option<&'a str> {
+        attribute
+            .as_ref()
+            .and_then(|name| assertion.attributes.get(name))
+            .and_then(|values| values.first())
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn identity(&self, assertion: &SamlAssertion) -> Result<ExternalIdentity, Error> {
+        let subject = match &self.config.subject_attribute {
+            Some(_) => self.first_value(assertion, &self.config.subject_attribute),
+            None => Some(assertion.name_id.as_str()).filter(|name_id| !name_id.is_empty()),
+        }
+        .ok_or_else(|| invalid_data("The assertion does not identify the user"))?;
+
+        let name_id_email = (assertion.name_id_format.as_deref() == Some(NAME_ID_EMAIL))
+            .then_some(assertion.name_id.as_str());
+        let email = match &self.config.email_attribute {
+            Some(_) => self.first_value(assertion, &self.config.email_attribute),
+            None => name_id_email,
+        }
+        .filter(|email| email.contains('@'))
+        .map(str::to_lowercase);
+
+        Ok(ExternalIdentity {
+            provider: self.identity_provider(),
+            subject: subject.to_owned(),
+            email_verified: email.is_some() && self.config.trust_email,
+            email,
+            name: self
+                .first_value(assertion, &self.config.name_attribute)
+                .map(str::to_owned),
+        })
+    }
+
+    /// Roles of a user who had `current` ones, once those of `role_mapping` follow the
+    /// assertion.
+    pub fn roles(&self, current: &[String], assertion: &SamlAssertion) -> Vec<String> {
+        let values = self
+            .config
+            .role_attribute
+            .as_ref()
+            .and_then(|name| assertion.attributes.get(name))
+            .into_iter()
+            .flatten()
+            .map(String::as_str);
+        sync_mapped_roles(current, &self.config.role_mapping, values)
+    }
+}
+
+#[derive(Deserialize)]
+struct SamlConfig {
+    #[serde(default)]
+    entity_id: Option<String>,
+    providers: Vec<SamlProviderConfig>,
+}
+
+/// The configured SAML identity providers, in the order they are offered to users.
+#[derive(Default)]
+pub struct SamlProviders {
+    entity_id: Option<String>,
+    providers: Vec<SamlProvider>,
+}
+
+impl SamlProviders {
+    pub fn from_json(json: &str) -> Result<SamlProviders, Error> {
+        let config: SamlConfig =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        let mut providers: Vec<SamlProvider> = Vec::new();
+        for provider in config.providers {
+            if providers.iter().any(|p| p.name() == provider.name) {
+                return Err(invalid_input(format!(
+                    "Duplicated SAML provider: {}",
+                    provider.name
+                )));
+            }
+            providers.push(SamlProvider::new(provider)?);
+        }
+
+        Ok(SamlProviders {
+            entity_id: config.entity_id,
+            providers,
+        })
+    }
+
+    /// Load the providers from `SAML_PROVIDERS_FILE`, none when the default file is missing.
+    pub fn from_env() -> Result<SamlProviders, Error> {
+        match std::env::var("SAML_PROVIDERS_FILE") {
+            Ok(path) => SamlProviders::from_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("saml_providers.json") {
+                Ok(json) => SamlProviders::from_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(SamlProviders::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SamlProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SamlProvider> {
+        self.providers.iter()
+    }
+
+    pub fn entity_id(&self, base_url: &str) -> String {
+        self.entity_id
+            .clone()
+            .unwrap_or_else(|| format!("{}/saml/metadata", base_url))
+    }
+
+    /// Metadata of the service provider, with an assertion consumer service per provider.
+    pub fn metadata(&self, base_url: &str) -> String {
+        let consumers: String = self
+            .providers
+            .iter()
+            .enumerate()
+            .map(|(index, provider)| {
+                format!(
+                    "<md:AssertionConsumerService Binding=\"{}\" Location=\"{}\" index=\"{}\"/>",
+                    BINDING_HTTP_POST,
+                    escape(acs_url(base_url, provider)),
+                    index
+                )
+            })
+            .collect();
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <md:EntityDescriptor xmlns:md=\"{}\" entityID=\"{}\">\
+            <md:SPSSODescriptor AuthnRequestsSigned=\"false\" WantAssertionsSigned=\"true\" \
+            protocolSupportEnumeration=\"{}\">\
+            <md:NameIDFormat>{}</md:NameIDFormat>{}\
+            </md:SPSSODescriptor></md:EntityDescriptor>\n",
+            NS_METADATA,
+            escape(self.entity_id(base_url)),
+            NS_PROTOCOL,
+            NAME_ID_EMAIL,
+            consumers
+        )
+    }
+}
+
+/// Assertion consumer service of a provider, its responses are posted to it.
+pub fn acs_url(base_url: &str, provider: &SamlProvider) -> String {
+    format!("{}/saml/{}/acs", base_url, provider.name())
+}
@@ -0,0 +1,820 @@
+//! SCIM 2.0 provisioning (RFC 7643 and RFC 7644) of users and groups by identity providers
+//! such as Okta or Azure AD.
+//!
+//! Provisioning clients are configured in a JSON file (`SCIM_CLIENTS_FILE`, `scim_clients.json`
+//! by default), each authenticating with its own bearer token, of which only the SHA-256 is
+//! configured :
+//!
+//! ```json
+//! {
+//!   "clients": [
+//!     {
+//!       "name": "okta",
+//!       "token_sha256": "<hex encoded SHA-256 of the token>",
+//!       "group_mapping": { "Administrators": "ROLE_ADMIN" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - users are those of `public.user`, `userName` being their email. Deleting a user, or
+//!   setting `active` to false, soft deletes them
+//! - groups belong to the client which pushed them, members of the groups of `group_mapping`
+//!   are given its roles, which follow the memberships
+
+use crate::config::roles::{sync_mapped_roles, Role};
+use crate::services::identity_provider::valid_provider_name;
+use crate::services::oauth::hash_token;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+pub const SCHEMA_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const SCHEMA_GROUP: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const SCHEMA_LIST_RESPONSE: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const SCHEMA_PATCH_OP: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const SCHEMA_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const SCHEMA_SERVICE_PROVIDER_CONFIG: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+pub const SCHEMA_RESOURCE_TYPE: &str = "urn:ietf:params:scim:schemas:core:2.0:ResourceType";
+pub const SCHEMA_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Schema";
+
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+fn invalid_input(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidInput, message.into())
+}
+
+/// Error of RFC 7644 section 3.12, `scim_type` detailing a bad request.
+#[derive(Debug, PartialEq)]
+pub struct ScimError {
+    pub status: u16,
+    pub scim_type: Option<&'static str>,
+    pub detail: String,
+}
+
+impl ScimError {
+    pub fn bad_request(scim_type: &'static str, detail: impl Into<String>) -> ScimError {
+        ScimError {
+            status: 400,
+            scim_type: Some(scim_type),
+            detail: detail.into(),
+        }
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> ScimError {
+        ScimError {
+            status: 404,
+            scim_type: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn conflict(detail: impl Into<String>) -> ScimError {
+        ScimError {
+            status: 409,
+            scim_type: Some("uniqueness"),
+            detail: detail.into(),
+        }
+    }
+
+    pub fn body(&self) -> Value {
+        let mut body = json!({
+            "schemas": [SCHEMA_ERROR],
+            "status": self.status.to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = json!(scim_type);
+        }
+        body
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScimClientConfig {
+    pub name: String,
+    pub token_sha256: String,
+    /// Roles given to the members of the groups, by display name.
+    #[serde(default)]
+    pub group_mapping: HashMap<String, String>,
+}
+
+pub struct ScimClient {
+    config: ScimClientConfig,
+    token_hash: Vec<u8>,
+}
+
+impl ScimClient {
+    pub fn new(config: ScimClientConfig) -> Result<ScimClient, Error> {
+        if !valid_provider_name(&config.name) {
+            return Err(invalid_input(format!(
+                "Invalid SCIM client name: {}",
+                config.name
+            )));
+        }
+        let token_hash = hex::decode(&config.token_sha256)
+            .ok()
+            .filter(|hash| hash.len() == 32)
+            .ok_or_else(|| {
+                invalid_input(format!(
+                    "The token_sha256 of the SCIM client {} is not a hex encoded SHA-256",
+                    config.name
+                ))
+            })?;
+        for role in config.group_mapping.values() {
+            Role::from_str(role)
+                .map_err(|_| invalid_input(format!("Invalid role {} of a SCIM group", role)))?;
+        }
+
+        Ok(ScimClient { config, token_hash })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Roles of a user who had `current` ones, once those of `group_mapping` follow the
+    /// groups of the client they are a member of.
+    pub fn roles<'a>(
+        &self,
+        current: &[String],
+        groups: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<String> {
+        sync_mapped_roles(current, &self.config.group_mapping, groups)
+    }
+}
+
+#[derive(Deserialize)]
+struct ScimConfig {
+    #[serde(default)]
+    clients: Vec<ScimClientConfig>,
+}
+
+#[derive(Default)]
+pub struct ScimClients {
+    clients: Vec<ScimClient>,
+}
+
+impl ScimClients {
+    pub fn from_json(json: &str) -> Result<ScimClients, Error> {
+        let config: ScimConfig =
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        let mut clients: Vec<ScimClient> = Vec::new();
+        for client in config.clients {
+            if clients.iter().any(|c| c.name() == client.name) {
+                return Err(invalid_input(format!(
+                    "Duplicated SCIM client: {}",
+                    client.name
+                )));
+            }
+            clients.push(ScimClient::new(client)?);
+        }
+
+        Ok(ScimClients { clients })
+    }
+
+    /// Load the clients from `SCIM_CLIENTS_FILE`, none when the default file is missing.
+    pub fn from_env() -> Result<ScimClients, Error> {
+        match std::env::var("SCIM_CLIENTS_FILE") {
+            Ok(path) => ScimClients::from_json(&std::fs::read_to_string(path)?),
+            Err(_) => match std::fs::read_to_string("scim_clients.json") {
+                Ok(json) => ScimClients::from_json(&json),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(ScimClients::default()),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    /// Client whose token the `Authorization` header bears.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Option<&ScimClient> {
+        let token = authorization?.strip_prefix("Bearer ")?.trim();
+        if token.is_empty() {
+            return None;
+        }
+        let hash = hex::decode(hash_token(token)).ok()?;
+        self.clients.iter().find(|client| {
+            ring::constant_time::verify_slices_are_equal(&client.token_hash, &hash).is_ok()
+        })
+    }
+}
+
+/// Filter of a list, only `attribute eq "value"` being supported.
+#[derive(Debug, PartialEq)]
+pub struct Filter {
+    /// Lowercased, attribute names are case-insensitive.
+    pub attribute: String,
+    pub value: String,
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> Result<Filter, ScimError> {
+        let unsupported =
+            || ScimError::bad_request("invalidFilter", format!("Unsupported filter: {}", filter));
+
+        let (attribute, rest) = filter
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(unsupported)?;
+        let (operator, value) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .ok_or_else(unsupported)?;
+        if !operator.eq_ignore_ascii_case("eq") {
+            return Err(unsupported());
+        }
+        // Values are JSON strings, with their escapes.
+        let value: String = serde_json::from_str(value.trim()).map_err(|_| unsupported())?;
+
+        Ok(Filter {
+            attribute: attribute.to_ascii_lowercase(),
+            value,
+        })
+    }
+}
+
+/// Query of a list : filter, pagination from the 1-based `startIndex`, and attributes left
+/// out of the resources.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+    pub excluded_attributes: Option<String>,
+}
+
+impl ListQuery {
+    pub fn filter(&self) -> Result<Option<Filter>, ScimError> {
+        self.filter.as_deref().map(Filter::parse).transpose()
+    }
+
+    pub fn start_index(&self) -> i64 {
+        self.start_index.unwrap_or(1).max(1)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.start_index() - 1
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.count
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(0, MAX_PAGE_SIZE)
+    }
+
+    pub fn excludes(&self, attribute: &str) -> bool {
+        self.excluded_attributes.as_deref().is_some_and(|excluded| {
+            excluded
+                .split(',')
+                .any(|excluded| excluded.trim().eq_ignore_ascii_case(attribute))
+        })
+    }
+
+    /// `ListResponse` of a page of `resources`, out of `total` ones.
+    pub fn response(&self, resources: Vec<Value>, total: i64) -> Value {
+        json!({
+            "schemas": [SCHEMA_LIST_RESPONSE],
+            "totalResults": total,
+            "startIndex": self.start_index(),
+            "itemsPerPage": resources.len(),
+            "Resources": resources,
+        })
+    }
+}
+
+fn string_value(value: &Value, attribute: &str) -> Result<String, ScimError> {
+    value.as_str().map(str::to_owned).ok_or_else(|| {
+        ScimError::bad_request("invalidValue", format!("{} must be a string", attribute))
+    })
+}
+
+fn optional_string_value(value: &Value, attribute: &str) -> Result<Option<String>, ScimError> {
+    match value {
+        Value::Null => Ok(None),
+        value => string_value(value, attribute).map(Some),
+    }
+}
+
+/// Azure AD sends booleans as strings in its patches.
+fn bool_value(value: &Value, attribute: &str) -> Result<bool, ScimError> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        Value::String(value) if value.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(value) if value.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::bad_request(
+            "invalidValue",
+            format!("{} must be a boolean", attribute),
+        )),
+    }
+}
+
+fn valid_email(email: &str) -> bool {
+    matches!(email.split_once('@'), Some((local, domain)) if !local.is_empty() && !domain.is_empty())
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Name {
+    pub formatted: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Email {
+    pub value: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// User created or replaced by a client.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserResource {
+    pub user_name: String,
+    pub external_id: Option<String>,
+    pub display_name: Option<String>,
+    pub name: Option<Name>,
+    #[serde(default)]
+    pub emails: Vec<Email>,
+    pub active: Option<bool>,
+    pub password: Option<String>,
+}
+
+/// What is kept of a user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserAttributes {
+    /// Lowercased.
+    pub email: String,
+    pub name: Option<String>,
+    pub external_id: Option<String>,
+    pub active: bool,
+}
+
+impl UserResource {
+    /// The email is `userName`, or the primary email when `userName` is not an email.
+    pub fn attributes(&self) -> Result<UserAttributes, ScimError> {
+        let email = if valid_email(self.user_name.trim()) {
+            self.user_name.trim()
+        } else {
+            self.emails
+                .iter()
+                .find(|email| email.primary)
+                .or_else(|| self.emails.first())
+                .map(|email| email.value.trim())
+                .filter(|email| valid_email(email))
+                .ok_or_else(|| {
+                    ScimError::bad_request("invalidValue", "userName must be an email")
+                })?
+        };
+        let name = self.display_name.clone().or_else(|| {
+            let name = self.name.as_ref()?;
+            name.formatted.clone().or_else(|| {
+                let parts: Vec<&str> = [name.given_name.as_deref(), name.family_name.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                Some(parts.join(" ")).filter(|name| !name.is_empty())
+            })
+        });
+
+        Ok(UserAttributes {
+            email: email.to_lowercase(),
+            name,
+            external_id: self.external_id.clone(),
+            active: self.active.unwrap_or(true),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchOp {
+    Add,
+    Replace,
+    Remove,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+impl PatchOperation {
+    /// Operations are case-insensitive, Azure AD capitalizes them.
+    pub fn op(&self) -> Result<PatchOp, ScimError> {
+        match self.op.to_ascii_lowercase().as_str() {
+            "add" => Ok(PatchOp::Add),
+            "replace" => Ok(PatchOp::Replace),
+            "remove" => Ok(PatchOp::Remove),
+            _ => Err(ScimError::bad_request(
+                "invalidSyntax",
+                format!("Unsupported operation: {}", self.op),
+            )),
+        }
+    }
+
+    fn value(&self) -> Result<&Value, ScimError> {
+        self.value
+            .as_ref()
+            .ok_or_else(|| ScimError::bad_request("invalidValue", "The operation has no value"))
+    }
+
+    /// Attributes and their values : the path and the value, or the members of the value
+    /// without path.
+    fn targets(&self) -> Result<Vec<(String, &Value)>, ScimError> {
+        match &self.path {
+            Some(path) => Ok(vec![(
+                path.to_ascii_lowercase(),
+                self.value.as_ref().unwrap_or(&Value::Null),
+            )]),
+            None => match self.value()? {
+                Value::Object(object) => Ok(flatten(object, "")),
+                _ => Err(ScimError::bad_request(
+                    "invalidValue",
+                    "An operation without path has an object value",
+                )),
+            },
+        }
+    }
+}
+
+/// `{"name": {"formatted": ..}}` as `name.formatted`.
+fn flatten<'a>(object: &'a Map<String, Value>, prefix: &str) -> Vec<(String, &'a Value)> {
+    let mut targets = Vec::new();
+    for (key, value) in object {
+        let path = format!("{}{}", prefix, key.to_ascii_lowercase());
+        match value {
+            Value::Object(object) if path == "name" => {
+                targets.extend(flatten(object, "name."));
+            }
+            value => targets.push((path, value)),
+        }
+    }
+    targets
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchRequest {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+impl PatchRequest {
+    fn validate(&self) -> Result<(), ScimError> {
+        if !self.schemas.iter().any(|schema| schema == SCHEMA_PATCH_OP) {
+            return Err(ScimError::bad_request(
+                "invalidSyntax",
+                format!("A patch has the schema {}", SCHEMA_PATCH_OP),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl UserAttributes {
+    /// Apply the operations of a patch. Attributes which are not kept, such as
+    /// `name.givenName` or those of extensions, are ignored.
+    pub fn patch(&mut self, request: &PatchRequest) -> Result<(), ScimError> {
+        request.validate()?;
+
+        for operation in &request.operations {
+            let op = operation.op()?;
+            for (path, value) in operation.targets()? {
+                let remove = op == PatchOp::Remove;
+                match path.as_str() {
+                    "username" if remove => {
+                        return Err(ScimError::bad_request("mutability", "userName is required"))
+                    }
+                    "username" => {
+                        let email = string_value(value, "userName")?;
+                        if !valid_email(email.trim()) {
+                            return Err(ScimError::bad_request(
+                                "invalidValue",
+                                "userName must be an email",
+                            ));
+                        }
+                        self.email = email.trim().to_lowercase();
+                    }
+                    "displayname" | "name.formatted" if remove => self.name = None,
+                    "displayname" | "name.formatted" => {
+                        self.name = optional_string_value(value, &path)?
+                    }
+                    "externalid" if remove => self.external_id = None,
+                    "externalid" => self.external_id = optional_string_value(value, "externalId")?,
+                    "active" if remove => {
+                        return Err(ScimError::bad_request("mutability", "active is required"))
+                    }
+                    "active" => self.active = bool_value(value, "active")?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Member {
+    pub value: String,
+}
+
+/// Group created or replaced by a client.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupResource {
+    pub display_name: String,
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub members: Vec<Member>,
+}
+
+impl GroupResource {
+    pub fn validate(&self) -> Result<(), ScimError> {
+        if self.display_name.trim().is_empty() {
+            return Err(ScimError::bad_request(
+                "invalidValue",
+                "displayName is required",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn member_ids(&self) -> Vec<String> {
+        self.members
+            .iter()
+            .map(|member| member.value.clone())
+            .collect()
+    }
+}
+
+/// Change of the members of a group, by user id.
+#[derive(Debug, PartialEq)]
+pub enum MembersChange {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+    Replace(Vec<String>),
+}
+
+/// Changes of a group patch, members ones in the order of the operations.
+#[derive(Debug, Default, PartialEq)]
+pub struct GroupPatch {
+    pub display_name: Option<String>,
+    pub external_id: Option<Option<String>>,
+    pub members: Vec<MembersChange>,
+}
+
+fn member_ids(value: &Value) -> Result<Vec<String>, ScimError> {
+    let members: Vec<Member> = match value {
+        Value::Null => Vec::new(),
+        Value::Array(_) => serde_json::from_value(value.clone()).map_err(|_| {
+            ScimError::bad_request("invalidValue", "members is a list of { \"value\": id }")
+        })?,
+        _ => {
+            return Err(ScimError::bad_request(
+                "invalidValue",
+                "members is a list of { \"value\": id }",
+            ))
+        }
+    };
+    Ok(members.into_iter().map(|member| member.value).collect())
+}
+
+/// Id of a `members[value eq "id"]` path.
+fn member_path(path: &str) -> Option<Result<String, ScimError>> {
+    let filter = path.strip_prefix("members[")?.strip_suffix(']')?;
+    Some(
+        Filter::parse(filter).and_then(|filter| match filter.attribute.as_str() {
+            "value" => Ok(filter.value),
+            _ => Err(ScimError::bad_request(
+                "invalidPath",
+                format!("Unsupported path: {}", path),
+            )),
+        }),
+    )
+}
+
+impl GroupPatch {
+    pub fn from_request(request: &PatchRequest) -> Result<GroupPatch, ScimError> {
+        request.validate()?;
+
+        let mut patch = GroupPatch::default();
+        for operation in &request.operations {
+            let op = operation.op()?;
+            // Paths are matched lowercased, but ids of a member path keep their case.
+            if let Some(member) = operation.path.as_deref().and_then(member_path) {
+                match op {
+                    PatchOp::Remove => patch.members.push(MembersChange::Remove(vec![member?])),
+                    _ => {
+                        return Err(ScimError::bad_request(
+                            "invalidPath",
+                            "Members are only removed by filter",
+                        ))
+                    }
+                }
+                continue;
+            }
+
+            for (path, value) in operation.targets()? {
+                match (path.as_str(), op) {
+                    ("displayname", PatchOp::Remove) => {
+                        return Err(ScimError::bad_request(
+                            "mutability",
+                            "displayName is required",
+                        ))
+                    }
+                    ("displayname", _) => {
+                        let display_name = string_value(value, "displayName")?;
+                        if display_name.trim().is_empty() {
+                            return Err(ScimError::bad_request(
+                                "invalidValue",
+                                "displayName is required",
+                            ));
+                        }
+                        patch.display_name = Some(display_name);
+                    }
+                    ("externalid", PatchOp::Remove) => patch.external_id = Some(None),
+                    ("externalid", _) => {
+                        patch.external_id = Some(optional_string_value(value, "externalId")?)
+                    }
+                    ("members", PatchOp::Add) => {
+                        patch.members.push(MembersChange::Add(member_ids(value)?))
+                    }
+                    ("members", PatchOp::Replace) => patch
+                        .members
+                        .push(MembersChange::Replace(member_ids(value)?)),
+                    // Without a value, every member is removed.
+                    ("members", PatchOp::Remove) => match value {
+                        Value::Null => patch.members.push(MembersChange::Replace(Vec::new())),
+                        value => patch
+                            .members
+                            .push(MembersChange::Remove(member_ids(value)?)),
+                    },
+                    _ => {}
+                }
+            }
+        }
+        Ok(patch)
+    }
+}
+
+/// Resource of a user, `location` being its URL.
+pub fn user_resource(id: &str, user: &UserAttributes, location: &str) -> Value {
+    let mut resource = json!({
+        "schemas": [SCHEMA_USER],
+        "id": id,
+        "userName": user.email,
+        "emails": [{ "value": user.email, "primary": true, "type": "work" }],
+        "active": user.active,
+        "meta": { "resourceType": "User", "location": location },
+    });
+    if let Some(name) = &user.name {
+        resource["displayName"] = json!(name);
+        resource["name"] = json!({ "formatted": name });
+    }
+    if let Some(external_id) = &user.external_id {
+        resource["externalId"] = json!(external_id);
+    }
+    resource
+}
+
+/// Resource of a group, `members` being left out when `None`.
+pub fn group_resource(
+    id: &str,
+    display_name: &str,
+    external_id: Option<&str>,
+    members: Option<Vec<(String, String)>>,
+    location: &str,
+) -> Value {
+    let mut resource = json!({
+        "schemas": [SCHEMA_GROUP],
+        "id": id,
+        "displayName": display_name,
+        "meta": { "resourceType": "Group", "location": location },
+    });
+    if let Some(external_id) = external_id {
+        resource["externalId"] = json!(external_id);
+    }
+    if let Some(members) = members {
+        resource["members"] = members
+            .into_iter()
+            .map(|(id, email)| json!({ "value": id, "display": email, "type": "User" }))
+            .collect();
+    }
+    resource
+}
+
+/// Features of RFC 7643 section 5 which are supported.
+pub fn service_provider_config(base_url: &str) -> Value {
+    json!({
+        "schemas": [SCHEMA_SERVICE_PROVIDER_CONFIG],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "Bearer token",
+            "description": "Token of the provisioning client",
+            "primary": true,
+        }],
+        "meta": {
+            "resourceType": "ServiceProviderConfig",
+            "location": format!("{}/scim/v2/ServiceProviderConfig", base_url),
+        },
+    })
+}
+
+pub fn resource_types(base_url: &str) -> Vec<Value> {
+    [
+        ("User", "/Users", SCHEMA_USER),
+        ("Group", "/Groups", SCHEMA_GROUP),
+    ]
+    .into_iter()
+    .map(|(name, endpoint, schema)| {
+        json!({
+            "schemas": [SCHEMA_RESOURCE_TYPE],
+            "id": name,
+            "name": name,
+            "endpoint": endpoint,
+            "schema": schema,
+            "meta": {
+                "resourceType": "ResourceType",
+                "location": format!("{}/scim/v2/ResourceTypes/{}", base_url, name),
+            },
+        })
+    })
+    .collect()
+}
+
+fn attribute(name: &str, kind: &str, required: bool, mutability: &str) -> Value {
+    json!({
+        "name": name,
+        "type": kind,
+        "multiValued": false,
+        "required": required,
+        "caseExact": false,
+        "mutability": mutability,
+        "returned": "default",
+        "uniqueness": if name == "userName" { "server" } else { "none" },
+    })
+}
+
+/// Attributes which are kept, of the User and Group schemas.
+pub fn schemas(base_url: &str) -> Vec<Value> {
+    let mut emails = attribute("emails", "complex", false, "readWrite");
+    emails["multiValued"] = json!(true);
+    let mut members = attribute("members", "complex", false, "readWrite");
+    members["multiValued"] = json!(true);
+    let mut password = attribute("password", "string", false, "writeOnly");
+    password["returned"] = json!("never");
+
+    [
+        (
+            SCHEMA_USER,
+            "User",
+            vec![
+                attribute("userName", "string", true, "readWrite"),
+                attribute("displayName", "string", false, "readWrite"),
+                attribute("externalId", "string", false, "readWrite"),
+                attribute("active", "boolean", false, "readWrite"),
+                emails,
+                password,
+            ],
+        ),
+        (
+            SCHEMA_GROUP,
+            "Group",
+            vec![
+                attribute("displayName", "string", true, "readWrite"),
+                attribute("externalId", "string", false, "readWrite"),
+                members,
+            ],
+        ),
+    ]
+    .into_iter()
+    .map(|(id, name, attributes)| {
+        json!({
+            "schemas": [SCHEMA_SCHEMA],
+            "id": id,
+            "name": name,
+            "attributes": attributes,
+            "meta": {
+                "resourceType": "Schema",
+                "location": format!("{}/scim/v2/Schemas/{}", base_url, id),
+            },
+        })
+    })
+    .collect()
+}
@@ -0,0 +1,76 @@
+//! Text messages sent to users, such as one-time codes.
+//!
+//! Messages go through an [`SmsSender`]. The service ships with senders for development and
+//! tests : messages are appended as JSON lines to `SMS_OUTBOX_FILE` when it is set, written
+//! to the log otherwise. Other gateways plug in by implementing the trait.
+
+use serde::Serialize;
+use std::future::Future;
+use std::io::Error;
+use std::pin::Pin;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sms {
+    /// E.164 phone number.
+    pub to: String,
+    pub body: String,
+}
+
+pub type SmsFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+pub trait SmsSender: Send + Sync {
+    fn send<'a>(&'a self, sms: &'a Sms) -> SmsFuture<'a>;
+}
+
+/// Writes the messages to the log, for development.
+pub struct LogSmsSender;
+
+impl SmsSender for LogSmsSender {
+    fn send<'a>(&'a self, sms: &'a Sms) -> SmsFuture<'a> {
+        Box::pin(async move {
+            log::info!("📱 SMS to {} : {}", sms.to, sms.body);
+            Ok(())
+        })
+    }
+}
+
+/// Appends the messages to a file, one JSON object per line.
+pub struct FileSmsSender {
+    path: String,
+}
+
+impl FileSmsSender {
+    pub fn new(path: &str) -> FileSmsSender {
+        FileSmsSender {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl SmsSender for FileSmsSender {
+    fn send<'a>(&'a self, sms: &'a Sms) -> SmsFuture<'a> {
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(sms)?;
+            line.push(b'\n');
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(&line).await?;
+            file.flush().await
+        })
+    }
+}
+
+/// The file sender of `SMS_OUTBOX_FILE`, the log one when it is not set.
+pub fn sms_sender_from_env() -> Box<dyn SmsSender> {
+    match std::env::var("SMS_OUTBOX_FILE") {
+        Ok(path) if !path.is_empty() => Box::new(FileSmsSender::new(&path)),
+        _ => {
+            log::warn!("No SMS gateway is configured, text messages are written to the log");
+            Box::new(LogSmsSender)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! One-time codes sent by SMS, the second factor of `login`.
+//!
+//! Users register a phone number on `PUT /api/v1/me/phone` and confirm it with the code
+//! sent to it on `POST /api/v1/me/phone/verify`. Once it is verified, `login` with a password
+//! answers with a challenge instead of a token and sends a code : the token is issued by
+//! `POST /api/v1/login/mfa` for the challenge and the code. A sign-in link asks for the
+//! code on a page which posts it to `POST /api/v1/login/magic-link/mfa`. The password grant
+//! and the password form of the pages refuse these users, who sign in on `login`.
+//!
+//! Codes expire after [`CODE_TTL_SECONDS`] and allow [`MAX_CODE_ATTEMPTS`] attempts, only
+//! their hash is stored. The codes sent to a user are rate limited.
+
+use crate::services::oauth::{generate_token, hash_token};
+use crate::services::rate_limit::RateLimiter;
+use crate::services::sms::Sms;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{Duration, Instant};
+
+pub const CODE_TTL_SECONDS: i64 = 300;
+pub const MAX_CODE_ATTEMPTS: i32 = 5;
+/// Codes sent to a user within [`RATE_LIMIT_WINDOW`].
+pub const MAX_CODES_PER_USER: usize = 5;
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(900);
+
+pub const PURPOSE_PHONE_VERIFICATION: &str = "phone_verification";
+pub const PURPOSE_LOGIN: &str = "login";
+
+const CODE_LENGTH: usize = 6;
+/// Digits of an E.164 number, country code included.
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+/// E.164 form of a phone number, `+33612345678`, `None` when it is not one. Spaces, dots,
+/// dashes and parentheses are ignored, the country code is required.
+pub fn normalized_phone_number(input: &str) -> Option<String> {
+    let number: String = input
+        .trim()
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '.' | '-' | '(' | ')'))
+        .collect();
+
+    let digits = number.strip_prefix('+')?;
+    let valid = (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits.len())
+        && digits.bytes().all(|c| c.is_ascii_digit())
+        && !digits.starts_with('0');
+    valid.then_some(number)
+}
+
+/// Phone number as shown to whoever signs in, `+3*******78`.
+pub fn masked_phone_number(number: &str) -> String {
+    let chars: Vec<char> = number.chars().collect();
+    if chars.len() <= 4 {
+        return number.to_owned();
+    }
+    let hidden = "*".repeat(chars.len() - 4);
+    format!(
+        "{}{}{}",
+        chars[..2].iter().collect::<String>(),
+        hidden,
+        chars[chars.len() - 2..].iter().collect::<String>()
+    )
+}
+
+/// 6 random digits.
+pub fn generate_code() -> String {
+    let rng = SystemRandom::new();
+    let mut bytes = [0u8; 4];
+    let modulus = 10u32.pow(CODE_LENGTH as u32);
+    // Rejection sampling keeps every code equally likely.
+    let limit = u32::MAX - u32::MAX % modulus;
+
+    loop {
+        rng.fill(&mut bytes)
+            .expect("Unable to generate a random code");
+        let value = u32::from_be_bytes(bytes);
+        if value < limit {
+            return format!("{:0width$}", value % modulus, width = CODE_LENGTH);
+        }
+    }
+}
+
+/// Codes are stored hashed with the user they were sent to.
+pub fn hash_code(user_id: &str, code: &str) -> String {
+    hash_token(&format!("{}:{}", user_id, code.trim()))
+}
+
+pub fn code_matches(code_hash: &str, user_id: &str, code: &str) -> bool {
+    ring::constant_time::verify_slices_are_equal(
+        code_hash.as_bytes(),
+        hash_code(user_id, code).as_bytes(),
+    )
+    .is_ok()
+}
+
+/// Second step of a `login` : the code goes by SMS, the challenge in the answer.
+pub struct PendingLoginChallenge {
+    pub challenge: String,
+    pub code: String,
+}
+
+impl PendingLoginChallenge {
+    pub fn generate() -> PendingLoginChallenge {
+        PendingLoginChallenge {
+            challenge: generate_token(),
+            code: generate_code(),
+        }
+    }
+}
+
+pub struct SmsCodeLimiter {
+    by_user: RateLimiter,
+}
+
+impl Default for SmsCodeLimiter {
+    fn default() -> SmsCodeLimiter {
+        SmsCodeLimiter {
+            by_user: RateLimiter::new(MAX_CODES_PER_USER, RATE_LIMIT_WINDOW),
+        }
+    }
+}
+
+impl SmsCodeLimiter {
+    /// Whether a code may be sent to the user.
+    pub fn allow(&self, user_id: &str, now: Instant) -> bool {
+        self.by_user.hit(user_id, now)
+    }
+}
+
+pub fn verification_sms(to: &str, code: &str) -> Sms {
+    Sms {
+        to: to.to_owned(),
+        body: format!(
+            "{} is your code to verify this phone number. It expires in {} minutes.",
+            code,
+            CODE_TTL_SECONDS / 60
+        ),
+    }
+}
+
+pub fn login_sms(to: &str, code: &str) -> Sms {
+    Sms {
+        to: to.to_owned(),
+        body: format!(
+            "{} is your sign-in code. It expires in {} minutes. Do not share it.",
+            code,
+            CODE_TTL_SECONDS / 60
+        ),
+    }
+}
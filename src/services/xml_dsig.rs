@@ -0,0 +1,533 @@
+//! Just enough XML for SAML : a namespace-aware tree, its exclusive canonicalization
+//! (<https://www.w3.org/TR/xml-exc-c14n/>) and the verification of enveloped XML signatures
+//! (<https://www.w3.org/TR/xmldsig-core1/>).
+//!
+//! Documents with a DTD are refused, entities other than the predefined ones and character
+//! references are never expanded.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{PKey, Public};
+use openssl::sign::Verifier;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Error, ErrorKind};
+
+pub const NS_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+pub const EXCLUSIVE_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+pub const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+pub const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+pub const RSA_SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha384";
+pub const RSA_SHA512: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha512";
+pub const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+pub const SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#sha384";
+pub const SHA512: &str = "http://www.w3.org/2001/04/xmlenc#sha512";
+
+const NS_XML: &str = "http://www.w3.org/XML/1998/namespace";
+const MAX_DEPTH: usize = 64;
+
+fn invalid_data(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub prefix: Option<String>,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub prefix: Option<String>,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Node>,
+    /// Namespaces in scope, by prefix, the default one under `""`.
+    in_scope: BTreeMap<String, String>,
+}
+
+fn split_qname(qname: &str) -> (Option<&str>, &str) {
+    match qname.split_once(':') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, qname),
+    }
+}
+
+/// Line endings are normalized to `\n`, as XML processors do.
+fn normalize_newlines(raw: &str) -> String {
+    raw.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn unescape(raw: &str) -> Result<String, Error> {
+    quick_xml::escape::unescape(raw)
+        .map(|value| value.into_owned())
+        .map_err(|err| invalid_data(err.to_string()))
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(bytes).map_err(|err| invalid_data(err.to_string()))
+}
+
+/// Element of a start tag, its namespaces resolved against those of its parent.
+fn start_element(
+    start: &BytesStart,
+    parent_scope: &BTreeMap<String, String>,
+) -> Result<Element, Error> {
+    let mut in_scope = parent_scope.clone();
+    let mut raw_attributes: Vec<(String, String)> = Vec::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|err| invalid_data(err.to_string()))?;
+        let key = utf8(attribute.key.as_ref())?.to_owned();
+        // Attribute values are normalized before references are expanded.
+        let raw = normalize_newlines(utf8(&attribute.value)?).replace(['\n', '\t'], " ");
+        let value = unescape(&raw)?;
+
+        if key == "xmlns" {
+            in_scope.insert(String::new(), value);
+        } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+            if value.is_empty() {
+                return Err(invalid_data(format!(
+                    "Empty namespace for prefix {}",
+                    prefix
+                )));
+            }
+            in_scope.insert(prefix.to_owned(), value);
+        } else {
+            raw_attributes.push((key, value));
+        }
+    }
+    if in_scope.get("").is_some_and(|uri| uri.is_empty()) {
+        in_scope.remove("");
+    }
+
+    let resolve = |prefix: &str| -> Result<String, Error> {
+        match prefix {
+            "xml" => Ok(NS_XML.to_owned()),
+            _ => in_scope
+                .get(prefix)
+                .cloned()
+                .ok_or_else(|| invalid_data(format!("Unbound namespace prefix {}", prefix))),
+        }
+    };
+
+    let mut attributes: Vec<Attribute> = Vec::new();
+    for (key, value) in raw_attributes {
+        let (prefix, name) = split_qname(&key);
+        let namespace = match prefix {
+            Some(prefix) => Some(resolve(prefix)?),
+            None => None,
+        };
+        attributes.push(Attribute {
+            prefix: prefix.map(str::to_owned),
+            name: name.to_owned(),
+            namespace,
+            value,
+        });
+    }
+
+    let qname = utf8(start.name().as_ref())?.to_owned();
+    let (prefix, name) = split_qname(&qname);
+    let namespace = match prefix {
+        Some(prefix) => Some(resolve(prefix)?),
+        None => in_scope.get("").cloned(),
+    };
+
+    Ok(Element {
+        prefix: prefix.map(str::to_owned),
+        name: name.to_owned(),
+        namespace,
+        attributes,
+        children: Vec::new(),
+        in_scope,
+    })
+}
+
+fn escape_text(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+impl Element {
+    /// Root element of a document.
+    pub fn parse(xml: &str) -> Result<Element, Error> {
+        let mut reader = Reader::from_str(xml);
+        let mut stack: Vec<Element> = Vec::new();
+        let root_scope = BTreeMap::new();
+
+        loop {
+            let event = reader
+                .read_event()
+                .map_err(|err| invalid_data(err.to_string()))?;
+            let scope = stack.last().map_or(&root_scope, |parent| &parent.in_scope);
+            match event {
+                Event::DocType(_) => return Err(invalid_data("Documents with a DTD are refused")),
+                Event::Start(start) => {
+                    if stack.len() >= MAX_DEPTH {
+                        return Err(invalid_data("Document too deep"));
+                    }
+                    let element = start_element(&start, scope)?;
+                    stack.push(element);
+                }
+                Event::Empty(start) => {
+                    let element = start_element(&start, scope)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Ok(element),
+                    }
+                }
+                Event::End(_) => {
+                    let element = stack
+                        .pop()
+                        .ok_or_else(|| invalid_data("Unexpected end tag"))?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Ok(element),
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(parent) = stack.last_mut() {
+                        let text = unescape(&normalize_newlines(utf8(&text)?))?;
+                        parent.children.push(Node::Text(text));
+                    }
+                }
+                Event::CData(text) => {
+                    if let Some(parent) = stack.last_mut() {
+                        let text = normalize_newlines(utf8(&text)?);
+                        parent.children.push(Node::Text(text));
+                    }
+                }
+                Event::Eof => return Err(invalid_data("No root element")),
+                Event::Comment(_) | Event::Decl(_) | Event::PI(_) => {}
+            }
+        }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace.as_deref() == Some(namespace) && self.name == name
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(
+        &'a self,
+        namespace: &'a str,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Element> {
+        self.elements()
+            .filter(move |element| element.is(namespace, name))
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.is(namespace, name))
+    }
+
+    /// Value of an attribute without namespace.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.namespace.is_none() && attribute.name == name)
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    /// Text content of the element, its child elements excluded.
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|child| match child {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect()
+    }
+
+    /// Every element of the tree, this one first.
+    pub fn descendants(&self) -> Vec<&Element> {
+        let mut elements = vec![self];
+        let mut next = 0;
+        while next < elements.len() {
+            elements.extend(elements[next].elements());
+            next += 1;
+        }
+        elements
+    }
+
+    fn qname(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Exclusive canonical form, without comments, of the element and its descendants but
+    /// `excluded`. `inclusive_prefixes` are the prefixes of the `InclusiveNamespaces`
+    /// parameter, `#default` standing for the default namespace.
+    pub fn exclusive_c14n(
+        &self,
+        excluded: Option<&Element>,
+        inclusive_prefixes: &[String],
+    ) -> String {
+        let mut out = String::new();
+        self.write_c14n(&mut out, &BTreeMap::new(), excluded, inclusive_prefixes);
+        out
+    }
+
+    fn write_c14n(
+        &self,
+        out: &mut String,
+        rendered: &BTreeMap<String, String>,
+        excluded: Option<&Element>,
+        inclusive_prefixes: &[String],
+    ) {
+        // Namespaces visibly utilized by the element and its attributes.
+        let mut used: BTreeSet<&str> = BTreeSet::new();
+        used.insert(self.prefix.as_deref().unwrap_or(""));
+        for attribute in &self.attributes {
+            if let Some(prefix) = attribute.prefix.as_deref() {
+                used.insert(prefix);
+            }
+        }
+        for prefix in inclusive_prefixes {
+            let prefix = if prefix == "#default" {
+                ""
+            } else {
+                prefix.as_str()
+            };
+            if self.in_scope.contains_key(prefix) {
+                used.insert(prefix);
+            }
+        }
+        used.remove("xml");
+
+        let mut output_scope = rendered.clone();
+        out.push('<');
+        out.push_str(&self.qname());
+        for prefix in used {
+            let uri = self.in_scope.get(prefix).map_or("", String::as_str);
+            let already_rendered = match prefix {
+                "" => rendered.get("").map_or("", String::as_str) == uri,
+                _ => rendered.get(prefix).map(String::as_str) == Some(uri),
+            };
+            if already_rendered {
+                continue;
+            }
+            match prefix {
+                "" => out.push_str(" xmlns=\""),
+                _ => {
+                    out.push_str(" xmlns:");
+                    out.push_str(prefix);
+                    out.push_str("=\"");
+                }
+            }
+            escape_attribute(uri, out);
+            out.push('"');
+            output_scope.insert(prefix.to_owned(), uri.to_owned());
+        }
+
+        let mut attributes: Vec<&Attribute> = self.attributes.iter().collect();
+        attributes.sort_by(|a, b| {
+            (a.namespace.as_deref().unwrap_or(""), &a.name)
+                .cmp(&(b.namespace.as_deref().unwrap_or(""), &b.name))
+        });
+        for attribute in attributes {
+            out.push(' ');
+            if let Some(prefix) = &attribute.prefix {
+                out.push_str(prefix);
+                out.push(':');
+            }
+            out.push_str(&attribute.name);
+            out.push_str("=\"");
+            escape_attribute(&attribute.value, out);
+            out.push('"');
+        }
+        out.push('>');
+
+        for child in &self.children {
+            match child {
+                Node::Text(text) => escape_text(text, out),
+                Node::Element(element) => {
+                    if excluded.is_some_and(|excluded| std::ptr::eq(excluded, element)) {
+                        continue;
+                    }
+                    element.write_c14n(out, &output_scope, excluded, inclusive_prefixes);
+                }
+            }
+        }
+
+        out.push_str("</");
+        out.push_str(&self.qname());
+        out.push('>');
+    }
+}
+
+/// `PrefixList` of the `InclusiveNamespaces` parameter of a transform.
+fn inclusive_prefixes(transform: &Element) -> Vec<String> {
+    transform
+        .child(EXCLUSIVE_C14N, "InclusiveNamespaces")
+        .and_then(|parameter| parameter.attribute("PrefixList"))
+        .map(|list| list.split_whitespace().map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+fn algorithm(element: Option<&Element>) -> Result<&str, Error> {
+    element
+        .and_then(|element| element.attribute("Algorithm"))
+        .ok_or_else(|| invalid_data("Missing signature algorithm"))
+}
+
+fn digest_of(method: &str) -> Result<MessageDigest, Error> {
+    match method {
+        SHA256 | RSA_SHA256 => Ok(MessageDigest::sha256()),
+        SHA384 | RSA_SHA384 => Ok(MessageDigest::sha384()),
+        SHA512 | RSA_SHA512 => Ok(MessageDigest::sha512()),
+        _ => Err(invalid_data(format!("Unsupported algorithm {}", method))),
+    }
+}
+
+fn base64_content(element: Option<&Element>) -> Result<Vec<u8>, Error> {
+    let text: String = element
+        .map(Element::text)
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect();
+    STANDARD
+        .decode(text)
+        .map_err(|err| invalid_data(err.to_string()))
+}
+
+/// Verify the `ds:Signature` child of `element` : it must sign `element` itself, referenced
+/// by its `id_attribute`, with exclusive canonicalization and an RSA key. Any key carried by
+/// the signature is ignored, only `public_key` is trusted.
+pub fn verify_enveloped_signature(
+    element: &Element,
+    id_attribute: &str,
+    public_key: &PKey<Public>,
+) -> Result<(), Error> {
+    let mut signatures = element.children_named(NS_DSIG, "Signature");
+    let signature = signatures
+        .next()
+        .ok_or_else(|| invalid_data("Missing signature"))?;
+    if signatures.next().is_some() {
+        return Err(invalid_data("More than one signature"));
+    }
+
+    let signed_info = signature
+        .child(NS_DSIG, "SignedInfo")
+        .ok_or_else(|| invalid_data("Missing SignedInfo"))?;
+    let c14n_method = signed_info
+        .child(NS_DSIG, "CanonicalizationMethod")
+        .ok_or_else(|| invalid_data("Missing canonicalization method"))?;
+    if algorithm(Some(c14n_method))? != EXCLUSIVE_C14N {
+        return Err(invalid_data(
+            "SignedInfo must use exclusive canonicalization",
+        ));
+    }
+    let signature_method = algorithm(signed_info.child(NS_DSIG, "SignatureMethod"))?;
+    if !matches!(signature_method, RSA_SHA256 | RSA_SHA384 | RSA_SHA512) {
+        return Err(invalid_data(format!(
+            "Unsupported signature algorithm {}",
+            signature_method
+        )));
+    }
+
+    let mut references = signed_info.children_named(NS_DSIG, "Reference");
+    let reference = references
+        .next()
+        .ok_or_else(|| invalid_data("Missing signature reference"))?;
+    if references.next().is_some() {
+        return Err(invalid_data("More than one signature reference"));
+    }
+    let id = element
+        .attribute(id_attribute)
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| invalid_data("The signed element has no ID"))?;
+    if reference.attribute("URI") != Some(&format!("#{}", id)) {
+        return Err(invalid_data(
+            "The signature does not reference the signed element",
+        ));
+    }
+
+    let mut enveloped = false;
+    let mut prefixes: Option<Vec<String>> = None;
+    for transform in reference
+        .child(NS_DSIG, "Transforms")
+        .into_iter()
+        .flat_map(|transforms| transforms.children_named(NS_DSIG, "Transform"))
+    {
+        match algorithm(Some(transform))? {
+            ENVELOPED_SIGNATURE => enveloped = true,
+            EXCLUSIVE_C14N => prefixes = Some(inclusive_prefixes(transform)),
+            other => return Err(invalid_data(format!("Unsupported transform {}", other))),
+        }
+    }
+    let prefixes = prefixes
+        .ok_or_else(|| invalid_data("The reference must use exclusive canonicalization"))?;
+    if !enveloped {
+        return Err(invalid_data("The signature must be enveloped"));
+    }
+
+    let digest_method = digest_of(algorithm(reference.child(NS_DSIG, "DigestMethod"))?)?;
+    let canonical = element.exclusive_c14n(Some(signature), &prefixes);
+    let digest = hash(digest_method, canonical.as_bytes())?;
+    let expected = base64_content(reference.child(NS_DSIG, "DigestValue"))?;
+    if ring::constant_time::verify_slices_are_equal(&digest, &expected).is_err() {
+        return Err(invalid_data(
+            "The digest of the signed element does not match",
+        ));
+    }
+
+    let canonical_signed_info = signed_info.exclusive_c14n(None, &inclusive_prefixes(c14n_method));
+    let signature_value = base64_content(signature.child(NS_DSIG, "SignatureValue"))?;
+    let mut verifier = Verifier::new(digest_of(signature_method)?, public_key)?;
+    verifier.update(canonical_signed_info.as_bytes())?;
+    match verifier.verify(&signature_value) {
+        Ok(true) => Ok(()),
+        _ => Err(invalid_data("Invalid signature")),
+    }
+}
+
+/// Every `ID` must be unique, otherwise a signature could cover another element than the
+/// one which is read.
+pub fn has_unique_ids(root: &Element, id_attribute: &str) -> bool {
+    let mut ids: BTreeSet<&str> = BTreeSet::new();
+    root.descendants()
+        .into_iter()
+        .filter_map(|element| element.attribute(id_attribute))
+        .all(|id| ids.insert(id))
+}
@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::Arc;
+use auth_api::config::roles::Role;
+use auth_api::grpc::envoy::authorization_client::AuthorizationClient;
+use auth_api::grpc::envoy::authorization_server::AuthorizationServer;
+use auth_api::grpc::envoy::{
+    AttributeContext, CheckRequest, HttpRequest, HttpResponse, Request, RPC_INVALID_ARGUMENT,
+    RPC_OK, RPC_PERMISSION_DENIED, RPC_UNAUTHENTICATED,
+};
+use auth_api::grpc::ext_authz::ExtAuthzServer;
+use auth_api::services::access_control::{SubjectGrants, TokenAuthority};
+use auth_api::services::forward_auth::ForwardAuthRules;
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+
+/// Accepts `admin-token` and `user-token` without touching the database.
+struct StubAuthority;
+
+impl TokenAuthority for StubAuthority {
+    fn resolve_token<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<SubjectGrants, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let (user_id, subject, roles) = match token {
+                "admin-token" => ("1", "admin@example.com", vec![Role::ADMIN]),
+                "user-token" => ("2", "user@example.com", vec![Role::USER]),
+                _ => return Err(Error::new(ErrorKind::InvalidData, "Unauthorized")),
+            };
+            Ok(SubjectGrants {
+                user_id: user_id.to_owned(),
+                subject: subject.to_owned(),
+                roles,
+                permissions: HashSet::new(),
+                scopes: HashSet::new(),
+            })
+        })
+    }
+}
+
+async fn start_server() -> AuthorizationClient<Channel> {
+    let rules = ForwardAuthRules::from_json(
+        r#"{ "rules": [
+            { "path_prefix": "/health", "public": true },
+            { "path_prefix": "/admin", "roles": ["ROLE_ADMIN"] }
+        ] }"#,
+    )
+    .unwrap();
+    let server = ExtAuthzServer::new(Arc::new(StubAuthority), Arc::new(rules));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(AuthorizationServer::new(server))
+            .serve_with_incoming(incoming),
+    );
+
+    AuthorizationClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn check_request(method: &str, path: &str, headers: &[(&str, &str)]) -> CheckRequest {
+    CheckRequest {
+        attributes: Some(AttributeContext {
+            request: Some(Request {
+                http: Some(HttpRequest {
+                    method: method.to_owned(),
+                    path: path.to_owned(),
+                    headers: headers
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect::<HashMap<_, _>>(),
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        }),
+    }
+}
+
+#[tokio::test]
+async fn test_ext_authz_allows_with_identity_headers() {
+    let mut client = start_server().await;
+
+    let res = client
+        .check(check_request("GET", "/admin/users", &[("authorization", "Bearer admin-token")]))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(res.status.unwrap().code, RPC_OK);
+    let headers = match res.http_response {
+        Some(HttpResponse::OkResponse(ok)) => ok.headers,
+        _ => panic!("expected an ok response"),
+    };
+    let headers: HashMap<String, String> = headers
+        .into_iter()
+        .filter_map(|h| h.header)
+        .map(|h| (h.key, h.value))
+        .collect();
+    assert_eq!(headers["x-auth-user-id"], "1");
+    assert_eq!(headers["x-auth-email"], "admin@example.com");
+    assert_eq!(headers["x-auth-roles"], "ROLE_ADMIN");
+}
+
+#[tokio::test]
+async fn test_ext_authz_reads_cookie() {
+    let mut client = start_server().await;
+
+    let res = client
+        .check(check_request("GET", "/profile", &[("cookie", "theme=dark; Authorization=user-token")]))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(res.status.unwrap().code, RPC_OK);
+}
+
+#[tokio::test]
+async fn test_ext_authz_denies() {
+    let mut client = start_server().await;
+
+    let forbidden = client
+        .check(check_request("GET", "/admin", &[("authorization", "user-token")]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(forbidden.status.unwrap().code, RPC_PERMISSION_DENIED);
+    match forbidden.http_response {
+        Some(HttpResponse::DeniedResponse(denied)) => assert_eq!(denied.status.unwrap().code, 403),
+        _ => panic!("expected a denied response"),
+    }
+
+    let unauthenticated = client
+        .check(check_request("GET", "/admin", &[("authorization", "Bearer forged")]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(unauthenticated.status.unwrap().code, RPC_UNAUTHENTICATED);
+
+    let missing = client
+        .check(check_request("GET", "/profile", &[]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(missing.status.unwrap().code, RPC_UNAUTHENTICATED);
+}
+
+#[tokio::test]
+async fn test_ext_authz_public_path_strips_identity_headers() {
+    let mut client = start_server().await;
+
+    let res = client
+        .check(check_request("GET", "/health", &[("x-auth-user-id", "1")]))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(res.status.unwrap().code, RPC_OK);
+    match res.http_response {
+        Some(HttpResponse::OkResponse(ok)) => {
+            assert!(ok.headers.is_empty());
+            assert!(ok.headers_to_remove.contains(&String::from("x-auth-user-id")));
+        }
+        _ => panic!("expected an ok response"),
+    }
+}
+
+#[tokio::test]
+async fn test_ext_authz_non_canonical_paths_do_not_bypass_rules() {
+    let mut client = start_server().await;
+
+    for path in ["//admin", "/%61dmin", "/x/../admin", "/health/../admin"] {
+        let forbidden = client
+            .check(check_request("GET", path, &[("authorization", "user-token")]))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(forbidden.status.unwrap().code, RPC_PERMISSION_DENIED, "{}", path);
+
+        let allowed = client
+            .check(check_request("GET", path, &[("authorization", "admin-token")]))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(allowed.status.unwrap().code, RPC_OK, "{}", path);
+    }
+
+    let unauthenticated = client
+        .check(check_request("GET", "/health/../admin", &[]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(unauthenticated.status.unwrap().code, RPC_UNAUTHENTICATED);
+
+    let invalid = client
+        .check(check_request("GET", "/health%2F..%2Fadmin", &[("authorization", "admin-token")]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(invalid.status.unwrap().code, RPC_INVALID_ARGUMENT);
+    match invalid.http_response {
+        Some(HttpResponse::DeniedResponse(denied)) => assert_eq!(denied.status.unwrap().code, 400),
+        _ => panic!("expected a denied response"),
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::Arc;
+use auth_api::config::roles::Role;
+use auth_api::grpc::auth::auth_service_client::AuthServiceClient;
+use auth_api::grpc::auth::auth_service_server::AuthServiceServer;
+use auth_api::grpc::auth::{
+    AuthGrpcServer, CheckRolesRequest, DirectoryFuture, GetUserRequest, ListUsersRequest, Lookup,
+    User, UserDirectory, VerifyTokenRequest,
+};
+use auth_api::services::access_control::{SubjectGrants, TokenAuthority};
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request};
+
+struct StubAuthority;
+
+impl TokenAuthority for StubAuthority {
+    fn resolve_token<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<SubjectGrants, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let (user_id, roles, permissions) = match token {
+                "admin-token" => ("u01", vec![Role::ADMIN], vec!["user:read"]),
+                "user-token" => ("u02", vec![Role::USER], vec![]),
+                _ => return Err(Error::new(ErrorKind::InvalidData, "Unauthorized")),
+            };
+            Ok(SubjectGrants {
+                user_id: user_id.to_owned(),
+                subject: format!("{}@example.com", user_id),
+                roles,
+                permissions: permissions.into_iter().map(String::from).collect::<HashSet<_>>(),
+                scopes: HashSet::new(),
+            })
+        })
+    }
+}
+
+struct StubDirectory(Vec<User>);
+
+impl UserDirectory for StubDirectory {
+    fn find_by_id<'a>(&'a self, id: &'a str) -> DirectoryFuture<'a, User> {
+        Box::pin(async move {
+            self.0.iter().find(|u| u.id == id).cloned().ok_or(sqlx::Error::RowNotFound)
+        })
+    }
+
+    fn find_by_email<'a>(&'a self, email: &'a str) -> DirectoryFuture<'a, User> {
+        Box::pin(async move {
+            self.0.iter().find(|u| u.email == email).cloned().ok_or(sqlx::Error::RowNotFound)
+        })
+    }
+
+    fn list<'a>(&'a self, limit: i64, after_id: Option<&'a str>) -> DirectoryFuture<'a, Vec<User>> {
+        Box::pin(async move {
+            Ok(self
+                .0
+                .iter()
+                .filter(|u| after_id.map(|after| u.id.as_str() > after).unwrap_or(true))
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        })
+    }
+}
+
+async fn start_server() -> AuthServiceClient<Channel> {
+    let users = (1..=5)
+        .map(|i| User {
+            id: format!("u{:02}", i),
+            email: format!("u{:02}@example.com", i),
+            roles: vec![String::from("ROLE_USER")],
+            organization: format!("org-{}", i),
+        })
+        .collect();
+    let server = AuthGrpcServer::new(Arc::new(StubAuthority), Arc::new(StubDirectory(users)));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(AuthServiceServer::new(server))
+            .serve_with_incoming(incoming),
+    );
+
+    AuthServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn with_token<T>(message: T, token: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn test_verify_token() {
+    let mut client = start_server().await;
+
+    let valid = client
+        .verify_token(VerifyTokenRequest { token: String::from("admin-token") })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(valid.valid);
+    let user = valid.user.unwrap();
+    assert_eq!(user.roles, vec!["ROLE_ADMIN"]);
+
+    let same_user = client
+        .get_user(with_token(
+            GetUserRequest { lookup: Some(Lookup::Id(user.id.clone())) },
+            "admin-token",
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(user.organization, "org-1");
+    assert_eq!(user.organization, same_user.organization);
+
+    let invalid = client
+        .verify_token(VerifyTokenRequest { token: String::from("forged") })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!invalid.valid);
+    assert!(invalid.user.is_none());
+}
+
+#[tokio::test]
+async fn test_check_roles() {
+    let mut client = start_server().await;
+
+    let res = client
+        .check_roles(CheckRolesRequest {
+            token: String::from("user-token"),
+            roles: vec![String::from("ROLE_ADMIN"), String::from("ROLE_USER")],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(res.allowed);
+
+    let res = client
+        .check_roles(CheckRolesRequest {
+            token: String::from("user-token"),
+            roles: vec![String::from("ROLE_ADMIN")],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!res.allowed);
+
+    let err = client
+        .check_roles(CheckRolesRequest {
+            token: String::from("user-token"),
+            roles: vec![String::from("admin")],
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_get_user_requires_permission() {
+    let mut client = start_server().await;
+
+    let user = client
+        .get_user(with_token(
+            GetUserRequest { lookup: Some(Lookup::Email(String::from("u03@example.com"))) },
+            "admin-token",
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(user.id, "u03");
+
+    let err = client
+        .get_user(with_token(
+            GetUserRequest { lookup: Some(Lookup::Id(String::from("u03"))) },
+            "user-token",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::PermissionDenied);
+
+    let err = client
+        .get_user(GetUserRequest { lookup: Some(Lookup::Id(String::from("u03"))) })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::Unauthenticated);
+
+    let err = client
+        .get_user(with_token(
+            GetUserRequest { lookup: Some(Lookup::Id(String::from("u42"))) },
+            "admin-token",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_list_users_pagination() {
+    let mut client = start_server().await;
+
+    let first = client
+        .list_users(with_token(ListUsersRequest { page_size: 3, page_token: String::new() }, "admin-token"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(first.users.len(), 3);
+    assert_eq!(first.next_page_token, "u03");
+
+    let second = client
+        .list_users(with_token(
+            ListUsersRequest { page_size: 3, page_token: first.next_page_token },
+            "admin-token",
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(second.users.len(), 2);
+    assert!(second.next_page_token.is_empty());
+}
@@ -0,0 +1,359 @@
+use std::sync::Mutex;
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use auth_api::services::crypto::SigningKey;
+use auth_api::services::identity_provider::{
+    safe_return_to, ExternalIdentity, IdentityProviders, PendingLogin,
+};
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use serde_json::{json, Value};
+
+const CLIENT_ID: &str = "auth-api";
+const CLIENT_SECRET: &str = "provider-secret";
+const REDIRECT_URI: &str = "https://auth.example.com/oauth/providers/mock/callback";
+
+fn generate_key() -> SigningKey {
+    let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", der.as_ref()));
+    SigningKey::from_pem(pem.as_bytes()).unwrap()
+}
+
+/// What the mock provider answers, changed by each test.
+struct MockState {
+    issuer: String,
+    key: SigningKey,
+    /// Key the ID tokens are signed with, the published one by default.
+    signing_key: Option<SigningKey>,
+    id_token_claims: Value,
+    userinfo: Value,
+    discovered_issuer: Option<String>,
+}
+
+async fn discovery(state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    let state = state.lock().unwrap();
+    HttpResponse::Ok().json(json!({
+        "issuer": state.discovered_issuer.clone().unwrap_or_else(|| state.issuer.clone()),
+        "authorization_endpoint": format!("{}/authorize", state.issuer),
+        "token_endpoint": format!("{}/token", state.issuer),
+        "userinfo_endpoint": format!("{}/userinfo", state.issuer),
+        "jwks_uri": format!("{}/jwks", state.issuer),
+    }))
+}
+
+async fn jwks(state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    let state = state.lock().unwrap();
+    HttpResponse::Ok().json(JwkSet {
+        keys: vec![state.key.jwk()],
+    })
+}
+
+async fn token(
+    state: web::Data<Mutex<MockState>>,
+    form: web::Form<Vec<(String, String)>>,
+) -> HttpResponse {
+    let param = |name: &str| {
+        form.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    if param("grant_type") != Some("authorization_code")
+        || param("code") != Some("code-1")
+        || param("client_id") != Some(CLIENT_ID)
+        || param("client_secret") != Some(CLIENT_SECRET)
+        || param("redirect_uri") != Some(REDIRECT_URI)
+        || param("code_verifier").is_none()
+    {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+
+    let state = state.lock().unwrap();
+    let key = state.signing_key.as_ref().unwrap_or(&state.key);
+    HttpResponse::Ok().json(json!({
+        "access_token": "access-1",
+        "token_type": "Bearer",
+        "id_token": key.sign(&state.id_token_claims).unwrap(),
+    }))
+}
+
+async fn userinfo(state: web::Data<Mutex<MockState>>, req: HttpRequest) -> HttpResponse {
+    match req.headers().get("Authorization").map(|v| v.to_str()) {
+        Some(Ok("Bearer access-1")) => HttpResponse::Ok().json(&state.lock().unwrap().userinfo),
+        _ => HttpResponse::Unauthorized().finish(),
+    }
+}
+
+/// Serves a provider on the loopback, its issuer being its URL.
+fn start_provider() -> (String, web::Data<Mutex<MockState>>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let now = Utc::now().timestamp();
+
+    let state = web::Data::new(Mutex::new(MockState {
+        issuer: issuer.clone(),
+        key: generate_key(),
+        signing_key: None,
+        id_token_claims: json!({
+            "iss": issuer,
+            "sub": "248289761001",
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": "nonce-1",
+            "email": "Jane@Example.com",
+            "email_verified": true,
+            "name": "Jane Doe",
+        }),
+        userinfo: json!({ "sub": "248289761001" }),
+        discovered_issuer: None,
+    }));
+
+    let data = state.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(discovery),
+            )
+            .route("/jwks", web::get().to(jwks))
+            .route("/token", web::post().to(token))
+            .route("/userinfo", web::get().to(userinfo))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_web::rt::spawn(server);
+
+    (issuer, state)
+}
+
+fn openid_providers(issuer: &str) -> IdentityProviders {
+    IdentityProviders::from_json(
+        &json!({
+            "providers": [{
+                "name": "mock",
+                "display_name": "Mock",
+                "issuer": issuer,
+                "client_id": CLIENT_ID,
+                "client_secret": CLIENT_SECRET,
+            }]
+        })
+        .to_string(),
+    )
+    .unwrap()
+}
+
+async fn authenticate(providers: &IdentityProviders) -> std::io::Result<ExternalIdentity> {
+    providers
+        .get("mock")
+        .unwrap()
+        .authenticate("code-1", REDIRECT_URI, &"v".repeat(43), "nonce-1")
+        .await
+}
+
+#[actix_web::test]
+async fn test_authorization_url_from_discovery() {
+    let (issuer, _) = start_provider();
+    let providers = openid_providers(&issuer);
+    let provider = providers.get("mock").unwrap();
+    assert_eq!(provider.display_name(), "Mock");
+
+    let login = PendingLogin::generate();
+    let url = provider
+        .authorization_url(REDIRECT_URI, &login)
+        .await
+        .unwrap();
+
+    let (endpoint, query) = url.split_once('?').unwrap();
+    assert_eq!(endpoint, format!("{}/authorize", issuer));
+    let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    assert_eq!(param("response_type").as_deref(), Some("code"));
+    assert_eq!(param("client_id").as_deref(), Some(CLIENT_ID));
+    assert_eq!(param("redirect_uri").as_deref(), Some(REDIRECT_URI));
+    assert_eq!(param("scope").as_deref(), Some("openid email profile"));
+    assert_eq!(param("state"), Some(login.state.clone()));
+    assert_eq!(param("nonce"), Some(login.nonce.clone()));
+    assert_eq!(param("code_challenge_method").as_deref(), Some("S256"));
+    assert!(auth_api::services::oauth::verify_pkce(
+        &login.code_verifier,
+        &param("code_challenge").unwrap()
+    ));
+}
+
+#[actix_web::test]
+async fn test_authenticate_with_id_token() {
+    let (issuer, _) = start_provider();
+
+    let identity = authenticate(&openid_providers(&issuer)).await.unwrap();
+    assert_eq!(
+        identity,
+        ExternalIdentity {
+            provider: String::from("mock"),
+            subject: String::from("248289761001"),
+            email: Some(String::from("jane@example.com")),
+            email_verified: true,
+            name: Some(String::from("Jane Doe")),
+        }
+    );
+}
+
+#[actix_web::test]
+async fn test_email_from_userinfo() {
+    let (issuer, state) = start_provider();
+    {
+        let mut state = state.lock().unwrap();
+        let claims = state.id_token_claims.as_object_mut().unwrap();
+        claims.remove("email");
+        claims.remove("email_verified");
+        state.userinfo = json!({ "sub": "248289761001", "email": "jane@example.com" });
+    }
+
+    let identity = authenticate(&openid_providers(&issuer)).await.unwrap();
+    assert_eq!(identity.email.as_deref(), Some("jane@example.com"));
+    assert!(!identity.email_verified);
+
+    // Userinfo about someone else is not merged.
+    state.lock().unwrap().userinfo = json!({ "sub": "other", "email": "eve@example.com" });
+    assert!(authenticate(&openid_providers(&issuer)).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_reject_invalid_id_tokens() {
+    let (issuer, state) = start_provider();
+    let now = Utc::now().timestamp();
+
+    let invalid_claims = [
+        ("nonce", json!("nonce-2")),
+        ("aud", json!("another-client")),
+        ("iss", json!("https://evil.example.com")),
+        ("exp", json!(now - 3600)),
+        ("azp", json!("another-client")),
+    ];
+    for (claim, value) in invalid_claims {
+        let original = state.lock().unwrap().id_token_claims.clone();
+        state.lock().unwrap().id_token_claims[claim] = value;
+        assert!(
+            authenticate(&openid_providers(&issuer)).await.is_err(),
+            "{} should be checked",
+            claim
+        );
+        state.lock().unwrap().id_token_claims = original;
+    }
+
+    state.lock().unwrap().id_token_claims["nonce"].take();
+    assert!(authenticate(&openid_providers(&issuer)).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_reject_id_token_signed_with_unknown_key() {
+    let (issuer, state) = start_provider();
+    state.lock().unwrap().signing_key = Some(generate_key());
+
+    assert!(authenticate(&openid_providers(&issuer)).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_reject_discovery_of_another_issuer() {
+    let (issuer, state) = start_provider();
+    state.lock().unwrap().discovered_issuer = Some(String::from("https://evil.example.com"));
+
+    let providers = openid_providers(&issuer);
+    let provider = providers.get("mock").unwrap();
+    assert!(provider.endpoints().await.is_err());
+    assert!(provider
+        .authorization_url(REDIRECT_URI, &PendingLogin::generate())
+        .await
+        .is_err());
+}
+
+#[actix_web::test]
+async fn test_authenticate_with_plain_oauth2_provider() {
+    let (issuer, state) = start_provider();
+    state.lock().unwrap().userinfo = json!({
+        "id": 583231,
+        "login": "octocat",
+        "email": "octocat@github.com",
+        "name": "The Octocat"
+    });
+    let providers = IdentityProviders::from_json(
+        &json!({
+            "providers": [{
+                "name": "mock",
+                "client_id": CLIENT_ID,
+                "client_secret": CLIENT_SECRET,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "userinfo_endpoint": format!("{}/userinfo", issuer),
+                "scopes": ["read:user", "user:email"],
+                "subject_claim": "id",
+                "trust_email": true
+            }]
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let url = providers
+        .get("mock")
+        .unwrap()
+        .authorization_url(REDIRECT_URI, &PendingLogin::generate())
+        .await
+        .unwrap();
+    assert!(url.contains("scope=read%3Auser+user%3Aemail"));
+    assert!(!url.contains("nonce="));
+
+    let identity = authenticate(&providers).await.unwrap();
+    assert_eq!(identity.subject, "583231");
+    assert_eq!(identity.email.as_deref(), Some("octocat@github.com"));
+    assert!(identity.email_verified);
+    assert_eq!(identity.name.as_deref(), Some("The Octocat"));
+}
+
+#[actix_web::test]
+async fn test_plain_http_only_on_loopback() {
+    let providers = openid_providers("http://accounts.example.com");
+    let result = providers.get("mock").unwrap().endpoints().await;
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_invalid_provider_configurations() {
+    let configs = [
+        json!({ "providers": [{ "name": "Google!", "issuer": "https://accounts.google.com", "client_id": "a" }] }),
+        json!({ "providers": [{ "name": "github", "client_id": "a",
+            "authorization_endpoint": "https://github.com/login/oauth/authorize" }] }),
+        json!({ "providers": [
+            { "name": "google", "issuer": "https://accounts.google.com", "client_id": "a" },
+            { "name": "google", "issuer": "https://accounts.google.com", "client_id": "b" }
+        ] }),
+    ];
+    for config in configs {
+        assert!(IdentityProviders::from_json(&config.to_string()).is_err());
+    }
+
+    let providers = IdentityProviders::from_json(r#"{ "providers": [] }"#).unwrap();
+    assert_eq!(providers.iter().count(), 0);
+}
+
+#[test]
+fn test_safe_return_to() {
+    assert_eq!(safe_return_to(None), "/");
+    assert_eq!(
+        safe_return_to(Some("/oauth/authorize?client_id=a")),
+        "/oauth/authorize?client_id=a"
+    );
+    assert_eq!(safe_return_to(Some("https://evil.example.com")), "/");
+    assert_eq!(safe_return_to(Some("//evil.example.com")), "/");
+    assert_eq!(safe_return_to(Some("/\\evil.example.com")), "/");
+    assert_eq!(safe_return_to(Some("/a\nb")), "/");
+}
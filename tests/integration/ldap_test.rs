@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use auth_api::services::ldap::{
+    DirectoryLogin, DirectoryUser, LdapConfig, LdapDirectory, LocalFallback,
+};
+use bytes::BytesMut;
+use ldap3::asn1::{
+    parse_tag, write, ASNTag, Enumerated, OctetString, Sequence, Set, StructureTag, Tag, TagClass,
+    PL,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const SERVICE_DN: &str = "cn=auth-api,ou=services,dc=example,dc=com";
+const SERVICE_PASSWORD: &str = "service-secret";
+const USER_DN: &str = "uid=jdoe,ou=users,dc=example,dc=com";
+const USER_EMAIL: &str = "jdoe@example.com";
+const USER_PASSWORD: &str = "user-secret";
+const ADMINS_DN: &str = "CN=Admins,OU=Groups,DC=example,DC=com";
+
+const BIND_REQUEST: u64 = 0;
+const BIND_RESPONSE: u64 = 1;
+const UNBIND_REQUEST: u64 = 2;
+const SEARCH_REQUEST: u64 = 3;
+const SEARCH_RESULT_ENTRY: u64 = 4;
+const SEARCH_RESULT_DONE: u64 = 5;
+const EQUALITY_FILTER: u64 = 3;
+
+/// What the stub directory was asked, for the tests to check.
+#[derive(Default)]
+struct StubLog {
+    binds: Vec<String>,
+    filters: Vec<String>,
+}
+
+fn octet_string(value: &str) -> Tag {
+    Tag::OctetString(OctetString {
+        inner: value.as_bytes().to_vec(),
+        ..Default::default()
+    })
+}
+
+fn ldap_result(op: u64, rc: i64) -> Tag {
+    Tag::Sequence(Sequence {
+        id: op,
+        class: TagClass::Application,
+        inner: vec![
+            Tag::Enumerated(Enumerated {
+                inner: rc,
+                ..Default::default()
+            }),
+            octet_string(""),
+            octet_string(""),
+        ],
+    })
+}
+
+fn search_entry(dn: &str, attributes: &[(&str, &[&str])]) -> Tag {
+    let attributes = attributes
+        .iter()
+        .map(|(name, values)| {
+            Tag::Sequence(Sequence {
+                inner: vec![
+                    octet_string(name),
+                    Tag::Set(Set {
+                        inner: values.iter().map(|value| octet_string(value)).collect(),
+                        ..Default::default()
+                    }),
+                ],
+                ..Default::default()
+            })
+        })
+        .collect();
+    Tag::Sequence(Sequence {
+        id: SEARCH_RESULT_ENTRY,
+        class: TagClass::Application,
+        inner: vec![
+            octet_string(dn),
+            Tag::Sequence(Sequence {
+                inner: attributes,
+                ..Default::default()
+            }),
+        ],
+    })
+}
+
+fn primitive(tag: &StructureTag) -> String {
+    match &tag.payload {
+        PL::P(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        PL::C(_) => String::new(),
+    }
+}
+
+fn constructed(tag: &StructureTag) -> &[StructureTag] {
+    match &tag.payload {
+        PL::C(tags) => tags,
+        PL::P(_) => &[],
+    }
+}
+
+/// Value of the first equality filter, the stub only answers `(attribute=value)`.
+fn equality_value(filter: &StructureTag) -> Option<String> {
+    if filter.class == TagClass::Context && filter.id == EQUALITY_FILTER {
+        return constructed(filter).get(1).map(primitive);
+    }
+    constructed(filter).iter().find_map(equality_value)
+}
+
+async fn send(stream: &mut TcpStream, message_id: &StructureTag, op: Tag) {
+    let message = StructureTag {
+        class: TagClass::Universal,
+        id: 16,
+        payload: PL::C(vec![message_id.clone(), op.into_structure()]),
+    };
+    let mut buf = BytesMut::new();
+    write::encode_into(&mut buf, message).unwrap();
+    stream.write_all(&buf).await.unwrap();
+}
+
+/// Answers binds, searches of the user by email, and unbinds.
+async fn serve(mut stream: TcpStream, log: Arc<Mutex<StubLog>>) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let (consumed, message) = match parse_tag(&buf) {
+            Ok((rest, message)) => (buf.len() - rest.len(), message),
+            Err(_) => match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    buf.extend_from_slice(&chunk[..n]);
+                    continue;
+                }
+            },
+        };
+        buf.drain(..consumed);
+
+        let parts = constructed(&message);
+        let (message_id, op) = match parts {
+            [message_id, op, ..] => (message_id, op),
+            _ => return,
+        };
+        match op.id {
+            BIND_REQUEST => {
+                let request = constructed(op);
+                let dn = primitive(&request[1]);
+                let password = primitive(&request[2]);
+                log.lock().unwrap().binds.push(dn.clone());
+                let valid = (dn == SERVICE_DN && password == SERVICE_PASSWORD)
+                    || (dn == USER_DN && password == USER_PASSWORD);
+                let rc = if valid { 0 } else { 49 };
+                send(&mut stream, message_id, ldap_result(BIND_RESPONSE, rc)).await;
+            }
+            SEARCH_REQUEST => {
+                let request = constructed(op);
+                let value = equality_value(&request[6]).unwrap_or_default();
+                log.lock().unwrap().filters.push(value.clone());
+                if value.eq_ignore_ascii_case(USER_EMAIL) {
+                    let entry = search_entry(
+                        USER_DN,
+                        &[
+                            ("mail", &["JDoe@example.com"]),
+                            ("displayName", &["John Doe"]),
+                            (
+                                "memberOf",
+                                &[ADMINS_DN, "cn=staff,ou=groups,dc=example,dc=com"],
+                            ),
+                        ],
+                    );
+                    send(&mut stream, message_id, entry).await;
+                }
+                send(&mut stream, message_id, ldap_result(SEARCH_RESULT_DONE, 0)).await;
+            }
+            UNBIND_REQUEST => return,
+            _ => return,
+        }
+    }
+}
+
+async fn start_directory() -> (String, Arc<Mutex<StubLog>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ldap://{}", listener.local_addr().unwrap());
+    let log = Arc::new(Mutex::new(StubLog::default()));
+    let served = log.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, served.clone()));
+        }
+    });
+    (url, log)
+}
+
+fn config(url: &str) -> LdapConfig {
+    LdapConfig {
+        url: url.to_owned(),
+        starttls: false,
+        ca_certificate_file: None,
+        bind_dn: Some(SERVICE_DN.to_owned()),
+        bind_password: Some(SERVICE_PASSWORD.to_owned()),
+        base_dn: "ou=users,dc=example,dc=com".to_owned(),
+        user_filter: None,
+        subject_attribute: None,
+        email_attribute: None,
+        name_attribute: None,
+        group_attribute: None,
+        group_base_dn: None,
+        group_filter: None,
+        group_mapping: HashMap::from([(ADMINS_DN.to_owned(), "ROLE_ADMIN".to_owned())]),
+        local_fallback: LocalFallback::NotFound,
+        timeout_ms: Some(2000),
+    }
+}
+
+#[tokio::test]
+async fn test_ldap_login_maps_groups_to_roles() {
+    let (url, log) = start_directory().await;
+    let directory = LdapDirectory::new(config(&url)).unwrap();
+
+    let user = match directory.authenticate(USER_EMAIL, USER_PASSWORD).await {
+        Ok(DirectoryLogin::Authenticated(user)) => user,
+        other => panic!("Unexpected login {:?}", other),
+    };
+    assert_eq!(user.dn, USER_DN);
+    assert_eq!(user.subject, USER_DN);
+    assert_eq!(user.email, USER_EMAIL);
+    assert_eq!(user.name.as_deref(), Some("John Doe"));
+    assert!(user.groups.contains(&ADMINS_DN.to_lowercase()));
+    assert_eq!(log.lock().unwrap().binds, vec![SERVICE_DN, USER_DN]);
+
+    let identity = directory.identity(&user);
+    assert_eq!(identity.provider, "ldap");
+    assert!(identity.email_verified);
+
+    let current = vec!["ROLE_USER".to_owned(), "ROLE_AUDITOR".to_owned()];
+    assert_eq!(
+        directory.roles(&current, &user),
+        vec!["ROLE_USER", "ROLE_AUDITOR", "ROLE_ADMIN"]
+    );
+
+    // Leaving the group revokes the mapped role only.
+    let user = DirectoryUser {
+        groups: vec![],
+        ..user
+    };
+    let current = vec!["ROLE_ADMIN".to_owned(), "ROLE_AUDITOR".to_owned()];
+    assert_eq!(directory.roles(&current, &user), vec!["ROLE_AUDITOR"]);
+    assert_eq!(
+        directory.roles(&["ROLE_ADMIN".to_owned()], &user),
+        vec!["ROLE_USER"]
+    );
+}
+
+#[tokio::test]
+async fn test_ldap_login_with_wrong_password() {
+    let (url, _) = start_directory().await;
+    let directory = LdapDirectory::new(config(&url)).unwrap();
+
+    let login = directory.authenticate(USER_EMAIL, "wrong").await;
+    assert_eq!(login.unwrap(), DirectoryLogin::InvalidCredentials);
+}
+
+#[tokio::test]
+async fn test_ldap_login_of_unknown_user() {
+    let (url, _) = start_directory().await;
+    let directory = LdapDirectory::new(config(&url)).unwrap();
+
+    let login = directory
+        .authenticate("nobody@example.com", USER_PASSWORD)
+        .await;
+    assert_eq!(login.unwrap(), DirectoryLogin::NotFound);
+}
+
+#[tokio::test]
+async fn test_ldap_login_without_password_does_not_bind() {
+    let (url, log) = start_directory().await;
+    let directory = LdapDirectory::new(config(&url)).unwrap();
+
+    let login = directory.authenticate(USER_EMAIL, "").await;
+    assert_eq!(login.unwrap(), DirectoryLogin::InvalidCredentials);
+    assert!(log.lock().unwrap().binds.is_empty());
+}
+
+#[tokio::test]
+async fn test_ldap_filter_is_escaped() {
+    let (url, log) = start_directory().await;
+    let directory = LdapDirectory::new(config(&url)).unwrap();
+
+    let login = directory.authenticate("*)(mail=*", USER_PASSWORD).await;
+    assert_eq!(login.unwrap(), DirectoryLogin::NotFound);
+    assert_eq!(log.lock().unwrap().filters, vec!["*)(mail=*"]);
+}
+
+#[tokio::test]
+async fn test_ldap_wrong_service_account() {
+    let (url, _) = start_directory().await;
+    let directory = LdapDirectory::new(LdapConfig {
+        bind_password: Some("wrong".to_owned()),
+        ..config(&url)
+    })
+    .unwrap();
+
+    assert!(directory
+        .authenticate(USER_EMAIL, USER_PASSWORD)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_ldap_unavailable_directory() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ldap://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let directory = LdapDirectory::new(config(&url)).unwrap();
+
+    let login = directory.authenticate(USER_EMAIL, USER_PASSWORD).await;
+    assert!(login.is_err());
+    assert!(!LocalFallback::Never.applies(&login));
+    assert!(!LocalFallback::NotFound.applies(&login));
+    assert!(LocalFallback::Unavailable.applies(&login));
+}
+
+#[test]
+fn test_local_fallback() {
+    let not_found = Ok(DirectoryLogin::NotFound);
+    let invalid = Ok(DirectoryLogin::InvalidCredentials);
+    assert!(!LocalFallback::Never.applies(&not_found));
+    assert!(LocalFallback::NotFound.applies(&not_found));
+    assert!(LocalFallback::Unavailable.applies(&not_found));
+    assert!(!LocalFallback::NotFound.applies(&invalid));
+    assert!(!LocalFallback::Unavailable.applies(&invalid));
+}
+
+#[test]
+fn test_ldap_config_validation() {
+    // Passwords are not sent in clear text out of the host.
+    assert!(LdapDirectory::new(config("ldap://ad.example.com")).is_err());
+    assert!(LdapDirectory::new(LdapConfig {
+        starttls: true,
+        ..config("ldap://ad.example.com")
+    })
+    .is_ok());
+    assert!(LdapDirectory::new(config("ldaps://ad.example.com:636")).is_ok());
+    assert!(LdapDirectory::new(config("ldap://[::1]:389")).is_ok());
+    assert!(LdapDirectory::new(config("http://ad.example.com")).is_err());
+
+    assert!(LdapDirectory::new(LdapConfig {
+        user_filter: Some("(mail=*)".to_owned()),
+        ..config("ldaps://ad.example.com")
+    })
+    .is_err());
+    assert!(LdapDirectory::new(LdapConfig {
+        group_mapping: HashMap::from([(ADMINS_DN.to_owned(), "admin".to_owned())]),
+        ..config("ldaps://ad.example.com")
+    })
+    .is_err());
+
+    let directory = LdapDirectory::from_json(
+        r#"{"url": "ldaps://ad.example.com", "base_dn": "dc=example,dc=com", "local_fallback": "never"}"#,
+    )
+    .unwrap();
+    assert_eq!(directory.local_fallback(), LocalFallback::Never);
+}
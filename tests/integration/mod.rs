@@ -1,4 +1,9 @@
+mod ext_authz_test;
+mod grpc_auth_test;
+mod identity_provider_test;
+mod ldap_test;
+
 #[test]
 pub fn test() {
     assert_eq!(1,1);
-}
\ No newline at end of file
+}
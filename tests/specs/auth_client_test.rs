@@ -0,0 +1,319 @@
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::{get, web, App, HttpResponse, Responder};
+use auth_api::services::crypto::{SigningKey, TokenConfig, TokenOptions};
+use auth_client::actix::Authenticated;
+use auth_client::jwks::KeysFuture;
+use auth_client::tower::AuthLayer;
+use auth_client::{token_from_headers, Error, HttpClient, JwksCache, KeySource, Verifier};
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tower::{service_fn, Layer, ServiceExt};
+
+fn generate_key() -> SigningKey {
+    let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", der.as_ref()));
+    SigningKey::from_pem(pem.as_bytes()).unwrap()
+}
+
+fn token(key: &SigningKey, sub: &str) -> String {
+    let config = TokenConfig::new("auth_api", "orders", 3600);
+    key.sign(&config.claims(sub, &TokenOptions::default()).unwrap())
+        .unwrap()
+}
+
+fn verifier(key: &SigningKey) -> Verifier {
+    Verifier::new(jwks(key))
+        .with_issuer("auth_api")
+        .with_audience(&["orders"])
+}
+
+fn jwks(key: &SigningKey) -> JwkSet {
+    JwkSet {
+        keys: vec![key.jwk()],
+    }
+}
+
+#[tokio::test]
+async fn test_verify_token_offline() {
+    let key = generate_key();
+    let verifier = verifier(&key);
+
+    let claims = verifier.verify(&token(&key, "user-1")).await.unwrap();
+    assert_eq!(claims.sub, "user-1");
+}
+
+#[tokio::test]
+async fn test_reject_token_for_another_audience() {
+    let key = generate_key();
+    let verifier = Verifier::new(jwks(&key))
+        .with_issuer("auth_api")
+        .with_audience(&["billing"]);
+
+    assert!(matches!(
+        verifier.verify(&token(&key, "user-1")).await,
+        Err(Error::InvalidToken(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_reject_token_from_unknown_key() {
+    let verifier = verifier(&generate_key());
+
+    let result = verifier.verify(&token(&generate_key(), "user-1")).await;
+    assert!(matches!(result, Err(Error::UnknownKey(_))));
+}
+
+#[tokio::test]
+async fn test_reject_algorithm_confusion() {
+    let key = generate_key();
+    let verifier = verifier(&key);
+
+    // HS256 signed with the public key, claiming the kid of the Ed25519 key.
+    let header = Header {
+        kid: Some(key.kid().to_owned()),
+        ..Default::default()
+    };
+    let public_key = match key.jwk().algorithm {
+        AlgorithmParameters::OctetKeyPair(params) => params.x,
+        _ => unreachable!(),
+    };
+    let claims = TokenConfig::new("auth_api", "orders", 3600)
+        .claims("admin", &TokenOptions::default())
+        .unwrap();
+    let forged = encode(
+        &header,
+        &claims,
+        &EncodingKey::from_secret(public_key.as_bytes()),
+    )
+    .unwrap();
+
+    assert!(matches!(
+        verifier.verify(&forged).await,
+        Err(Error::InvalidToken(_))
+    ));
+}
+
+#[test]
+fn test_token_from_headers() {
+    assert_eq!(token_from_headers(Some("Bearer abc"), None), Some("abc"));
+    assert_eq!(token_from_headers(Some("abc"), None), Some("abc"));
+    assert_eq!(
+        token_from_headers(None, Some("XSRF-TOKEN=x; Authorization=abc")),
+        Some("abc")
+    );
+    assert_eq!(token_from_headers(None, Some("XSRF-TOKEN=x")), None);
+    assert_eq!(token_from_headers(None, None), None);
+}
+
+/// Serves the keys of `key` once per connection, as the `/.well-known/jwks.json` endpoint.
+async fn serve_jwks(key: &SigningKey) -> String {
+    let body = serde_json::to_string(&jwks(key)).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap_or(0);
+            let status = match buf[..n].starts_with(b"GET /.well-known/jwks.json HTTP/1.1") {
+                true => "200 OK",
+                false => "400 Bad Request",
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    format!("http://{}/.well-known/jwks.json", addr)
+}
+
+#[tokio::test]
+async fn test_fetch_jwks_over_http() {
+    let key = generate_key();
+    let verifier = Verifier::from_jwks_url(&serve_jwks(&key).await)
+        .unwrap()
+        .with_issuer("auth_api")
+        .with_audience(&["orders"]);
+
+    let claims = verifier.verify(&token(&key, "user-1")).await.unwrap();
+    assert_eq!(claims.sub, "user-1");
+}
+
+#[test]
+fn test_reject_non_http_jwks_url() {
+    assert!(Verifier::from_jwks_url("ftp://auth/.well-known/jwks.json").is_err());
+}
+
+#[test]
+fn test_plain_http_jwks_url_only_on_loopback() {
+    assert!(Verifier::from_jwks_url("https://auth.example.com/.well-known/jwks.json").is_ok());
+    assert!(Verifier::from_jwks_url("http://127.0.0.1:4000/.well-known/jwks.json").is_ok());
+    assert!(Verifier::from_jwks_url("http://localhost:4000/.well-known/jwks.json").is_ok());
+    assert!(Verifier::from_jwks_url("http://auth-service:4000/.well-known/jwks.json").is_err());
+    assert!(Verifier::from_jwks_url("http://auth.example.com/.well-known/jwks.json").is_err());
+}
+
+#[test]
+fn test_http_client_supported_urls() {
+    let supports = |client: &HttpClient, url: &str| client.supports(&url.parse().unwrap());
+    let client = HttpClient::default();
+    assert!(supports(&client, "https://idp.example.com/token"));
+    assert!(supports(&client, "http://[::1]:4000/token"));
+    assert!(!supports(&client, "http://idp.example.com/token"));
+    assert!(!supports(&client, "ftp://idp.example.com/token"));
+
+    let sidecar = HttpClient::default().with_plain_http();
+    assert!(supports(&sidecar, "http://claims-sidecar:8080/claims"));
+}
+
+#[tokio::test]
+async fn test_http_client_limits_answers() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(String::from_utf8_lossy(&buf[..n]).starts_with("GET /large HTTP/1.1"));
+            let body = "x".repeat(2048);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let request = || {
+        http::Request::get(format!("http://{}/large", addr))
+            .body(http_body_util::Full::new(bytes::Bytes::new()))
+            .unwrap()
+    };
+    let client = HttpClient::default().with_timeout(Duration::from_secs(5));
+    let response = client.send(request()).await.unwrap();
+    assert_eq!(response.body().len(), 2048);
+
+    let err = client
+        .with_max_response_bytes(1024)
+        .send(request())
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+/// Returns the current key set and counts fetches.
+struct RotatingSource {
+    keys: std::sync::Mutex<JwkSet>,
+    fetches: AtomicUsize,
+}
+
+impl KeySource for RotatingSource {
+    fn fetch(&self) -> KeysFuture<'_> {
+        Box::pin(async move {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(self.keys.lock().unwrap().clone())
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_jwks_cache_refreshes_on_unknown_kid() {
+    let old_key = generate_key();
+    let new_key = generate_key();
+    let source = Arc::new(RotatingSource {
+        keys: std::sync::Mutex::new(jwks(&old_key)),
+        fetches: AtomicUsize::new(0),
+    });
+    let cache = JwksCache::new(source.clone()).with_min_refresh_interval(Duration::ZERO);
+
+    assert!(cache.find(Some(old_key.kid())).await.is_ok());
+    assert!(cache.find(Some(old_key.kid())).await.is_ok());
+    assert_eq!(source.fetches.load(Ordering::SeqCst), 1);
+
+    *source.keys.lock().unwrap() = jwks(&new_key);
+    assert!(cache.find(Some(new_key.kid())).await.is_ok());
+    assert_eq!(source.fetches.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_jwks_cache_rate_limits_unknown_kid() {
+    let key = generate_key();
+    let source = Arc::new(RotatingSource {
+        keys: std::sync::Mutex::new(jwks(&key)),
+        fetches: AtomicUsize::new(0),
+    });
+    let cache = JwksCache::new(source.clone());
+
+    assert!(cache.find(Some(key.kid())).await.is_ok());
+    assert!(cache.find(Some("unknown")).await.is_err());
+    assert!(cache.find(Some("unknown")).await.is_err());
+    assert_eq!(source.fetches.load(Ordering::SeqCst), 1);
+}
+
+#[get("/me")]
+async fn me(user: Authenticated) -> impl Responder {
+    HttpResponse::Ok().body(user.0.sub)
+}
+
+#[actix_web::test]
+async fn test_actix_extractor() {
+    let key = generate_key();
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(verifier(&key)))
+            .service(me),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::get()
+        .uri("/me")
+        .insert_header(("Cookie", format!("Authorization={}", token(&key, "user-1"))))
+        .to_request();
+    let res = actix_web::test::call_service(&app, req).await;
+    assert!(res.status().is_success());
+    assert_eq!(actix_web::test::read_body(res).await, "user-1");
+
+    let req = actix_web::test::TestRequest::get().uri("/me").to_request();
+    let res = actix_web::test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn test_tower_layer() {
+    let key = generate_key();
+    let layer = AuthLayer::new(Arc::new(verifier(&key)));
+    let service = layer.layer(service_fn(|req: http::Request<String>| async move {
+        let claims = req.extensions().get::<auth_client::Claims>().unwrap();
+        Ok::<_, Infallible>(http::Response::new(claims.sub.clone()))
+    }));
+
+    let req = http::Request::get("/")
+        .header("Authorization", format!("Bearer {}", token(&key, "user-1")))
+        .body(String::new())
+        .unwrap();
+    let res = service.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body(), "user-1");
+
+    let req = http::Request::get("/")
+        .header("Authorization", "Bearer forged")
+        .body(String::new())
+        .unwrap();
+    let res = service.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), 401);
+    assert_eq!(res.headers()["WWW-Authenticate"], "Bearer");
+}
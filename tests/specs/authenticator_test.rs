@@ -0,0 +1,174 @@
+use auth_api::repository::user_repository::User;
+use auth_api::services::authenticator::{
+    generate_api_key, AuthFuture, AuthOutcome, Authenticator, Authenticators, Credentials,
+    API_KEY_PREFIX,
+};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn user(id: &str) -> User {
+    serde_json::from_value(json!({
+        "id": id,
+        "email": "jane@example.com",
+        "password": "",
+        "role": ["ROLE_USER"],
+        "organization": null
+    }))
+    .unwrap()
+}
+
+fn user_id(user: Option<User>) -> Option<String> {
+    user.map(|user| serde_json::to_value(user).unwrap()["id"].as_str().unwrap().to_owned())
+}
+
+enum Decision {
+    Accept(&'static str),
+    Reject,
+    Skip,
+}
+
+/// Decides the same for every password, counting the credentials it was given.
+struct FakeAuthenticator {
+    decision: Decision,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FakeAuthenticator {
+    fn new(decision: Decision) -> (FakeAuthenticator, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let authenticator = FakeAuthenticator {
+            decision,
+            calls: calls.clone(),
+        };
+        (authenticator, calls)
+    }
+}
+
+impl Authenticator for FakeAuthenticator {
+    fn authenticate<'a>(&'a self, credentials: &'a Credentials<'a>) -> AuthFuture<'a> {
+        Box::pin(async move {
+            if !matches!(credentials, Credentials::Password { .. }) {
+                return AuthOutcome::Skipped;
+            }
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.decision {
+                Decision::Accept(id) => AuthOutcome::Authenticated(user(id)),
+                Decision::Reject => AuthOutcome::Rejected,
+                Decision::Skip => AuthOutcome::Skipped,
+            }
+        })
+    }
+}
+
+const PASSWORD: Credentials = Credentials::Password {
+    email: "jane@example.com",
+    password: "secret",
+};
+
+#[tokio::test]
+async fn test_authenticators_are_tried_in_the_order_of_the_realm() {
+    let (directory, directory_calls) = FakeAuthenticator::new(Decision::Skip);
+    let (local, local_calls) = FakeAuthenticator::new(Decision::Accept("local-user"));
+    let (other, other_calls) = FakeAuthenticator::new(Decision::Accept("other-user"));
+    let mut authenticators = Authenticators::default();
+    authenticators.register("ldap", directory).unwrap();
+    authenticators.register("local", local).unwrap();
+    authenticators.register("other", other).unwrap();
+    let authenticators = authenticators
+        .with_realms_json(
+            r#"{"realms": {"default": ["ldap", "local", "other"], "partners": ["other", "local"]}}"#,
+        )
+        .unwrap();
+
+    let user = authenticators.authenticate(None, &PASSWORD).await;
+    assert_eq!(user_id(user).as_deref(), Some("local-user"));
+    assert_eq!(directory_calls.load(Ordering::SeqCst), 1);
+    assert_eq!(local_calls.load(Ordering::SeqCst), 1);
+    assert_eq!(other_calls.load(Ordering::SeqCst), 0);
+
+    let user = authenticators.authenticate(Some("partners"), &PASSWORD).await;
+    assert_eq!(user_id(user).as_deref(), Some("other-user"));
+    assert_eq!(local_calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_rejected_credentials_stop_the_chain() {
+    let (directory, _) = FakeAuthenticator::new(Decision::Reject);
+    let (local, local_calls) = FakeAuthenticator::new(Decision::Accept("local-user"));
+    let mut authenticators = Authenticators::default();
+    authenticators.register("ldap", directory).unwrap();
+    authenticators.register("local", local).unwrap();
+    let authenticators = authenticators.with_default_realm().unwrap();
+
+    assert!(authenticators.authenticate(None, &PASSWORD).await.is_none());
+    assert_eq!(local_calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_credentials_skipped_by_every_authenticator() {
+    let (local, local_calls) = FakeAuthenticator::new(Decision::Accept("local-user"));
+    let mut authenticators = Authenticators::default();
+    authenticators.register("local", local).unwrap();
+    let authenticators = authenticators.with_default_realm().unwrap();
+
+    let api_key = generate_api_key();
+    let user = authenticators
+        .authenticate(None, &Credentials::ApiKey(&api_key))
+        .await;
+    assert!(user.is_none());
+    assert_eq!(local_calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_unknown_realm() {
+    let (local, local_calls) = FakeAuthenticator::new(Decision::Accept("local-user"));
+    let mut authenticators = Authenticators::default();
+    authenticators.register("local", local).unwrap();
+    let authenticators = authenticators.with_default_realm().unwrap();
+
+    assert!(authenticators.has_realm("default"));
+    assert!(!authenticators.has_realm("partners"));
+    assert!(authenticators
+        .authenticate(Some("partners"), &PASSWORD)
+        .await
+        .is_none());
+    assert_eq!(local_calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_invalid_realms() {
+    let authenticators = || {
+        let mut authenticators = Authenticators::default();
+        authenticators
+            .register("local", FakeAuthenticator::new(Decision::Skip).0)
+            .unwrap();
+        authenticators
+    };
+
+    let mut duplicated = authenticators();
+    assert!(duplicated
+        .register("local", FakeAuthenticator::new(Decision::Skip).0)
+        .is_err());
+
+    for json in [
+        r#"{"realms": {"partners": ["local"]}}"#,
+        r#"{"realms": {"default": []}}"#,
+        r#"{"realms": {"default": ["ldap"]}}"#,
+        r#"{"realms": {"default": ["local", "local"]}}"#,
+        r#"{"realms": {"default": ["local"], "partners/eu": ["local"]}}"#,
+        r#"{"realms": ["local"]}"#,
+    ] {
+        assert!(authenticators().with_realms_json(json).is_err(), "{}", json);
+    }
+    assert!(authenticators()
+        .with_realms_json(r#"{"realms": {"default": ["local"], "partners-eu": ["local"]}}"#)
+        .is_ok());
+}
+
+#[test]
+fn test_generate_api_key() {
+    let key = generate_api_key();
+    assert!(key.starts_with(API_KEY_PREFIX));
+    assert_ne!(key, generate_api_key());
+}
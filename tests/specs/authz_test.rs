@@ -0,0 +1,152 @@
+use std::str::FromStr;
+use auth_api::services::authz::{
+    ConsistencyToken, ObjectRef, RelationAuthz, RelationTuple, StoreFuture, SubjectRef, TupleStore,
+};
+
+const NAMESPACES: &str = r#"{
+  "namespaces": [
+    { "name": "group", "relations": { "member": null } },
+    { "name": "folder", "relations": { "viewer": null } },
+    {
+      "name": "document",
+      "relations": {
+        "parent": null,
+        "owner": null,
+        "banned": null,
+        "editor": { "union": ["this", { "computed_userset": { "relation": "owner" } }] },
+        "viewer": {
+          "exclusion": {
+            "base": { "union": [
+              "this",
+              { "computed_userset": { "relation": "editor" } },
+              { "tuple_to_userset": { "tupleset": "parent", "computed_userset": "viewer" } }
+            ] },
+            "subtract": { "computed_userset": { "relation": "banned" } }
+          }
+        }
+      }
+    }
+  ]
+}"#;
+
+/// Tuples with the revision they were written at.
+struct MemoryStore(Vec<(i64, RelationTuple)>);
+
+impl MemoryStore {
+    fn new(tuples: &[(i64, &str)]) -> MemoryStore {
+        MemoryStore(
+            tuples
+                .iter()
+                .map(|(revision, tuple)| (*revision, RelationTuple::from_str(tuple).unwrap()))
+                .collect(),
+        )
+    }
+}
+
+impl TupleStore for MemoryStore {
+    fn read_tuples<'a>(
+        &'a self,
+        object: &'a ObjectRef,
+        relation: &'a str,
+        revision: i64,
+    ) -> StoreFuture<'a, Vec<SubjectRef>> {
+        Box::pin(async move {
+            Ok(self
+                .0
+                .iter()
+                .filter(|(r, t)| *r <= revision && &t.object == object && t.relation == relation)
+                .map(|(_, t)| t.subject.clone())
+                .collect())
+        })
+    }
+}
+
+fn tuple(tuple: &str) -> RelationTuple {
+    RelationTuple::from_str(tuple).unwrap()
+}
+
+fn store() -> MemoryStore {
+    MemoryStore::new(&[
+        (1, "document:readme#owner@user:alice"),
+        (1, "document:readme#parent@folder:docs"),
+        (1, "folder:docs#viewer@group:eng#member"),
+        (1, "group:eng#member@user:bob"),
+        (2, "group:eng#member@user:carol"),
+        (2, "document:readme#banned@user:bob"),
+    ])
+}
+
+#[test]
+fn test_parse_tuple() {
+    let parsed = tuple("folder:docs#viewer@group:eng#member");
+    assert_eq!(parsed.object.namespace, "folder");
+    assert_eq!(parsed.subject.relation.as_deref(), Some("member"));
+    assert_eq!(parsed.to_string(), "folder:docs#viewer@group:eng#member");
+
+    assert!(RelationTuple::from_str("document:readme@user:alice").is_err());
+    assert!(RelationTuple::from_str("document#viewer@user:alice").is_err());
+    assert!(RelationTuple::from_str("Document:readme#viewer@user:alice").is_err());
+}
+
+#[test]
+fn test_consistency_token() {
+    assert_eq!(ConsistencyToken::decode(&ConsistencyToken::encode(42)).unwrap(), 42);
+    assert!(ConsistencyToken::decode("42").is_err());
+    assert!(ConsistencyToken::decode("zk_-1").is_err());
+}
+
+#[test]
+fn test_invalid_namespace_config() {
+    let config = r#"{ "namespaces": [{ "name": "doc", "relations": {
+        "viewer": { "computed_userset": { "relation": "owner" } } } }] }"#;
+    assert!(RelationAuthz::from_json(config).is_err());
+}
+
+#[test]
+fn test_validate_tuple() {
+    let authz = RelationAuthz::from_json(NAMESPACES).unwrap();
+    assert!(authz.validate_tuple(&tuple("document:readme#viewer@user:alice")).is_ok());
+    assert!(authz.validate_tuple(&tuple("document:readme#admin@user:alice")).is_err());
+    assert!(authz.validate_tuple(&tuple("repo:api#viewer@user:alice")).is_err());
+}
+
+#[tokio::test]
+async fn test_check_rewrites() {
+    let authz = RelationAuthz::from_json(NAMESPACES).unwrap();
+    let store = store();
+
+    // computed userset : owner => editor => viewer
+    assert!(authz.check(&store, &tuple("document:readme#viewer@user:alice"), 2).await.unwrap());
+    assert!(authz.check(&store, &tuple("document:readme#editor@user:alice"), 2).await.unwrap());
+    // tuple to userset through the parent folder and the group userset
+    assert!(authz.check(&store, &tuple("document:readme#viewer@user:carol"), 2).await.unwrap());
+    // exclusion
+    assert!(!authz.check(&store, &tuple("document:readme#viewer@user:bob"), 2).await.unwrap());
+    assert!(!authz.check(&store, &tuple("document:readme#editor@user:bob"), 2).await.unwrap());
+    assert!(!authz.check(&store, &tuple("document:readme#viewer@user:dave"), 2).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_check_at_revision() {
+    let authz = RelationAuthz::from_json(NAMESPACES).unwrap();
+    let store = store();
+
+    assert!(authz.check(&store, &tuple("document:readme#viewer@user:bob"), 1).await.unwrap());
+    assert!(!authz.check(&store, &tuple("document:readme#viewer@user:carol"), 1).await.unwrap());
+    assert!(authz.check(&store, &tuple("document:readme#viewer@user:carol"), 2).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_expand() {
+    let authz = RelationAuthz::from_json(NAMESPACES).unwrap();
+    let store = store();
+
+    let tree = authz
+        .expand(&store, &ObjectRef::from_str("document:readme").unwrap(), "editor", 2)
+        .await
+        .unwrap();
+    let json = serde_json::to_value(tree).unwrap();
+
+    assert_eq!(json["type"], "union");
+    assert_eq!(json["children"][1]["subjects"][0], "user:alice");
+}
@@ -0,0 +1,207 @@
+use auth_api::services::claims::{
+    ClaimsEnricher, ClaimsFuture, ClaimsProvider, ClaimsSubject, HttpClaimsProvider,
+    StaticClaimsProvider,
+};
+use auth_api::services::crypto::{TokenConfig, TokenOptions};
+use serde_json::{json, Map, Value};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+fn subject(roles: &[&str]) -> ClaimsSubject {
+    ClaimsSubject {
+        user_id: String::from("user-1"),
+        email: String::from("user@example.com"),
+        roles: roles.iter().map(|role| role.to_string()).collect(),
+        audience: String::from("auth_api"),
+    }
+}
+
+fn object(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+/// Never connected to, `sql` providers are not used by these tests.
+fn lazy_pool() -> Pool<Postgres> {
+    PgPoolOptions::new()
+        .connect_lazy("postgres://postgres@localhost/unused")
+        .unwrap()
+}
+
+struct FailingProvider;
+
+impl ClaimsProvider for FailingProvider {
+    fn claims<'a>(&'a self, _: &'a ClaimsSubject) -> ClaimsFuture<'a> {
+        Box::pin(async { Err(std::io::Error::other("down")) })
+    }
+}
+
+struct FixedProvider(Value);
+
+impl ClaimsProvider for FixedProvider {
+    fn claims<'a>(&'a self, _: &'a ClaimsSubject) -> ClaimsFuture<'a> {
+        Box::pin(async move { Ok(object(self.0.clone())) })
+    }
+}
+
+#[tokio::test]
+async fn test_static_provider_with_role_mapping() {
+    let enricher = ClaimsEnricher::from_json(
+        r#"{ "providers": [{
+            "type": "static", "namespace": "app",
+            "claims": { "plan": "free", "beta": false },
+            "by_role": { "ROLE_ADMIN": { "plan": "internal" } }
+        }] }"#,
+        lazy_pool(),
+    )
+    .unwrap();
+
+    let claims = enricher.enrich(&subject(&["ROLE_USER"])).await.unwrap();
+    assert_eq!(Value::Object(claims), json!({ "app": { "plan": "free", "beta": false } }));
+
+    let claims = enricher.enrich(&subject(&["ROLE_ADMIN"])).await.unwrap();
+    assert_eq!(claims["app"]["plan"], "internal");
+}
+
+#[tokio::test]
+async fn test_reject_reserved_or_invalid_namespace() {
+    for namespace in ["sub", "roles", "Tenant", "", "9lives", "a.b"] {
+        let mut enricher = ClaimsEnricher::default();
+        let result = enricher.register(namespace, StaticClaimsProvider::default(), false, 1024);
+        assert!(result.is_err(), "{} should be rejected", namespace);
+    }
+
+    let mut enricher = ClaimsEnricher::default();
+    assert!(enricher.register("tenant", StaticClaimsProvider::default(), false, 1024).is_ok());
+    assert!(enricher.register("tenant", StaticClaimsProvider::default(), false, 1024).is_err());
+}
+
+#[tokio::test]
+async fn test_optional_provider_failure_is_skipped() {
+    let mut enricher = ClaimsEnricher::default();
+    enricher.register("flags", FailingProvider, false, 1024).unwrap();
+    enricher
+        .register("tenant", FixedProvider(json!({ "id": "t1" })), false, 1024)
+        .unwrap();
+
+    let claims = enricher.enrich(&subject(&[])).await.unwrap();
+    assert_eq!(Value::Object(claims), json!({ "tenant": { "id": "t1" } }));
+}
+
+#[tokio::test]
+async fn test_required_provider_failure_fails_issuance() {
+    let mut enricher = ClaimsEnricher::default();
+    enricher.register("tenant", FailingProvider, true, 1024).unwrap();
+
+    assert!(enricher.enrich(&subject(&[])).await.is_err());
+}
+
+#[tokio::test]
+async fn test_size_limits() {
+    let large = json!({ "flags": "x".repeat(200) });
+
+    let mut enricher = ClaimsEnricher::default();
+    enricher.register("flags", FixedProvider(large.clone()), false, 100).unwrap();
+    assert!(enricher.enrich(&subject(&[])).await.unwrap().is_empty());
+
+    let mut enricher = ClaimsEnricher::default();
+    enricher.register("flags", FixedProvider(large.clone()), true, 100).unwrap();
+    assert!(enricher.enrich(&subject(&[])).await.is_err());
+
+    // Each provider fits, both do not.
+    let mut enricher = ClaimsEnricher::new(300);
+    enricher.register("first", FixedProvider(large.clone()), false, 1024).unwrap();
+    enricher.register("second", FixedProvider(large), false, 1024).unwrap();
+    let claims = enricher.enrich(&subject(&[])).await.unwrap();
+    assert!(claims.contains_key("first"));
+    assert!(!claims.contains_key("second"));
+}
+
+#[tokio::test]
+async fn test_http_provider() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        let request = String::from_utf8_lossy(&buf[..n]).to_string();
+        assert!(request.starts_with("POST /claims"));
+        assert!(request.contains(r#""sub":"user-1""#));
+
+        let body = r#"{"dark_mode":true}"#;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let enricher = ClaimsEnricher::from_json(
+        &format!(
+            r#"{{ "providers": [{{ "type": "http", "namespace": "flags", "required": true, "url": "http://{}/claims" }}] }}"#,
+            addr
+        ),
+        lazy_pool(),
+    )
+    .unwrap();
+
+    let claims = enricher.enrich(&subject(&[])).await.unwrap();
+    assert_eq!(claims["flags"]["dark_mode"], true);
+}
+
+#[tokio::test]
+async fn test_http_provider_reads_at_most_max_bytes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0; 4096];
+        let _ = stream.read(&mut buf).await.unwrap();
+
+        // Announces far more than it sends, the answer must be refused without waiting for it.
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 100000000\r\n\r\n";
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let _ = stream.write_all(&[b' '; 4096]).await;
+        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    });
+
+    let provider = HttpClaimsProvider::new(
+        &format!("http://{}/claims", addr),
+        std::time::Duration::from_secs(5),
+        1024,
+    )
+    .unwrap();
+
+    let err = provider.claims(&subject(&[])).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_custom_claims_cannot_override_registered_ones() {
+    let config = TokenConfig::new("auth_api", "web", 600);
+    let options = TokenOptions {
+        claims: object(json!({ "sub": "admin", "tenant": { "id": "t1" } })),
+        ..Default::default()
+    };
+
+    let claims = config.claims("user-1", &options).unwrap();
+    assert_eq!(claims.sub, "user-1");
+    let json = serde_json::to_value(&claims).unwrap();
+    assert_eq!(json["sub"], "user-1");
+    assert_eq!(json["tenant"]["id"], "t1");
+}
+
+#[tokio::test]
+async fn test_unknown_provider_type() {
+    let result = ClaimsEnricher::from_json(
+        r#"{ "providers": [{ "type": "ldap", "namespace": "app" }] }"#,
+        lazy_pool(),
+    );
+    assert!(result.is_err());
+}
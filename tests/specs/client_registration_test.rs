@@ -0,0 +1,342 @@
+use auth_api::controllers::oauth::pages::ConsentPage;
+use auth_api::repository::oauth_repository::{ClientMetadata, OAuthClient};
+use auth_api::services::client_registration::{
+    initial_access_token_valid, uses_secret, ClientRegistration, RegistrationRequest,
+    RegistrationResponse,
+};
+use auth_api::services::crypto::{TokenConfig, TokenOptions};
+use auth_api::services::oauth::{AuthorizeParams, OAuthErrorCode};
+use auth_api::services::oidc::ProviderMetadata;
+use chrono::Utc;
+use serde_json::json;
+
+fn registration() -> ClientRegistration {
+    ClientRegistration {
+        name: String::from("Dashboard"),
+        redirect_uris: vec![String::from("https://dashboard.example.com/callback")],
+        scopes: vec![String::from("openid"), String::from("reports:read")],
+        grant_types: vec![
+            String::from("authorization_code"),
+            String::from("refresh_token"),
+        ],
+        token_endpoint_auth_method: String::from("client_secret_basic"),
+        jwks: None,
+        first_party: false,
+        metadata: ClientMetadata::default(),
+    }
+}
+
+fn error_of(registration: ClientRegistration) -> OAuthErrorCode {
+    registration.validate().unwrap_err().error
+}
+
+#[test]
+fn test_valid_registration() {
+    assert!(registration().validate().is_ok());
+
+    let device = ClientRegistration {
+        redirect_uris: vec![],
+        grant_types: vec![String::from("urn:ietf:params:oauth:grant-type:device_code")],
+        token_endpoint_auth_method: String::from("none"),
+        ..registration()
+    };
+    assert!(device.validate().is_ok());
+}
+
+#[test]
+fn test_invalid_registrations() {
+    let no_name = ClientRegistration {
+        name: String::from("  "),
+        ..registration()
+    };
+    assert_eq!(error_of(no_name), OAuthErrorCode::InvalidClientMetadata);
+
+    let client_credentials = ClientRegistration {
+        grant_types: vec![String::from("client_credentials")],
+        ..registration()
+    };
+    assert_eq!(
+        error_of(client_credentials),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+
+    let unknown_grant = ClientRegistration {
+        grant_types: vec![String::from("password")],
+        ..registration()
+    };
+    assert_eq!(
+        error_of(unknown_grant),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+
+    let no_redirect = ClientRegistration {
+        redirect_uris: vec![],
+        ..registration()
+    };
+    assert_eq!(error_of(no_redirect), OAuthErrorCode::InvalidRedirectUri);
+
+    let http_redirect = ClientRegistration {
+        redirect_uris: vec![String::from("http://dashboard.example.com/callback")],
+        ..registration()
+    };
+    assert_eq!(error_of(http_redirect), OAuthErrorCode::InvalidRedirectUri);
+
+    let unknown_method = ClientRegistration {
+        token_endpoint_auth_method: String::from("tls_client_auth"),
+        ..registration()
+    };
+    assert_eq!(
+        error_of(unknown_method),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+
+    let no_jwks = ClientRegistration {
+        token_endpoint_auth_method: String::from("private_key_jwt"),
+        ..registration()
+    };
+    assert_eq!(error_of(no_jwks), OAuthErrorCode::InvalidClientMetadata);
+
+    let spaced_scope = ClientRegistration {
+        scopes: vec![String::from("a b")],
+        ..registration()
+    };
+    assert_eq!(
+        error_of(spaced_scope),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+}
+
+#[test]
+fn test_password_grant_is_reserved_to_first_party_clients() {
+    let third_party = ClientRegistration {
+        grant_types: vec![String::from("password"), String::from("refresh_token")],
+        ..registration()
+    };
+    assert_eq!(
+        error_of(third_party.clone()),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+
+    let first_party = ClientRegistration {
+        first_party: true,
+        ..third_party
+    };
+    assert!(first_party.validate().is_ok());
+}
+
+#[test]
+fn test_registration_metadata() {
+    let valid = ClientRegistration {
+        metadata: ClientMetadata {
+            access_token_lifetime_seconds: Some(900),
+            refresh_token_lifetime_seconds: Some(86400),
+            logo_uri: Some(String::from("https://dashboard.example.com/logo.png")),
+            client_uri: Some(String::from("https://dashboard.example.com")),
+            policy_uri: Some(String::from("https://dashboard.example.com/privacy")),
+            tos_uri: Some(String::from("https://dashboard.example.com/tos")),
+        },
+        ..registration()
+    };
+    assert!(valid.validate().is_ok());
+
+    let negative_lifetime = ClientRegistration {
+        metadata: ClientMetadata {
+            access_token_lifetime_seconds: Some(0),
+            ..ClientMetadata::default()
+        },
+        ..registration()
+    };
+    assert_eq!(
+        error_of(negative_lifetime),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+
+    let http_logo = ClientRegistration {
+        metadata: ClientMetadata {
+            logo_uri: Some(String::from("http://dashboard.example.com/logo.png")),
+            ..ClientMetadata::default()
+        },
+        ..registration()
+    };
+    assert_eq!(error_of(http_logo), OAuthErrorCode::InvalidClientMetadata);
+
+    let script_policy = ClientRegistration {
+        metadata: ClientMetadata {
+            policy_uri: Some(String::from("javascript:alert(1)")),
+            ..ClientMetadata::default()
+        },
+        ..registration()
+    };
+    assert_eq!(
+        error_of(script_policy),
+        OAuthErrorCode::InvalidClientMetadata
+    );
+}
+
+#[test]
+fn test_uses_secret() {
+    assert!(uses_secret("client_secret_basic"));
+    assert!(uses_secret("client_secret_post"));
+    assert!(!uses_secret("private_key_jwt"));
+    assert!(!uses_secret("none"));
+}
+
+#[test]
+fn test_dynamic_registration_request() {
+    let request: RegistrationRequest = serde_json::from_value(json!({
+        "client_name": "Grafana",
+        "redirect_uris": ["https://grafana.example.com/login/generic_oauth"],
+        "scope": "openid email",
+        "logo_uri": "https://grafana.example.com/logo.svg"
+    }))
+    .unwrap();
+
+    let registration = request.into_registration().unwrap();
+    assert_eq!(registration.name, "Grafana");
+    assert_eq!(registration.grant_types, vec!["authorization_code"]);
+    assert_eq!(registration.scopes, vec!["openid", "email"]);
+    assert_eq!(
+        registration.token_endpoint_auth_method,
+        "client_secret_basic"
+    );
+    assert_eq!(
+        registration.metadata.logo_uri.as_deref(),
+        Some("https://grafana.example.com/logo.svg")
+    );
+}
+
+#[test]
+fn test_dynamic_registration_is_restricted() {
+    let permission_scope: RegistrationRequest = serde_json::from_value(json!({
+        "client_name": "Grafana",
+        "redirect_uris": ["https://grafana.example.com/login/generic_oauth"],
+        "scope": "openid user:delete"
+    }))
+    .unwrap();
+    assert_eq!(
+        permission_scope.into_registration().unwrap_err().error,
+        OAuthErrorCode::InvalidClientMetadata
+    );
+
+    let implicit: RegistrationRequest = serde_json::from_value(json!({
+        "client_name": "Grafana",
+        "redirect_uris": ["https://grafana.example.com/login/generic_oauth"],
+        "response_types": ["token"]
+    }))
+    .unwrap();
+    assert!(implicit.into_registration().is_err());
+
+    let lifetime: RegistrationRequest = serde_json::from_value(json!({
+        "client_name": "Grafana",
+        "redirect_uris": ["https://grafana.example.com/login/generic_oauth"],
+        "access_token_lifetime_seconds": 31536000
+    }))
+    .unwrap();
+    let registration = lifetime.into_registration().unwrap();
+    assert_eq!(registration.metadata.access_token_lifetime_seconds, None);
+}
+
+#[test]
+fn test_registration_response() {
+    let created_at = Utc::now();
+    let client = OAuthClient {
+        client_id: String::from("client-1"),
+        client_secret_hash: Some(String::from("hash")),
+        name: String::from("Grafana"),
+        redirect_uris: vec![String::from("https://grafana.example.com/callback")],
+        scopes: vec![String::from("openid"), String::from("email")],
+        grant_types: vec![String::from("authorization_code")],
+        token_endpoint_auth_method: String::from("client_secret_basic"),
+        jwks: None,
+        first_party: false,
+        metadata: ClientMetadata::default(),
+        previous_client_secret_hash: None,
+        previous_secret_expires_at: None,
+        created_at,
+    };
+
+    let response = serde_json::to_value(RegistrationResponse::new(
+        client,
+        Some(String::from("secret")),
+    ))
+    .unwrap();
+
+    assert_eq!(response["client_id"], "client-1");
+    assert_eq!(response["client_secret"], "secret");
+    assert_eq!(response["client_secret_expires_at"], 0);
+    assert_eq!(response["client_id_issued_at"], created_at.timestamp());
+    assert_eq!(response["scope"], "openid email");
+    assert!(response.get("client_secret_hash").is_none());
+}
+
+#[test]
+fn test_initial_access_token() {
+    assert!(initial_access_token_valid(
+        Some("Bearer registration-token"),
+        Some("registration-token")
+    ));
+    assert!(!initial_access_token_valid(
+        Some("Bearer other"),
+        Some("registration-token")
+    ));
+    assert!(!initial_access_token_valid(
+        Some("registration-token"),
+        Some("registration-token")
+    ));
+    assert!(!initial_access_token_valid(
+        None,
+        Some("registration-token")
+    ));
+    // Registration is disabled without initial access token.
+    assert!(!initial_access_token_valid(Some("Bearer "), None));
+}
+
+#[test]
+fn test_consent_page_shows_client_metadata() {
+    let metadata = ClientMetadata {
+        logo_uri: Some(String::from("https://app.example.com/logo.png?a=1&b=\"2\"")),
+        policy_uri: Some(String::from("https://app.example.com/privacy")),
+        ..ClientMetadata::default()
+    };
+    let params = AuthorizeParams::default();
+
+    let html = ConsentPage {
+        client_name: "App",
+        client_metadata: &metadata,
+        scopes: &[],
+        user_email: Some("jane@example.com"),
+        params: &params,
+        csrf_token: "token",
+        message: None,
+    }
+    .render();
+
+    assert!(html.contains("src=\"https://app.example.com/logo.png?a=1&amp;b=&quot;2&quot;\""));
+    assert!(html.contains("href=\"https://app.example.com/privacy\""));
+    assert!(!html.contains("Terms of service"));
+}
+
+#[test]
+fn test_access_token_lifetime_override() {
+    let config = TokenConfig::new("auth_api", "auth_api", 3600);
+    let options = TokenOptions {
+        lifetime: Some(300),
+        ..TokenOptions::default()
+    };
+
+    let claims = config.claims("user-1", &options).unwrap();
+    assert_eq!(claims.exp - claims.iat, 300);
+
+    let claims = config.claims("user-1", &TokenOptions::default()).unwrap();
+    assert_eq!(claims.exp - claims.iat, 3600);
+}
+
+#[test]
+fn test_registration_endpoint_is_not_advertised_by_default() {
+    let metadata = ProviderMetadata::new(
+        "https://auth.example.com",
+        "https://auth.example.com",
+        "EdDSA",
+    );
+    assert_eq!(metadata.registration_endpoint, None);
+}
@@ -0,0 +1,46 @@
+use auth_api::services::client_registration::RegistrationRequest;
+use auth_api::services::consent::consent_required;
+use serde_json::json;
+
+fn scopes(scopes: &[&str]) -> Vec<String> {
+    scopes.iter().map(|scope| scope.to_string()).collect()
+}
+
+#[test]
+fn test_consent_required_without_grant() {
+    assert!(consent_required(false, None, &scopes(&["openid"])));
+}
+
+#[test]
+fn test_consent_not_required_for_first_party_clients() {
+    assert!(!consent_required(true, None, &scopes(&["openid", "email"])));
+}
+
+#[test]
+fn test_consent_covers_requested_scopes() {
+    let granted = scopes(&["openid", "email", "profile"]);
+
+    assert!(!consent_required(
+        false,
+        Some(&granted),
+        &scopes(&["openid", "email"])
+    ));
+    assert!(!consent_required(false, Some(&granted), &granted));
+    assert!(consent_required(
+        false,
+        Some(&granted),
+        &scopes(&["openid", "offline_access"])
+    ));
+}
+
+#[test]
+fn test_registered_clients_are_not_first_party() {
+    let request: RegistrationRequest = serde_json::from_value(json!({
+        "client_name": "Grafana",
+        "redirect_uris": ["https://grafana.example.com/login/generic_oauth"],
+        "first_party": true
+    }))
+    .unwrap();
+
+    assert!(!request.into_registration().unwrap().first_party);
+}
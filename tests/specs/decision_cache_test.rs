@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+use std::thread::sleep;
+use std::time::Duration;
+use auth_api::config::roles::Role;
+use auth_api::services::access_control::{AccessControl, DecisionCache, SubjectGrants};
+
+fn grants() -> SubjectGrants {
+    SubjectGrants {
+        user_id: String::from("1"),
+        subject: String::from("test@example.com"),
+        roles: vec![Role::ADMIN],
+        permissions: HashSet::from([String::from("user:read")]),
+        scopes: HashSet::new(),
+    }
+}
+
+#[test]
+fn test_decision_cache_hit() {
+    let cache = DecisionCache::new(Duration::from_secs(30));
+    cache.insert("token", 1, grants());
+
+    let cached = cache.get("token", 1).unwrap();
+    assert_eq!(cached.subject, "test@example.com");
+    assert!(cached.permissions.contains("user:read"));
+    assert!(cache.get("other_token", 1).is_none());
+}
+
+#[test]
+fn test_decision_cache_role_version() {
+    let cache = DecisionCache::new(Duration::from_secs(30));
+    cache.insert("token", 1, grants());
+
+    assert!(cache.get("token", 2).is_none());
+}
+
+#[test]
+fn test_decision_cache_expiration() {
+    let cache = DecisionCache::new(Duration::from_millis(10));
+    cache.insert("token", 1, grants());
+    sleep(Duration::from_millis(20));
+
+    assert!(cache.get("token", 1).is_none());
+}
+
+#[test]
+fn test_token_id() {
+    assert_eq!(AccessControl::token_id("token"), AccessControl::token_id("token"));
+    assert_ne!(AccessControl::token_id("token"), AccessControl::token_id("other_token"));
+    assert_eq!(AccessControl::token_id("token").len(), 64);
+}
@@ -0,0 +1,162 @@
+use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use actix_web::http::StatusCode;
+use auth_api::config::permissions::{AUTHZ_WRITE, OAUTH_CLIENT_MANAGE, SERVICE_ACCOUNT_MANAGE};
+use auth_api::controllers::oauth::token_controller::delegated_token_options;
+use auth_api::controllers::v1::auth_controller::login_audience;
+use auth_api::repository::oauth_repository::{ClientMetadata, OAuthClient};
+use auth_api::services::access_control::{AccessControl, Authorization, GrantAccess};
+use auth_api::services::crypto::{Jwt, JwtService, TokenConfig, TokenOptions};
+use chrono::Utc;
+use serde_json::Map;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashSet;
+use std::env;
+use std::time::Duration;
+
+/// A client registered by anyone, granted `openid profile` by an admin.
+fn third_party_client() -> OAuthClient {
+    OAuthClient {
+        client_id: String::from("third-party"),
+        client_secret_hash: None,
+        name: String::from("Third party"),
+        redirect_uris: vec![String::from("https://third-party.example/callback")],
+        scopes: vec![String::from("openid"), String::from("profile")],
+        grant_types: vec![String::from("authorization_code")],
+        token_endpoint_auth_method: String::from("none"),
+        jwks: None,
+        first_party: false,
+        metadata: ClientMetadata::default(),
+        previous_client_secret_hash: None,
+        previous_secret_expires_at: None,
+        created_at: Utc::now(),
+    }
+}
+
+fn bearer(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+    );
+    headers
+}
+
+/// Never connected to, the tokens are refused before any query.
+fn access_control() -> AccessControl {
+    let pool = PgPoolOptions::new()
+        .connect_lazy("postgres://postgres@localhost/unused")
+        .unwrap();
+    AccessControl::with_pool(pool, Duration::from_secs(30))
+}
+
+fn delegated_admin_token(config: &TokenConfig) -> String {
+    let options = delegated_token_options(
+        config,
+        &third_party_client(),
+        vec![String::from("ROLE_ADMIN")],
+        "openid profile",
+        Map::new(),
+    );
+    JwtService::generate_jwt_with("admin-1", &options).unwrap()
+}
+
+#[test]
+fn test_delegated_tokens_are_for_the_oauth_audience() {
+    env::set_var("JWT_SECRET", "valid_secret");
+    let config = TokenConfig::from_env();
+    let token = delegated_admin_token(&config);
+
+    let claims = JwtService::verify_jwt_for(&token, &config, &[&config.oauth_audience]).unwrap();
+    assert_eq!(claims.aud, config.oauth_audience);
+    assert_ne!(claims.aud, config.audience);
+    assert_eq!(claims.client_id.as_deref(), Some("third-party"));
+
+    assert!(JwtService::verify_jwt(&token).is_err());
+    assert!(AccessControl::claims_from_request(&bearer(&token)).is_err());
+}
+
+#[tokio::test]
+async fn test_delegated_tokens_are_refused_on_management_endpoints() {
+    env::set_var("JWT_SECRET", "valid_secret");
+    let token = delegated_admin_token(&TokenConfig::from_env());
+    let access_control = access_control();
+
+    for permission in [OAUTH_CLIENT_MANAGE, SERVICE_ACCOUNT_MANAGE, AUTHZ_WRITE] {
+        assert!(matches!(
+            access_control
+                .with_request_permission(&bearer(&token), permission)
+                .await,
+            Authorization::Unauthorized(_)
+        ));
+    }
+    assert!(access_control.grants_for_token(&token).await.is_err());
+}
+
+#[tokio::test]
+async fn test_delegated_tokens_need_a_scope_for_the_permission() {
+    env::set_var("JWT_SECRET", "valid_secret");
+    // Delegated for the audience of this service, e.g. when `JWT_OAUTH_AUDIENCE` is the same.
+    let options = TokenOptions {
+        roles: vec![String::from("ROLE_ADMIN")],
+        scope: Some(String::from("openid profile")),
+        client_id: Some(String::from("third-party")),
+        ..Default::default()
+    };
+    let token = JwtService::generate_jwt_with("admin-1", &options).unwrap();
+    assert!(AccessControl::claims_from_request(&bearer(&token)).is_ok());
+
+    let access_control = access_control();
+    for permission in [OAUTH_CLIENT_MANAGE, SERVICE_ACCOUNT_MANAGE, AUTHZ_WRITE] {
+        match access_control
+            .with_request_permission(&bearer(&token), permission)
+            .await
+        {
+            Authorization::Unauthorized(err) => assert_eq!(err.to_string(), "Insufficient scope"),
+            Authorization::Authorized => panic!("{} granted without its scope", permission),
+        }
+    }
+}
+
+fn set(values: &[&str]) -> HashSet<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[test]
+fn test_delegated_permissions() {
+    let admin = set(&["*"]);
+    assert_eq!(
+        AccessControl::delegated_permissions(&admin, &set(&["openid", "profile"])),
+        set(&["openid", "profile"])
+    );
+
+    let user = set(&["user:read", "stats:read"]);
+    assert_eq!(
+        AccessControl::delegated_permissions(&user, &set(&["user:*", "oauth_client:manage"])),
+        set(&["user:read"])
+    );
+    assert_eq!(
+        AccessControl::delegated_permissions(&set(&["user:*"]), &set(&["user:read"])),
+        set(&["user:read"])
+    );
+    assert!(AccessControl::delegated_permissions(&user, &HashSet::new()).is_empty());
+}
+
+#[test]
+fn test_login_does_not_issue_tokens_of_the_oauth_audience() {
+    let mut config = TokenConfig::new("auth_api", "auth_api", 3600);
+    config.add_audience("mobile", 60);
+
+    let response = login_audience(&config, Some("oauth")).unwrap_err();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = login_audience(&config, Some("unknown")).unwrap_err();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(
+        login_audience(&config, None).unwrap(),
+        (String::from("auth_api"), 3600)
+    );
+    assert_eq!(
+        login_audience(&config, Some("mobile")).unwrap(),
+        (String::from("mobile"), 60)
+    );
+}
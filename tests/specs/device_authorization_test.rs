@@ -0,0 +1,107 @@
+use auth_api::controllers::oauth::pages::DeviceConsentPage;
+use auth_api::repository::oauth_repository::ClientMetadata;
+use auth_api::services::device_authorization::{
+    check_poll, format_user_code, generate_user_code, normalize_user_code, STATUS_APPROVED,
+    STATUS_DENIED, STATUS_PENDING,
+};
+use auth_api::services::oauth::OAuthErrorCode;
+use auth_api::services::oidc::ProviderMetadata;
+use chrono::{Duration, Utc};
+
+#[test]
+fn test_generate_user_code() {
+    for _ in 0..100 {
+        let code = generate_user_code();
+        assert_eq!(code.len(), 8);
+        assert_eq!(normalize_user_code(&code), Some(code.clone()));
+        assert!(!code.contains(['A', 'E', 'I', 'O', 'U', 'Y', '0', '1']));
+    }
+    assert_ne!(generate_user_code(), generate_user_code());
+}
+
+#[test]
+fn test_normalize_user_code() {
+    assert_eq!(
+        normalize_user_code("wdjb-mjht"),
+        Some(String::from("WDJBMJHT"))
+    );
+    assert_eq!(
+        normalize_user_code(" WDJB MJHT "),
+        Some(String::from("WDJBMJHT"))
+    );
+    assert_eq!(normalize_user_code("WDJB-MJH"), None);
+    assert_eq!(normalize_user_code("WDJB-MJHTX"), None);
+    assert_eq!(normalize_user_code("WDJB-MJH0"), None);
+    assert_eq!(normalize_user_code("WDJB-MJHA"), None);
+}
+
+#[test]
+fn test_format_user_code() {
+    assert_eq!(format_user_code("WDJBMJHT"), "WDJB-MJHT");
+}
+
+#[test]
+fn test_check_poll() {
+    let now = Utc::now();
+    let expires_at = now + Duration::seconds(600);
+
+    let pending = check_poll(STATUS_PENDING, None, 5, expires_at, now).unwrap_err();
+    assert_eq!(pending.error, OAuthErrorCode::AuthorizationPending);
+
+    let slow = now - Duration::seconds(6);
+    let pending = check_poll(STATUS_PENDING, Some(slow), 5, expires_at, now).unwrap_err();
+    assert_eq!(pending.error, OAuthErrorCode::AuthorizationPending);
+
+    let fast = now - Duration::seconds(2);
+    let slow_down = check_poll(STATUS_PENDING, Some(fast), 5, expires_at, now).unwrap_err();
+    assert_eq!(slow_down.error, OAuthErrorCode::SlowDown);
+
+    assert!(check_poll(STATUS_APPROVED, Some(fast), 5, expires_at, now).is_ok());
+
+    let denied = check_poll(STATUS_DENIED, None, 5, expires_at, now).unwrap_err();
+    assert_eq!(denied.error, OAuthErrorCode::AccessDenied);
+
+    let consumed = check_poll("consumed", None, 5, expires_at, now).unwrap_err();
+    assert_eq!(consumed.error, OAuthErrorCode::InvalidGrant);
+
+    let expired = check_poll(STATUS_APPROVED, None, 5, now, now).unwrap_err();
+    assert_eq!(expired.error, OAuthErrorCode::ExpiredToken);
+}
+
+#[test]
+fn test_device_consent_page_escapes_values() {
+    let scopes = vec![String::from("<script>")];
+    let html = DeviceConsentPage {
+        client_name: "<b>CLI</b>",
+        client_metadata: &ClientMetadata::default(),
+        scopes: &scopes,
+        user_code: "WDJB-MJHT",
+        user_email: None,
+        csrf_token: "token",
+        message: None,
+    }
+    .render();
+
+    assert!(html.contains("&lt;b&gt;CLI&lt;/b&gt;"));
+    assert!(html.contains("&lt;script&gt;"));
+    assert!(!html.contains("<script>"));
+    assert!(html.contains("WDJB-MJHT"));
+    assert!(html.contains("name=\"password\""));
+}
+
+#[test]
+fn test_provider_metadata_device_authorization_endpoint() {
+    let metadata = ProviderMetadata::new(
+        "https://auth.example.com",
+        "https://auth.example.com",
+        "EdDSA",
+    );
+
+    assert_eq!(
+        metadata.device_authorization_endpoint,
+        "https://auth.example.com/oauth/device_authorization"
+    );
+    assert!(metadata.grant_types_supported.contains(&String::from(
+        "urn:ietf:params:oauth:grant-type:device_code"
+    )));
+}
@@ -0,0 +1,90 @@
+use auth_api::config::roles::Role;
+use auth_api::services::forward_auth::{canonical_path, ForwardAuthDecision, ForwardAuthRules};
+
+const RULES: &str = r#"{
+  "rules": [
+    { "path_prefix": "/health", "public": true },
+    { "path_prefix": "/admin", "roles": ["ROLE_ADMIN"] },
+    { "path_prefix": "/api", "methods": ["DELETE"], "roles": ["ROLE_SUPER_ADMIN"] }
+  ]
+}"#;
+
+#[test]
+fn test_public_paths() {
+    let rules = ForwardAuthRules::from_json(RULES).unwrap();
+
+    assert!(rules.is_public("GET", "/health"));
+    assert!(rules.is_public("GET", "/health/live?verbose=1"));
+    assert!(!rules.is_public("GET", "/healthz"));
+    assert!(!rules.is_public("GET", "/admin"));
+}
+
+#[test]
+fn test_role_rules() {
+    let rules = ForwardAuthRules::from_json(RULES).unwrap();
+
+    assert_eq!(rules.evaluate("GET", "/admin/users", &[Role::ADMIN]), ForwardAuthDecision::Allowed);
+    assert_eq!(rules.evaluate("GET", "/admin/users", &[Role::USER]), ForwardAuthDecision::Forbidden);
+    assert_eq!(rules.evaluate("GET", "/administrator", &[Role::USER]), ForwardAuthDecision::Allowed);
+}
+
+#[test]
+fn test_method_rules() {
+    let rules = ForwardAuthRules::from_json(RULES).unwrap();
+
+    assert_eq!(rules.evaluate("GET", "/api/items", &[Role::USER]), ForwardAuthDecision::Allowed);
+    assert_eq!(rules.evaluate("delete", "/api/items/1", &[Role::ADMIN]), ForwardAuthDecision::Forbidden);
+    assert_eq!(
+        rules.evaluate("DELETE", "/api/items/1", &[Role::SUPER_ADMIN]),
+        ForwardAuthDecision::Allowed
+    );
+}
+
+#[test]
+fn test_invalid_rules() {
+    assert!(ForwardAuthRules::from_json(r#"{ "rules": [{ "path_prefix": "/", "roles": ["admin"] }] }"#).is_err());
+}
+
+#[test]
+fn test_canonical_paths() {
+    assert_eq!(canonical_path("/admin/users?page=2#top").as_deref(), Some("/admin/users"));
+    assert_eq!(canonical_path("//admin").as_deref(), Some("/admin"));
+    assert_eq!(canonical_path("/%61dmin/%7Euser").as_deref(), Some("/admin/~user"));
+    assert_eq!(canonical_path("/x/../admin/./users/").as_deref(), Some("/admin/users"));
+    assert_eq!(canonical_path("/%2e%2E/admin").as_deref(), Some("/admin"));
+    assert_eq!(canonical_path("/files/a%20b%3f").as_deref(), Some("/files/a%20b%3F"));
+    assert_eq!(canonical_path("").as_deref(), None);
+
+    assert_eq!(canonical_path("/admin%2Fusers"), None);
+    assert_eq!(canonical_path("/admin%5cusers"), None);
+    assert_eq!(canonical_path("/admin\\users"), None);
+    assert_eq!(canonical_path("/admin%zz"), None);
+    assert_eq!(canonical_path("/admin%6"), None);
+    assert_eq!(canonical_path("http://example.com/admin"), None);
+    assert_eq!(canonical_path("/admin;x/users"), None);
+    assert_eq!(canonical_path("/admin;jsessionid=1"), None);
+    assert_eq!(canonical_path("/admin%3Bx/users"), None);
+}
+
+#[test]
+fn test_non_canonical_paths_do_not_bypass_rules() {
+    let rules = ForwardAuthRules::from_json(RULES).unwrap();
+
+    for path in ["//admin", "/%61dmin", "/x/../admin", "/health/../admin", "/health/%2e%2e/admin"] {
+        assert!(!rules.is_public("GET", path), "{}", path);
+        assert_eq!(rules.evaluate("GET", path, &[Role::USER]), ForwardAuthDecision::Forbidden, "{}", path);
+        assert_eq!(rules.evaluate("GET", path, &[Role::ADMIN]), ForwardAuthDecision::Allowed, "{}", path);
+    }
+    assert!(rules.is_public("GET", "//health/./live"));
+
+    assert!(!rules.is_public("GET", "/health/..%2Fadmin"));
+    assert_eq!(
+        rules.evaluate("GET", "/health%2F..%2Fadmin", &[Role::ADMIN]),
+        ForwardAuthDecision::InvalidPath
+    );
+    assert_eq!(
+        rules.evaluate("GET", "/admin;x/users", &[Role::USER]),
+        ForwardAuthDecision::InvalidPath
+    );
+    assert!(!rules.is_public("GET", "/health;x/../admin"));
+}
@@ -0,0 +1,90 @@
+use auth_api::controllers::oauth::pages::external_login_done_page;
+use auth_api::repository::user_repository::User;
+use auth_api::services::identity_provider::{
+    recently_authenticated, ExternalIdentity, Provisioning, REAUTHENTICATION_MAX_AGE_SECONDS,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+fn identity(email: Option<&str>, email_verified: bool) -> ExternalIdentity {
+    ExternalIdentity {
+        provider: String::from("google"),
+        subject: String::from("248289761001"),
+        email: email.map(String::from),
+        email_verified,
+        name: None,
+    }
+}
+
+fn user(email_verified: bool) -> User {
+    serde_json::from_value(json!({
+        "id": "user-1",
+        "email": "jane@example.com",
+        "password": "",
+        "role": ["ROLE_USER"],
+        "organization": null,
+        "email_verified": email_verified
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_provision_new_users_with_verified_email() {
+    let verified = identity(Some("jane@example.com"), true);
+    assert_eq!(Provisioning::of(&verified, None), Provisioning::Create);
+
+    assert_eq!(
+        Provisioning::of(&identity(Some("jane@example.com"), false), None),
+        Provisioning::Refuse
+    );
+    assert_eq!(
+        Provisioning::of(&identity(None, true), None),
+        Provisioning::Refuse
+    );
+}
+
+#[test]
+fn test_link_only_when_both_emails_are_verified() {
+    let verified = identity(Some("jane@example.com"), true);
+    assert_eq!(
+        Provisioning::of(&verified, Some(&user(true))),
+        Provisioning::Link(String::from("user-1"))
+    );
+
+    // Someone may have registered the email without owning it.
+    assert_eq!(
+        Provisioning::of(&verified, Some(&user(false))),
+        Provisioning::Refuse
+    );
+    // Someone may have set the email on their provider account without owning it.
+    assert_eq!(
+        Provisioning::of(
+            &identity(Some("jane@example.com"), false),
+            Some(&user(true))
+        ),
+        Provisioning::Refuse
+    );
+}
+
+#[test]
+fn test_recently_authenticated() {
+    let now = Utc::now();
+    assert!(recently_authenticated(now, now));
+    assert!(recently_authenticated(
+        now - Duration::seconds(REAUTHENTICATION_MAX_AGE_SECONDS),
+        now
+    ));
+    assert!(!recently_authenticated(
+        now - Duration::seconds(REAUTHENTICATION_MAX_AGE_SECONDS + 1),
+        now
+    ));
+}
+
+#[test]
+fn test_done_page_navigates_to_return_to() {
+    let page = external_login_done_page("Account linked", "/settings?tab=\"security\"");
+    assert!(page.contains("<title>Account linked</title>"));
+    assert!(page.contains(
+        "<meta http-equiv=\"refresh\" content=\"0; url=/settings?tab=&quot;security&quot;\">"
+    ));
+}
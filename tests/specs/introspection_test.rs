@@ -0,0 +1,116 @@
+use auth_api::repository::token_repository::RefreshToken;
+use auth_api::services::crypto::{Jwt, JwtService, TokenOptions};
+use auth_api::services::introspection::Introspection;
+use auth_api::services::oidc::ProviderMetadata;
+use auth_api::services::revocation::RevocationList;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use std::env;
+
+fn refresh_token(revoked: bool, expires_in: i64) -> RefreshToken {
+    let now = Utc::now();
+    RefreshToken {
+        client_id: String::from("cli"),
+        user_id: String::from("user-1"),
+        scope: String::from("openid profile"),
+        auth_time: now,
+        created_at: now,
+        expires_at: now + Duration::seconds(expires_in),
+        revoked_at: revoked.then_some(now),
+    }
+}
+
+#[test]
+fn test_revoked_access_token_is_rejected() {
+    env::set_var("JWT_SECRET", "valid_secret");
+    let token = JwtService::generate_jwt("user-1").unwrap();
+    let claims = JwtService::verify_jwt(&token).unwrap();
+
+    RevocationList::revoke(&claims.jti, claims.exp);
+
+    assert!(RevocationList::is_revoked(&claims.jti));
+    assert!(JwtService::verify_jwt(&token).is_err());
+}
+
+#[test]
+fn test_revocation_sync_drops_expired_entries() {
+    let past = (Utc::now() - Duration::seconds(10)).timestamp() as u64;
+    let future = (Utc::now() + Duration::seconds(600)).timestamp() as u64;
+
+    RevocationList::sync([
+        (String::from("expired-jti"), past),
+        (String::from("revoked-jti"), future),
+    ]);
+
+    assert!(!RevocationList::is_revoked("expired-jti"));
+    assert!(RevocationList::is_revoked("revoked-jti"));
+    assert!(!RevocationList::is_revoked("other-jti"));
+}
+
+#[test]
+fn test_introspect_access_token() {
+    env::set_var("JWT_SECRET", "valid_secret");
+    let options = TokenOptions {
+        scope: Some(String::from("openid email")),
+        client_id: Some(String::from("cli")),
+        ..TokenOptions::default()
+    };
+    let token = JwtService::generate_jwt_with("user-1", &options).unwrap();
+    let claims = JwtService::verify_jwt(&token).unwrap();
+
+    let introspection = Introspection::from_claims(&claims);
+
+    assert!(introspection.active);
+    assert_eq!(introspection.sub.as_deref(), Some("user-1"));
+    assert_eq!(introspection.scope.as_deref(), Some("openid email"));
+    assert_eq!(introspection.client_id.as_deref(), Some("cli"));
+    assert_eq!(introspection.exp, Some(claims.exp));
+    assert_eq!(introspection.jti, Some(claims.jti));
+}
+
+#[test]
+fn test_introspect_refresh_token() {
+    let now = Utc::now();
+
+    let active = Introspection::from_refresh_token(&refresh_token(false, 600), now);
+    assert!(active.active);
+    assert_eq!(active.sub.as_deref(), Some("user-1"));
+    assert_eq!(active.client_id.as_deref(), Some("cli"));
+    assert_eq!(active.scope.as_deref(), Some("openid profile"));
+    assert_eq!(active.token_type.as_deref(), Some("refresh_token"));
+
+    let revoked = Introspection::from_refresh_token(&refresh_token(true, 600), now);
+    assert_eq!(revoked, Introspection::inactive());
+
+    let expired = Introspection::from_refresh_token(&refresh_token(false, -1), now);
+    assert_eq!(expired, Introspection::inactive());
+}
+
+#[test]
+fn test_inactive_token_discloses_nothing() {
+    assert_eq!(
+        serde_json::to_value(Introspection::inactive()).unwrap(),
+        json!({ "active": false })
+    );
+}
+
+#[test]
+fn test_provider_metadata_token_endpoints() {
+    let metadata = ProviderMetadata::new(
+        "https://auth.example.com",
+        "https://auth.example.com",
+        "EdDSA",
+    );
+
+    assert_eq!(
+        metadata.introspection_endpoint,
+        "https://auth.example.com/oauth/introspect"
+    );
+    assert_eq!(
+        metadata.revocation_endpoint,
+        "https://auth.example.com/oauth/revoke"
+    );
+    assert!(metadata
+        .grant_types_supported
+        .contains(&String::from("refresh_token")));
+}
@@ -2,6 +2,8 @@ use std::env;
 
 use auth_api::services::crypto::JwtService;
 use auth_api::services::crypto::Jwt;
+use auth_api::services::crypto::{Claims, TokenConfig, TokenOptions};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header};
 
 #[test]
 fn test_generate_jwt_success() {
@@ -24,6 +26,20 @@ fn test_generate_jwt_missing_secret() {
     let _ = JwtService::generate_jwt(email);
 }
 
+#[test]
+fn test_verify_jwt_without_secret_is_invalid() {
+    env::remove_var("JWT_SECRET");
+    let token = encode(
+        &Header::default(),
+        &serde_json::json!({ "sub": "user-1" }),
+        &EncodingKey::from_secret(b"any_secret"),
+    )
+    .unwrap();
+
+    assert!(JwtService::verify_jwt(&token).is_err());
+    assert!(JwtService::verify_jwt("not.a.token").is_err());
+}
+
 #[test]
 fn test_generate_jwt_empty_email() {
     env::set_var("JWT_SECRET", "valid_secret");
@@ -66,4 +82,112 @@ fn test_verify_jwt_invalid_secret() {
     env::set_var("JWT_SECRET", "invalid_secret");
     let result = JwtService::verify_jwt(valid_token.unwrap().as_str());
     assert!(result.is_err());
-}
\ No newline at end of file
+}
+#[test]
+fn test_claims_carry_standard_fields() {
+    let mut config = TokenConfig::new("https://auth.example.com", "web", 600);
+    config.add_audience("mobile", 3600);
+    let options = TokenOptions {
+        audience: Some(String::from("mobile")),
+        roles: vec![String::from("ROLE_USER")],
+        scope: Some(String::from("orders:read")),
+        ..Default::default()
+    };
+
+    let claims = config.claims("user-1", &options).unwrap();
+    assert_eq!(claims.iss, "https://auth.example.com");
+    assert_eq!(claims.sub, "user-1");
+    assert_eq!(claims.aud, "mobile");
+    assert_eq!(claims.exp - claims.iat, 3600);
+    assert_eq!(claims.nbf, claims.iat);
+    assert_eq!(claims.roles, vec!["ROLE_USER"]);
+    assert_eq!(claims.scope.as_deref(), Some("orders:read"));
+
+    let other = config.claims("user-1", &options).unwrap();
+    assert_ne!(claims.jti, other.jti);
+
+    let default = config.claims("user-1", &TokenOptions::default()).unwrap();
+    assert_eq!(default.aud, "web");
+    assert_eq!(default.exp - default.iat, 600);
+}
+
+#[test]
+fn test_claims_unknown_audience() {
+    let config = TokenConfig::new("auth_api", "web", 600);
+    let options = TokenOptions {
+        audience: Some(String::from("billing")),
+        ..Default::default()
+    };
+    assert!(config.claims("user-1", &options).is_err());
+}
+
+fn sign(claims: &Claims) -> String {
+    encode(&Header::default(), claims, &EncodingKey::from_secret(b"secret")).unwrap()
+}
+
+fn check(config: &TokenConfig, token: &str) -> bool {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(b"secret"),
+        &config.validation(Algorithm::HS256),
+    )
+    .is_ok()
+}
+
+#[test]
+fn test_validation_checks_issuer_and_audience() {
+    let config = TokenConfig::new("auth_api", "web", 600);
+    let claims = config.claims("user-1", &TokenOptions::default()).unwrap();
+    assert!(check(&config, &sign(&claims)));
+
+    let other_issuer = TokenConfig::new("evil", "web", 600);
+    assert!(!check(&other_issuer, &sign(&claims)));
+
+    let other_audience = TokenConfig::new("auth_api", "billing", 600);
+    assert!(!check(&other_audience, &sign(&claims)));
+}
+
+#[test]
+fn test_validation_other_audiences_only_when_allowed() {
+    let mut config = TokenConfig::new("auth_api", "web", 600);
+    config.add_audience("cli", 3600);
+    let options = TokenOptions {
+        audience: Some(String::from("cli")),
+        ..Default::default()
+    };
+    let token = sign(&config.claims("user-1", &options).unwrap());
+    assert!(!check(&config, &token));
+
+    let allowed = |audiences: &[&str]| {
+        decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(b"secret"),
+            &config.validation_for(Algorithm::HS256, audiences),
+        )
+        .is_ok()
+    };
+    assert!(allowed(&["cli"]));
+    assert!(allowed(&config.audiences()));
+    assert!(!allowed(&["web"]));
+}
+
+#[test]
+fn test_validation_leeway() {
+    let mut config = TokenConfig::new("auth_api", "web", 600);
+    config.leeway = 30;
+    let mut claims = config.claims("user-1", &TokenOptions::default()).unwrap();
+
+    // Issued by a server whose clock is 10 seconds ahead.
+    claims.nbf += 10;
+    assert!(check(&config, &sign(&claims)));
+
+    claims.nbf += 60;
+    assert!(!check(&config, &sign(&claims)));
+
+    claims.nbf = claims.iat;
+    claims.exp = claims.iat - 10;
+    assert!(check(&config, &sign(&claims)));
+
+    claims.exp = claims.iat - 60;
+    assert!(!check(&config, &sign(&claims)));
+}
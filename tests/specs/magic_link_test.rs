@@ -0,0 +1,150 @@
+use auth_api::controllers::oauth::pages::sms_code_page;
+use auth_api::controllers::{base_url, configured_base_url};
+use auth_api::services::crypto::TokenConfig;
+use auth_api::services::magic_link::{
+    magic_link_email, normalized_email, MagicLinkLimiter, PendingMagicLink, MAX_LINKS_PER_EMAIL,
+    MAX_LINKS_PER_IP, RATE_LIMIT_WINDOW,
+};
+use auth_api::services::mailer::{Email, LogMailer, Mailer};
+use auth_api::services::rate_limit::RateLimiter;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_rate_limiter_window() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert!(limiter.hit("jane@example.com", start));
+    assert!(limiter.hit("jane@example.com", start + Duration::from_secs(10)));
+    assert!(!limiter.hit("jane@example.com", start + Duration::from_secs(20)));
+    assert!(limiter.hit("john@example.com", start + Duration::from_secs(20)));
+
+    // Refused hits are not counted, the first hit leaves the window after a minute.
+    assert!(limiter.hit("jane@example.com", start + Duration::from_secs(60)));
+    assert!(!limiter.hit("jane@example.com", start + Duration::from_secs(65)));
+    assert!(limiter.hit("jane@example.com", start + Duration::from_secs(70)));
+}
+
+#[test]
+fn test_magic_links_are_limited_by_email_and_ip() {
+    let limiter = MagicLinkLimiter::default();
+    let start = Instant::now();
+
+    for _ in 0..MAX_LINKS_PER_EMAIL {
+        assert!(limiter.allow("jane@example.com", Some("203.0.113.7"), start));
+    }
+    assert!(!limiter.allow("jane@example.com", Some("198.51.100.1"), start));
+    assert!(limiter.allow("jane@example.com", None, start + RATE_LIMIT_WINDOW));
+
+    let limiter = MagicLinkLimiter::default();
+    for i in 0..MAX_LINKS_PER_IP {
+        let email = format!("user{}@example.com", i);
+        assert!(limiter.allow(&email, Some("203.0.113.7"), start));
+    }
+    assert!(!limiter.allow("jane@example.com", Some("203.0.113.7"), start));
+    assert!(limiter.allow("jane@example.com", Some("198.51.100.1"), start));
+}
+
+#[test]
+fn test_normalized_email() {
+    assert_eq!(
+        normalized_email(" Jane@Example.com ").as_deref(),
+        Some("jane@example.com")
+    );
+    assert_eq!(normalized_email("jane"), None);
+    assert_eq!(normalized_email("@example.com"), None);
+    assert_eq!(normalized_email("jane@"), None);
+    assert_eq!(normalized_email("jane doe@example.com"), None);
+    assert_eq!(
+        normalized_email(&format!("{}@example.com", "a".repeat(250))),
+        None
+    );
+}
+
+#[test]
+fn test_pending_magic_links_are_random() {
+    let link = PendingMagicLink::generate();
+    let other = PendingMagicLink::generate();
+
+    assert_ne!(link.token, link.nonce);
+    assert_ne!(link.token, other.token);
+    assert!(link.token.len() >= 43);
+}
+
+#[test]
+fn test_magic_link_email() {
+    let email = magic_link_email(
+        "jane@example.com",
+        "https://auth.example.com/api/v1/login/magic-link/callback?token=abc",
+    );
+
+    assert_eq!(email.to, "jane@example.com");
+    assert!(email
+        .body
+        .contains("https://auth.example.com/api/v1/login/magic-link/callback?token=abc"));
+    assert!(email.body.contains("15 minutes"));
+}
+
+#[tokio::test]
+async fn test_log_mailer() {
+    let email = Email {
+        to: String::from("jane@example.com"),
+        subject: String::from("Your sign-in link"),
+        body: String::from("https://auth.example.com"),
+    };
+
+    assert!(LogMailer.send(&email).await.is_ok());
+}
+
+#[test]
+fn test_sms_code_page() {
+    let html = sms_code_page(
+        "challenge\"><script>",
+        "/settings?tab=\"security\"",
+        Some("+3********78"),
+        None,
+    );
+    assert!(html.contains("<form method=\"post\" action=\"mfa\">"));
+    assert!(html.contains(
+        "<input type=\"hidden\" name=\"challenge\" value=\"challenge&quot;&gt;&lt;script&gt;\">"
+    ));
+    assert!(html.contains(
+        "<input type=\"hidden\" name=\"return_to\" value=\"/settings?tab=&quot;security&quot;\">"
+    ));
+    assert!(html.contains("+3********78"));
+    assert!(!html.contains("<script>"));
+    assert!(!html.contains("role=\"alert\""));
+
+    let html = sms_code_page("challenge", "/", None, Some("Invalid or expired code"));
+    assert!(html.contains("<p role=\"alert\">Invalid or expired code</p>"));
+    assert!(!html.contains("A code was sent"));
+}
+
+#[test]
+fn test_links_are_built_from_a_configured_url() {
+    assert_eq!(
+        base_url("https://auth.example.com/").as_deref(),
+        Some("https://auth.example.com")
+    );
+    assert_eq!(
+        base_url("http://localhost:4000").as_deref(),
+        Some("http://localhost:4000")
+    );
+    assert_eq!(base_url("auth_api"), None);
+    assert_eq!(base_url("https://"), None);
+    assert_eq!(
+        base_url("https://example.com/auth/").as_deref(),
+        Some("https://example.com/auth")
+    );
+    assert_eq!(base_url("https://auth.example.com?next=/"), None);
+    assert_eq!(base_url("https://user@evil.example.com"), None);
+    assert_eq!(base_url("javascript:alert(1)"), None);
+
+    let config = TokenConfig::new("https://auth.example.com", "auth_api", 60);
+    assert_eq!(
+        configured_base_url(&config).as_deref(),
+        Some("https://auth.example.com")
+    );
+    let config = TokenConfig::new("auth_api", "auth_api", 60);
+    assert_eq!(configured_base_url(&config), None);
+}
@@ -1,5 +1,26 @@
 mod hash_test;
 mod jwt_test;
 mod access_control_test;
+mod authz_test;
+mod permission_test;
+mod policy_test;
 mod role;
 mod csrf_test;
+mod decision_cache_test;
+mod forward_auth_test;
+mod auth_client_test;
+mod claims_test;
+mod oauth_test;
+mod oidc_test;
+mod service_account_test;
+mod device_authorization_test;
+mod introspection_test;
+mod client_registration_test;
+mod consent_test;
+mod identity_test;
+mod saml_test;
+mod scim_test;
+mod authenticator_test;
+mod magic_link_test;
+mod sms_code_test;
+mod delegated_token_test;
@@ -0,0 +1,223 @@
+use actix_web::App;
+use auth_api::controllers::openid_configuration;
+use auth_api::repository::oauth_repository::{ClientMetadata, OAuthClient};
+use auth_api::repository::user_repository::User;
+use auth_api::services::crypto::{SigningKey, TokenConfig};
+use auth_api::services::oauth::{AuthorizeParams, GRANT_AUTHORIZATION_CODE};
+use auth_api::services::oidc::{
+    user_claims, userinfo, IdTokenClaims, ProviderMetadata, ID_TOKEN_LIFETIME_SECONDS,
+};
+use chrono::Utc;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use serde_json::{json, Value};
+
+fn user() -> User {
+    serde_json::from_value(json!({
+        "id": "user-1",
+        "email": "jane@example.com",
+        "password": "",
+        "role": ["ROLE_USER"],
+        "organization": null,
+        "name": "Jane Doe",
+        "email_verified": true
+    }))
+    .unwrap()
+}
+
+fn scopes(scopes: &[&str]) -> Vec<String> {
+    scopes.iter().map(|scope| scope.to_string()).collect()
+}
+
+fn generate_key() -> SigningKey {
+    let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", der.as_ref()));
+    SigningKey::from_pem(pem.as_bytes()).unwrap()
+}
+
+fn id_token_claims(scope: &[&str], nonce: Option<&str>) -> IdTokenClaims {
+    let config = TokenConfig::new("https://auth.example.com", "auth_api", 3600);
+    IdTokenClaims::new(
+        &config,
+        &user(),
+        "grafana",
+        &scopes(scope),
+        nonce.map(String::from),
+        1_700_000_000,
+    )
+}
+
+#[test]
+fn test_id_token_required_claims() {
+    let claims = id_token_claims(&["openid"], Some("n-0S6_WzA2Mj"));
+
+    assert_eq!(claims.iss, "https://auth.example.com");
+    assert_eq!(claims.sub, "user-1");
+    assert_eq!(claims.aud, "grafana");
+    assert_eq!(claims.azp, "grafana");
+    assert_eq!(claims.exp, claims.iat + ID_TOKEN_LIFETIME_SECONDS);
+    assert_eq!(claims.auth_time, 1_700_000_000);
+    assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+    // Only `openid`, no user claims are released.
+    assert!(claims.user.is_empty());
+}
+
+#[test]
+fn test_id_token_without_nonce_omits_it() {
+    let claims = serde_json::to_value(id_token_claims(&["openid"], None)).unwrap();
+
+    assert!(claims.get("nonce").is_none());
+    assert!(claims.get("auth_time").is_some());
+}
+
+#[test]
+fn test_scopes_map_to_user_claims() {
+    let claims = user_claims(&user(), &scopes(&["openid", "email"]));
+    assert_eq!(claims.get("email"), Some(&json!("jane@example.com")));
+    assert_eq!(claims.get("email_verified"), Some(&json!(true)));
+    assert!(claims.get("name").is_none());
+
+    let claims = user_claims(&user(), &scopes(&["openid", "profile"]));
+    assert_eq!(claims.get("name"), Some(&json!("Jane Doe")));
+    assert_eq!(claims.get("preferred_username"), Some(&json!("jane@example.com")));
+    assert!(claims.get("email").is_none());
+}
+
+#[test]
+fn test_userinfo_always_has_sub() {
+    let info = userinfo(&user(), &scopes(&["openid"]));
+    assert_eq!(info.get("sub"), Some(&json!("user-1")));
+    assert_eq!(info.len(), 1);
+
+    let info = userinfo(&user(), &scopes(&["openid", "profile", "email"]));
+    assert_eq!(info.get("sub"), Some(&json!("user-1")));
+    assert_eq!(info.get("email"), Some(&json!("jane@example.com")));
+}
+
+#[test]
+fn test_id_token_verifies_with_published_key() {
+    let key = generate_key();
+    let token = key
+        .sign(&id_token_claims(&["openid", "email"], Some("abc")))
+        .unwrap();
+
+    let header = decode_header(&token).unwrap();
+    assert_eq!(header.alg, Algorithm::EdDSA);
+    assert_eq!(header.kid.as_deref(), Some(key.kid()));
+
+    let mut validation = Validation::new(Algorithm::EdDSA);
+    validation.set_issuer(&["https://auth.example.com"]);
+    validation.set_audience(&["grafana"]);
+    let decoded = decode::<Value>(
+        &token,
+        &DecodingKey::from_jwk(&key.jwk()).unwrap(),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+
+    assert_eq!(decoded["sub"], "user-1");
+    assert_eq!(decoded["nonce"], "abc");
+    assert_eq!(decoded["email"], "jane@example.com");
+    assert_eq!(decoded["email_verified"], true);
+
+    // An ID token is not valid for another client.
+    validation.set_audience(&["other"]);
+    assert!(decode::<Value>(
+        &token,
+        &DecodingKey::from_jwk(&key.jwk()).unwrap(),
+        &validation
+    )
+    .is_err());
+}
+
+#[test]
+fn test_nonce_is_carried_by_the_authorization() {
+    let client = OAuthClient {
+        client_id: String::from("grafana"),
+        client_secret_hash: None,
+        name: String::from("Grafana"),
+        redirect_uris: vec![String::from("https://grafana.example.com/login/generic_oauth")],
+        scopes: scopes(&["openid", "profile", "email"]),
+        grant_types: vec![String::from(GRANT_AUTHORIZATION_CODE)],
+        token_endpoint_auth_method: String::from("none"),
+        jwks: None,
+        first_party: false,
+        metadata: ClientMetadata::default(),
+        previous_client_secret_hash: None,
+        previous_secret_expires_at: None,
+        created_at: Utc::now(),
+    };
+    let params = AuthorizeParams {
+        response_type: String::from("code"),
+        client_id: String::from("grafana"),
+        redirect_uri: String::from("https://grafana.example.com/login/generic_oauth"),
+        scope: Some(String::from("openid email")),
+        state: None,
+        code_challenge: String::from("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"),
+        code_challenge_method: String::from("S256"),
+        nonce: Some(String::from("n-0S6_WzA2Mj")),
+    };
+
+    let grant = params.validate(&client).unwrap();
+    assert_eq!(grant.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+    assert_eq!(grant.scopes, scopes(&["openid", "email"]));
+}
+
+#[test]
+fn test_provider_metadata() {
+    let metadata = ProviderMetadata::new(
+        "https://auth.example.com",
+        "https://auth.example.com/",
+        "EdDSA",
+    );
+
+    assert_eq!(metadata.issuer, "https://auth.example.com");
+    assert_eq!(
+        metadata.authorization_endpoint,
+        "https://auth.example.com/oauth/authorize"
+    );
+    assert_eq!(metadata.token_endpoint, "https://auth.example.com/oauth/token");
+    assert_eq!(
+        metadata.userinfo_endpoint,
+        "https://auth.example.com/oauth/userinfo"
+    );
+    assert_eq!(
+        metadata.jwks_uri,
+        "https://auth.example.com/.well-known/jwks.json"
+    );
+    assert!(metadata.scopes_supported.contains(&String::from("openid")));
+    assert_eq!(metadata.response_types_supported, vec!["code"]);
+    assert_eq!(metadata.subject_types_supported, vec!["public"]);
+    assert_eq!(metadata.id_token_signing_alg_values_supported, vec!["EdDSA"]);
+    assert_eq!(metadata.code_challenge_methods_supported, vec!["S256"]);
+}
+
+#[actix_web::test]
+async fn test_openid_configuration_endpoint() {
+    let app = actix_web::test::init_service(App::new().service(openid_configuration)).await;
+    let req = actix_web::test::TestRequest::get()
+        .uri("/.well-known/openid-configuration")
+        .insert_header(("Host", "auth.example.com"))
+        .to_request();
+
+    let body: Value = actix_web::test::call_and_read_body_json(&app, req).await;
+
+    // Metadata required by OpenID Connect Discovery 1.0 section 3.
+    for field in [
+        "issuer",
+        "authorization_endpoint",
+        "token_endpoint",
+        "jwks_uri",
+        "response_types_supported",
+        "subject_types_supported",
+        "id_token_signing_alg_values_supported",
+    ] {
+        assert!(body.get(field).is_some(), "missing {}", field);
+    }
+    assert!(body["authorization_endpoint"]
+        .as_str()
+        .unwrap()
+        .ends_with("/oauth/authorize"));
+}
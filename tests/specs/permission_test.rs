@@ -0,0 +1,43 @@
+use std::collections::HashSet;
+use auth_api::config::permissions::{is_valid_permission, permission_matches};
+use auth_api::services::access_control::{AccessControl, Authorization, GrantAccess};
+
+#[test]
+fn test_is_valid_permission() {
+    assert!(is_valid_permission("user:delete"));
+    assert!(is_valid_permission("user:*"));
+    assert!(is_valid_permission("*"));
+
+    assert!(!is_valid_permission("user"));
+    assert!(!is_valid_permission("User:Delete"));
+    assert!(!is_valid_permission(":delete"));
+    assert!(!is_valid_permission("*:delete"));
+}
+
+#[test]
+fn test_permission_matches() {
+    assert!(permission_matches("user:delete", "user:delete"));
+    assert!(permission_matches("user:*", "user:delete"));
+    assert!(permission_matches("*", "stats:read"));
+
+    assert!(!permission_matches("user:read", "user:delete"));
+    assert!(!permission_matches("stats:*", "user:delete"));
+}
+
+#[test]
+fn test_from_permissions() {
+    let permissions: HashSet<String> = ["user:read", "stats:*"].iter().map(|p| p.to_string()).collect();
+
+    assert!(matches!(
+        AccessControl::from_permissions(&permissions, "stats:read"),
+        Authorization::Authorized
+    ));
+    assert!(matches!(
+        AccessControl::from_permissions(&permissions, "user:delete"),
+        Authorization::Unauthorized(_)
+    ));
+    assert!(matches!(
+        AccessControl::from_permissions(&HashSet::new(), "user:read"),
+        Authorization::Unauthorized(_)
+    ));
+}
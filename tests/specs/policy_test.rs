@@ -0,0 +1,93 @@
+use auth_api::services::policy::{ip_in_cidr, PolicyContext, PolicyDecision, PolicyEngine};
+use chrono::{TimeZone, Utc};
+
+const POLICIES: &str = r#"{
+  "policies": [
+    {
+      "id": "no-hard-delete-super-admin",
+      "effect": "deny",
+      "actions": ["user:hard_delete"],
+      "conditions": [{ "attribute": "resource.roles", "operator": "contains", "value": "ROLE_SUPER_ADMIN" }],
+      "reason": "Super admin accounts cannot be hard deleted"
+    },
+    {
+      "id": "admin-own-organization",
+      "effect": "allow",
+      "actions": ["user:delete", "user:hard_delete"],
+      "conditions": [{ "attribute": "subject.organization", "operator": "equals_attribute", "value": "resource.organization" }]
+    },
+    {
+      "id": "office-network",
+      "effect": "deny",
+      "actions": ["stats:read"],
+      "conditions": [{ "attribute": "environment.hour", "operator": "hour_between", "value": [22, 6] }]
+    }
+  ]
+}"#;
+
+fn context(subject_org: &str, resource_org: &str, resource_roles: Vec<&str>) -> PolicyContext {
+    PolicyContext::new(None, Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap())
+        .with_subject("organization", subject_org)
+        .with_resource("organization", resource_org)
+        .with_resource("roles", resource_roles)
+}
+
+#[test]
+fn test_allow_same_organization() {
+    let engine = PolicyEngine::from_json(POLICIES).unwrap();
+
+    let decision = engine.evaluate("user:delete", &context("acme", "acme", vec!["ROLE_USER"]));
+    assert!(matches!(decision, PolicyDecision::Allow(_)));
+
+    let decision = engine.evaluate("user:delete", &context("acme", "globex", vec!["ROLE_USER"]));
+    assert!(matches!(decision, PolicyDecision::Deny(_)));
+}
+
+#[test]
+fn test_deny_overrides_allow() {
+    let engine = PolicyEngine::from_json(POLICIES).unwrap();
+
+    let decision = engine.evaluate(
+        "user:hard_delete",
+        &context("acme", "acme", vec!["ROLE_SUPER_ADMIN"]),
+    );
+    assert_eq!(
+        decision,
+        PolicyDecision::Deny(String::from("Super admin accounts cannot be hard deleted"))
+    );
+}
+
+#[test]
+fn test_no_applicable_policy() {
+    let engine = PolicyEngine::from_json(POLICIES).unwrap();
+
+    let decision = engine.evaluate("user:undelete", &context("acme", "globex", vec![]));
+    assert!(matches!(decision, PolicyDecision::Allow(_)));
+    assert!(matches!(
+        engine.evaluate("stats:read", &PolicyContext::default()),
+        PolicyDecision::Allow(_)
+    ));
+}
+
+#[test]
+fn test_hour_between_wraps_midnight() {
+    let engine = PolicyEngine::from_json(POLICIES).unwrap();
+    let night = PolicyContext::new(None, Utc.with_ymd_and_hms(2024, 1, 1, 23, 0, 0).unwrap());
+
+    assert!(matches!(engine.evaluate("stats:read", &night), PolicyDecision::Deny(_)));
+}
+
+#[test]
+fn test_ip_in_cidr() {
+    assert!(ip_in_cidr("10.1.2.3", "10.0.0.0/8"));
+    assert!(!ip_in_cidr("11.1.2.3", "10.0.0.0/8"));
+    assert!(ip_in_cidr("192.168.1.1", "192.168.1.1"));
+    assert!(ip_in_cidr("::1", "::1/128"));
+    assert!(ip_in_cidr("8.8.8.8", "0.0.0.0/0"));
+    assert!(!ip_in_cidr("::1", "10.0.0.0/8"));
+}
+
+#[test]
+fn test_invalid_policy_file() {
+    assert!(PolicyEngine::from_json("{ \"policies\": [{ \"id\": 1 }] }").is_err());
+}
@@ -29,4 +29,15 @@ fn test_from_str_valid() {
 #[test]
 fn test_from_str_invalid() {
     assert!(Role::from_str("INVALID_ROLE").is_err());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_from_str_custom_role() {
+    let role = Role::from_str("ROLE_SUPPORT_L2").unwrap();
+    assert_eq!(role.to_str(), "ROLE_SUPPORT_L2");
+    assert!(!role.is_builtin());
+    assert!(Role::ADMIN.is_builtin());
+
+    assert!(Role::from_str("ROLE_").is_err());
+    assert!(Role::from_str("ROLE_lowercase").is_err());
+}
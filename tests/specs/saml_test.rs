@@ -0,0 +1,449 @@
+use auth_api::services::saml::{ResponseContext, SamlProviders};
+use auth_api::services::xml_dsig::{verify_enveloped_signature, Element, NS_DSIG};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use flate2::read::DeflateDecoder;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+use std::io::Read;
+
+/// Signed by the key of `tests/fixtures/saml/idp.key`, its canonical form was computed
+/// independently of `xml_dsig`.
+const RESPONSE: &str = include_str!("../fixtures/saml/response.xml");
+const IDP_KEY: &str = include_str!("../fixtures/saml/idp.key");
+
+const SP_ENTITY_ID: &str = "https://auth.example.com/saml/metadata";
+const ACS_URL: &str = "https://auth.example.com/saml/acme/acs";
+const REQUEST_ID: &str = "_request-fixture";
+
+fn providers() -> SamlProviders {
+    SamlProviders::from_json(
+        r#"{
+            "providers": [{
+                "name": "acme",
+                "display_name": "ACME",
+                "entity_id": "https://idp.example.com/metadata",
+                "sso_url": "https://idp.example.com/sso?tenant=acme",
+                "certificate_file": "tests/fixtures/saml/idp.crt",
+                "email_attribute": "email",
+                "name_attribute": "displayName",
+                "role_attribute": "groups",
+                "role_mapping": { "admins": "ROLE_ADMIN", "auditors": "ROLE_AUDITOR" },
+                "trust_email": true
+            }]
+        }"#,
+    )
+    .unwrap()
+}
+
+fn issued_at() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2026-10-19T10:00:30Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn context(now: DateTime<Utc>) -> ResponseContext<'static> {
+    ResponseContext {
+        sp_entity_id: SP_ENTITY_ID,
+        acs_url: ACS_URL,
+        request_id: REQUEST_ID,
+        now,
+    }
+}
+
+fn encode(xml: &str) -> String {
+    STANDARD.encode(xml)
+}
+
+fn unsigned(xml: &str) -> String {
+    let start = xml.find("<ds:Signature").unwrap();
+    let end = xml.find("</ds:Signature>").unwrap() + "</ds:Signature>".len();
+    format!("{}{}", &xml[..start], &xml[end..])
+}
+
+/// Sign the assertion of `xml`, an unsigned response, as the test identity provider does.
+fn sign_assertion(xml: &str, key_pem: &[u8]) -> String {
+    let response = Element::parse(xml).unwrap();
+    let assertion = response
+        .elements()
+        .find(|element| element.name == "Assertion")
+        .unwrap();
+    let id = assertion.attribute("ID").unwrap();
+    let digest = hash(
+        MessageDigest::sha256(),
+        assertion.exclusive_c14n(None, &[]).as_bytes(),
+    )
+    .unwrap();
+
+    let signed_info = format!(
+        "<ds:SignedInfo xmlns:ds=\"{ds}\">\
+        <ds:CanonicalizationMethod Algorithm=\"http://www.w3.org/2001/10/xml-exc-c14n#\"></ds:CanonicalizationMethod>\
+        <ds:SignatureMethod Algorithm=\"http://www.w3.org/2001/04/xmldsig-more#rsa-sha256\"></ds:SignatureMethod>\
+        <ds:Reference URI=\"#{id}\"><ds:Transforms>\
+        <ds:Transform Algorithm=\"http://www.w3.org/2000/09/xmldsig#enveloped-signature\"></ds:Transform>\
+        <ds:Transform Algorithm=\"http://www.w3.org/2001/10/xml-exc-c14n#\"></ds:Transform>\
+        </ds:Transforms>\
+        <ds:DigestMethod Algorithm=\"http://www.w3.org/2001/04/xmlenc#sha256\"></ds:DigestMethod>\
+        <ds:DigestValue>{digest}</ds:DigestValue></ds:Reference></ds:SignedInfo>",
+        ds = NS_DSIG,
+        id = id,
+        digest = STANDARD.encode(digest)
+    );
+    let key = PKey::private_key_from_pem(key_pem).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer.update(signed_info.as_bytes()).unwrap();
+    let signature = format!(
+        "<ds:Signature xmlns:ds=\"{}\">{}<ds:SignatureValue>{}</ds:SignatureValue></ds:Signature>",
+        NS_DSIG,
+        signed_info.replace(&format!(" xmlns:ds=\"{}\"", NS_DSIG), ""),
+        STANDARD.encode(signer.sign_to_vec().unwrap())
+    );
+
+    let issuer_end = xml.rfind("</saml:Issuer>").unwrap() + "</saml:Issuer>".len();
+    format!("{}{}{}", &xml[..issuer_end], signature, &xml[issuer_end..])
+}
+
+/// The fixture response, changed with `edit` then signed again.
+fn resigned(edit: impl Fn(String) -> String) -> String {
+    sign_assertion(&edit(unsigned(RESPONSE)), IDP_KEY.as_bytes())
+}
+
+#[test]
+fn test_validate_fixture_response() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let assertion = provider
+        .validate_response(&encode(RESPONSE), &context(issued_at()))
+        .unwrap();
+    assert_eq!(assertion.id, "_assertion-fixture");
+    assert_eq!(assertion.name_id, "jane.doe@example.com");
+    assert_eq!(assertion.attributes["groups"], vec!["admins", "R&D"]);
+    assert_eq!(
+        assertion.expires_at,
+        DateTime::parse_from_rfc3339("2026-10-19T10:05:00Z").unwrap()
+    );
+
+    let identity = provider.identity(&assertion).unwrap();
+    assert_eq!(identity.provider, "saml:acme");
+    assert_eq!(identity.subject, "jane.doe@example.com");
+    assert_eq!(identity.email.as_deref(), Some("jane.doe@example.com"));
+    assert!(identity.email_verified);
+    assert_eq!(identity.name.as_deref(), Some("Jane Doe"));
+}
+
+#[test]
+fn test_base64_responses_may_be_wrapped() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let encoded = encode(RESPONSE);
+    let wrapped: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect();
+    assert!(provider
+        .validate_response(&wrapped.join("\r\n"), &context(issued_at()))
+        .is_ok());
+}
+
+#[test]
+fn test_refuse_tampered_assertions() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let tampered = RESPONSE.replace("Jane.Doe@Example.com", "admin@example.com");
+    assert!(provider
+        .validate_response(&encode(&tampered), &context(issued_at()))
+        .is_err());
+
+    assert!(provider
+        .validate_response(&encode(&unsigned(RESPONSE)), &context(issued_at()))
+        .is_err());
+}
+
+#[test]
+fn test_refuse_assertions_signed_by_another_key() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let other_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let forged = sign_assertion(
+        &unsigned(RESPONSE),
+        &other_key.private_key_to_pem_pkcs8().unwrap(),
+    );
+    assert!(provider
+        .validate_response(&encode(&forged), &context(issued_at()))
+        .is_err());
+
+    // Signed again by the provider, the same response is accepted.
+    assert!(provider
+        .validate_response(&encode(&resigned(|xml| xml)), &context(issued_at()))
+        .is_ok());
+}
+
+#[test]
+fn test_refuse_wrapped_assertions() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    // A forged assertion next to the signed one.
+    let start = RESPONSE.find("<saml:Assertion").unwrap();
+    let end = RESPONSE.find("</saml:Assertion>").unwrap() + "</saml:Assertion>".len();
+    let forged = unsigned(&RESPONSE[start..end])
+        .replace("_assertion-fixture", "_forged")
+        .replace("jane.doe@example.com", "admin@example.com");
+    let wrapped = format!("{}{}{}", &RESPONSE[..end], forged, &RESPONSE[end..]);
+    assert!(provider
+        .validate_response(&encode(&wrapped), &context(issued_at()))
+        .is_err());
+
+    // The forged assertion reusing the ID of the signed one.
+    let same_id = forged.replace("_forged", "_assertion-fixture");
+    let wrapped = format!("{}{}{}", &RESPONSE[..start], same_id, &RESPONSE[start..]);
+    assert!(provider
+        .validate_response(&encode(&wrapped), &context(issued_at()))
+        .is_err());
+}
+
+#[test]
+fn test_refuse_expired_or_future_assertions() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+    let response = encode(RESPONSE);
+
+    assert!(provider
+        .validate_response(&response, &context(issued_at() + Duration::minutes(10)))
+        .is_err());
+    assert!(provider
+        .validate_response(&response, &context(issued_at() - Duration::minutes(10)))
+        .is_err());
+    // Clocks may be slightly off.
+    assert!(provider
+        .validate_response(&response, &context(issued_at() - Duration::seconds(60)))
+        .is_ok());
+}
+
+#[test]
+fn test_refuse_assertions_for_another_request_or_service_provider() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+    let response = encode(RESPONSE);
+
+    let other_request = ResponseContext {
+        request_id: "_another-request",
+        ..context(issued_at())
+    };
+    assert!(provider
+        .validate_response(&response, &other_request)
+        .is_err());
+
+    let other_sp = ResponseContext {
+        sp_entity_id: "https://other.example.com/saml/metadata",
+        ..context(issued_at())
+    };
+    assert!(provider.validate_response(&response, &other_sp).is_err());
+
+    let other_acs = ResponseContext {
+        acs_url: "https://auth.example.com/saml/other/acs",
+        ..context(issued_at())
+    };
+    assert!(provider.validate_response(&response, &other_acs).is_err());
+
+    // Even signed, the subject must be confirmed for this service provider.
+    let other_recipient = resigned(|xml| {
+        xml.replace(
+            "Recipient=\"https://auth.example.com/saml/acme/acs\"",
+            "Recipient=\"https://other.example.com/acs\"",
+        )
+    });
+    assert!(provider
+        .validate_response(&encode(&other_recipient), &context(issued_at()))
+        .is_err());
+}
+
+#[test]
+fn test_refuse_responses_of_other_issuers_or_failed() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let other_issuer = resigned(|xml| {
+        xml.replace(
+            "https://idp.example.com/metadata",
+            "https://evil.example.com",
+        )
+    });
+    assert!(provider
+        .validate_response(&encode(&other_issuer), &context(issued_at()))
+        .is_err());
+
+    let failed = RESPONSE.replace(
+        "urn:oasis:names:tc:SAML:2.0:status:Success",
+        "urn:oasis:names:tc:SAML:2.0:status:Responder",
+    );
+    assert!(provider
+        .validate_response(&encode(&failed), &context(issued_at()))
+        .is_err());
+}
+
+#[test]
+fn test_refuse_documents_with_a_dtd() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let with_dtd = RESPONSE.replacen(
+        "<samlp:Response",
+        "<!DOCTYPE samlp:Response [<!ENTITY e \"entity\">]>\n<samlp:Response",
+        1,
+    );
+    assert!(provider
+        .validate_response(&encode(&with_dtd), &context(issued_at()))
+        .is_err());
+}
+
+#[test]
+fn test_map_attributes_to_roles() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+    let assertion = provider
+        .validate_response(&encode(RESPONSE), &context(issued_at()))
+        .unwrap();
+
+    assert_eq!(
+        provider.roles(&[String::from("ROLE_USER")], &assertion),
+        vec!["ROLE_USER", "ROLE_ADMIN"]
+    );
+    // Mapped roles follow the provider, the others are kept.
+    assert_eq!(
+        provider.roles(
+            &[String::from("ROLE_AUDITOR"), String::from("ROLE_SUPPORT")],
+            &assertion
+        ),
+        vec!["ROLE_SUPPORT", "ROLE_ADMIN"]
+    );
+
+    let mut without_groups = assertion.clone();
+    without_groups.attributes.remove("groups");
+    assert_eq!(
+        provider.roles(&[String::from("ROLE_ADMIN")], &without_groups),
+        vec!["ROLE_USER"]
+    );
+}
+
+#[test]
+fn test_authn_request_with_redirect_binding() {
+    let providers = providers();
+    let provider = providers.get("acme").unwrap();
+
+    let request = provider.authn_request("_request-1", SP_ENTITY_ID, ACS_URL, issued_at());
+    let url = provider.redirect_url(&request).unwrap();
+    assert!(url.starts_with("https://idp.example.com/sso?tenant=acme&SAMLRequest="));
+
+    let query = url.split_once('?').unwrap().1;
+    let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap();
+    let deflated = STANDARD.decode(&params[1].1).unwrap();
+    let mut xml = String::new();
+    DeflateDecoder::new(deflated.as_slice())
+        .read_to_string(&mut xml)
+        .unwrap();
+    assert_eq!(xml, request);
+
+    let request = Element::parse(&xml).unwrap();
+    assert_eq!(request.name, "AuthnRequest");
+    assert_eq!(request.attribute("ID"), Some("_request-1"));
+    assert_eq!(
+        request.attribute("AssertionConsumerServiceURL"),
+        Some(ACS_URL)
+    );
+    assert_eq!(
+        request.attribute("IssueInstant"),
+        Some("2026-10-19T10:00:30Z")
+    );
+    let issuer = request.elements().next().unwrap();
+    assert_eq!(issuer.text(), SP_ENTITY_ID);
+}
+
+#[test]
+fn test_service_provider_metadata() {
+    let providers = providers();
+    let metadata = Element::parse(&providers.metadata("https://auth.example.com")).unwrap();
+
+    assert_eq!(metadata.attribute("entityID"), Some(SP_ENTITY_ID));
+    let descriptor = metadata.elements().next().unwrap();
+    let consumer = descriptor
+        .elements()
+        .find(|element| element.name == "AssertionConsumerService")
+        .unwrap();
+    assert_eq!(consumer.attribute("Location"), Some(ACS_URL));
+}
+
+#[test]
+fn test_refuse_invalid_providers() {
+    let config = |name: &str, role: &str, certificate: &str| {
+        format!(
+            r#"{{ "providers": [{{ "name": "{}", "entity_id": "https://idp.example.com",
+                "sso_url": "https://idp.example.com/sso", "certificate_file": "{}",
+                "role_mapping": {{ "admins": "{}" }} }}] }}"#,
+            name, certificate, role
+        )
+    };
+    let certificate = "tests/fixtures/saml/idp.crt";
+
+    assert!(SamlProviders::from_json(&config("acme", "ROLE_ADMIN", certificate)).is_ok());
+    assert!(SamlProviders::from_json(&config("Acme Corp", "ROLE_ADMIN", certificate)).is_err());
+    assert!(SamlProviders::from_json(&config("acme", "admin", certificate)).is_err());
+    assert!(
+        SamlProviders::from_json(&config("acme", "ROLE_ADMIN", "tests/fixtures/saml/idp.key"))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_exclusive_canonicalization() {
+    let xml = "<?xml version=\"1.0\"?>\r\n\
+        <root xmlns=\"urn:default\" xmlns:a=\"urn:a\" xmlns:unused=\"urn:unused\">\r\n\
+        <a:child z=\"1\" a:attr=\"x&#xA;y\" b='&quot;q&quot;'/>\
+        <child xmlns=\"\">text &amp; &lt;more&gt;\r\n</child>\
+        <!-- comment --><![CDATA[<cdata>]]></root>";
+    let root = Element::parse(xml).unwrap();
+
+    assert_eq!(
+        root.exclusive_c14n(None, &[]),
+        "<root xmlns=\"urn:default\">\n\
+        <a:child xmlns:a=\"urn:a\" b=\"&quot;q&quot;\" z=\"1\" a:attr=\"x&#xA;y\"></a:child>\
+        <child xmlns=\"\">text &amp; &lt;more&gt;\n</child>&lt;cdata&gt;</root>"
+    );
+
+    let child = root.elements().next().unwrap();
+    assert_eq!(
+        child.exclusive_c14n(None, &[String::from("unused")]),
+        "<a:child xmlns:a=\"urn:a\" xmlns:unused=\"urn:unused\" b=\"&quot;q&quot;\" z=\"1\" a:attr=\"x&#xA;y\"></a:child>"
+    );
+}
+
+#[test]
+fn test_verify_fixture_signature_directly() {
+    let certificate = openssl::x509::X509::from_pem(
+        std::fs::read("tests/fixtures/saml/idp.crt")
+            .unwrap()
+            .as_slice(),
+    )
+    .unwrap();
+    let response = Element::parse(RESPONSE).unwrap();
+    let assertion = response
+        .elements()
+        .find(|element| element.name == "Assertion")
+        .unwrap();
+
+    assert!(
+        verify_enveloped_signature(assertion, "ID", &certificate.public_key().unwrap()).is_ok()
+    );
+    // The response itself is not signed.
+    assert!(
+        verify_enveloped_signature(&response, "ID", &certificate.public_key().unwrap()).is_err()
+    );
+}
@@ -0,0 +1,391 @@
+use auth_api::services::oauth::hash_token;
+use auth_api::services::scim::{
+    group_resource, resource_types, schemas, service_provider_config, user_resource, Filter,
+    GroupPatch, GroupResource, ListQuery, MembersChange, PatchRequest, ScimClients, ScimError,
+    UserAttributes, UserResource, SCHEMA_ERROR, SCHEMA_USER,
+};
+use serde_json::{json, Value};
+
+const TOKEN: &str = "okta-provisioning-token";
+
+fn clients() -> ScimClients {
+    ScimClients::from_json(
+        &json!({
+            "clients": [
+                { "name": "okta", "token_sha256": hash_token(TOKEN), "group_mapping": { "Admins": "ROLE_ADMIN" } },
+                { "name": "azure", "token_sha256": hash_token("azure-token") }
+            ]
+        })
+        .to_string(),
+    )
+    .unwrap()
+}
+
+fn patch(operations: Value) -> PatchRequest {
+    serde_json::from_value(json!({
+        "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+        "Operations": operations,
+    }))
+    .unwrap()
+}
+
+fn user() -> UserAttributes {
+    UserAttributes {
+        email: "jdoe@example.com".to_owned(),
+        name: Some("John Doe".to_owned()),
+        external_id: Some("00u1".to_owned()),
+        active: true,
+    }
+}
+
+#[test]
+fn test_scim_client_authentication() {
+    let clients = clients();
+
+    let client = clients.authenticate(Some(&format!("Bearer {}", TOKEN)));
+    assert_eq!(client.map(|client| client.name()), Some("okta"));
+    let client = clients.authenticate(Some("Bearer azure-token"));
+    assert_eq!(client.map(|client| client.name()), Some("azure"));
+
+    assert!(clients.authenticate(None).is_none());
+    assert!(clients.authenticate(Some("Bearer ")).is_none());
+    assert!(clients.authenticate(Some("Bearer wrong-token")).is_none());
+    assert!(clients.authenticate(Some(TOKEN)).is_none());
+    assert!(ScimClients::default()
+        .authenticate(Some(&format!("Bearer {}", TOKEN)))
+        .is_none());
+}
+
+#[test]
+fn test_scim_clients_validation() {
+    let hash = hash_token(TOKEN);
+    let config = |client: Value| json!({ "clients": [client] }).to_string();
+
+    assert!(
+        ScimClients::from_json(&config(json!({ "name": "okta", "token_sha256": TOKEN }))).is_err()
+    );
+    assert!(
+        ScimClients::from_json(&config(json!({ "name": "Okta!", "token_sha256": hash }))).is_err()
+    );
+    assert!(ScimClients::from_json(&config(
+        json!({ "name": "okta", "token_sha256": hash, "group_mapping": { "Admins": "admin" } })
+    ))
+    .is_err());
+    assert!(ScimClients::from_json(
+        &json!({ "clients": [
+            { "name": "okta", "token_sha256": hash },
+            { "name": "okta", "token_sha256": hash_token("other") }
+        ] })
+        .to_string()
+    )
+    .is_err());
+    assert!(ScimClients::from_json(r#"{"clients": []}"#).is_ok());
+}
+
+#[test]
+fn test_scim_client_roles_follow_groups() {
+    let clients = clients();
+    let client = clients
+        .authenticate(Some(&format!("Bearer {}", TOKEN)))
+        .unwrap();
+
+    let current = vec!["ROLE_USER".to_owned()];
+    assert_eq!(
+        client.roles(&current, ["Admins", "Staff"]),
+        vec!["ROLE_USER", "ROLE_ADMIN"]
+    );
+
+    let current = vec!["ROLE_USER".to_owned(), "ROLE_ADMIN".to_owned()];
+    assert_eq!(client.roles(&current, ["Staff"]), vec!["ROLE_USER"]);
+    assert_eq!(
+        client.roles(&["ROLE_ADMIN".to_owned()], []),
+        vec!["ROLE_USER"]
+    );
+}
+
+#[test]
+fn test_scim_filter() {
+    assert_eq!(
+        Filter::parse(r#"userName eq "jdoe@example.com""#).unwrap(),
+        Filter {
+            attribute: "username".to_owned(),
+            value: "jdoe@example.com".to_owned(),
+        }
+    );
+    assert_eq!(
+        Filter::parse(r#" displayName EQ "R&D \"team\"" "#)
+            .unwrap()
+            .value,
+        r#"R&D "team""#
+    );
+
+    for filter in [
+        "userName",
+        r#"userName co "jdoe""#,
+        r#"userName eq jdoe"#,
+        r#"userName eq "jdoe" and active eq true"#,
+    ] {
+        let err = Filter::parse(filter).unwrap_err();
+        assert_eq!(err.status, 400);
+        assert_eq!(err.scim_type, Some("invalidFilter"));
+    }
+}
+
+#[test]
+fn test_scim_pagination() {
+    let query = ListQuery::default();
+    assert_eq!(
+        (query.start_index(), query.offset(), query.limit()),
+        (1, 0, 100)
+    );
+
+    let query: ListQuery =
+        serde_json::from_value(json!({ "startIndex": 11, "count": 10 })).unwrap();
+    assert_eq!((query.offset(), query.limit()), (10, 10));
+
+    let query: ListQuery =
+        serde_json::from_value(json!({ "startIndex": 0, "count": 5000 })).unwrap();
+    assert_eq!((query.start_index(), query.limit()), (1, 200));
+
+    let query: ListQuery = serde_json::from_value(json!({ "count": -1 })).unwrap();
+    assert_eq!(query.limit(), 0);
+
+    let query: ListQuery =
+        serde_json::from_value(json!({ "startIndex": 3, "excludedAttributes": "meta, Members" }))
+            .unwrap();
+    assert!(query.excludes("members"));
+    assert!(!query.excludes("displayName"));
+
+    let response = query.response(vec![json!({ "id": "1" })], 42);
+    assert_eq!(response["totalResults"], 42);
+    assert_eq!(response["startIndex"], 3);
+    assert_eq!(response["itemsPerPage"], 1);
+    assert_eq!(response["Resources"][0]["id"], "1");
+}
+
+#[test]
+fn test_scim_user_attributes() {
+    let resource: UserResource = serde_json::from_value(json!({
+        "schemas": [SCHEMA_USER],
+        "userName": "JDoe@Example.com",
+        "externalId": "00u1",
+        "name": { "givenName": "John", "familyName": "Doe" },
+        "emails": [{ "value": "jdoe@example.com", "primary": true }],
+        "active": true,
+    }))
+    .unwrap();
+    assert_eq!(resource.attributes().unwrap(), user());
+
+    // Azure AD user names are user principal names, the email is the primary one.
+    let resource: UserResource = serde_json::from_value(json!({
+        "userName": "jdoe",
+        "displayName": "John",
+        "emails": [{ "value": "john@example.com" }, { "value": "jdoe@example.com", "primary": true }],
+        "active": false,
+    }))
+    .unwrap();
+    let attributes = resource.attributes().unwrap();
+    assert_eq!(attributes.email, "jdoe@example.com");
+    assert_eq!(attributes.name.as_deref(), Some("John"));
+    assert!(!attributes.active);
+
+    let resource: UserResource = serde_json::from_value(json!({ "userName": "jdoe" })).unwrap();
+    assert_eq!(
+        resource.attributes().unwrap_err().scim_type,
+        Some("invalidValue")
+    );
+}
+
+#[test]
+fn test_scim_user_patch() {
+    let mut attributes = user();
+    attributes
+        .patch(&patch(json!([
+            { "op": "Replace", "path": "active", "value": "False" },
+            { "op": "replace", "value": { "userName": "John.Doe@example.com", "name": { "formatted": "J. Doe" }, "title": "CTO" } },
+            { "op": "remove", "path": "externalId" },
+        ])))
+        .unwrap();
+    assert_eq!(
+        attributes,
+        UserAttributes {
+            email: "john.doe@example.com".to_owned(),
+            name: Some("J. Doe".to_owned()),
+            external_id: None,
+            active: false,
+        }
+    );
+
+    let mut attributes = user();
+    attributes
+        .patch(&patch(
+            json!([{ "op": "replace", "value": { "active": true, "displayName": "Johnny" } }]),
+        ))
+        .unwrap();
+    assert_eq!(attributes.name.as_deref(), Some("Johnny"));
+    assert!(attributes.active);
+}
+
+#[test]
+fn test_scim_user_patch_errors() {
+    let cases = [
+        (
+            json!([{ "op": "remove", "path": "userName" }]),
+            "mutability",
+        ),
+        (
+            json!([{ "op": "replace", "path": "userName", "value": "jdoe" }]),
+            "invalidValue",
+        ),
+        (
+            json!([{ "op": "replace", "path": "active", "value": "maybe" }]),
+            "invalidValue",
+        ),
+        (
+            json!([{ "op": "move", "path": "active", "value": true }]),
+            "invalidSyntax",
+        ),
+        (
+            json!([{ "op": "replace", "value": "jdoe" }]),
+            "invalidValue",
+        ),
+    ];
+    for (operations, scim_type) in cases {
+        let err = user().patch(&patch(operations)).unwrap_err();
+        assert_eq!(err.scim_type, Some(scim_type));
+    }
+
+    let request: PatchRequest = serde_json::from_value(json!({
+        "Operations": [{ "op": "replace", "path": "active", "value": false }],
+    }))
+    .unwrap();
+    assert_eq!(
+        user().patch(&request).unwrap_err().scim_type,
+        Some("invalidSyntax")
+    );
+}
+
+#[test]
+fn test_scim_group_patch() {
+    let request = patch(json!([
+        { "op": "replace", "value": { "id": "group-1", "displayName": "Admins" } },
+        { "op": "add", "path": "members", "value": [{ "value": "user-1" }, { "value": "user-2" }] },
+        { "op": "remove", "path": "members[value eq \"User-3\"]" },
+        { "op": "Remove", "path": "members", "value": [{ "value": "user-4" }] },
+        { "op": "replace", "path": "externalId", "value": "00g1" },
+    ]));
+    assert_eq!(
+        GroupPatch::from_request(&request).unwrap(),
+        GroupPatch {
+            display_name: Some("Admins".to_owned()),
+            external_id: Some(Some("00g1".to_owned())),
+            members: vec![
+                MembersChange::Add(vec!["user-1".to_owned(), "user-2".to_owned()]),
+                MembersChange::Remove(vec!["User-3".to_owned()]),
+                MembersChange::Remove(vec!["user-4".to_owned()]),
+            ],
+        }
+    );
+
+    let request = patch(json!([
+        { "op": "remove", "path": "members" },
+        { "op": "replace", "path": "members", "value": [{ "value": "user-1" }] },
+    ]));
+    assert_eq!(
+        GroupPatch::from_request(&request).unwrap().members,
+        vec![
+            MembersChange::Replace(vec![]),
+            MembersChange::Replace(vec!["user-1".to_owned()]),
+        ]
+    );
+}
+
+#[test]
+fn test_scim_group_patch_errors() {
+    let cases = [
+        (
+            json!([{ "op": "remove", "path": "displayName" }]),
+            "mutability",
+        ),
+        (
+            json!([{ "op": "replace", "path": "displayName", "value": " " }]),
+            "invalidValue",
+        ),
+        (
+            json!([{ "op": "add", "path": "members", "value": "user-1" }]),
+            "invalidValue",
+        ),
+        (
+            json!([{ "op": "add", "path": "members[value eq \"user-1\"]" }]),
+            "invalidPath",
+        ),
+        (
+            json!([{ "op": "remove", "path": "members[display eq \"jdoe\"]" }]),
+            "invalidPath",
+        ),
+    ];
+    for (operations, scim_type) in cases {
+        let err = GroupPatch::from_request(&patch(operations)).unwrap_err();
+        assert_eq!(err.scim_type, Some(scim_type));
+    }
+
+    let group: GroupResource = serde_json::from_value(json!({ "displayName": "" })).unwrap();
+    assert!(group.validate().is_err());
+    let group: GroupResource = serde_json::from_value(json!({
+        "displayName": "Admins",
+        "members": [{ "value": "user-1", "display": "jdoe@example.com" }],
+    }))
+    .unwrap();
+    assert!(group.validate().is_ok());
+    assert_eq!(group.member_ids(), vec!["user-1"]);
+}
+
+#[test]
+fn test_scim_resources() {
+    let location = "https://auth.example.com/scim/v2/Users/user-1";
+    let resource = user_resource("user-1", &user(), location);
+    assert_eq!(resource["schemas"], json!([SCHEMA_USER]));
+    assert_eq!(resource["userName"], "jdoe@example.com");
+    assert_eq!(resource["displayName"], "John Doe");
+    assert_eq!(resource["externalId"], "00u1");
+    assert_eq!(resource["active"], true);
+    assert_eq!(resource["meta"]["location"], location);
+    assert!(resource.get("password").is_none());
+
+    let members = vec![("user-1".to_owned(), "jdoe@example.com".to_owned())];
+    let resource = group_resource("group-1", "Admins", None, Some(members), "/Groups/group-1");
+    assert_eq!(resource["members"][0]["value"], "user-1");
+    assert!(resource.get("externalId").is_none());
+    let resource = group_resource("group-1", "Admins", None, None, "/Groups/group-1");
+    assert!(resource.get("members").is_none());
+
+    let err = ScimError::conflict("A user already has the email jdoe@example.com").body();
+    assert_eq!(err["schemas"], json!([SCHEMA_ERROR]));
+    assert_eq!(err["status"], "409");
+    assert_eq!(err["scimType"], "uniqueness");
+}
+
+#[test]
+fn test_scim_discovery() {
+    let base_url = "https://auth.example.com";
+    let config = service_provider_config(base_url);
+    assert_eq!(config["patch"]["supported"], true);
+    assert_eq!(config["bulk"]["supported"], false);
+    assert_eq!(config["filter"]["maxResults"], 200);
+
+    let types = resource_types(base_url);
+    assert_eq!(types.len(), 2);
+    assert_eq!(types[0]["endpoint"], "/Users");
+    assert_eq!(
+        types[1]["schema"],
+        "urn:ietf:params:scim:schemas:core:2.0:Group"
+    );
+
+    let schemas = schemas(base_url);
+    assert_eq!(schemas[0]["id"], SCHEMA_USER);
+    assert!(schemas[0]["attributes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|attribute| attribute["name"] == "userName" && attribute["required"] == true));
+}
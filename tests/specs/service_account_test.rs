@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use auth_api::services::access_control::{AccessControl, Authorization, GrantAccess};
+use auth_api::services::crypto::{SigningKey, TokenConfig, TokenOptions};
+use auth_api::services::oauth::{
+    client_assertion, validate_client_jwks, verify_client_assertion, OAuthErrorCode,
+    CLIENT_ASSERTION_TYPE_JWT_BEARER,
+};
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use serde_json::{json, Value};
+
+const TOKEN_ENDPOINT: &str = "https://auth.example.com/oauth/token";
+
+fn generate_key() -> SigningKey {
+    let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", der.as_ref()));
+    SigningKey::from_pem(pem.as_bytes()).unwrap()
+}
+
+fn jwks(key: &SigningKey) -> JwkSet {
+    JwkSet {
+        keys: vec![key.jwk()],
+    }
+}
+
+fn assertion_claims(client_id: &str, lifetime: i64) -> Value {
+    json!({
+        "iss": client_id,
+        "sub": client_id,
+        "aud": TOKEN_ENDPOINT,
+        "exp": Utc::now().timestamp() + lifetime,
+        "jti": "assertion-1",
+    })
+}
+
+fn verify(assertion: &str, key: &SigningKey) -> Result<(), OAuthErrorCode> {
+    verify_client_assertion(
+        assertion,
+        "job-runner",
+        &jwks(key),
+        &[String::from(TOKEN_ENDPOINT)],
+    )
+    .map_err(|err| err.error)
+}
+
+#[test]
+fn test_from_scopes() {
+    let scopes = HashSet::from([String::from("user:read"), String::from("reports")]);
+
+    assert!(matches!(
+        AccessControl::from_scopes(&scopes, "reports"),
+        Authorization::Authorized
+    ));
+    assert!(matches!(
+        AccessControl::from_scopes(&scopes, "user:delete"),
+        Authorization::Unauthorized(_)
+    ));
+    assert!(matches!(
+        AccessControl::from_scopes(&HashSet::new(), "reports"),
+        Authorization::Unauthorized(_)
+    ));
+}
+
+#[test]
+fn test_service_account_token_claims() {
+    let config = TokenConfig::new("auth_api", "auth_api", 3600);
+    let options = TokenOptions {
+        scope: Some(String::from("user:read reports")),
+        client_id: Some(String::from("job-runner")),
+        ..TokenOptions::default()
+    };
+
+    let claims = config.claims("job-runner", &options).unwrap();
+    assert!(claims.is_service_account());
+    assert!(claims.roles.is_empty());
+    assert_eq!(
+        AccessControl::token_scopes(&claims),
+        HashSet::from([String::from("user:read"), String::from("reports")])
+    );
+
+    // A token delegated by a user to a client is not the client's own token.
+    let claims = config.claims("user-1", &options).unwrap();
+    assert!(!claims.is_service_account());
+
+    let claims = config.claims("user-1", &TokenOptions::default()).unwrap();
+    assert!(!claims.is_service_account());
+    assert!(AccessControl::token_scopes(&claims).is_empty());
+}
+
+#[test]
+fn test_client_assertion_identifies_the_client() {
+    let key = generate_key();
+    let assertion = key.sign(&assertion_claims("job-runner", 60)).unwrap();
+
+    let credentials =
+        client_assertion(CLIENT_ASSERTION_TYPE_JWT_BEARER, &assertion, None).unwrap();
+    assert_eq!(credentials.client_id, "job-runner");
+    assert_eq!(credentials.client_assertion.as_deref(), Some(assertion.as_str()));
+    assert_eq!(credentials.client_secret, None);
+
+    let err = client_assertion(CLIENT_ASSERTION_TYPE_JWT_BEARER, &assertion, Some("other"))
+        .unwrap_err();
+    assert_eq!(err.error, OAuthErrorCode::InvalidClient);
+
+    let err = client_assertion("urn:unknown", &assertion, None).unwrap_err();
+    assert_eq!(err.error, OAuthErrorCode::InvalidRequest);
+
+    let err = client_assertion(CLIENT_ASSERTION_TYPE_JWT_BEARER, "not-a-jwt", None).unwrap_err();
+    assert_eq!(err.error, OAuthErrorCode::InvalidClient);
+}
+
+#[test]
+fn test_verify_client_assertion() {
+    let key = generate_key();
+
+    let assertion = key.sign(&assertion_claims("job-runner", 60)).unwrap();
+    assert_eq!(verify(&assertion, &key), Ok(()));
+
+    // Signed by another key.
+    assert_eq!(
+        verify(&assertion, &generate_key()),
+        Err(OAuthErrorCode::InvalidClient)
+    );
+}
+
+#[test]
+fn test_verify_client_assertion_claims() {
+    let key = generate_key();
+
+    let mut claims = assertion_claims("job-runner", 60);
+    claims["aud"] = json!("https://other.example.com/oauth/token");
+    let assertion = key.sign(&claims).unwrap();
+    assert_eq!(verify(&assertion, &key), Err(OAuthErrorCode::InvalidClient));
+
+    let assertion = key.sign(&assertion_claims("other-client", 60)).unwrap();
+    assert_eq!(verify(&assertion, &key), Err(OAuthErrorCode::InvalidClient));
+
+    let mut claims = assertion_claims("job-runner", 60);
+    claims["sub"] = json!("user-1");
+    let assertion = key.sign(&claims).unwrap();
+    assert_eq!(verify(&assertion, &key), Err(OAuthErrorCode::InvalidClient));
+
+    let assertion = key.sign(&assertion_claims("job-runner", -600)).unwrap();
+    assert_eq!(verify(&assertion, &key), Err(OAuthErrorCode::InvalidClient));
+
+    // Long-lived assertions could be replayed.
+    let assertion = key.sign(&assertion_claims("job-runner", 3600)).unwrap();
+    assert_eq!(verify(&assertion, &key), Err(OAuthErrorCode::InvalidClient));
+}
+
+#[test]
+fn test_verify_client_assertion_rejects_hmac() {
+    let key = generate_key();
+    let header = Header {
+        kid: Some(key.kid().to_owned()),
+        ..Header::default()
+    };
+
+    let assertion = encode(
+        &header,
+        &assertion_claims("job-runner", 60),
+        &EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap();
+
+    assert_eq!(verify(&assertion, &key), Err(OAuthErrorCode::InvalidClient));
+}
+
+#[test]
+fn test_validate_client_jwks() {
+    assert!(validate_client_jwks(&jwks(&generate_key())).is_ok());
+    assert!(validate_client_jwks(&JwkSet { keys: vec![] }).is_err());
+
+    let symmetric: JwkSet = serde_json::from_value(json!({
+        "keys": [{"kty": "oct", "k": "c2VjcmV0", "kid": "hmac"}]
+    }))
+    .unwrap();
+    assert!(validate_client_jwks(&symmetric).is_err());
+}
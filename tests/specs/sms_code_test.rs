@@ -0,0 +1,123 @@
+use auth_api::services::sms::{FileSmsSender, LogSmsSender, Sms, SmsSender};
+use auth_api::services::sms_code::{
+    code_matches, generate_code, hash_code, login_sms, masked_phone_number,
+    normalized_phone_number, verification_sms, PendingLoginChallenge, SmsCodeLimiter,
+    MAX_CODES_PER_USER, RATE_LIMIT_WINDOW,
+};
+use std::time::Instant;
+
+#[test]
+fn test_normalized_phone_number() {
+    assert_eq!(
+        normalized_phone_number("+33612345678").as_deref(),
+        Some("+33612345678")
+    );
+    assert_eq!(
+        normalized_phone_number(" +1 (415) 555-0132 ").as_deref(),
+        Some("+14155550132")
+    );
+    assert_eq!(
+        normalized_phone_number("+44.20.7946.0958").as_deref(),
+        Some("+442079460958")
+    );
+
+    assert_eq!(normalized_phone_number("0612345678"), None);
+    assert_eq!(normalized_phone_number("+0612345678"), None);
+    assert_eq!(normalized_phone_number("+123456"), None);
+    assert_eq!(normalized_phone_number("+1234567890123456"), None);
+    assert_eq!(normalized_phone_number("+33 6 12 34 56 7a"), None);
+    assert_eq!(normalized_phone_number("++33612345678"), None);
+    assert_eq!(normalized_phone_number(""), None);
+}
+
+#[test]
+fn test_masked_phone_number() {
+    assert_eq!(masked_phone_number("+33612345678"), "+3********78");
+    assert_eq!(masked_phone_number("+123"), "+123");
+}
+
+#[test]
+fn test_generated_codes() {
+    let code = generate_code();
+    assert_eq!(code.len(), 6);
+    assert!(code.bytes().all(|c| c.is_ascii_digit()));
+
+    let codes: std::collections::HashSet<String> = (0..20).map(|_| generate_code()).collect();
+    assert!(codes.len() > 1);
+
+    let challenge = PendingLoginChallenge::generate();
+    assert!(challenge.challenge.len() >= 43);
+    assert_eq!(challenge.code.len(), 6);
+}
+
+#[test]
+fn test_code_hashes() {
+    let hash = hash_code("user-1", "123456");
+
+    assert_eq!(hash.len(), 64);
+    assert!(!hash.contains("123456"));
+    assert!(code_matches(&hash, "user-1", "123456"));
+    assert!(code_matches(&hash, "user-1", " 123456 "));
+    assert!(!code_matches(&hash, "user-1", "123457"));
+    assert!(!code_matches(&hash, "user-2", "123456"));
+    assert!(!code_matches(&hash, "user-1", ""));
+}
+
+#[test]
+fn test_codes_are_limited_by_user() {
+    let limiter = SmsCodeLimiter::default();
+    let start = Instant::now();
+
+    for _ in 0..MAX_CODES_PER_USER {
+        assert!(limiter.allow("user-1", start));
+    }
+    assert!(!limiter.allow("user-1", start));
+    assert!(limiter.allow("user-2", start));
+    assert!(limiter.allow("user-1", start + RATE_LIMIT_WINDOW));
+}
+
+#[test]
+fn test_sms_bodies() {
+    let sms = verification_sms("+33612345678", "123456");
+    assert_eq!(sms.to, "+33612345678");
+    assert!(sms.body.contains("123456"));
+    assert!(sms.body.contains("5 minutes"));
+
+    let sms = login_sms("+33612345678", "654321");
+    assert!(sms.body.contains("654321"));
+}
+
+#[tokio::test]
+async fn test_file_sms_sender() {
+    let path = std::env::temp_dir().join(format!("sms-outbox-{}.jsonl", generate_code()));
+    let sender = FileSmsSender::new(path.to_str().unwrap());
+
+    for body in ["first", "second"] {
+        let sms = Sms {
+            to: String::from("+33612345678"),
+            body: String::from(body),
+        };
+        assert!(sender.send(&sms).await.is_ok());
+    }
+
+    let outbox = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<serde_json::Value> = outbox
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["to"], "+33612345678");
+    assert_eq!(lines[0]["body"], "first");
+    assert_eq!(lines[1]["body"], "second");
+}
+
+#[tokio::test]
+async fn test_log_sms_sender() {
+    let sms = Sms {
+        to: String::from("+33612345678"),
+        body: String::from("123456 is your sign-in code."),
+    };
+
+    assert!(LogSmsSender.send(&sms).await.is_ok());
+}